@@ -3,7 +3,7 @@ use alloc::vec::Vec;
 use parity_scale_codec::{Decode, Encode, Error as CodecError};
 
 use crate::prpc::{Signature, SignatureType};
-pub use phala_crypto::{aead, ecdh, CryptoError};
+pub use phala_crypto::{aead, ecdh, ecdh::EncryptionScheme, CryptoError};
 
 #[derive(Clone, Encode, Decode, Debug)]
 pub struct EncryptedData {
@@ -14,9 +14,21 @@ pub struct EncryptedData {
 
 impl EncryptedData {
     pub fn decrypt(&self, key: &ecdh::EcdhKey) -> Result<Vec<u8>, CryptoError> {
-        let sk = ecdh::agree(key, &self.pubkey)?;
+        self.decrypt_with(key, EncryptionScheme::Raw, &[], &[])
+    }
+
+    /// Decrypts the data, which must be encrypted with the same scheme, context and associated
+    /// data.
+    pub fn decrypt_with(
+        &self,
+        key: &ecdh::EcdhKey,
+        scheme: EncryptionScheme,
+        context: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        let sk = ecdh::agree_with(scheme, key, &self.pubkey, context)?;
         let mut tmp_data = self.data.clone();
-        let msg = aead::decrypt(&self.iv, &sk, &mut tmp_data)?;
+        let msg = aead::decrypt_with_aad(&self.iv, &sk, aad, &mut tmp_data)?;
         Ok(msg.to_vec())
     }
 
@@ -26,9 +38,34 @@ impl EncryptedData {
         iv: aead::IV,
         data: &[u8],
     ) -> Result<Self, CryptoError> {
-        let sk = ecdh::agree(key, &remote_pubkey[..])?;
+        Self::encrypt_with(
+            key,
+            remote_pubkey,
+            EncryptionScheme::Raw,
+            &[],
+            iv,
+            &[],
+            data,
+        )
+    }
+
+    /// Encrypts the data with the key derived by `scheme` for `context`, authenticating the
+    /// associated data along with it.
+    ///
+    /// Neither the scheme nor the associated data is carried in the result. The receiver must
+    /// learn them from elsewhere.
+    pub fn encrypt_with(
+        key: &ecdh::EcdhKey,
+        remote_pubkey: &ecdh::EcdhPublicKey,
+        scheme: EncryptionScheme,
+        context: &[u8],
+        iv: aead::IV,
+        aad: &[u8],
+        data: &[u8],
+    ) -> Result<Self, CryptoError> {
+        let sk = ecdh::agree_with(scheme, key, &remote_pubkey[..], context)?;
         let mut data = data.to_vec();
-        aead::encrypt(&iv, &sk, &mut data)?;
+        aead::encrypt_with_aad(&iv, &sk, aad, &mut data)?;
         Ok(Self {
             iv,
             pubkey: key.public(),
@@ -18,6 +18,48 @@ pub struct InitArgs {
 
     /// The git commit hash which this binary was built from.
     pub git_revision: String,
+
+    /// The block interval to checkpoint the runtime state. 0 to disable checkpoints.
+    pub checkpoint_interval: u32,
+
+    /// Whether to attest with a DCAP quote instead of an IAS report.
+    pub dcap_attestation: bool,
+
+    /// The egress queue capacity of each contract. 0 for unlimited.
+    pub contract_egress_capacity: u32,
+
+    /// What to do when the egress queue of a contract is full.
+    pub contract_egress_policy: EgressOverflowPolicy,
+}
+
+/// The overflow policy of the contract egress queues.
+///
+/// Dropping the oldest messages is not offered, since the contract messages are synced to the
+/// chain in sequence.
+#[derive(Debug, Encode, Decode, Clone, Copy, PartialEq, Eq)]
+pub enum EgressOverflowPolicy {
+    /// Refuse the new messages.
+    Reject,
+    /// Hold the contract from processing its commands until the queue gets purged.
+    Block,
+}
+
+impl Default for EgressOverflowPolicy {
+    fn default() -> Self {
+        Self::Reject
+    }
+}
+
+impl core::str::FromStr for EgressOverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "block" => Ok(Self::Block),
+            _ => Err(alloc::format!("Unknown egress overflow policy: {}", s)),
+        }
+    }
 }
 
 pub fn git_revision() -> String {
@@ -8,12 +8,20 @@ mod proto_generated;
 
 pub mod prpc {
     pub use crate::proto_generated::*;
+    use alloc::collections::BTreeMap;
     use alloc::vec::Vec;
     use phala_types::messaging::{MessageOrigin, SignedMessage};
     pub use prpc::{client, server, Message};
     pub type EgressMessages = Vec<(MessageOrigin, Vec<SignedMessage>)>;
+    /// The sequence of the first message to fetch from each sender
+    pub type EgressCursor = BTreeMap<MessageOrigin, u64>;
 
     pub const SIG_LEN: usize = 64;
+
+    /// The `Attestation.provider` of an IAS report
+    pub const ATTESTATION_PROVIDER_IAS: &str = "SGX";
+    /// The `Attestation.provider` of a DCAP quote, carried in `Attestation.dcap_quote`
+    pub const ATTESTATION_PROVIDER_DCAP: &str = "SGX-DCAP";
 }
 
 #[cfg(feature = "pruntime-client")]
@@ -21,6 +29,7 @@ pub mod pruntime_client;
 
 pub mod actions {
     pub const ACTION_GET_INFO: u8 = 2;
+    pub const ACTION_GET_METRICS: u8 = 3;
 
     pub const BIN_ACTION_START: u8 = 128;
     pub const BIN_ACTION_SYNC_PARA_HEADER: u8 = BIN_ACTION_START;
@@ -212,7 +221,7 @@ pub mod storage_sync {
     use alloc::vec::Vec;
     use chain::Hash;
     use derive_more::Display;
-    use parity_scale_codec::Encode;
+    use parity_scale_codec::{Decode, Encode};
 
     type Storage = phala_trie_storage::TrieStorage<RuntimeHasher>;
 
@@ -299,6 +308,7 @@ pub mod storage_sync {
         ) -> Result<()>;
     }
 
+    #[derive(Encode, Decode, Clone)]
     pub struct BlockSyncState<Validator> {
         validator: Validator,
         main_bridge: u64,
@@ -427,6 +437,7 @@ pub mod storage_sync {
         pub next_block_number: chain::BlockNumber,
     }
 
+    #[derive(Encode, Decode, Clone)]
     pub struct SolochainSynchronizer<Validator> {
         sync_state: BlockSyncState<Validator>,
         state_roots: VecDeque<Hash>,
@@ -478,6 +489,7 @@ pub mod storage_sync {
         }
     }
 
+    #[derive(Encode, Decode, Clone)]
     pub struct ParachainSynchronizer<Validator> {
         sync_state: BlockSyncState<Validator>,
         last_relaychain_state_root: Option<Hash>,
@@ -579,6 +591,77 @@ pub mod storage_sync {
         }
     }
 
+    /// A synchronizer for either solochain or parachain mode which can be persisted
+    #[derive(Encode, Decode, Clone)]
+    pub enum Synchronizer<Validator> {
+        Solo(SolochainSynchronizer<Validator>),
+        Para(ParachainSynchronizer<Validator>),
+    }
+
+    impl<Validator: BlockValidator> Synchronizer<Validator> {
+        pub fn new_solochain(validator: Validator, main_bridge: u64) -> Self {
+            Self::Solo(SolochainSynchronizer::new(validator, main_bridge))
+        }
+
+        pub fn new_parachain(
+            validator: Validator,
+            main_bridge: u64,
+            headernum_next: chain::BlockNumber,
+        ) -> Self {
+            Self::Para(ParachainSynchronizer::new(
+                validator,
+                main_bridge,
+                headernum_next,
+            ))
+        }
+
+        fn inner(&self) -> &dyn StorageSynchronizer {
+            match self {
+                Self::Solo(s) => s,
+                Self::Para(s) => s,
+            }
+        }
+
+        fn inner_mut(&mut self) -> &mut dyn StorageSynchronizer {
+            match self {
+                Self::Solo(s) => s,
+                Self::Para(s) => s,
+            }
+        }
+    }
+
+    impl<Validator: BlockValidator> StorageSynchronizer for Synchronizer<Validator> {
+        fn counters(&self) -> Counters {
+            self.inner().counters()
+        }
+
+        fn sync_header(
+            &mut self,
+            headers: Vec<HeaderToSync>,
+            authority_set_change: Option<AuthoritySetChange>,
+        ) -> Result<chain::BlockNumber> {
+            self.inner_mut().sync_header(headers, authority_set_change)
+        }
+
+        fn sync_parachain_header(
+            &mut self,
+            headers: Vec<chain::Header>,
+            proof: StorageProof,
+            storage_key: &[u8],
+        ) -> Result<chain::BlockNumber> {
+            self.inner_mut()
+                .sync_parachain_header(headers, proof, storage_key)
+        }
+
+        fn feed_block(
+            &mut self,
+            block: &BlockHeaderWithChanges,
+            storage: &mut Storage,
+        ) -> Result<()> {
+            self.inner_mut().feed_block(block, storage)
+        }
+    }
+
     #[cfg(test)]
     mod tests {
         // TODO.kevin: Write some tests
@@ -0,0 +1,260 @@
+//! Software implementation of the platform abstraction layer
+//!
+//! `NativePlatform` allows running Phactory as a plain process without any TEE hardware. It is
+//! meant for CI and local development only: the sealing key sits on the disk next to the sealed
+//! files, and the attestation report is a mock that no real validator would accept.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as _, Result};
+use log::{info, warn};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use phactory_pal::{Machine, Sealing, RA};
+
+/// The sealing key file name under the sealing directory
+pub const SEAL_KEY_FILE: &str = "native-seal.key";
+
+/// Length of the report data that fits in a quote (same as SGX_REPORT_DATA_SIZE)
+pub const REPORT_DATA_SIZE: usize = 64;
+
+const SEAL_KEY_LEN: usize = 32;
+// The offset of the report data in a quote body, matching the IAS quote layout.
+const QUOTE_REPORT_DATA_OFFSET: usize = 368;
+const QUOTE_BODY_LEN: usize = QUOTE_REPORT_DATA_OFFSET + REPORT_DATA_SIZE;
+// The version and the attestation key type (ECDSA P-256) of a DCAP quote
+const DCAP_QUOTE_VERSION: u16 = 3;
+const DCAP_ATTESTATION_KEY_TYPE: u16 = 2;
+
+/// A platform running outside of any TEE
+#[derive(Clone)]
+pub struct NativePlatform {
+    seal_key: Arc<[u8; SEAL_KEY_LEN]>,
+}
+
+impl NativePlatform {
+    /// Creates a platform sealing with the given key
+    pub fn new(seal_key: [u8; SEAL_KEY_LEN]) -> Self {
+        NativePlatform {
+            seal_key: Arc::new(seal_key),
+        }
+    }
+
+    /// Loads the sealing key from `key_file`, or generates and saves a new one if it's missing
+    pub fn load_or_generate(key_file: impl AsRef<Path>) -> Result<Self> {
+        let key_file = key_file.as_ref();
+        let mut seal_key = [0u8; SEAL_KEY_LEN];
+        match fs::read(key_file) {
+            Ok(data) => {
+                if data.len() != SEAL_KEY_LEN {
+                    return Err(anyhow!(
+                        "Invalid seal key length {} in {:?}",
+                        data.len(),
+                        key_file
+                    ));
+                }
+                seal_key.copy_from_slice(&data);
+                info!("Loaded seal key from {:?}", key_file);
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                warn!("Seal key not found, generating a new one at {:?}", key_file);
+                SystemRandom::new()
+                    .fill(&mut seal_key)
+                    .map_err(|_| anyhow!("Failed to generate seal key"))?;
+                if let Some(dir) = key_file.parent() {
+                    fs::create_dir_all(dir).context("Create sealing dir")?;
+                }
+                fs::write(key_file, seal_key).context("Write seal key")?;
+            }
+            Err(err) => {
+                return Err(anyhow!("Read seal key failed: {:?} path={:?}", err, key_file))
+            }
+        }
+        Ok(Self::new(seal_key))
+    }
+
+    fn aead_key(&self) -> LessSafeKey {
+        let key = UnboundKey::new(&AES_256_GCM, &self.seal_key[..]).expect("Valid key length");
+        LessSafeKey::new(key)
+    }
+}
+
+impl Sealing for NativePlatform {
+    type SealError = anyhow::Error;
+    type UnsealError = anyhow::Error;
+
+    /// Seals the data as `iv || ciphertext || tag` with AES-256-GCM
+    fn seal_data(&self, path: impl AsRef<Path>, data: &[u8]) -> Result<(), Self::SealError> {
+        let path = path.as_ref();
+        let mut iv = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut iv)
+            .map_err(|_| anyhow!("Failed to generate iv"))?;
+        let mut sealed = data.to_vec();
+        self.aead_key()
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(iv), Aad::empty(), &mut sealed)
+            .map_err(|_| anyhow!("Seal data failed: path={:?}", path))?;
+        let mut output = iv.to_vec();
+        output.extend_from_slice(&sealed);
+        fs::write(path, output)
+            .map_err(|err| anyhow!("Write sealed data failed: {:?} path={:?}", err, path))
+    }
+
+    fn unseal_data(&self, path: impl AsRef<Path>) -> Result<Option<Vec<u8>>, Self::UnsealError> {
+        let path = path.as_ref();
+        let mut data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) => {
+                if matches!(err.kind(), ErrorKind::NotFound) {
+                    return Ok(None);
+                } else {
+                    return Err(anyhow!("Read seal file failed: {:?} path={:?}", err, path));
+                }
+            }
+        };
+        if data.len() < NONCE_LEN {
+            return Err(anyhow!("Sealed data too short: path={:?}", path));
+        }
+        let mut iv = [0u8; NONCE_LEN];
+        iv.copy_from_slice(&data[..NONCE_LEN]);
+        let plain = self
+            .aead_key()
+            .open_in_place(
+                Nonce::assume_unique_for_key(iv),
+                Aad::empty(),
+                &mut data[NONCE_LEN..],
+            )
+            .map_err(|_| anyhow!("Unseal data failed: path={:?}", path))?;
+        Ok(Some(plain.to_vec()))
+    }
+}
+
+impl RA for NativePlatform {
+    type Error = anyhow::Error;
+
+    /// Creates a mock IAS-style report
+    ///
+    /// The output is deterministic for the same input: the report body carries `data` at the
+    /// same offset as a real quote, the signature is the SHA-256 of the report and there's no
+    /// signing certificate.
+    fn create_attestation_report(
+        &self,
+        data: &[u8],
+    ) -> Result<(String, String, String), Self::Error> {
+        if data.len() > REPORT_DATA_SIZE {
+            return Err(anyhow!("data length over {} bytes", REPORT_DATA_SIZE));
+        }
+        let mut quote_body = vec![0u8; QUOTE_BODY_LEN];
+        quote_body[QUOTE_REPORT_DATA_OFFSET..QUOTE_REPORT_DATA_OFFSET + data.len()]
+            .copy_from_slice(data);
+        let report = serde_json::json!({
+            "id": "native",
+            "timestamp": "1970-01-01T00:00:00.000000",
+            "version": 4,
+            "isvEnclaveQuoteStatus": "OK",
+            "isvEnclaveQuoteBody": base64::encode(&quote_body),
+        })
+        .to_string();
+        let signature = base64::encode(digest::digest(&digest::SHA256, report.as_bytes()));
+        Ok((report, signature, String::new()))
+    }
+
+    /// Creates a mock DCAP quote
+    ///
+    /// It has the header and the report body of a real quote, but no signature data at all.
+    fn create_dcap_quote(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        if data.len() > REPORT_DATA_SIZE {
+            return Err(anyhow!("data length over {} bytes", REPORT_DATA_SIZE));
+        }
+        let mut quote = vec![0u8; QUOTE_BODY_LEN];
+        quote[0..2].copy_from_slice(&DCAP_QUOTE_VERSION.to_le_bytes());
+        quote[2..4].copy_from_slice(&DCAP_ATTESTATION_KEY_TYPE.to_le_bytes());
+        quote[QUOTE_REPORT_DATA_OFFSET..QUOTE_REPORT_DATA_OFFSET + data.len()]
+            .copy_from_slice(data);
+        // Empty signature data
+        quote.extend_from_slice(&0u32.to_le_bytes());
+        Ok(quote)
+    }
+}
+
+impl Machine for NativePlatform {
+    /// A 16 bytes id derived from the seal key, the same size as the SGX seal key
+    fn machine_id(&self) -> Vec<u8> {
+        let mut ctx = digest::Context::new(&digest::SHA256);
+        ctx.update(b"phactory-native-machine-id");
+        ctx.update(&self.seal_key[..]);
+        ctx.finish().as_ref()[..16].to_vec()
+    }
+
+    fn cpu_core_num(&self) -> u32 {
+        num_cpus::get() as u32
+    }
+
+    fn cpu_feature_level(&self) -> u32 {
+        let mut cpu_feature_level: u32 = 1;
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            // Atom doesn't support AVX
+            if is_x86_feature_detected!("avx2") {
+                info!("CPU Support AVX2");
+                cpu_feature_level += 1;
+
+                // Customer-level Core doesn't support AVX512
+                if is_x86_feature_detected!("avx512f") {
+                    info!("CPU Support AVX512");
+                    cpu_feature_level += 1;
+                }
+            }
+        }
+        cpu_feature_level
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seal_unseal_roundtrip() {
+        let dir = tempdir::TempDir::new("native-pal").unwrap();
+        let platform = NativePlatform::load_or_generate(dir.path().join(SEAL_KEY_FILE)).unwrap();
+        let path = dir.path().join("data.seal");
+
+        assert_eq!(platform.unseal_data(&path).unwrap(), None);
+        platform.seal_data(&path, b"hello").unwrap();
+        assert_ne!(fs::read(&path).unwrap(), b"hello");
+        assert_eq!(platform.unseal_data(&path).unwrap(), Some(b"hello".to_vec()));
+
+        // The key is persisted and reused
+        let reloaded = NativePlatform::load_or_generate(dir.path().join(SEAL_KEY_FILE)).unwrap();
+        assert_eq!(reloaded.unseal_data(&path).unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(reloaded.machine_id(), platform.machine_id());
+
+        // Another key can't unseal it
+        let other = NativePlatform::new([1u8; SEAL_KEY_LEN]);
+        assert!(other.unseal_data(&path).is_err());
+    }
+
+    #[test]
+    fn mock_attestation_is_deterministic() {
+        let platform = NativePlatform::new([0u8; SEAL_KEY_LEN]);
+        let a = platform.create_attestation_report(b"data").unwrap();
+        let b = platform.create_attestation_report(b"data").unwrap();
+        assert_eq!(a, b);
+        assert!(platform
+            .create_attestation_report(&[0u8; REPORT_DATA_SIZE + 1])
+            .is_err());
+
+        let quote = platform.create_dcap_quote(b"data").unwrap();
+        assert_eq!(&quote[..2], &[3, 0]);
+        assert_eq!(
+            &quote[QUOTE_REPORT_DATA_OFFSET..QUOTE_REPORT_DATA_OFFSET + 4],
+            b"data"
+        );
+    }
+}
@@ -17,7 +17,10 @@ pub trait Sealing {
 
 pub trait RA {
     type Error: ErrorType;
+    /// Creates an IAS report of an EPID quote, returns (report, signature, signing cert)
     fn create_attestation_report(&self, data: &[u8]) -> Result<(String, String, String), Self::Error>;
+    /// Creates an ECDSA (DCAP) quote, to be verified on chain
+    fn create_dcap_quote(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error>;
 }
 
 pub trait Machine {
@@ -0,0 +1,141 @@
+//! The workloads of the benchmark suite.
+//!
+//! Each unit is sized to take roughly the same time on a typical machine, so that no component
+//! dominates a round. The registry pallet weighs the components by their running time anyway.
+
+// TODO.kevin: block_box will do best-effort to prevent compiler optimizations, but not guaranteed.
+use core::hint::black_box;
+
+use chain::pallet_registry::BenchComponent;
+use phala_crypto::aead;
+use phala_trie_storage::TrieStorage;
+use sp_core::{hashing::blake2_256, sr25519, Pair};
+
+use super::Benchmark;
+
+/// Number of messages to sign in a unit
+const SIGN_BATCH: usize = 16;
+/// Number of bytes to encrypt in a unit
+const AES_CHUNK: usize = 256 * 1024;
+/// Number of keys to insert and look up in a unit
+const TRIE_BATCH: u32 = 64;
+/// The trie keys wrap around to bound the trie size
+const TRIE_KEY_SPACE: u32 = 4096;
+/// Number of bytes to copy in a unit
+const MEMORY_CHUNK: usize = 1024 * 1024;
+
+pub(super) fn new(component: BenchComponent) -> Box<dyn Benchmark> {
+    match component {
+        BenchComponent::Sr25519Sign => Box::new(Sr25519Sign::new()),
+        BenchComponent::AesGcm => Box::new(AesGcm::new()),
+        BenchComponent::Trie => Box::new(Trie::new()),
+        BenchComponent::MemoryBandwidth => Box::new(MemoryBandwidth::new()),
+    }
+}
+
+struct Sr25519Sign {
+    pair: sr25519::Pair,
+    message: [u8; 64],
+}
+
+impl Sr25519Sign {
+    fn new() -> Self {
+        Sr25519Sign {
+            pair: sr25519::Pair::from_seed(&[1u8; 32]),
+            message: [0u8; 64],
+        }
+    }
+}
+
+impl Benchmark for Sr25519Sign {
+    fn run_once(&mut self) {
+        for i in 0..SIGN_BATCH {
+            self.message[0] = i as u8;
+            let _ = black_box(self.pair.sign(black_box(&self.message)));
+        }
+    }
+}
+
+struct AesGcm {
+    key: [u8; 32],
+    iv: aead::IV,
+    buffer: Vec<u8>,
+}
+
+impl AesGcm {
+    fn new() -> Self {
+        AesGcm {
+            key: [1u8; 32],
+            iv: Default::default(),
+            buffer: vec![0u8; AES_CHUNK],
+        }
+    }
+}
+
+impl Benchmark for AesGcm {
+    fn run_once(&mut self) {
+        aead::encrypt(&self.iv, &self.key, &mut self.buffer)
+            .expect("Encrypt with a valid key should never fail; qed.");
+        // Drop the auth tag, keep encrypting the cipher text
+        self.buffer.truncate(AES_CHUNK);
+        let _ = black_box(&self.buffer);
+    }
+}
+
+struct Trie {
+    storage: TrieStorage<crate::RuntimeHasher>,
+    next_key: u32,
+}
+
+impl Trie {
+    fn new() -> Self {
+        Trie {
+            storage: Default::default(),
+            next_key: 0,
+        }
+    }
+}
+
+impl Benchmark for Trie {
+    fn run_once(&mut self) {
+        let start = self.next_key;
+        let keys: Vec<_> = (start..start + TRIE_BATCH)
+            .map(|i| blake2_256(&(i % TRIE_KEY_SPACE).to_le_bytes()).to_vec())
+            .collect();
+        self.next_key = (start + TRIE_BATCH) % TRIE_KEY_SPACE;
+
+        let changes = keys
+            .iter()
+            .map(|key| (key.clone(), Some(start.to_le_bytes().to_vec())))
+            .collect();
+        let (root, transaction) = self
+            .storage
+            .calc_root_if_changes(&changes, &Default::default());
+        self.storage.apply_changes(root, transaction);
+        for key in keys.iter() {
+            let _ = black_box(self.storage.get(key));
+        }
+    }
+}
+
+struct MemoryBandwidth {
+    src: Vec<u8>,
+    dst: Vec<u8>,
+}
+
+impl MemoryBandwidth {
+    fn new() -> Self {
+        MemoryBandwidth {
+            src: vec![1u8; MEMORY_CHUNK],
+            dst: vec![0u8; MEMORY_CHUNK],
+        }
+    }
+}
+
+impl Benchmark for MemoryBandwidth {
+    fn run_once(&mut self) {
+        self.dst.copy_from_slice(black_box(&self.src));
+        let _ = black_box(&self.dst);
+        core::mem::swap(&mut self.src, &mut self.dst);
+    }
+}
@@ -0,0 +1,129 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use log::debug;
+
+use chain::pallet_registry::{BenchComponent, DEFAULT_BENCH_COMPONENT_WEIGHT};
+
+mod components;
+
+/// A component of the benchmark suite
+trait Benchmark {
+    /// Runs one unit of the workload
+    fn run_once(&mut self);
+}
+
+static ITERATION_COUNTER: AtomicU64 = AtomicU64::new(0);
+static PAUSED: AtomicBool = AtomicBool::new(true);
+static SCORE: AtomicU64 = AtomicU64::new(0);
+
+const NUM_COMPONENTS: usize = BenchComponent::ALL.len();
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_WEIGHT: AtomicU32 = AtomicU32::new(DEFAULT_BENCH_COMPONENT_WEIGHT);
+/// The units of work done by each component, in the order of `BenchComponent::ALL`
+static COMPONENT_ITERATIONS: [AtomicU64; NUM_COMPONENTS] = [ZERO; NUM_COMPONENTS];
+/// The units of each component in a round, in per mille, in the order of `BenchComponent::ALL`
+static COMPONENT_WEIGHTS: [AtomicU32; NUM_COMPONENTS] = [DEFAULT_WEIGHT; NUM_COMPONENTS];
+
+/// Runs the benchmark suite until paused.
+///
+/// Each iteration is a round running each component as many units as its weight, in per mille.
+/// The fractional units are carried over to the next rounds. The work is counted instead of timed
+/// since the time is told by the untrusted host.
+pub fn run() {
+    let mut suite: Vec<Box<dyn Benchmark>> = BenchComponent::ALL
+        .iter()
+        .map(|component| components::new(*component))
+        .collect();
+    let mut credits = [0u32; NUM_COMPONENTS];
+    let since = now();
+    let start = iteration_counter();
+    loop {
+        for (i, bench) in suite.iter_mut().enumerate() {
+            credits[i] += COMPONENT_WEIGHTS[i].load(Ordering::Relaxed);
+            while credits[i] >= DEFAULT_BENCH_COMPONENT_WEIGHT {
+                bench.run_once();
+                COMPONENT_ITERATIONS[i].fetch_add(1, Ordering::Relaxed);
+                credits[i] -= DEFAULT_BENCH_COMPONENT_WEIGHT;
+            }
+        }
+        let count = ITERATION_COUNTER.fetch_add(1, Ordering::Relaxed);
+        if count % 100 == 0 {
+            let score = est_score(since, start);
+            debug!(
+                "Benchmark counnter increased to {}, est score={}",
+                count, score,
+            );
+            SCORE.store(score, Ordering::Relaxed);
+        }
+        if PAUSED.load(Ordering::Relaxed) {
+            return;
+        }
+    }
+}
+
+pub fn iteration_counter() -> u64 {
+    ITERATION_COUNTER.load(Ordering::Relaxed)
+}
+
+pub fn reset_iteration_counter() {
+    ITERATION_COUNTER.store(0, Ordering::Relaxed);
+}
+
+/// The units of work done by each component since the last reset
+pub fn component_iterations() -> Vec<(BenchComponent, u64)> {
+    BenchComponent::ALL
+        .iter()
+        .zip(COMPONENT_ITERATIONS.iter())
+        .map(|(component, count)| (*component, count.load(Ordering::Relaxed)))
+        .collect()
+}
+
+pub fn reset_component_iterations() {
+    for count in COMPONENT_ITERATIONS.iter() {
+        count.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Sets the units of each component in a round, in per mille
+///
+/// The components not listed keep their weights.
+pub fn set_weights(weights: &[(BenchComponent, u32)]) {
+    for (component, weight) in weights {
+        if let Some(i) = BenchComponent::ALL.iter().position(|c| c == component) {
+            COMPONENT_WEIGHTS[i].store(*weight, Ordering::Relaxed);
+        }
+    }
+}
+
+pub fn pause() {
+    PAUSED.store(true, Ordering::Relaxed)
+}
+
+pub fn resume() {
+    PAUSED.store(false, Ordering::Relaxed)
+}
+
+pub fn puasing() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+pub fn score() -> u64 {
+    SCORE.load(Ordering::Relaxed)
+}
+
+fn est_score(since: u64, start: u64) -> u64 {
+    let now = now();
+    if now <= since {
+        return 0;
+    }
+    // Normalize to 6s (standard block time)
+    (ITERATION_COUNTER.load(Ordering::Relaxed) - start) * 6 / (now - since)
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("Get time failed")
+        .as_secs()
+}
@@ -22,6 +22,12 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             "state_root": info.state_root,
             "dev_mode": info.dev_mode,
             "pending_messages": info.pending_messages,
+            "egress_queues": info.egress_queues.iter().map(|q| json!({
+                "sender": q.sender,
+                "depth": q.depth,
+                "capacity": q.capacity,
+                "dropped": q.dropped,
+            })).collect::<Vec<_>>(),
             "score": info.score,
             "machine_id": machine_id,
             "version": info.version,
@@ -69,6 +75,7 @@ impl<Platform: pal::Platform> Phactory<Platform> {
 
         match action {
             ACTION_GET_INFO => self.get_info_json(),
+            ACTION_GET_METRICS => Ok(Value::String(self.render_metrics())),
             BIN_ACTION_SYNC_HEADER => self.bin_sync_header(load_scale(input)?),
             BIN_ACTION_SYNC_PARA_HEADER => self.bin_sync_para_header(load_scale(input)?),
             BIN_ACTION_SYNC_COMBINED_HEADERS => self.bin_sync_combined_headers(load_scale(input)?),
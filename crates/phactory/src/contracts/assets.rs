@@ -78,6 +78,16 @@ pub enum Response {
     Error(String),
 }
 
+#[derive(Encode, Decode)]
+pub enum Snapshot {
+    V1 {
+        next_id: u32,
+        assets: BTreeMap<u32, BTreeMap<AccountId, chain::Balance>>,
+        metadata: BTreeMap<u32, AssetMetadata>,
+        history: BTreeMap<AccountId, Vec<AssetsTx>>,
+    },
+}
+
 impl Assets {
     pub fn new() -> Self {
         let assets = BTreeMap::<u32, BTreeMap<AccountId, chain::Balance>>::new();
@@ -95,6 +105,7 @@ impl contracts::NativeContract for Assets {
     type Cmd = Command;
     type QReq = Request;
     type QResp = Response;
+    type Snapshot = Snapshot;
 
     fn id(&self) -> contracts::ContractId32 {
         contracts::ASSETS
@@ -291,6 +302,32 @@ impl contracts::NativeContract for Assets {
             Ok(resp) => resp,
         }
     }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::V1 {
+            next_id: self.next_id,
+            assets: self.assets.clone(),
+            metadata: self.metadata.clone(),
+            history: self.history.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) -> Result<()> {
+        match snapshot {
+            Snapshot::V1 {
+                next_id,
+                assets,
+                metadata,
+                history,
+            } => {
+                self.next_id = next_id;
+                self.assets = assets;
+                self.metadata = metadata;
+                self.history = history;
+            }
+        }
+        Ok(())
+    }
 }
 
 fn is_tracked(_id: &AccountId) -> bool {
@@ -56,6 +56,20 @@ pub struct BtcLottery {
     admin: AccountId,
 }
 
+/// The bitcoin keys and addresses are kept in their string forms (WIF for the private keys)
+#[derive(Encode, Decode)]
+pub enum Snapshot {
+    V1 {
+        round_id: u32,
+        token_set: BTreeMap<u32, Vec<String>>,
+        lottery_set: BTreeMap<u32, BTreeMap<String, String>>,
+        tx_set: Vec<Vec<u8>>,
+        sequence: SequenceType,
+        utxo: BTreeMap<u32, BTreeMap<AddressString, (Txid, u32, u64)>>,
+        admin: AccountId,
+    },
+}
+
 impl core::fmt::Debug for BtcLottery {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(f, "Hi")
@@ -321,6 +335,7 @@ impl contracts::NativeContract for BtcLottery {
     type Cmd = Command;
     type QReq = Request;
     type QResp = Response;
+    type Snapshot = Snapshot;
 
     // Returns the contract id
     fn id(&self) -> contracts::ContractId32 {
@@ -405,6 +420,83 @@ impl contracts::NativeContract for BtcLottery {
             },
         }
     }
+
+    fn snapshot(&self) -> Snapshot {
+        let lottery_set = self
+            .lottery_set
+            .iter()
+            .map(|(round_id, keys)| {
+                let keys = keys
+                    .iter()
+                    .map(|(token_id, sk)| (token_id.clone(), sk.to_wif()))
+                    .collect();
+                (*round_id, keys)
+            })
+            .collect();
+        let utxo = self
+            .utxo
+            .iter()
+            .map(|(round_id, utxo)| {
+                let utxo = utxo
+                    .iter()
+                    .map(|(addr, utxo)| (addr.to_string(), *utxo))
+                    .collect();
+                (*round_id, utxo)
+            })
+            .collect();
+        Snapshot::V1 {
+            round_id: self.round_id,
+            token_set: self.token_set.clone(),
+            lottery_set,
+            tx_set: self.tx_set.clone(),
+            sequence: self.sequence,
+            utxo,
+            admin: self.admin.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) -> Result<()> {
+        match snapshot {
+            Snapshot::V1 {
+                round_id,
+                token_set,
+                lottery_set,
+                tx_set,
+                sequence,
+                utxo,
+                admin,
+            } => {
+                let lottery_set = lottery_set
+                    .into_iter()
+                    .map(|(round_id, keys)| {
+                        let keys = keys
+                            .into_iter()
+                            .map(|(token_id, wif)| Ok((token_id, PrivateKey::from_wif(&wif)?)))
+                            .collect::<Result<_>>()?;
+                        Ok((round_id, keys))
+                    })
+                    .collect::<Result<_>>()?;
+                let utxo = utxo
+                    .into_iter()
+                    .map(|(round_id, utxo)| {
+                        let utxo = utxo
+                            .into_iter()
+                            .map(|(addr, utxo)| Ok((Address::from_str(&addr)?, utxo)))
+                            .collect::<Result<_>>()?;
+                        Ok((round_id, utxo))
+                    })
+                    .collect::<Result<_>>()?;
+                self.round_id = round_id;
+                self.token_set = token_set;
+                self.lottery_set = lottery_set;
+                self.tx_set = tx_set;
+                self.sequence = sequence;
+                self.utxo = utxo;
+                self.admin = admin;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl BtcLottery {
@@ -1,5 +1,6 @@
 use super::{NativeContext, TransactionError, TransactionResult};
-use std::collections::{HashMap, HashSet};
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use csv_core::{ReadRecordResult, Reader};
 use log::info;
 
@@ -87,6 +88,16 @@ pub enum Response {
     Get(String),
 }
 
+/// The dataset is sorted by key to be encoded deterministically
+#[derive(Encode, Decode)]
+pub enum Snapshot {
+    V1 {
+        items: Vec<Item>,
+        orders: Vec<Order>,
+        dataset: BTreeMap<String, Vec<u8>>,
+    },
+}
+
 #[derive(Debug)]
 pub struct DataPlaza {
     items: Vec<Item>,
@@ -253,6 +264,7 @@ impl contracts::NativeContract for DataPlaza {
     type Cmd = Command;
     type QReq = Request;
     type QResp = Response;
+    type Snapshot = Snapshot;
 
     fn id(&self) -> contracts::ContractId32 {
         contracts::DATA_PLAZA
@@ -324,4 +336,31 @@ impl contracts::NativeContract for DataPlaza {
             },
         }
     }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::V1 {
+            items: self.items.clone(),
+            orders: self.orders.clone(),
+            dataset: self
+                .dataset
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) -> Result<()> {
+        match snapshot {
+            Snapshot::V1 {
+                items,
+                orders,
+                dataset,
+            } => {
+                self.items = items;
+                self.orders = orders;
+                self.dataset = dataset.into_iter().collect();
+            }
+        }
+        Ok(())
+    }
 }
@@ -48,6 +48,14 @@ pub enum Response {
     Error(String),
 }
 
+#[derive(Encode, Decode)]
+pub enum Snapshot {
+    V1 {
+        geolocation_info: BTreeMap<AccountId, CoordinateInfo>,
+        city_distribution: BTreeMap<String, Vec<AccountId>>,
+    },
+}
+
 impl Geolocation {
     pub fn new() -> Self {
         Geolocation {
@@ -61,6 +69,7 @@ impl contracts::NativeContract for Geolocation {
     type Cmd = Command;
     type QReq = Request;
     type QResp = Result<Response, Error>;
+    type Snapshot = Snapshot;
 
     fn id(&self) -> contracts::ContractId32 {
         contracts::GEOLOCATION
@@ -159,4 +168,24 @@ impl contracts::NativeContract for Geolocation {
             }
         }
     }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::V1 {
+            geolocation_info: self.geolocation_info.clone(),
+            city_distribution: self.city_distribution.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) -> Result<()> {
+        match snapshot {
+            Snapshot::V1 {
+                geolocation_info,
+                city_distribution,
+            } => {
+                self.geolocation_info = geolocation_info;
+                self.city_distribution = city_distribution;
+            }
+        }
+        Ok(())
+    }
 }
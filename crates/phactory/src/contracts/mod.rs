@@ -62,12 +62,27 @@ mod support {
             req: OpaqueQuery,
         ) -> Result<OpaqueReply, OpaqueError>;
         fn process_messages(&mut self, env: &mut ExecuteEnv);
+        /// Exports the contract state as SCALE encoded bytes
+        fn snapshot(&self) -> Vec<u8>;
+        /// Replaces the contract state with a previous snapshot
+        fn restore(&mut self, snapshot: &[u8]) -> Result<()>;
+        /// The blake2_256 hash of the snapshot, to compare the state between workers
+        fn state_digest(&self) -> [u8; 32] {
+            sp_core::hashing::blake2_256(&self.snapshot())
+        }
+        /// Whether there are commands held until the egress queue gets purged, which are not in
+        /// the snapshot
+        fn has_held_commands(&self) -> bool {
+            false
+        }
     }
 
     pub trait NativeContract {
         type Cmd: Decode + Debug;
         type QReq: Decode + Debug;
         type QResp: Encode + Debug;
+        /// The versioned state of the contract. It must be encoded deterministically.
+        type Snapshot: Encode + Decode;
 
         fn id(&self) -> ContractId32;
         fn handle_command(
@@ -83,6 +98,8 @@ mod support {
             origin: Option<&chain::AccountId>,
             req: Self::QReq,
         ) -> Self::QResp;
+        fn snapshot(&self) -> Self::Snapshot;
+        fn restore(&mut self, snapshot: Self::Snapshot) -> Result<()>;
     }
 
     pub struct NativeCompatContract<Con, Cmd, CmdWrp, CmdPlr, QReq, QResp>
@@ -150,6 +167,20 @@ mod support {
             Ok(response.encode())
         }
 
+        fn snapshot(&self) -> Vec<u8> {
+            self.contract.snapshot().encode()
+        }
+
+        fn restore(&mut self, snapshot: &[u8]) -> Result<()> {
+            let snapshot = Decode::decode(&mut &snapshot[..])
+                .map_err(|_| anyhow::anyhow!("Invalid snapshot for contract {}", self.id()))?;
+            self.contract.restore(snapshot)
+        }
+
+        fn has_held_commands(&self) -> bool {
+            self.send_mq.is_blocked() && matches!(self.cmd_rcv_mq.peek_ind(), Ok(Some(_)))
+        }
+
         fn process_messages(&mut self, env: &mut ExecuteEnv) {
             let storage = env.block.storage;
             let key_map = |topic: &[u8]| {
@@ -166,6 +197,10 @@ mod support {
                 secret_mq,
             };
             loop {
+                // Hold the commands until the egress queue gets purged
+                if self.send_mq.is_blocked() {
+                    break;
+                }
                 let ok = phala_mq::select! {
                     next_cmd = self.cmd_rcv_mq => match next_cmd {
                         Ok((_, cmd, origin)) => {
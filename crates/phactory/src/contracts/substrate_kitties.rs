@@ -35,7 +35,7 @@ lazy_static! {
 }
 
 /// SubstrateKitties contract states.
-#[derive(Default, Clone)]
+#[derive(Encode, Decode, Default, Clone)]
 pub struct SubstrateKitties {
     schrodingers: BTreeMap<String, Vec<u8>>,
     /// Use Vec<u8> to represent kitty id
@@ -54,6 +54,11 @@ pub struct SubstrateKitties {
     left_kitties: Vec<Vec<u8>>,
 }
 
+#[derive(Encode, Decode)]
+pub enum Snapshot {
+    V1(SubstrateKitties),
+}
+
 impl core::fmt::Debug for SubstrateKitties {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(f, "Hi")
@@ -136,6 +141,7 @@ impl contracts::NativeContract for SubstrateKitties {
     type Cmd = Command;
     type QReq = Request;
     type QResp = Response;
+    type Snapshot = Snapshot;
 
     // Returns the contract id
     fn id(&self) -> contracts::ContractId32 {
@@ -323,4 +329,15 @@ impl contracts::NativeContract for SubstrateKitties {
             Ok(resp) => resp,
         }
     }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::V1(self.clone())
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) -> anyhow::Result<()> {
+        match snapshot {
+            Snapshot::V1(state) => *self = state,
+        }
+        Ok(())
+    }
 }
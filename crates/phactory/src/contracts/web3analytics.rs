@@ -212,6 +212,22 @@ pub enum Response {
     Error(String),
 }
 
+/// The key and the parser are constant and not included
+#[derive(Encode, Decode)]
+pub enum Snapshot {
+    V1 {
+        encrypted: bool,
+        page_views: Vec<PageView>,
+        online_users: Vec<OnlineUser>,
+        hourly_stat: HourlyStat,
+        daily_stat: DailyStat,
+        weekly_sites: Vec<WeeklySite>,
+        weekly_devices: Vec<WeeklyDevice>,
+        total_stat: HourlyPageViewStat,
+        no_tracking: BTreeMap<AccountId, bool>,
+    },
+}
+
 pub struct Web3Analytics {
     encrypted: bool,
     page_views: Vec<PageView>,
@@ -885,6 +901,7 @@ impl contracts::NativeContract for Web3Analytics {
     type Cmd = Command;
     type QReq = Request;
     type QResp = Response;
+    type Snapshot = Snapshot;
 
     fn id(&self) -> contracts::ContractId32 {
         contracts::WEB3_ANALYTICS
@@ -1021,4 +1038,45 @@ impl contracts::NativeContract for Web3Analytics {
             Ok(resp) => resp,
         }
     }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::V1 {
+            encrypted: self.encrypted,
+            page_views: self.page_views.clone(),
+            online_users: self.online_users.clone(),
+            hourly_stat: self.hourly_stat.clone(),
+            daily_stat: self.daily_stat.clone(),
+            weekly_sites: self.weekly_sites.clone(),
+            weekly_devices: self.weekly_devices.clone(),
+            total_stat: self.total_stat.clone(),
+            no_tracking: self.no_tracking.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) -> Result<()> {
+        match snapshot {
+            Snapshot::V1 {
+                encrypted,
+                page_views,
+                online_users,
+                hourly_stat,
+                daily_stat,
+                weekly_sites,
+                weekly_devices,
+                total_stat,
+                no_tracking,
+            } => {
+                self.encrypted = encrypted;
+                self.page_views = page_views;
+                self.online_users = online_users;
+                self.hourly_stat = hourly_stat;
+                self.daily_stat = daily_stat;
+                self.weekly_sites = weekly_sites;
+                self.weekly_devices = weekly_devices;
+                self.total_stat = total_stat;
+                self.no_tracking = no_tracking;
+            }
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,612 @@
+//! Hands the worker identity over to another pRuntime build.
+//!
+//! The sealed files can only be unsealed by the enclave build which sealed them, so a new build
+//! would start as a new worker. Instead, the secrets are handed over in three steps:
+//!
+//! 1. The new pRuntime generates an ephemeral ECDH key and attests to it in a `HandoverChallenge`.
+//! 2. The old pRuntime validates the attestation against the `PRuntimeAllowList` in the chain
+//!    state it has synced, and encrypts its runtime data and the sealed master key to the
+//!    ephemeral key. The package is signed by the identity key.
+//! 3. The new pRuntime verifies the package against the identity inside, and seals the secrets
+//!    with its own sealing key.
+//!
+//! After that, the new pRuntime can be initialized as usual. It syncs the chain from the start
+//! and registers with the same identity, keeping the stake bindings and the gatekeeper role.
+//!
+//! Only attested challenges are answered, so the secrets never leave the allowed enclaves. Once
+//! the package is exported, the old pRuntime retires: it stops signing egress messages and
+//! answering challenges, so the identity is never used by two pRuntimes at once. The retirement
+//! is not persisted. If the new pRuntime fails to accept the package, restarting the old one
+//! brings it back.
+//!
+//! Both sides may run different versions, so the wire types must stay backward compatible.
+
+use super::*;
+use crate::system::{chain_state, master_key};
+use chain::pallet_registry::{Attestation, AttestationValidator, IasValidator};
+use phactory_api::{crypto::EncryptedData, prpc as pb};
+use phala_crypto::ecdh;
+use sp_core::hashing::blake2_256;
+
+/// Binds the attested report data to the handover, so that no other attestation can be replayed
+const CHALLENGE_TAG: &[u8] = b"phala/handover/challenge";
+
+/// The ephemeral key of the receiving pRuntime, with the attestation committing to it
+#[derive(Encode, Decode, Clone, Debug)]
+pub struct HandoverChallenge {
+    pub ecdh_pubkey: ecdh::EcdhPublicKey,
+    pub attestation: Attestation,
+}
+
+impl HandoverChallenge {
+    /// The user data hash the attestation should commit to
+    pub fn report_data_hash(ecdh_pubkey: &ecdh::EcdhPublicKey) -> [u8; 32] {
+        let mut data = CHALLENGE_TAG.to_vec();
+        data.extend_from_slice(ecdh_pubkey);
+        blake2_256(&data)
+    }
+}
+
+#[derive(Encode, Decode, Clone, Debug)]
+struct HandoverSecrets {
+    runtime_data: RuntimeDataSeal,
+    /// The signed master key history as sealed by the sender, if it has one
+    master_key: Option<Vec<u8>>,
+}
+
+/// The secrets encrypted to the ephemeral key of a `HandoverChallenge`
+#[derive(Encode, Decode, Clone, Debug)]
+pub struct HandoverPackage {
+    /// The encoded `HandoverSecrets`, encrypted with `EncryptionScheme::HkdfSha256`
+    encrypted_secrets: EncryptedData,
+    /// Signed by the identity key over the challenge pubkey and the encrypted secrets
+    signature: sr25519::Signature,
+}
+
+impl HandoverPackage {
+    fn data_be_signed(
+        ecdh_pubkey: &ecdh::EcdhPublicKey,
+        encrypted_secrets: &EncryptedData,
+    ) -> Vec<u8> {
+        (ecdh_pubkey, encrypted_secrets).encode()
+    }
+}
+
+fn chain_attestation(attestation: pb::Attestation) -> Result<Attestation> {
+    match attestation.provider.as_str() {
+        pb::ATTESTATION_PROVIDER_IAS => {
+            let payload = attestation
+                .payload
+                .ok_or_else(|| anyhow!("Missing attestation payload"))?;
+            Ok(Attestation::SgxIas {
+                ra_report: payload.report.as_bytes().to_vec(),
+                signature: payload.signature,
+                raw_signing_cert: payload.signing_cert,
+            })
+        }
+        pb::ATTESTATION_PROVIDER_DCAP => Ok(Attestation::SgxDcap {
+            quote: attestation.dcap_quote,
+        }),
+        provider => Err(anyhow!("Unknown attestation provider: {}", provider)),
+    }
+}
+
+/// Checks if the chain would accept a worker with the attestation of the challenge
+///
+/// The attestation must commit to the challenge key, and the pRuntime must be in the
+/// `PRuntimeAllowList` even if the chain doesn't verify it for the registration. Unattested
+/// challenges are always rejected, because the secrets could be handed to anyone.
+fn verify_challenge<Validator: AttestationValidator>(
+    challenge: &HandoverChallenge,
+    chain_storage: &Storage,
+) -> Result<()> {
+    if let Attestation::None = challenge.attestation {
+        return Err(anyhow!("Unattested handover challenge"));
+    }
+    let provider = challenge.attestation.provider();
+    let config =
+        chain_state::attestation_provider_config(provider, chain_storage).ok_or_else(|| {
+            anyhow!(
+                "Attestation provider {:?} not accepted by the chain",
+                provider
+            )
+        })?;
+    let now = chain_storage
+        .timestamp_now()
+        .ok_or_else(|| anyhow!("No timestamp in the chain state"))?
+        / 1000;
+    let fields = Validator::validate(
+        &challenge.attestation,
+        &HandoverChallenge::report_data_hash(&challenge.ecdh_pubkey),
+        now,
+        true,
+        chain_state::pruntime_allowlist(provider, chain_storage),
+        chain_state::dcap_collateral(chain_storage),
+    )
+    .map_err(|err| anyhow!("Invalid attestation: {:?}", err))?;
+    config
+        .confidence_level(fields.confidence_level)
+        .ok_or_else(|| {
+            anyhow!(
+                "Confidence level {} rejected by the chain",
+                fields.confidence_level
+            )
+        })?;
+    Ok(())
+}
+
+impl<Platform: pal::Platform> Phactory<Platform> {
+    /// Creates a challenge to receive the secrets of another pRuntime (on the new pRuntime)
+    ///
+    /// Only a pRuntime without any identity can receive the secrets. A new challenge replaces the
+    /// previous one. The challenge must be attested, since unattested ones are never answered.
+    pub(crate) fn handover_create_challenge(&mut self, skip_ra: bool) -> Result<HandoverChallenge> {
+        if skip_ra {
+            return Err(anyhow!("Handover requires remote attestation"));
+        }
+        self.ensure_no_identity()?;
+        let ecdh_key = ecdh::EcdhKey::create(&generate_random_info())
+            .map_err(|err| anyhow!("Failed to create ECDH key: {:?}", err))?;
+        let ecdh_pubkey = ecdh_key.public();
+        let report_data_hash = HandoverChallenge::report_data_hash(&ecdh_pubkey);
+        let attestation = prpc_service::create_attestation(
+            &self.platform,
+            self.args.dcap_attestation,
+            &report_data_hash,
+        )
+        .map_err(|err| anyhow!("{}", err))?;
+        let attestation = chain_attestation(attestation)?;
+        self.handover_ecdh_key = Some(ecdh_key);
+        Ok(HandoverChallenge {
+            ecdh_pubkey,
+            attestation,
+        })
+    }
+
+    /// Exports the secrets to the pRuntime which created the challenge (on the old pRuntime)
+    ///
+    /// Retires this pRuntime on success.
+    pub(crate) fn handover_start(
+        &mut self,
+        challenge: HandoverChallenge,
+    ) -> Result<HandoverPackage> {
+        self.handover_start_with::<IasValidator>(challenge)
+    }
+
+    fn handover_start_with<Validator: AttestationValidator>(
+        &mut self,
+        challenge: HandoverChallenge,
+    ) -> Result<HandoverPackage> {
+        if self.retired {
+            return Err(anyhow!("Identity already handed over"));
+        }
+        let state = self
+            .runtime_state
+            .as_ref()
+            .ok_or_else(|| anyhow!("Runtime not initialized"))?;
+        verify_challenge::<Validator>(&challenge, &state.chain_storage)?;
+
+        let runtime_data = self
+            .load_runtime_data()
+            .map_err(|err| anyhow!("Failed to load runtime data: {}", err))?;
+        let master_key = master_key::export(self.args.sealing_path.clone(), &self.platform)?;
+        info!(
+            "Handing over the identity {} to {}, with master key: {}",
+            hex::encode(state.identity_key.public()),
+            hex::encode(challenge.ecdh_pubkey),
+            master_key.is_some()
+        );
+        let secrets = HandoverSecrets {
+            runtime_data: RuntimeDataSeal::V2(runtime_data),
+            master_key,
+        };
+        let encrypted_secrets = EncryptedData::encrypt_with(
+            &state.ecdh_key,
+            &challenge.ecdh_pubkey,
+            ecdh::EncryptionScheme::HkdfSha256,
+            ecdh::context::HANDOVER,
+            generate_random_iv(),
+            &[],
+            &secrets.encode(),
+        )
+        .map_err(|err| anyhow!("Failed to encrypt the secrets: {:?}", err))?;
+        let signature = state.identity_key.sign(&HandoverPackage::data_be_signed(
+            &challenge.ecdh_pubkey,
+            &encrypted_secrets,
+        ));
+        self.retired = true;
+        info!("Retired, the identity is taken over by the new pRuntime");
+        Ok(HandoverPackage {
+            encrypted_secrets,
+            signature,
+        })
+    }
+
+    /// Seals the secrets received from another pRuntime (on the new pRuntime)
+    ///
+    /// Returns the identity pubkey taken over.
+    pub(crate) fn handover_accept(&mut self, package: HandoverPackage) -> Result<sr25519::Public> {
+        self.ensure_no_identity()?;
+        let ecdh_key = self
+            .handover_ecdh_key
+            .as_ref()
+            .ok_or_else(|| anyhow!("No handover challenge created"))?;
+        let data = package
+            .encrypted_secrets
+            .decrypt_with(
+                ecdh_key,
+                ecdh::EncryptionScheme::HkdfSha256,
+                ecdh::context::HANDOVER,
+                &[],
+            )
+            .map_err(|err| anyhow!("Failed to decrypt the secrets: {:?}", err))?;
+        let secrets = HandoverSecrets::decode(&mut &data[..])
+            .map_err(|_| anyhow!("Unsupported handover secrets"))?;
+        let runtime_data = match secrets.runtime_data {
+            RuntimeDataSeal::V1(data) => data.into(),
+            RuntimeDataSeal::V2(data) => data,
+        };
+
+        let identity_key = sr25519::Pair::restore_from_secret_key(&runtime_data.sk);
+        let signed =
+            HandoverPackage::data_be_signed(&ecdh_key.public(), &package.encrypted_secrets);
+        if !sr25519::Pair::verify(&package.signature, &signed, &identity_key.public()) {
+            return Err(anyhow!("Bad signature of the handover package"));
+        }
+        let sender_ecdh_key = identity_key
+            .derive_ecdh_key()
+            .map_err(|err| anyhow!("Failed to derive ECDH key: {:?}", err))?;
+        if sender_ecdh_key.public() != package.encrypted_secrets.pubkey {
+            return Err(anyhow!("Secrets not encrypted by the identity"));
+        }
+
+        if let Some(master_key) = &secrets.master_key {
+            master_key::import(
+                self.args.sealing_path.clone(),
+                master_key,
+                &identity_key,
+                &self.platform,
+            )?;
+        }
+        // The salt is kept, which is the legacy one if the identity was upgraded from V1
+        self.seal_runtime_data(&runtime_data)?;
+        self.handover_ecdh_key = None;
+        info!(
+            "Took over the identity {}",
+            hex::encode(identity_key.public())
+        );
+        Ok(identity_key.public())
+    }
+
+    fn ensure_no_identity(&self) -> Result<()> {
+        if self.runtime_info.is_some() {
+            return Err(anyhow!("Runtime already initialized"));
+        }
+        match self.load_runtime_data() {
+            Err(Error::PersistentRuntimeNotFound) => Ok(()),
+            Ok(_) => Err(anyhow!("Identity already exists")),
+            Err(err) => Err(anyhow!("Failed to load persistent data: {}", err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light_validation::utils::storage_prefix;
+    use chain::pallet_registry::{
+        AttestationError, AttestationProvider, AttestationProviderConfig, DcapCollateral, IasFields,
+    };
+    use std::convert::Infallible;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use trie::{trie_types::TrieDBMut, MemoryDB, TrieMut};
+
+    /// Seals in memory and quotes the report data as is
+    #[derive(Clone, Default)]
+    struct TestPlatform {
+        sealed: Arc<Mutex<BTreeMap<PathBuf, Vec<u8>>>>,
+    }
+
+    impl pal::Sealing for TestPlatform {
+        type SealError = Infallible;
+        type UnsealError = Infallible;
+
+        fn seal_data(&self, path: impl AsRef<Path>, data: &[u8]) -> Result<(), Infallible> {
+            let path = path.as_ref().to_path_buf();
+            self.sealed.lock().unwrap().insert(path, data.to_vec());
+            Ok(())
+        }
+
+        fn unseal_data(&self, path: impl AsRef<Path>) -> Result<Option<Vec<u8>>, Infallible> {
+            Ok(self.sealed.lock().unwrap().get(path.as_ref()).cloned())
+        }
+    }
+
+    impl pal::RA for TestPlatform {
+        type Error = anyhow::Error;
+
+        fn create_attestation_report(&self, _data: &[u8]) -> Result<(String, String, String)> {
+            Err(anyhow!("No IAS in tests"))
+        }
+
+        fn create_dcap_quote(&self, data: &[u8]) -> Result<Vec<u8>> {
+            Ok(data.to_vec())
+        }
+    }
+
+    impl pal::Machine for TestPlatform {
+        fn machine_id(&self) -> Vec<u8> {
+            vec![0; 16]
+        }
+
+        fn cpu_core_num(&self) -> u32 {
+            1
+        }
+
+        fn cpu_feature_level(&self) -> u32 {
+            1
+        }
+    }
+
+    /// Accepts the quotes of `TestPlatform`
+    struct TestValidator;
+
+    impl AttestationValidator for TestValidator {
+        fn validate(
+            attestation: &Attestation,
+            user_data_hash: &[u8; 32],
+            _now: u64,
+            _verify_pruntime_hash: bool,
+            _pruntime_allowlist: Vec<Vec<u8>>,
+            _dcap_collateral: Option<DcapCollateral>,
+        ) -> Result<IasFields, AttestationError> {
+            match attestation {
+                Attestation::SgxDcap { quote } if quote[..] == user_data_hash[..] => {
+                    Ok(IasFields {
+                        mr_enclave: [0u8; 32],
+                        mr_signer: [0u8; 32],
+                        isv_prod_id: [0u8; 2],
+                        isv_svn: [0u8; 2],
+                        report_data: [0u8; 64],
+                        confidence_level: 1,
+                    })
+                }
+                _ => Err(AttestationError::InvalidUserDataHash),
+            }
+        }
+    }
+
+    fn new_phactory() -> Phactory<TestPlatform> {
+        let mut phactory = Phactory::new(TestPlatform::default());
+        phactory.args = InitArgs {
+            sealing_path: "/sealed".into(),
+            dcap_attestation: true,
+            ..Default::default()
+        };
+        phactory
+    }
+
+    /// A genesis block with an empty GRANDPA authority set
+    fn genesis_block() -> blocks::GenesisBlockInfo {
+        let authority_set = blocks::AuthoritySet {
+            list: vec![],
+            id: 0,
+        };
+        // Prefixed by AUTHORITIES_VERISON
+        let authorities = (1u8, &authority_set.list).encode();
+        let mut db = MemoryDB::<blocks::RuntimeHasher>::default();
+        let mut state_root = Default::default();
+        {
+            let mut trie = TrieDBMut::new(&mut db, &mut state_root);
+            trie.insert(b":grandpa_authorities", &authorities).unwrap();
+        }
+        let proof = db.drain().into_iter().map(|(_, (node, _))| node).collect();
+        blocks::GenesisBlockInfo {
+            block_header: chain::Header {
+                parent_hash: Default::default(),
+                number: 0,
+                state_root,
+                extrinsics_root: Default::default(),
+                digest: Default::default(),
+            },
+            authority_set,
+            proof,
+        }
+    }
+
+    /// Accepts both the DCAP and the unattested workers
+    fn genesis_state() -> blocks::StorageState {
+        let provider_key = |provider: AttestationProvider| {
+            storage_map_prefix_twox_64_concat(b"PhalaRegistry", b"AttestationProviders", &provider)
+        };
+        vec![
+            (
+                provider_key(AttestationProvider::Dcap),
+                AttestationProviderConfig::default().encode(),
+            ),
+            (
+                provider_key(AttestationProvider::None),
+                AttestationProviderConfig::default().encode(),
+            ),
+            (storage_prefix("Timestamp", "Now"), 1_000_000u64.encode()),
+        ]
+    }
+
+    fn init_runtime(phactory: &mut Phactory<TestPlatform>) {
+        phactory
+            .init_runtime(true, false, genesis_block(), genesis_state(), None, None)
+            .unwrap();
+    }
+
+    fn ecdh_key(seed: u8) -> ecdh::EcdhKey {
+        ecdh::EcdhKey::create(&[seed; 32]).unwrap()
+    }
+
+    fn runtime_data(identity_key: &sr25519::Pair) -> PersistentRuntimeData {
+        let genesis_block_hash = H256::repeat_byte(1);
+        PersistentRuntimeData {
+            genesis_block_hash,
+            sk: identity_key.dump_secret_key(),
+            dev_mode: false,
+            kdf_salt: kdf_salt_from_genesis(&genesis_block_hash.0),
+        }
+    }
+
+    fn encrypt(
+        identity_key: &sr25519::Pair,
+        receiver: &ecdh::EcdhPublicKey,
+        secrets: &HandoverSecrets,
+    ) -> EncryptedData {
+        EncryptedData::encrypt_with(
+            &identity_key.derive_ecdh_key().unwrap(),
+            receiver,
+            ecdh::EncryptionScheme::HkdfSha256,
+            ecdh::context::HANDOVER,
+            Default::default(),
+            &[],
+            &secrets.encode(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn challenge_report_data_should_bind_the_key() {
+        let key1 = ecdh_key(1).public();
+        let key2 = ecdh_key(2).public();
+        assert_ne!(
+            HandoverChallenge::report_data_hash(&key1),
+            HandoverChallenge::report_data_hash(&key2)
+        );
+        // Not the plain hash of the key, which any other attestation could commit to
+        assert_ne!(
+            HandoverChallenge::report_data_hash(&key1),
+            blake2_256(&key1)
+        );
+    }
+
+    #[test]
+    fn handover_secrets_should_only_open_with_the_challenge_key() {
+        let identity_key = sr25519::Pair::from_seed(&[3u8; 32]);
+        let receiver = ecdh_key(1);
+        let secrets = HandoverSecrets {
+            runtime_data: RuntimeDataSeal::V2(runtime_data(&identity_key)),
+            master_key: Some(vec![1, 2, 3]),
+        };
+        let encrypted = encrypt(&identity_key, &receiver.public(), &secrets);
+
+        let open = |key: &ecdh::EcdhKey| {
+            encrypted.decrypt_with(
+                key,
+                ecdh::EncryptionScheme::HkdfSha256,
+                ecdh::context::HANDOVER,
+                &[],
+            )
+        };
+        let data = open(&receiver).unwrap();
+        let decoded = HandoverSecrets::decode(&mut &data[..]).unwrap();
+        assert_eq!(decoded.encode(), secrets.encode());
+        assert!(open(&ecdh_key(2)).is_err());
+    }
+
+    #[test]
+    fn handover_signature_should_cover_the_challenge_key() {
+        let identity_key = sr25519::Pair::from_seed(&[3u8; 32]);
+        let receiver = ecdh_key(1).public();
+        let secrets = HandoverSecrets {
+            runtime_data: RuntimeDataSeal::V2(runtime_data(&identity_key)),
+            master_key: None,
+        };
+        let encrypted = encrypt(&identity_key, &receiver, &secrets);
+        let signature = identity_key.sign(&HandoverPackage::data_be_signed(&receiver, &encrypted));
+
+        let verify = |receiver: &ecdh::EcdhPublicKey| {
+            let signed = HandoverPackage::data_be_signed(receiver, &encrypted);
+            sr25519::Pair::verify(&signature, &signed, &identity_key.public())
+        };
+        assert!(verify(&receiver));
+        assert!(!verify(&ecdh_key(2).public()));
+    }
+
+    #[test]
+    fn handover_should_move_the_identity_to_the_attested_pruntime() {
+        let mut old = new_phactory();
+        init_runtime(&mut old);
+        let identity_key = old.runtime_state.as_ref().unwrap().identity_key.clone();
+        let master_key = sr25519::Pair::from_seed(&[9u8; 32]);
+        master_key::seal(
+            old.args.sealing_path.clone(),
+            &master_key,
+            &Default::default(),
+            &identity_key,
+            &old.platform,
+        );
+
+        let mut new = new_phactory();
+        assert!(new.handover_create_challenge(true).is_err());
+        let challenge = new.handover_create_challenge(false).unwrap();
+
+        // Neither an unattested challenge nor a replayed attestation is answered
+        let unattested = HandoverChallenge {
+            ecdh_pubkey: challenge.ecdh_pubkey,
+            attestation: Attestation::None,
+        };
+        assert!(old
+            .handover_start_with::<TestValidator>(unattested)
+            .is_err());
+        let replayed = HandoverChallenge {
+            ecdh_pubkey: ecdh_key(1).public(),
+            attestation: challenge.attestation.clone(),
+        };
+        assert!(old.handover_start_with::<TestValidator>(replayed).is_err());
+        assert!(!old.retired);
+
+        let package = old
+            .handover_start_with::<TestValidator>(challenge.clone())
+            .unwrap();
+        assert!(old.retired);
+        assert!(old.handover_start_with::<TestValidator>(challenge).is_err());
+        assert!(old.dispatch_block(vec![]).is_err());
+
+        assert_eq!(new.handover_accept(package).unwrap(), identity_key.public());
+        let (handed_master_key, _) =
+            master_key::try_unseal(new.args.sealing_path.clone(), &identity_key, &new.platform)
+                .unwrap();
+        assert_eq!(handed_master_key.public(), master_key.public());
+        init_runtime(&mut new);
+        assert_eq!(
+            new.runtime_state.as_ref().unwrap().identity_key.public(),
+            identity_key.public()
+        );
+    }
+
+    #[test]
+    fn handover_should_keep_the_salt_of_an_identity_upgraded_from_v1() {
+        let mut old = new_phactory();
+        let identity_key = sr25519::Pair::from_seed(&[3u8; 32]);
+        let v1_data = RuntimeDataSeal::V1(PersistentRuntimeDataV1 {
+            genesis_block_hash: genesis_block().block_header.hash(),
+            sk: identity_key.dump_secret_key(),
+            dev_mode: false,
+        });
+        let path = PathBuf::from(&old.args.sealing_path).join(RUNTIME_SEALED_DATA_FILE);
+        old.platform
+            .sealed
+            .lock()
+            .unwrap()
+            .insert(path, v1_data.encode());
+        init_runtime(&mut old);
+
+        let mut new = new_phactory();
+        let challenge = new.handover_create_challenge(false).unwrap();
+        let package = old.handover_start_with::<TestValidator>(challenge).unwrap();
+        assert_eq!(new.handover_accept(package).unwrap(), identity_key.public());
+
+        let runtime_data = new.load_runtime_data().unwrap();
+        assert_eq!(runtime_data.kdf_salt, KDF_SALT);
+        assert_ne!(
+            runtime_data.kdf_salt,
+            kdf_salt_from_genesis(&runtime_data.genesis_block_hash.0)
+        );
+    }
+}
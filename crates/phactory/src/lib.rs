@@ -28,20 +28,24 @@ use sp_core::{crypto::Pair, sr25519, H256};
 
 // use pink::InkModule;
 
-use phactory_api::ecall_args::{InitArgs, git_revision};
+use phactory_api::ecall_args::{EgressOverflowPolicy, InitArgs, git_revision};
 use phactory_api::blocks::{self, SyncCombinedHeadersReq, SyncParachainHeaderReq};
 use phactory_api::prpc::InitRuntimeResponse;
-use phactory_api::storage_sync::{
-    ParachainSynchronizer, SolochainSynchronizer, StorageSynchronizer,
-};
+use phactory_api::storage_sync::{StorageSynchronizer, Synchronizer};
 
 use phala_crypto::{
     aead,
     ecdh::EcdhKey,
-    sr25519::{Persistence, Sr25519SecretKey, KDF, SEED_BYTES},
+    sr25519::{
+        kdf_salt_from_genesis, KdfSalt, Persistence, Sr25519SecretKey, KDF, KDF_SALT, SEED_BYTES,
+    },
+};
+use phala_mq::{
+    BindTopic, ContractId, MessageDispatcher, MessageOrigin, MessageSendQueue,
+    MessageSendQueueSnapshot, OverflowPolicy, QueueLimit,
 };
-use phala_mq::{BindTopic, ContractId, MessageDispatcher, MessageOrigin, MessageSendQueue};
 use phala_pallets::pallet_mq;
+use phala_trie_storage::StorageDump;
 use phala_types::WorkerRegistrationInfo;
 
 pub mod benchmark;
@@ -49,7 +53,9 @@ pub mod benchmark;
 mod bin_api_service;
 mod contracts;
 mod cryptography;
+mod handover;
 mod light_validation;
+mod metrics;
 mod prpc_service;
 mod rpc_types;
 mod secret_channel;
@@ -64,17 +70,21 @@ use storage::{Storage, StorageExt};
 use types::BlockInfo;
 use types::Error;
 
+pub use system::{GatekeeperReplay, WorkerSample};
+
 // TODO: Completely remove the reference to Phala/Khala runtime. Instead we can create a minimal
 // runtime definition locally.
 type RuntimeHasher = <chain::Runtime as frame_system::Config>::Hashing;
 
+type ContractMap = BTreeMap<ContractId, Box<dyn contracts::Contract + Send>>;
+
 struct RuntimeState {
-    contracts: BTreeMap<ContractId, Box<dyn contracts::Contract + Send>>,
+    contracts: ContractMap,
     send_mq: MessageSendQueue,
     recv_mq: MessageDispatcher,
 
     // chain storage synchonizing
-    storage_synchronizer: Box<dyn StorageSynchronizer + Send>,
+    storage_synchronizer: Synchronizer<LightValidation<chain::Runtime>>,
     chain_storage: Storage,
     genesis_block_hash: H256,
     identity_key: sr25519::Pair,
@@ -105,6 +115,30 @@ struct PersistentRuntimeData {
     genesis_block_hash: H256,
     sk: Sr25519SecretKey,
     dev_mode: bool,
+    /// The salt of the identity, for the key derivations local to the worker
+    ///
+    /// The gatekeeper doesn't use it, since the identities upgraded from V1 keep the legacy salt
+    /// while all the gatekeepers of a network must derive the same keys.
+    kdf_salt: KdfSalt,
+}
+
+#[derive(Encode, Decode, Clone, Debug)]
+struct PersistentRuntimeDataV1 {
+    genesis_block_hash: H256,
+    sk: Sr25519SecretKey,
+    dev_mode: bool,
+}
+
+impl From<PersistentRuntimeDataV1> for PersistentRuntimeData {
+    fn from(data: PersistentRuntimeDataV1) -> Self {
+        PersistentRuntimeData {
+            // the keys were derived with the hard-coded salt regardless of the network
+            kdf_salt: KDF_SALT,
+            genesis_block_hash: data.genesis_block_hash,
+            sk: data.sk,
+            dev_mode: data.dev_mode,
+        }
+    }
 }
 
 impl PersistentRuntimeData {
@@ -124,7 +158,53 @@ impl PersistentRuntimeData {
 
 #[derive(Encode, Decode, Clone, Debug)]
 enum RuntimeDataSeal {
-    V1(PersistentRuntimeData),
+    V1(PersistentRuntimeDataV1),
+    V2(PersistentRuntimeData),
+}
+
+/// Runtime state checkpoint filepath
+pub const CHECKPOINT_FILE: &str = "checkpoint.seal";
+
+/// A snapshot of the runtime state
+///
+/// The side tasks are not included. They are dropped when restarting from a checkpoint.
+#[derive(Encode, Decode)]
+struct RuntimeCheckpoint {
+    genesis_block_hash: H256,
+    identity_pubkey: sr25519::Public,
+    /// The last dispatched block when the checkpoint was taken
+    block_number: chain::BlockNumber,
+    storage_root: chain::Hash,
+    chain_storage: StorageDump,
+    storage_synchronizer: Synchronizer<LightValidation<chain::Runtime>>,
+    send_mq: MessageSendQueueSnapshot,
+    system: system::SystemSnapshot,
+    contracts: BTreeMap<ContractId, Vec<u8>>,
+}
+
+#[derive(Encode, Decode)]
+enum CheckpointSeal {
+    V1(RuntimeCheckpoint),
+}
+
+fn snapshot_contracts(contracts: &ContractMap) -> BTreeMap<ContractId, Vec<u8>> {
+    contracts
+        .iter()
+        .map(|(id, contract)| (*id, contract.snapshot()))
+        .collect()
+}
+
+fn restore_contracts(
+    contracts: &mut ContractMap,
+    snapshots: &BTreeMap<ContractId, Vec<u8>>,
+) -> Result<()> {
+    for (id, contract) in contracts.iter_mut() {
+        let snapshot = snapshots
+            .get(id)
+            .ok_or_else(|| anyhow!("Missing snapshot for contract {}", id))?;
+        contract.restore(snapshot)?;
+    }
+    Ok(())
 }
 
 pub struct Phactory<Platform> {
@@ -137,6 +217,11 @@ pub struct Phactory<Platform> {
     runtime_state: Option<RuntimeState>,
     system: Option<system::System<Platform>>,
     side_task_man: SideTaskManager,
+    metrics: metrics::Metrics,
+    /// The ephemeral key of the pending handover challenge
+    handover_ecdh_key: Option<EcdhKey>,
+    /// Set once the identity is handed over to another pRuntime
+    retired: bool,
 }
 
 impl<Platform: pal::Platform> Phactory<Platform> {
@@ -152,6 +237,9 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             runtime_state: None,
             system: None,
             side_task_man: Default::default(),
+            metrics: Default::default(),
+            handover_ecdh_key: None,
+            retired: false,
         }
     }
 
@@ -211,19 +299,149 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             genesis_block_hash,
             sk,
             dev_mode,
+            kdf_salt: kdf_salt_from_genesis(&genesis_block_hash.0),
         };
+        self.seal_runtime_data(&data)?;
+        Ok(data)
+    }
+
+    /// Seals the runtime data as is, e.g. the one handed over with the salt of the identity
+    fn seal_runtime_data(&self, data: &PersistentRuntimeData) -> Result<()> {
+        let data = RuntimeDataSeal::V2(data.clone());
+        let encoded_vec = data.encode();
+        info!("Length of encoded slice: {}", encoded_vec.len());
+        let filepath = PathBuf::from(&self.args.sealing_path).join(RUNTIME_SEALED_DATA_FILE);
+        self.platform
+            .seal_data(filepath, &encoded_vec)
+            .map_err(Into::into)
+            .context("Seal runtime data")?;
+        info!("Persistent Runtime Data saved");
+        Ok(())
+    }
+
+    fn checkpoint_path(&self) -> PathBuf {
+        PathBuf::from(&self.args.sealing_path).join(CHECKPOINT_FILE)
+    }
+
+    /// Whether any contract holds its commands until its egress queue gets purged
+    fn has_held_contract_commands(&self) -> bool {
+        self.runtime_state
+            .as_ref()
+            .map(|state| {
+                state
+                    .contracts
+                    .values()
+                    .any(|contract| contract.has_held_commands())
+            })
+            .unwrap_or(false)
+    }
+
+    fn take_checkpoint(&self, block_number: chain::BlockNumber) -> Result<()> {
+        let state = self
+            .runtime_state
+            .as_ref()
+            .ok_or_else(|| anyhow!("Runtime not initialized"))?;
+        let system = self
+            .system
+            .as_ref()
+            .ok_or_else(|| anyhow!("Runtime not initialized"))?;
+        let checkpoint = RuntimeCheckpoint {
+            genesis_block_hash: state.genesis_block_hash,
+            identity_pubkey: state.identity_key.public(),
+            block_number,
+            storage_root: *state.chain_storage.root(),
+            chain_storage: state.chain_storage.dump(),
+            storage_synchronizer: state.storage_synchronizer.clone(),
+            send_mq: state.send_mq.snapshot(),
+            system: system.snapshot(),
+            contracts: snapshot_contracts(&state.contracts),
+        };
+        let encoded_vec = CheckpointSeal::V1(checkpoint).encode();
+        self.platform
+            .seal_data(self.checkpoint_path(), &encoded_vec)
+            .map_err(Into::into)
+            .context("Seal checkpoint")?;
+        info!(
+            "Checkpoint saved at block {}, size={}",
+            block_number,
+            encoded_vec.len()
+        );
+        Ok(())
+    }
+
+    /// Restore the freshly initialized runtime state from the sealed checkpoint.
+    ///
+    /// Returns the block number of the checkpoint, or None if there is no checkpoint. Checkpoints
+    /// from another chain or identity, or behind the start header of the runtime, are rejected
+    /// without touching the given states.
+    fn restore_checkpoint(
+        &self,
+        start_header_number: chain::BlockNumber,
+        state: &mut RuntimeState,
+        system: &mut system::System<Platform>,
+    ) -> Result<Option<chain::BlockNumber>> {
+        let data = match self
+            .platform
+            .unseal_data(self.checkpoint_path())
+            .map_err(Into::into)
+            .context("Unseal checkpoint")?
         {
-            let data = RuntimeDataSeal::V1(data.clone());
-            let encoded_vec = data.encode();
-            info!("Length of encoded slice: {}", encoded_vec.len());
-            let filepath = PathBuf::from(&self.args.sealing_path).join(RUNTIME_SEALED_DATA_FILE);
-            self.platform
-                .seal_data(filepath, &encoded_vec)
-                .map_err(Into::into)
-                .context("Seal runtime data")?;
-            info!("Persistent Runtime Data saved");
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let checkpoint = match CheckpointSeal::decode(&mut &data[..])
+            .map_err(|_| anyhow!("Unsupported checkpoint version"))?
+        {
+            CheckpointSeal::V1(checkpoint) => checkpoint,
+        };
+
+        if checkpoint.genesis_block_hash != state.genesis_block_hash {
+            return Err(anyhow!(
+                "Genesis block hash mismatch, checkpoint={:?}",
+                checkpoint.genesis_block_hash
+            ));
         }
-        Ok(data)
+        if checkpoint.identity_pubkey != state.identity_key.public() {
+            return Err(anyhow!("Identity key mismatch"));
+        }
+        if std::mem::discriminant(&checkpoint.storage_synchronizer)
+            != std::mem::discriminant(&state.storage_synchronizer)
+        {
+            return Err(anyhow!("Solochain/parachain mode mismatch"));
+        }
+        let counters = checkpoint.storage_synchronizer.counters();
+        if counters.next_header_number <= start_header_number {
+            return Err(anyhow!(
+                "Stale checkpoint at header {}, runtime starts from {}",
+                counters.next_header_number - 1,
+                start_header_number
+            ));
+        }
+        if !state.contracts.keys().eq(checkpoint.contracts.keys()) {
+            return Err(anyhow!("Installed contracts mismatch"));
+        }
+        let chain_storage = Storage::restore(checkpoint.chain_storage);
+        if *chain_storage.root() != checkpoint.storage_root {
+            return Err(anyhow!("Storage root mismatch"));
+        }
+
+        let fresh_contracts = snapshot_contracts(&state.contracts);
+        let result = restore_contracts(&mut state.contracts, &checkpoint.contracts)
+            .context("Restore contracts")
+            .and_then(|_| {
+                system
+                    .restore(checkpoint.system, &mut state.recv_mq)
+                    .context("Restore system")
+            });
+        if let Err(err) = result {
+            restore_contracts(&mut state.contracts, &fresh_contracts)
+                .expect("Contracts should be able to restore their own snapshots");
+            return Err(err);
+        }
+        state.send_mq.restore(checkpoint.send_mq);
+        state.chain_storage = chain_storage;
+        state.storage_synchronizer = checkpoint.storage_synchronizer;
+        Ok(Some(checkpoint.block_number))
     }
 
     fn load_runtime_data(&self) -> Result<PersistentRuntimeData, Error> {
@@ -235,7 +453,8 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             .ok_or(Error::PersistentRuntimeNotFound)?;
         let data: RuntimeDataSeal = Decode::decode(&mut &data[..]).map_err(Error::DecodeError)?;
         match data {
-            RuntimeDataSeal::V1(data) => Ok(data),
+            RuntimeDataSeal::V1(data) => Ok(data.into()),
+            RuntimeDataSeal::V2(data) => Ok(data),
         }
     }
 }
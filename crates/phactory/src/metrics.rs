@@ -0,0 +1,374 @@
+//! Worker metrics in the Prometheus text exposition format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{benchmark, Phactory};
+
+/// The topic label of the ingress messages nobody subscribes to
+const OTHER_TOPIC: &str = "other";
+
+/// The upper bounds (in seconds) of the RPC latency histogram buckets
+const LATENCY_BUCKETS: [f64; 9] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0];
+
+#[derive(Default, Clone)]
+struct RpcStats {
+    calls: u64,
+    errors: u64,
+    latency_sum: f64,
+    /// Non-cumulative counts of each bucket, the last one for +Inf
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+}
+
+/// The counters collected along the way. They are reset on restart.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    rpc: BTreeMap<String, RpcStats>,
+    mq_ingress: BTreeMap<String, u64>,
+    last_dispatch_time: Option<SystemTime>,
+}
+
+impl Metrics {
+    pub fn record_rpc(&mut self, method: &str, latency: Duration, ok: bool) {
+        let stats = self.rpc.entry(method.to_string()).or_default();
+        let secs = latency.as_secs_f64();
+        stats.calls += 1;
+        if !ok {
+            stats.errors += 1;
+        }
+        stats.latency_sum += secs;
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        stats.buckets[bucket] += 1;
+    }
+
+    /// Counts an ingress message by its topic
+    ///
+    /// Anyone can push messages to any topic on chain, so the ones without a subscriber are all
+    /// counted as `other` to keep the labels bounded.
+    pub fn record_mq_ingress(&mut self, topic: &[u8], subscribed: bool) {
+        let topic = if subscribed {
+            String::from_utf8_lossy(topic).into_owned()
+        } else {
+            OTHER_TOPIC.to_string()
+        };
+        *self.mq_ingress.entry(topic).or_default() += 1;
+    }
+
+    pub fn record_block_dispatched(&mut self) {
+        self.last_dispatch_time = Some(SystemTime::now());
+    }
+
+    pub fn write_to(&self, out: &mut Exposition) {
+        out.header(
+            "phactory_rpc_requests_total",
+            "counter",
+            "Number of pRPC requests handled",
+        );
+        for (method, stats) in &self.rpc {
+            out.sample(
+                "phactory_rpc_requests_total",
+                &[("method", method)],
+                stats.calls,
+            );
+        }
+        out.header(
+            "phactory_rpc_errors_total",
+            "counter",
+            "Number of pRPC requests failed",
+        );
+        for (method, stats) in &self.rpc {
+            out.sample(
+                "phactory_rpc_errors_total",
+                &[("method", method)],
+                stats.errors,
+            );
+        }
+        out.header(
+            "phactory_rpc_latency_seconds",
+            "histogram",
+            "Latency of the pRPC requests",
+        );
+        for (method, stats) in &self.rpc {
+            let mut cumulative = 0;
+            for (i, count) in stats.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = match LATENCY_BUCKETS.get(i) {
+                    Some(bound) => bound.to_string(),
+                    None => "+Inf".to_string(),
+                };
+                out.sample(
+                    "phactory_rpc_latency_seconds_bucket",
+                    &[("method", method), ("le", &le)],
+                    cumulative,
+                );
+            }
+            out.sample(
+                "phactory_rpc_latency_seconds_sum",
+                &[("method", method)],
+                stats.latency_sum,
+            );
+            out.sample(
+                "phactory_rpc_latency_seconds_count",
+                &[("method", method)],
+                stats.calls,
+            );
+        }
+
+        out.header(
+            "phactory_mq_ingress_messages_total",
+            "counter",
+            "Number of messages dispatched from the chain, by topic",
+        );
+        for (topic, count) in &self.mq_ingress {
+            out.sample(
+                "phactory_mq_ingress_messages_total",
+                &[("topic", topic)],
+                count,
+            );
+        }
+
+        if let Some(time) = self.last_dispatch_time {
+            out.header(
+                "phactory_last_block_dispatched_timestamp_seconds",
+                "gauge",
+                "When the last block was dispatched, in unix time",
+            );
+            let secs = time
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            out.sample(
+                "phactory_last_block_dispatched_timestamp_seconds",
+                &[],
+                secs,
+            );
+        }
+    }
+}
+
+/// All the lifecycle phases of a worker, exported as a set of 0/1 gauges
+const WORKER_PHASES: [&str; 5] = ["unregistered", "idle", "benchmarking", "mining", "paused"];
+
+impl<Platform: pal::Platform> Phactory<Platform> {
+    /// Renders the metrics of the worker in the Prometheus text exposition format
+    pub(crate) fn render_metrics(&self) -> String {
+        let info = self.get_info();
+        let mut out = Exposition::default();
+
+        out.gauge(
+            "phactory_initialized",
+            "Whether the runtime is initialized",
+            info.initialized as u8,
+        );
+        out.gauge(
+            "phactory_registered",
+            "Whether the worker is registered on chain",
+            info.registered as u8,
+        );
+
+        // Sync progress
+        out.gauge(
+            "phactory_next_header_number",
+            "The next relaychain (or solochain) header to sync",
+            info.headernum,
+        );
+        out.gauge(
+            "phactory_next_para_header_number",
+            "The next parachain header to sync",
+            info.para_headernum,
+        );
+        out.gauge(
+            "phactory_next_block_number",
+            "The next block to dispatch",
+            info.blocknum,
+        );
+        // The blocks are dispatched along the parachain headers in parachain mode
+        let synced_headers = if info.para_headernum > 0 {
+            info.para_headernum
+        } else {
+            info.headernum
+        };
+        out.gauge(
+            "phactory_sync_lag_blocks",
+            "Number of the synced headers whose blocks are not dispatched yet",
+            synced_headers.saturating_sub(info.blocknum),
+        );
+
+        // Message queues
+        out.gauge(
+            "phactory_mq_pending_messages",
+            "Number of egress messages not accepted by the chain yet",
+            info.pending_messages,
+        );
+        if let Some(state) = &self.runtime_state {
+            out.header(
+                "phactory_mq_egress_messages_total",
+                "counter",
+                "Number of messages sent to the chain, by topic",
+            );
+            for (topic, count) in state.send_mq.sent_by_topic() {
+                out.sample(
+                    "phactory_mq_egress_messages_total",
+                    &[("topic", &String::from_utf8_lossy(&topic))],
+                    count,
+                );
+            }
+        }
+        out.header(
+            "phactory_mq_egress_queue_depth",
+            "gauge",
+            "Number of messages waiting in the egress queue, by sender",
+        );
+        for queue in &info.egress_queues {
+            out.sample(
+                "phactory_mq_egress_queue_depth",
+                &[("sender", &queue.sender)],
+                queue.depth,
+            );
+        }
+        out.header(
+            "phactory_mq_egress_dropped_total",
+            "counter",
+            "Number of egress messages rejected or dropped due to the queue limit, by sender",
+        );
+        for queue in &info.egress_queues {
+            out.sample(
+                "phactory_mq_egress_dropped_total",
+                &[("sender", &queue.sender)],
+                queue.dropped,
+            );
+        }
+
+        out.gauge(
+            "phactory_running_side_tasks",
+            "Number of the running side tasks",
+            info.running_side_tasks,
+        );
+
+        // Mining
+        out.gauge(
+            "phactory_benchmark_iterations",
+            "Number of the benchmark iterations since the start",
+            benchmark::iteration_counter(),
+        );
+        out.gauge(
+            "phactory_benchmark_score",
+            "The estimated benchmark score",
+            info.score,
+        );
+        let phase = self.system.as_ref().map(|system| system.worker_phase());
+        out.header(
+            "phactory_worker_phase",
+            "gauge",
+            "The lifecycle phase of the worker, 1 for the current one",
+        );
+        for name in WORKER_PHASES.iter() {
+            out.sample(
+                "phactory_worker_phase",
+                &[("phase", name)],
+                (phase == Some(*name)) as u8,
+            );
+        }
+
+        self.metrics.write_to(&mut out);
+        out.into_string()
+    }
+}
+
+/// A builder of the Prometheus text exposition
+#[derive(Default)]
+pub(crate) struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    pub fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            self.text.push('{');
+            for (i, (key, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.text.push(',');
+                }
+                let _ = write!(self.text, "{}=\"{}\"", key, escape_label(value));
+            }
+            self.text.push('}');
+        }
+        let _ = writeln!(self.text, " {}", value);
+    }
+
+    /// Writes a metric with a single sample
+    pub fn gauge(&mut self, name: &str, help: &str, value: impl std::fmt::Display) {
+        self.header(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+
+    pub fn into_string(self) -> String {
+        self.text
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_should_render_rpc_histogram() {
+        let mut metrics = Metrics::default();
+        metrics.record_rpc("PhactoryAPI.GetInfo", Duration::from_millis(3), true);
+        metrics.record_rpc("PhactoryAPI.GetInfo", Duration::from_secs(20), false);
+        metrics.record_mq_ingress(b"phala/system/event", true);
+        metrics.record_mq_ingress(b"spam/1", false);
+        metrics.record_mq_ingress(b"spam/2", false);
+
+        let mut out = Exposition::default();
+        metrics.write_to(&mut out);
+        let text = out.into_string();
+        let has = |line: &str| text.lines().any(|l| l == line);
+        assert!(has(
+            r#"phactory_rpc_requests_total{method="PhactoryAPI.GetInfo"} 2"#
+        ));
+        assert!(has(
+            r#"phactory_rpc_errors_total{method="PhactoryAPI.GetInfo"} 1"#
+        ));
+        assert!(has(
+            r#"phactory_rpc_latency_seconds_bucket{method="PhactoryAPI.GetInfo",le="0.001"} 0"#
+        ));
+        assert!(has(
+            r#"phactory_rpc_latency_seconds_bucket{method="PhactoryAPI.GetInfo",le="0.005"} 1"#
+        ));
+        assert!(has(
+            r#"phactory_rpc_latency_seconds_bucket{method="PhactoryAPI.GetInfo",le="+Inf"} 2"#
+        ));
+        assert!(has(
+            r#"phactory_mq_ingress_messages_total{topic="phala/system/event"} 1"#
+        ));
+        assert!(has(
+            r#"phactory_mq_ingress_messages_total{topic="other"} 2"#
+        ));
+        assert!(!text.contains("spam"));
+    }
+
+    #[test]
+    fn label_values_should_be_escaped() {
+        let mut out = Exposition::default();
+        out.sample("m", &[("topic", "a\"b\\c")], 1);
+        assert_eq!(out.into_string(), "m{topic=\"a\\\"b\\\\c\"} 1\n");
+    }
+}
@@ -5,6 +5,7 @@ use pb::{
     phactory_api_server::{PhactoryApi, PhactoryApiServer},
     server::Error as RpcError,
 };
+use parity_scale_codec::Compact;
 use phactory_api::{blocks, crypto, prpc as pb};
 use phala_types::{contract, WorkerPublicKey,
                   messaging::{CoordinateInfo, GeolocationCommand}};
@@ -22,7 +23,26 @@ fn from_debug(e: impl core::fmt::Debug) -> RpcError {
     RpcError::AppError(format!("{:?}", e))
 }
 
-pub const VERSION: u32 = 1;
+pub const VERSION: u32 = 2;
+
+/// The egress queue limit of each native contract, keeping a runaway contract from blowing up the
+/// enclave memory. None for unlimited.
+///
+/// Rejected messages don't consume sequence numbers and blocked contracts hold on their commands,
+/// so all workers must share the same limit.
+fn contract_egress_limit(args: &InitArgs) -> Option<QueueLimit> {
+    if args.contract_egress_capacity == 0 {
+        return None;
+    }
+    let policy = match args.contract_egress_policy {
+        EgressOverflowPolicy::Reject => OverflowPolicy::Reject,
+        EgressOverflowPolicy::Block => OverflowPolicy::Block,
+    };
+    Some(QueueLimit {
+        capacity: args.contract_egress_capacity as usize,
+        policy,
+    })
+}
 
 fn now() -> u64 {
     use std::time::SystemTime;
@@ -32,6 +52,49 @@ fn now() -> u64 {
     now.as_secs()
 }
 
+/// Creates an attestation committing to `data_hash`, as an IAS report or a DCAP quote
+pub(crate) fn create_attestation(
+    platform: &impl pal::RA,
+    dcap: bool,
+    data_hash: &[u8],
+) -> RpcResult<pb::Attestation> {
+    if dcap {
+        let quote = platform.create_dcap_quote(data_hash).map_err(|e| {
+            let message = format!("Failed to create DCAP quote: {:?}", e);
+            error!("{}", message);
+            from_display(message)
+        })?;
+        Ok(pb::Attestation {
+            version: 1,
+            provider: pb::ATTESTATION_PROVIDER_DCAP.to_string(),
+            payload: None,
+            dcap_quote: quote,
+            timestamp: now(),
+        })
+    } else {
+        let (attn_report, sig, cert) = match platform.create_attestation_report(data_hash) {
+            Ok(r) => r,
+            Err(e) => {
+                let message = format!("Failed to create attestation report: {:?}", e);
+                error!("{}", message);
+                return Err(from_display(message));
+            }
+        };
+        Ok(pb::Attestation {
+            version: 1,
+            provider: pb::ATTESTATION_PROVIDER_IAS.to_string(),
+            payload: Some(pb::AttestationReport {
+                report: attn_report,
+                signature: base64::decode(sig).map_err(from_display)?,
+                signing_cert: base64::decode_config(cert, base64::STANDARD)
+                    .map_err(from_display)?,
+            }),
+            dcap_quote: Vec::new(),
+            timestamp: now(),
+        })
+    }
+}
+
 // Drop latest messages if needed to fit in size.
 fn fit_size(mut messages: pb::EgressMessages, size: usize) -> pb::EgressMessages {
     while messages.encoded_size() > size {
@@ -61,6 +124,14 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             .ok_or_else(|| from_display("Runtime not initialized"))
     }
 
+    /// Refuses to produce anything signed by the identity once it is handed over
+    fn ensure_not_retired(&self) -> RpcResult<()> {
+        if self.retired {
+            return Err(from_display("Retired after the handover"));
+        }
+        Ok(())
+    }
+
     pub fn get_info(&self) -> pb::PhactoryInfo {
         let initialized = self.runtime_info.is_some();
         let state = self.runtime_state.as_ref();
@@ -69,12 +140,23 @@ impl<Platform: pal::Platform> Phactory<Platform> {
         let ecdh_public_key = state.map(|state| hex::encode(&state.ecdh_key.public()));
         let dev_mode = self.dev_mode;
 
-        let (state_root, pending_messages, counters) = match state.as_ref() {
+        let (state_root, pending_messages, egress_queues, counters) = match state.as_ref() {
             Some(state) => {
                 let state_root = hex::encode(state.chain_storage.root());
                 let pending_messages = state.send_mq.count_messages();
+                let egress_queues = state
+                    .send_mq
+                    .stats()
+                    .into_iter()
+                    .map(|stats| pb::EgressQueueInfo {
+                        sender: stats.sender.to_string(),
+                        depth: stats.depth as _,
+                        capacity: stats.limit.map(|limit| limit.capacity as _).unwrap_or(0),
+                        dropped: stats.dropped,
+                    })
+                    .collect();
                 let counters = state.storage_synchronizer.counters();
-                (state_root, pending_messages, counters)
+                (state_root, pending_messages, egress_queues, counters)
             }
             None => Default::default(),
         };
@@ -107,6 +189,7 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             state_root,
             dev_mode,
             pending_messages: pending_messages as _,
+            egress_queues,
             score,
             version: self.args.version.clone(),
             git_revision: self.args.git_revision.clone(),
@@ -201,6 +284,7 @@ impl<Platform: pal::Platform> Phactory<Platform> {
         &mut self,
         blocks: Vec<blocks::BlockHeaderWithChanges>,
     ) -> RpcResult<pb::SyncedTo> {
+        self.ensure_not_retired()?;
         info!(
             "dispatch_block from={:?} to={:?}",
             blocks.first().map(|h| h.block_header.number),
@@ -219,6 +303,20 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             self.handle_inbound_messages(block.block_header.number)?;
             self.poll_side_tasks(block.block_header.number)?;
             last_block = block.block_header.number;
+            self.metrics.record_block_dispatched();
+
+            let interval = self.args.checkpoint_interval;
+            if interval > 0 && last_block % interval == 0 {
+                if self.has_held_contract_commands() {
+                    // The commands held by the contracts would be lost in the checkpoint
+                    warn!(
+                        "Skipped the checkpoint at block {} with commands held",
+                        last_block
+                    );
+                } else if let Err(err) = self.take_checkpoint(last_block) {
+                    error!("Failed to take checkpoint at block {}: {:?}", last_block, err);
+                }
+            }
         }
 
         Ok(pb::SyncedTo {
@@ -226,7 +324,7 @@ impl<Platform: pal::Platform> Phactory<Platform> {
         })
     }
 
-    fn init_runtime(
+    pub(crate) fn init_runtime(
         &mut self,
         skip_ra: bool,
         is_parachain: bool,
@@ -303,21 +401,17 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             .expect("Bridge initialize failed");
 
         let storage_synchronizer = if is_parachain {
-            Box::new(ParachainSynchronizer::new(
-                light_client,
-                main_bridge,
-                next_headernum,
-            )) as _
+            Synchronizer::new_parachain(light_client, main_bridge, next_headernum)
         } else {
-            Box::new(SolochainSynchronizer::new(light_client, main_bridge)) as _
+            Synchronizer::new_solochain(light_client, main_bridge)
         };
 
         let id_pair = identity_key.clone();
         let send_mq = MessageSendQueue::default();
         let mut recv_mq = MessageDispatcher::default();
 
-        let mut contracts: BTreeMap<ContractId, Box<dyn contracts::Contract + Send>> =
-            Default::default();
+        let mut contracts: ContractMap = Default::default();
+        let contract_egress_limit = contract_egress_limit(&self.args);
 
         if self.dev_mode {
             // Install contracts when running in dev_mode.
@@ -327,13 +421,14 @@ impl<Platform: pal::Platform> Phactory<Platform> {
                 ($id: expr, $inner: expr) => {{
                     let contract_id = contract::id256($id);
                     let sender = MessageOrigin::native_contract($id);
+                    send_mq.set_limit(sender.clone(), contract_egress_limit);
                     let mq = send_mq.channel(sender, id_pair.clone());
                     // TODO.kevin: use real contract key
                     let contract_key = ecdh_key.clone();
+                    let cmd_topic = contract::command_topic(contract_id);
                     let cmd_mq = PeelingReceiver::new_secret(
-                        recv_mq
-                            .subscribe(contract::command_topic(contract_id))
-                            .into(),
+                        recv_mq.subscribe_with_sequence(cmd_topic.clone()).into(),
+                        cmd_topic,
                         contract_key,
                     );
                     let wrapped = Box::new(contracts::NativeCompatContract::new(
@@ -392,14 +487,25 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             runtime_state.chain_storage.root()
         );
 
-        let system = system::System::new(
+        let mut system = system::System::new(
             self.platform.clone(),
             self.args.sealing_path.clone(),
             &id_pair,
+            genesis_block_hash,
             &runtime_state.send_mq,
             &mut runtime_state.recv_mq,
         );
 
+        if self.args.checkpoint_interval > 0 {
+            match self.restore_checkpoint(next_headernum, &mut runtime_state, &mut system) {
+                Ok(Some(block_number)) => {
+                    info!("Restored from checkpoint at block {}", block_number)
+                }
+                Ok(None) => info!("No checkpoint found, starting from genesis"),
+                Err(err) => warn!("Ignored checkpoint: {:?}", err),
+            }
+        }
+
         let resp = pb::InitRuntimeResponse::new(
             runtime_info,
             genesis_block_hash,
@@ -415,6 +521,7 @@ impl<Platform: pal::Platform> Phactory<Platform> {
     }
 
     fn get_runtime_info(&mut self) -> RpcResult<pb::InitRuntimeResponse> {
+        self.ensure_not_retired()?;
         let skip_ra = self.skip_ra;
 
         let mut cached_resp = self
@@ -436,33 +543,18 @@ impl<Platform: pal::Platform> Phactory<Platform> {
                 info!("Encoded runtime info");
                 info!("{:?}", hex::encode(&cached_resp.encoded_runtime_info));
 
-                let (attn_report, sig, cert) =
-                    match self.platform.create_attestation_report(&runtime_info_hash) {
-                        Ok(r) => r,
-                        Err(e) => {
-                            let message = format!("Failed to create attestation report: {:?}", e);
-                            error!("{}", message);
-                            return Err(from_display(message));
-                        }
-                    };
-
-                cached_resp.attestation = Some(pb::Attestation {
-                    version: 1,
-                    provider: "SGX".to_string(),
-                    payload: Some(pb::AttestationReport {
-                        report: attn_report,
-                        signature: base64::decode(sig).map_err(from_display)?,
-                        signing_cert: base64::decode_config(cert, base64::STANDARD)
-                            .map_err(from_display)?,
-                    }),
-                    timestamp: now(),
-                });
+                cached_resp.attestation = Some(create_attestation(
+                    &self.platform,
+                    self.args.dcap_attestation,
+                    &runtime_info_hash,
+                )?);
             }
         }
         Ok(cached_resp.clone())
     }
 
     fn get_egress_messages(&mut self, output_buf_len: usize) -> RpcResult<pb::EgressMessages> {
+        self.ensure_not_retired()?;
         let messages: Vec<_> = self
             .runtime_state
             .as_ref()
@@ -472,6 +564,60 @@ impl<Platform: pal::Platform> Phactory<Platform> {
         Ok(fit_size(messages, output_buf_len))
     }
 
+    /// Returns the pending messages from the cursor on, in the order of the senders and sequences.
+    ///
+    /// Stops before the first message exceeding `max_bytes`, so the client can continue with the
+    /// returned cursor.
+    fn get_egress_messages_page(
+        &mut self,
+        cursor: pb::EgressCursor,
+        max_bytes: usize,
+    ) -> RpcResult<pb::EgressMessagesPage> {
+        self.ensure_not_retired()?;
+        let grouped = self
+            .runtime_state
+            .as_ref()
+            .map(|state| state.send_mq.all_messages_grouped())
+            .unwrap_or_default();
+
+        // The length prefix of the outer Vec
+        let mut size = Compact(u32::MAX).encoded_size();
+        let mut messages: pb::EgressMessages = Vec::new();
+        let mut next_cursor = cursor;
+        let mut has_more = false;
+        'outer: for (sender, queue) in grouped {
+            let start = next_cursor.get(&sender).copied().unwrap_or(0);
+            let sender_size = sender.encoded_size() + Compact(u32::MAX).encoded_size();
+            let mut page_queue = Vec::new();
+            for message in queue.into_iter().filter(|msg| msg.sequence >= start) {
+                let mut msg_size = message.encoded_size();
+                if page_queue.is_empty() {
+                    msg_size += sender_size;
+                }
+                if size + msg_size > max_bytes {
+                    if messages.is_empty() && page_queue.is_empty() {
+                        return Err(from_display(format!(
+                            "Message {} of {} exceeds the page size",
+                            message.sequence, sender
+                        )));
+                    }
+                    has_more = true;
+                    if !page_queue.is_empty() {
+                        messages.push((sender, page_queue));
+                    }
+                    break 'outer;
+                }
+                size += msg_size;
+                next_cursor.insert(sender.clone(), message.sequence + 1);
+                page_queue.push(message);
+            }
+            if !page_queue.is_empty() {
+                messages.push((sender, page_queue));
+            }
+        }
+        Ok(pb::EgressMessagesPage::new(messages, next_cursor, has_more))
+    }
+
     fn send_coordinate_info(&mut self, request: pb::SendCoordinateInfoRequest) -> RpcResult<()> {
         // local ecdh_key
         let ecdh_key = self.runtime_state()?.ecdh_key.clone();
@@ -541,9 +687,21 @@ impl<Platform: pal::Platform> Phactory<Platform> {
 
         let ecdh_key = self.runtime_state()?.ecdh_key.clone();
 
+        // The response is encrypted with the same scheme as the request.
+        // Old clients leave the field as 0, the raw scheme.
+        let scheme = crypto::EncryptionScheme::from_u32(request.encryption_scheme)
+            .ok_or_else(|| from_display("Unknown encryption scheme"))?;
+
         // Decrypt data
         let encrypted_req = request.decode_encrypted_data()?;
-        let data = encrypted_req.decrypt(&ecdh_key).map_err(from_debug)?;
+        let data = encrypted_req
+            .decrypt_with(
+                &ecdh_key,
+                scheme,
+                crypto::ecdh::context::CONTRACT_QUERY,
+                &[],
+            )
+            .map_err(from_debug)?;
 
         // Decode head
         let mut data_cursor = &data[..];
@@ -585,10 +743,13 @@ impl<Platform: pal::Platform> Phactory<Platform> {
         let response_data = response.encode();
 
         // Encrypt
-        let encrypted_resp = crypto::EncryptedData::encrypt(
+        let encrypted_resp = crypto::EncryptedData::encrypt_with(
             &ecdh_key,
             &encrypted_req.pubkey,
+            scheme,
+            crypto::ecdh::context::CONTRACT_QUERY,
             crate::generate_random_iv(),
+            &[],
             &response_data,
         )
         .map_err(from_debug)?;
@@ -622,7 +783,11 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             output_buf_len,
             phactory: self,
         });
-        let (code, data) = match server.dispatch_request(path, data.to_vec()) {
+        let since = std::time::SystemTime::now();
+        let result = server.dispatch_request(path, data.to_vec());
+        let latency = since.elapsed().unwrap_or_default();
+        self.metrics.record_rpc(path, latency, result.is_ok());
+        let (code, data) = match result {
             Ok(data) => (200, data),
             Err(err) => {
                 error!("Rpc error: {:?}", err);
@@ -656,10 +821,14 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             .chain_storage
             .mq_messages()
             .map_err(|_| from_display("Can not get mq messages from storage"))?;
+        let sequences = state
+            .chain_storage
+            .mq_message_sequences()
+            .map_err(|_| from_display("Can not get mq message sequences from storage"))?;
 
         state.recv_mq.reset_local_index();
 
-        for message in messages {
+        for (i, message) in messages.into_iter().enumerate() {
             use phala_types::messaging::SystemEvent;
             macro_rules! log_message {
                 ($msg: expr, $t: ident) => {{
@@ -684,7 +853,11 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             } else {
                 info!("mq dispatching message: {:?}", message);
             }
-            state.recv_mq.dispatch(message);
+            let topic = message.destination.path().clone();
+            // The blocks before the sequences were recorded have none
+            let sequence = sequences.get(i).copied().flatten();
+            let receivers = state.recv_mq.dispatch_with_sequence(message, sequence);
+            self.metrics.record_mq_ingress(&topic, receivers > 0);
         }
 
         let mut guard = scopeguard::guard(&mut state.recv_mq, |mq| {
@@ -807,6 +980,21 @@ impl<Platform: pal::Platform> PhactoryApi for RpcService<'_, Platform> {
             .map(pb::GetEgressMessagesResponse::new)
     }
 
+    fn get_egress_messages_page(
+        &mut self,
+        request: pb::GetEgressMessagesPageRequest,
+    ) -> RpcResult<pb::EgressMessagesPage> {
+        // The ENCLAVE OUTPUT BUFFER is a fixed size big buffer.
+        assert!(self.output_buf_len >= 1024);
+        let buf_limit = self.output_buf_len - 1024;
+        let max_bytes = match request.max_bytes as usize {
+            0 => buf_limit,
+            n => n.min(buf_limit),
+        };
+        let cursor = request.decode_cursor()?;
+        self.phactory.get_egress_messages_page(cursor, max_bytes)
+    }
+
     fn contract_query(
         &mut self,
         request: pb::ContractQueryRequest,
@@ -834,6 +1022,122 @@ impl<Platform: pal::Platform> PhactoryApi for RpcService<'_, Platform> {
         Ok(state)
     }
 
+    fn get_worker_tokenomic_events(
+        &mut self,
+        request: pb::GetWorkerTokenomicEventsRequest,
+    ) -> RpcResult<pb::WorkerTokenomicEvents> {
+        let system = self.phactory.system()?;
+        let gk = system
+            .gatekeeper
+            .as_ref()
+            .ok_or_else(|| from_display("Not a gatekeeper"))?;
+        let pubkey: WorkerPublicKey = request
+            .public_key
+            .as_slice()
+            .try_into()
+            .map_err(|_| from_display("Bad public key"))?;
+        gk.worker_tokenomic_events(&pubkey)
+            .ok_or_else(|| from_display("Worker not found"))
+    }
+
+    fn get_gatekeeper_state(
+        &mut self,
+        request: pb::GetGatekeeperStateRequest,
+    ) -> RpcResult<pb::GatekeeperState> {
+        let next_block_number = self
+            .phactory
+            .runtime_state()?
+            .storage_synchronizer
+            .counters()
+            .next_block_number;
+        let system = self.phactory.system()?;
+        let gk = system
+            .gatekeeper
+            .as_ref()
+            .ok_or_else(|| from_display("Not a gatekeeper"))?;
+        let mut state = gk.export_state(request.dump);
+        state.next_block_number = next_block_number;
+        Ok(state)
+    }
+
+    fn sign_gatekeeper_unregistration(&mut self, _: ()) -> RpcResult<pb::GatekeeperUnregistration> {
+        let phactory = &*self.phactory;
+        phactory.ensure_not_retired()?;
+        let state = phactory
+            .runtime_state
+            .as_ref()
+            .ok_or_else(|| from_display("Runtime not initialized"))?;
+        let system = phactory
+            .system
+            .as_ref()
+            .ok_or_else(|| from_display("Runtime not initialized"))?;
+        let (unregistration, signature) = system
+            .sign_gatekeeper_unregistration(&state.chain_storage)
+            .map_err(from_display)?;
+        Ok(pb::GatekeeperUnregistration {
+            pubkey: unregistration.pubkey.to_vec(),
+            nonce: unregistration.nonce,
+            signature: signature.0.to_vec(),
+        })
+    }
+
+    fn get_heartbeat_history(&mut self, _: ()) -> RpcResult<pb::HeartbeatHistory> {
+        Ok(self.phactory.system()?.heartbeat_history())
+    }
+
+    fn get_contract_state_digests(&mut self, _: ()) -> RpcResult<pb::ContractStateDigests> {
+        let state = self.phactory.runtime_state()?;
+        let digests = state
+            .contracts
+            .iter()
+            .map(|(id, contract)| pb::ContractStateDigest {
+                contract_id: id.as_bytes().to_vec(),
+                digest: contract.state_digest().to_vec(),
+            })
+            .collect();
+        Ok(pb::ContractStateDigests { digests })
+    }
+
+    /// Create a challenge to take over the identity of another pRuntime
+    fn handover_create_challenge(
+        &mut self,
+        request: pb::HandoverChallengeRequest,
+    ) -> RpcResult<pb::HandoverChallenge> {
+        let challenge = self
+            .phactory
+            .handover_create_challenge(request.skip_ra)
+            .map_err(from_display)?;
+        Ok(pb::HandoverChallenge {
+            encoded_challenge: challenge.encode(),
+        })
+    }
+
+    /// Hand the identity of this pRuntime over to the one which created the challenge, and retire
+    fn handover_start(&mut self, request: pb::HandoverChallenge) -> RpcResult<pb::HandoverPackage> {
+        let challenge = Decode::decode(&mut &request.encoded_challenge[..])
+            .map_err(|_| from_display("Bad handover challenge"))?;
+        let package = self
+            .phactory
+            .handover_start(challenge)
+            .map_err(from_display)?;
+        Ok(pb::HandoverPackage {
+            encoded_package: package.encode(),
+        })
+    }
+
+    /// Take over the identity in the package
+    fn handover_accept(&mut self, request: pb::HandoverPackage) -> RpcResult<pb::HandoverAccepted> {
+        let package = Decode::decode(&mut &request.encoded_package[..])
+            .map_err(|_| from_display("Bad handover package"))?;
+        let public_key = self
+            .phactory
+            .handover_accept(package)
+            .map_err(from_display)?;
+        Ok(pb::HandoverAccepted {
+            public_key: public_key.to_vec(),
+        })
+    }
+
     fn send_coordinate_info (&mut self, request: pb::SendCoordinateInfoRequest) -> RpcResult<()> {
         self.phactory.send_coordinate_info(request)
     }
@@ -2,16 +2,40 @@ pub use receiver::*;
 pub use sender::*;
 
 use crate::light_validation::utils::storage_map_prefix_blake2_128_concat;
-use phactory_api::crypto::EncryptedData;
+use phactory_api::crypto::{EncryptedData, EncryptionScheme};
 use parity_scale_codec::{Decode, Encode};
+use phala_mq::MessageOrigin;
 
+/// The payload of a message sent through a secret channel.
+///
+/// New variants must be appended at the end to keep the old payloads decodable.
 #[derive(Encode, Decode, Debug)]
 pub enum Payload<T> {
     Plain(T),
+    /// Encrypted without associated data. Only accepted from the onchain senders, e.g. the users
+    /// with the old clients, since the payloads could be replayed by the offchain senders.
     Encrypted(EncryptedData),
+    /// Encrypted with the `MessageAad` of the message as the associated data.
+    EncryptedWithAad {
+        /// The egress sequence of the message from the sender.
+        sequence: u64,
+        /// How the key is derived, with `ecdh::context::MQ_PAYLOAD` as the context.
+        scheme: EncryptionScheme,
+        data: EncryptedData,
+    },
+}
+
+/// The associated data binding an encrypted payload to the message carrying it, so that the
+/// ciphertext can not be replayed to another topic or on behalf of another sender.
+#[derive(Encode, Debug)]
+pub struct MessageAad<'a> {
+    pub topic: &'a [u8],
+    pub sender: &'a MessageOrigin,
+    pub sequence: u64,
 }
 
 mod sender {
+    use super::{EncryptionScheme, MessageAad, Payload};
     use phactory_api::crypto::{ecdh, EncryptedData};
     use parity_scale_codec::Encode;
     use phala_mq::{BindTopic, Path, Sr25519MessageChannel};
@@ -45,16 +69,38 @@ mod sender {
             message: &M,
             remote_pubkey: Option<&ecdh::EcdhPublicKey>,
         ) {
+            let remote_pubkey = match remote_pubkey {
+                Some(remote_pubkey) => remote_pubkey,
+                None => return self.mq.send_data(Payload::Plain(message).encode(), to),
+            };
+            let to = to.into();
             let data = message.encode();
-            let payload = if let Some(remote_pubkey) = remote_pubkey {
+            // The payload is built once the sequence is assigned, to bind it into the AAD.
+            let _ = self.mq.try_send_data_with(to.clone(), |sequence| {
+                let aad = MessageAad {
+                    topic: &to,
+                    sender: self.mq.sender(),
+                    sequence,
+                };
+                let scheme = EncryptionScheme::HkdfSha256;
                 let iv = crate::generate_random_iv();
-                let data = EncryptedData::encrypt(self.key, remote_pubkey, iv, &data)
-                    .expect("Encrypt message failed?");
-                super::Payload::Encrypted(data)
-            } else {
-                super::Payload::Plain(message)
-            };
-            self.mq.send_data(payload.encode(), to)
+                let data = EncryptedData::encrypt_with(
+                    self.key,
+                    remote_pubkey,
+                    scheme,
+                    ecdh::context::MQ_PAYLOAD,
+                    iv,
+                    &aad.encode(),
+                    &data,
+                )
+                .expect("Encrypt message failed?");
+                Payload::<M>::EncryptedWithAad {
+                    sequence,
+                    scheme,
+                    data,
+                }
+                .encode()
+            });
         }
 
         pub fn send<M: Encode + BindTopic>(
@@ -68,16 +114,22 @@ mod sender {
 }
 
 mod receiver {
-    use super::Payload;
+    use super::{EncryptionScheme, MessageAad, Payload};
     use core::marker::PhantomData;
     use phactory_api::crypto::ecdh;
-    use parity_scale_codec::Decode;
-    use phala_mq::{MessageOrigin, ReceiveError, TypedReceiver};
+    use parity_scale_codec::{Decode, Encode};
+    use phala_mq::{MessageOrigin, Path, ReceiveError, TypedReceiver};
 
     pub trait Peeler {
         type Wrp;
         type Msg;
-        fn peel(&self, msg: Self::Wrp) -> Result<Self::Msg, anyhow::Error>;
+        /// `sequence` is the egress sequence of the sender dispatched with the message, if known.
+        fn peel(
+            &self,
+            msg: Self::Wrp,
+            origin: &MessageOrigin,
+            sequence: Option<u64>,
+        ) -> Result<Self::Msg, anyhow::Error>;
     }
 
     pub struct PlainPeeler<T>(PhantomData<T>);
@@ -85,20 +137,28 @@ mod receiver {
     impl<T> Peeler for PlainPeeler<T> {
         type Wrp = T;
         type Msg = T;
-        fn peel(&self, msg: Self::Wrp) -> Result<Self::Msg, anyhow::Error> {
+        fn peel(
+            &self,
+            msg: Self::Wrp,
+            _origin: &MessageOrigin,
+            _sequence: Option<u64>,
+        ) -> Result<Self::Msg, anyhow::Error> {
             Ok(msg)
         }
     }
 
     pub struct SecretPeeler<T> {
         ecdh_key: ecdh::EcdhKey,
+        /// The topic the receiver subscribed to, which the encrypted payloads must be bound to.
+        topic: Path,
         _t: PhantomData<T>,
     }
 
     impl<T> SecretPeeler<T> {
-        pub fn new(ecdh_key: ecdh::EcdhKey) -> Self {
+        pub fn new(ecdh_key: ecdh::EcdhKey, topic: impl Into<Path>) -> Self {
             SecretPeeler {
                 ecdh_key,
+                topic: topic.into(),
                 _t: PhantomData,
             }
         }
@@ -107,18 +167,55 @@ mod receiver {
     impl<T: Decode> Peeler for SecretPeeler<T> {
         type Wrp = Payload<T>;
         type Msg = T;
-        fn peel(&self, msg: Self::Wrp) -> Result<Self::Msg, anyhow::Error> {
-            match msg {
-                Payload::Plain(msg) => Ok(msg),
-                Payload::Encrypted(msg) => {
-                    let data = msg.decrypt(&self.ecdh_key).map_err(|err| {
-                        anyhow::anyhow!("SecretPeeler decrypt message failed: {:?}", err)
-                    })?;
-                    let msg = Decode::decode(&mut &data[..])
-                        .map_err(|_| anyhow::anyhow!("SCALE decode decrypted data failed"))?;
-                    Ok(msg)
+        fn peel(
+            &self,
+            msg: Self::Wrp,
+            origin: &MessageOrigin,
+            mq_sequence: Option<u64>,
+        ) -> Result<Self::Msg, anyhow::Error> {
+            let (msg, scheme, aad) = match msg {
+                Payload::Plain(msg) => return Ok(msg),
+                // The onchain senders are authenticated by the chain, and there is no sequence to
+                // bind for them anyway.
+                Payload::Encrypted(msg) if !origin.is_offchain() => {
+                    (msg, EncryptionScheme::Raw, vec![])
                 }
-            }
+                Payload::Encrypted(_) => {
+                    return Err(anyhow::anyhow!(
+                        "SecretPeeler rejected the payload encrypted without AAD from {}",
+                        origin
+                    ));
+                }
+                Payload::EncryptedWithAad {
+                    sequence,
+                    scheme,
+                    data,
+                } => {
+                    // The offchain senders' messages are ordered by the sequences checked by the
+                    // chain, so an old ciphertext can't be replayed in a new message. The onchain
+                    // senders have no sequence but are authenticated by the chain.
+                    if origin.is_offchain() && mq_sequence != Some(sequence) {
+                        return Err(anyhow::anyhow!(
+                            "SecretPeeler sequence mismatch: {} in the payload, {:?} in the mq",
+                            sequence,
+                            mq_sequence
+                        ));
+                    }
+                    let aad = MessageAad {
+                        topic: &self.topic,
+                        sender: origin,
+                        sequence,
+                    };
+                    (data, scheme, aad.encode())
+                }
+            };
+            // Fails if the payload was encrypted for another topic or by another sender.
+            let data = msg
+                .decrypt_with(&self.ecdh_key, scheme, ecdh::context::MQ_PAYLOAD, &aad)
+                .map_err(|err| anyhow::anyhow!("SecretPeeler decrypt message failed: {:?}", err))?;
+            let msg = Decode::decode(&mut &data[..])
+                .map_err(|_| anyhow::anyhow!("SCALE decode decrypted data failed"))?;
+            Ok(msg)
         }
     }
 
@@ -140,10 +237,15 @@ mod receiver {
     }
 
     impl<Msg, Wrp> PeelingReceiver<Msg, Wrp, SecretPeeler<Msg>> {
-        pub fn new_secret(receiver: TypedReceiver<Wrp>, ecdh_key: ecdh::EcdhKey) -> Self {
+        /// The `topic` must be the one `receiver` subscribed to.
+        pub fn new_secret(
+            receiver: TypedReceiver<Wrp>,
+            topic: impl Into<Path>,
+            ecdh_key: ecdh::EcdhKey,
+        ) -> Self {
             PeelingReceiver {
                 receiver,
-                peeler: SecretPeeler::new(ecdh_key),
+                peeler: SecretPeeler::new(ecdh_key, topic),
                 _msg: Default::default(),
             }
         }
@@ -158,13 +260,13 @@ mod receiver {
         pub fn try_next(&mut self) -> Result<Option<(u64, Msg, MessageOrigin)>, anyhow::Error> {
             let omsg = self
                 .receiver
-                .try_next()
+                .try_next_with_sequence()
                 .map_err(|e| anyhow::anyhow!("{}", e))?;
-            let (seq, msg, origin) = match omsg {
+            let (seq, msg, origin, mq_sequence) = match omsg {
                 Some(x) => x,
                 None => return Ok(None),
             };
-            let msg = self.peeler.peel(msg)?;
+            let msg = self.peeler.peel(msg, &origin, mq_sequence)?;
             Ok(Some((seq, msg, origin)))
         }
 
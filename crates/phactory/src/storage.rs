@@ -73,6 +73,10 @@ mod storage_ext {
         fn mq_messages(&self) -> Result<Vec<Message>, Error> {
             self.get_decoded_or_default(storage_prefix("PhalaMq", "OutboundMessages"))
         }
+        /// The egress sequences of the `mq_messages` synced from offchain
+        fn mq_message_sequences(&self) -> Result<Vec<Option<u64>>, Error> {
+            self.get_decoded_or_default(storage_prefix("PhalaMq", "OutboundMessageSequences"))
+        }
         fn timestamp_now(&self) -> Option<chain::Moment> {
             self.get_decoded(storage_prefix("Timestamp", "Now"))
         }
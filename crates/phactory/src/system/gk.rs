@@ -1,17 +1,24 @@
-use super::{TypedReceiver, WorkerState};
+use super::{
+    master_key::{MasterKeyShare, SharedMasterKeyHistory},
+    TypedReceiver, WorkerState,
+};
 use phala_crypto::{
     aead, ecdh,
-    sr25519::{Persistence, KDF},
+    sr25519::{kdf_salt_from_genesis, KdfSalt, Persistence, KDF},
+    threshold::{self, PartialEvaluation},
+    vrf,
 };
 use phala_mq::MessageDispatcher;
 use phala_types::{
     messaging::{
-        GatekeeperEvent, KeyDistribution, MessageOrigin, MiningInfoUpdateEvent, MiningReportEvent,
-        RandomNumber, RandomNumberEvent, SettleInfo, SystemEvent, WorkerEvent, WorkerEventWithKey,
+        random_number_vrf_message, GatekeeperEvent, KeyDistribution, MessageOrigin,
+        MiningInfoUpdateEvent, MiningReportEvent, PartialRandomNumberEvent, RandomNumber,
+        RandomNumberEvent, RandomNumberProof, SettleInfo, SystemEvent, ThresholdEvent, WorkerEvent,
+        WorkerEventWithKey, RANDOM_NUMBER_VRF_CONTEXT, RANDOM_NUMBER_VRF_LABEL,
     },
     EcdhPublicKey, WorkerPublicKey,
 };
-use sp_core::{hashing, sr25519};
+use sp_core::{hashing, sr25519, H256};
 
 use crate::types::BlockInfo;
 
@@ -23,6 +30,7 @@ use std::{
 use fixed_macro::types::U64F64 as fp;
 use log::debug;
 use msg_trait::MessageChannel;
+use parity_scale_codec::{Decode, Encode};
 use phactory_api::prpc as pb;
 use tokenomic::{FixedPoint, TokenomicInfo};
 
@@ -31,17 +39,22 @@ use tokenomic::{FixedPoint, TokenomicInfo};
 /// WARNING: this interval need to be large enough considering the latency of mq
 const VRF_INTERVAL: u32 = 5;
 
+/// The first pRuntime version able to decode the master key distributions with an encryption
+/// scheme, i.e. `MasterKeyDistributionWithScheme` and `MasterKeyHistoryDistribution`
+pub const MASTER_KEY_SCHEME_RUNTIME_VERSION: u32 = 2;
+
 // pesudo_random_number = blake2_256(last_random_number, block_number, derived_master_key)
 //
 // NOTICE: we abandon the random number involving master key signature, since the malleability of sr25519 signature
 // refer to: https://github.com/w3f/schnorrkel/blob/34cdb371c14a73cbe86dfd613ff67d61662b4434/old/README.md#a-note-on-signature-malleability
 fn next_random_number(
     master_key: &sr25519::Pair,
+    kdf_salt: &KdfSalt,
     block_number: chain::BlockNumber,
     last_random_number: RandomNumber,
 ) -> RandomNumber {
     let derived_random_key = master_key
-        .derive_sr25519_pair(&[b"random_number"])
+        .derive_sr25519_pair(kdf_salt, &[b"random_number"])
         .expect("should not fail with valid info");
 
     let mut buf: Vec<u8> = last_random_number.to_vec();
@@ -51,6 +64,54 @@ fn next_random_number(
     hashing::blake2_256(buf.as_ref())
 }
 
+// Once the VRF beacon is enabled:
+// pesudo_random_number = vrf(master_key, last_random_number ++ block_number)
+//
+// The proof is verifiable against the on-chain master pubkey, so the random numbers can be used by
+// anyone. The proofs are deterministic, so all the gatekeepers produce identical messages.
+fn next_vrf_random_number(
+    master_key: &sr25519::Pair,
+    block_number: chain::BlockNumber,
+    last_random_number: RandomNumber,
+) -> (RandomNumber, RandomNumberProof) {
+    let (random_number, vrf_output, vrf_proof) = vrf::vrf_sign(
+        master_key.as_ref(),
+        RANDOM_NUMBER_VRF_CONTEXT,
+        &random_number_vrf_message(block_number, &last_random_number),
+        RANDOM_NUMBER_VRF_LABEL,
+    );
+    (
+        random_number,
+        RandomNumberProof {
+            vrf_output,
+            vrf_proof,
+        },
+    )
+}
+
+/// Max number of the random number rounds waiting for the partial evaluations
+const MAX_PENDING_RANDOM_ROUNDS: usize = 16;
+
+// With the threshold master key:
+// pesudo_random_number = blake2_256(master_key * hash_to_point(last_random_number, block_number))
+//
+// The share holders evaluate the input with their shares, and any `threshold` of the partial
+// evaluations are combined to the same result.
+fn random_number_input(
+    block_number: chain::BlockNumber,
+    last_random_number: &RandomNumber,
+) -> Vec<u8> {
+    random_number_vrf_message(block_number, last_random_number)
+}
+
+/// A random number waiting for the partial evaluations of the share holders
+#[derive(Encode, Decode, Clone)]
+struct RandomRound {
+    last_random_number: RandomNumber,
+    partials: Vec<PartialEvaluation>,
+}
+
+#[derive(Encode, Decode, Clone)]
 struct WorkerInfo {
     state: WorkerState,
     waiting_heartbeats: VecDeque<chain::BlockNumber>,
@@ -61,6 +122,8 @@ struct WorkerInfo {
     last_heartbeat_at_block: chain::BlockNumber,
     last_gk_responsive_event: i32,
     last_gk_responsive_event_at_block: chain::BlockNumber,
+    /// The recent tokenomic events, at most `tokenomic::MAX_EVENTS`
+    tokenomic_events: VecDeque<tokenomic::Event>,
 }
 
 impl WorkerInfo {
@@ -75,24 +138,93 @@ impl WorkerInfo {
             last_heartbeat_at_block: 0,
             last_gk_responsive_event: 0,
             last_gk_responsive_event_at_block: 0,
+            tokenomic_events: Default::default(),
         }
     }
+
+    /// Record a tokenomic event happened in the block, with V after it taken from the current state
+    ///
+    /// Consecutive slashes are merged into one event to keep the history of an unresponsive worker
+    /// from being flushed.
+    fn record_tokenomic_event(
+        &mut self,
+        kind: tokenomic::EventKind,
+        block_number: chain::BlockNumber,
+        v_before: FixedPoint,
+        amount: FixedPoint,
+    ) {
+        let v_after = self.tokenomic.v;
+        if kind == tokenomic::EventKind::Slash {
+            if let Some(last) = self.tokenomic_events.back_mut() {
+                if last.kind == kind && last.last_block_number + 1 == block_number {
+                    last.last_block_number = block_number;
+                    last.v_after = v_after;
+                    last.amount += amount;
+                    return;
+                }
+            }
+        }
+        if self.tokenomic_events.len() >= tokenomic::MAX_EVENTS {
+            let _ = self.tokenomic_events.pop_front();
+        }
+        self.tokenomic_events.push_back(tokenomic::Event {
+            kind,
+            block_number,
+            last_block_number: block_number,
+            v_before,
+            v_after,
+            amount,
+        });
+    }
+}
+
+/// The persistable part of `Gatekeeper`, used for checkpoints
+#[derive(Encode, Decode, Clone)]
+pub(crate) struct GatekeeperSnapshot {
+    master_pubkey_on_chain: bool,
+    registered_on_chain: bool,
+    workers: BTreeMap<WorkerPublicKey, WorkerInfo>,
+    last_random_number: RandomNumber,
+    iv_seq: u64,
+    tokenomic_params: tokenomic::Params,
+    random_rounds: BTreeMap<chain::BlockNumber, RandomRound>,
+    combined_random_numbers: BTreeMap<chain::BlockNumber, RandomNumber>,
+    vrf_beacon_since: Option<chain::BlockNumber>,
+    scheduled_tokenomic_params: Option<(chain::BlockNumber, tokenomic::Params)>,
 }
 
 pub(crate) struct Gatekeeper<MsgChan> {
     master_key: sr25519::Pair,
+    /// The master keys replaced by rotations, with the block numbers they were retired at
+    retired_master_keys: Vec<(chain::BlockNumber, sr25519::Pair)>,
+    /// The share of the threshold master key, with the block number it was activated at
+    master_key_share: Option<(chain::BlockNumber, MasterKeyShare)>,
+    /// The salt to derive the sub-keys from the master key
+    ///
+    /// All the gatekeepers must derive the same random numbers and IVs, so the salt comes from the
+    /// genesis block instead of the runtime data, which keeps the legacy salt for the identities
+    /// upgraded from V1.
+    kdf_salt: KdfSalt,
     master_pubkey_on_chain: bool,
     registered_on_chain: bool,
     egress: MsgChan, // TODO.kevin: syncing the egress state while migrating.
     gatekeeper_events: TypedReceiver<GatekeeperEvent>,
     mining_events: TypedReceiver<MiningReportEvent>,
     system_events: TypedReceiver<SystemEvent>,
+    threshold_events: TypedReceiver<ThresholdEvent>,
     workers: BTreeMap<WorkerPublicKey, WorkerInfo>,
     // Randomness
     last_random_number: RandomNumber,
     iv_seq: u64,
+    random_rounds: BTreeMap<chain::BlockNumber, RandomRound>,
+    /// The random numbers combined from the partial evaluations, waiting for the verification
+    combined_random_numbers: BTreeMap<chain::BlockNumber, RandomNumber>,
+    /// The block since which the random numbers are VRF outputs
+    vrf_beacon_since: Option<chain::BlockNumber>,
     // Tokenomic
     tokenomic_params: tokenomic::Params,
+    /// The tokenomic parameters to switch to, with the block they take effect at
+    scheduled_tokenomic_params: Option<(chain::BlockNumber, tokenomic::Params)>,
 }
 
 impl<MsgChan> Gatekeeper<MsgChan>
@@ -101,6 +233,9 @@ where
 {
     pub fn new(
         master_key: sr25519::Pair,
+        retired_master_keys: Vec<(chain::BlockNumber, sr25519::Pair)>,
+        master_key_share: Option<(chain::BlockNumber, MasterKeyShare)>,
+        genesis_block_hash: H256,
         recv_mq: &mut MessageDispatcher,
         egress: MsgChan,
     ) -> Self {
@@ -108,23 +243,31 @@ where
 
         Self {
             master_key,
+            retired_master_keys,
+            master_key_share,
+            kdf_salt: kdf_salt_from_genesis(&genesis_block_hash.0),
             master_pubkey_on_chain: false,
             registered_on_chain: false,
             egress,
             gatekeeper_events: recv_mq.subscribe_bound(),
             mining_events: recv_mq.subscribe_bound(),
             system_events: recv_mq.subscribe_bound(),
+            threshold_events: recv_mq.subscribe_bound(),
             workers: Default::default(),
             last_random_number: [0_u8; 32],
             iv_seq: 0,
+            random_rounds: Default::default(),
+            combined_random_numbers: Default::default(),
+            vrf_beacon_since: None,
             tokenomic_params: tokenomic::test_params(),
+            scheduled_tokenomic_params: None,
         }
     }
 
     fn generate_iv(&mut self, block_number: chain::BlockNumber) -> aead::IV {
         let derived_key = self
             .master_key
-            .derive_sr25519_pair(&[b"iv_generator"])
+            .derive_sr25519_pair(&self.kdf_salt, &[b"iv_generator"])
             .expect("should not fail with valid info");
 
         let mut buf: Vec<u8> = Vec::new();
@@ -139,13 +282,71 @@ where
             .expect("should never fail given correct length; qed;")
     }
 
+    /// The master key in use at the given block
+    fn master_key_at(&self, block_number: chain::BlockNumber) -> &sr25519::Pair {
+        self.retired_master_keys
+            .iter()
+            .find(|(retired_at, _)| block_number < *retired_at)
+            .map(|(_, key)| key)
+            .unwrap_or(&self.master_key)
+    }
+
+    /// Switch to the new master key activated on chain since `block_number`
+    ///
+    /// The egress is replaced since the messages of `MessageOrigin::Gatekeeper` must be signed
+    /// with the current master key. The dummy mode is kept as it's bound to the sender.
+    pub fn rotate_master_key(
+        &mut self,
+        master_key: sr25519::Pair,
+        block_number: chain::BlockNumber,
+        egress: MsgChan,
+    ) {
+        info!(
+            "Gatekeeper: rotate master key to {} in block {}",
+            hex::encode(master_key.public()),
+            block_number
+        );
+        let retired = core::mem::replace(&mut self.master_key, master_key);
+        self.retired_master_keys.push((block_number, retired));
+        self.egress = egress;
+    }
+
+    /// The share of the threshold master key in use at the given block
+    fn master_key_share_at(&self, block_number: chain::BlockNumber) -> Option<&MasterKeyShare> {
+        self.master_key_share
+            .as_ref()
+            .filter(|(activated_at, _)| block_number >= *activated_at)
+            .map(|(_, share)| share)
+    }
+
+    /// Switch to the threshold master key activated on chain since `block_number`
+    ///
+    /// The share only evaluates the random beacon jointly with the other share holders. The full
+    /// master keys are kept to replay the blocks before, but are never shared since then.
+    ///
+    /// The egress is replaced since nobody can sign with the threshold master key, and the
+    /// gatekeepers sign with their identity keys instead.
+    pub fn enable_threshold_master_key(
+        &mut self,
+        master_key_share: MasterKeyShare,
+        block_number: chain::BlockNumber,
+        egress: MsgChan,
+    ) {
+        info!(
+            "Gatekeeper: switch to threshold master key {} in block {}",
+            hex::encode(master_key_share.master_pubkey),
+            block_number
+        );
+        self.master_key_share = Some((block_number, master_key_share));
+        self.egress = egress;
+    }
+
     pub fn register_on_chain(&mut self) {
         info!("Gatekeeper: register on chain");
         self.egress.set_dummy(false);
         self.registered_on_chain = true;
     }
 
-    #[allow(unused)]
     pub fn unregister_on_chain(&mut self) {
         info!("Gatekeeper: unregister on chain");
         self.egress.set_dummy(true);
@@ -160,37 +361,140 @@ where
         self.master_pubkey_on_chain = true;
     }
 
+    pub fn snapshot(&self) -> GatekeeperSnapshot {
+        GatekeeperSnapshot {
+            master_pubkey_on_chain: self.master_pubkey_on_chain,
+            registered_on_chain: self.registered_on_chain,
+            workers: self.workers.clone(),
+            last_random_number: self.last_random_number,
+            iv_seq: self.iv_seq,
+            tokenomic_params: self.tokenomic_params.clone(),
+            random_rounds: self.random_rounds.clone(),
+            combined_random_numbers: self.combined_random_numbers.clone(),
+            vrf_beacon_since: self.vrf_beacon_since,
+            scheduled_tokenomic_params: self.scheduled_tokenomic_params.clone(),
+        }
+    }
+
+    /// Restore the state from a snapshot.
+    ///
+    /// The dummy mode of the egress is not touched here. It's restored along with the send queue.
+    pub fn restore(&mut self, snapshot: GatekeeperSnapshot) {
+        self.master_pubkey_on_chain = snapshot.master_pubkey_on_chain;
+        self.registered_on_chain = snapshot.registered_on_chain;
+        self.workers = snapshot.workers;
+        self.last_random_number = snapshot.last_random_number;
+        self.iv_seq = snapshot.iv_seq;
+        self.tokenomic_params = snapshot.tokenomic_params;
+        self.random_rounds = snapshot.random_rounds;
+        self.combined_random_numbers = snapshot.combined_random_numbers;
+        self.vrf_beacon_since = snapshot.vrf_beacon_since;
+        self.scheduled_tokenomic_params = snapshot.scheduled_tokenomic_params;
+    }
+
+    /// Share the master key to a new gatekeeper
+    ///
+    /// The retired master keys are shared along with the current one if there are any, since the
+    /// new gatekeeper replays the blocks before the rotations.
+    ///
+    /// Nothing is shared once the threshold master key is activated, so that the full master keys
+    /// never leave the gatekeepers holding them since then.
+    ///
+    /// A receiver registered with a pRuntime older than `MASTER_KEY_SCHEME_RUNTIME_VERSION` gets
+    /// the legacy `MasterKeyDistribution` with the raw ECDH secret, which carries the current
+    /// master key only.
     pub fn share_master_key(
         &mut self,
         pubkey: &WorkerPublicKey,
         ecdh_pubkey: &EcdhPublicKey,
+        receiver_runtime_version: u32,
+        rotation_id: u32,
         block_number: chain::BlockNumber,
     ) {
+        if self.master_key_share_at(block_number).is_some() {
+            error!(
+                "Gatekeeper: refuse to share the master key to {} with the threshold master key",
+                hex::encode(pubkey)
+            );
+            return;
+        }
         info!("Gatekeeper: try dispatch master key");
         let derived_key = self
             .master_key
-            .derive_sr25519_pair(&[&crate::generate_random_info()])
+            .derive_sr25519_pair(&self.kdf_salt, &[&crate::generate_random_info()])
             .expect("should not fail with valid info; qed.");
         let my_ecdh_key = derived_key
             .derive_ecdh_key()
             .expect("ecdh key derivation should never failed with valid master key; qed.");
-        let secret = ecdh::agree(&my_ecdh_key, &ecdh_pubkey.0)
-            .expect("should never fail with valid ecdh key; qed.");
+        let legacy_receiver = receiver_runtime_version < MASTER_KEY_SCHEME_RUNTIME_VERSION;
+        let scheme = if legacy_receiver {
+            ecdh::EncryptionScheme::Raw
+        } else {
+            ecdh::EncryptionScheme::HkdfSha256
+        };
+        let secret = ecdh::agree_with(
+            scheme,
+            &my_ecdh_key,
+            &ecdh_pubkey.0,
+            ecdh::context::MASTER_KEY,
+        )
+        .expect("should never fail with valid ecdh key; qed.");
         let iv = self.generate_iv(block_number);
-        let mut data = self.master_key.dump_secret_key().to_vec();
-
-        aead::encrypt(&iv, &secret, &mut data).expect("Failed to encrypt master key");
-        self.egress
-            .push_message(KeyDistribution::master_key_distribution(
-                *pubkey,
-                my_ecdh_key
-                    .public()
-                    .as_ref()
-                    .try_into()
-                    .expect("should never fail given pubkey with correct length; qed;"),
-                data,
-                iv,
-            ));
+        let my_ecdh_pubkey = my_ecdh_key
+            .public()
+            .as_ref()
+            .try_into()
+            .expect("should never fail given pubkey with correct length; qed;");
+
+        if legacy_receiver {
+            if !self.retired_master_keys.is_empty() {
+                warn!(
+                    "Gatekeeper: {} can't receive the retired master keys with pRuntime v{}",
+                    hex::encode(pubkey),
+                    receiver_runtime_version
+                );
+            }
+            let mut data = self.master_key.dump_secret_key().to_vec();
+            aead::encrypt(&iv, &secret, &mut data).expect("Failed to encrypt master key");
+            self.egress
+                .push_message(KeyDistribution::master_key_distribution(
+                    *pubkey,
+                    my_ecdh_pubkey,
+                    data,
+                    iv,
+                ));
+        } else if self.retired_master_keys.is_empty() {
+            let mut data = self.master_key.dump_secret_key().to_vec();
+            aead::encrypt(&iv, &secret, &mut data).expect("Failed to encrypt master key");
+            self.egress
+                .push_message(KeyDistribution::master_key_distribution_with_scheme(
+                    scheme.to_u32(),
+                    *pubkey,
+                    my_ecdh_pubkey,
+                    data,
+                    iv,
+                ));
+        } else {
+            let history = SharedMasterKeyHistory {
+                secret: self.master_key.dump_secret_key(),
+                rotation_id,
+                retired: self
+                    .retired_master_keys
+                    .iter()
+                    .map(|(retired_at, key)| (*retired_at, key.dump_secret_key()))
+                    .collect(),
+            };
+            let mut data = history.encode();
+            aead::encrypt(&iv, &secret, &mut data).expect("Failed to encrypt master key");
+            self.egress
+                .push_message(KeyDistribution::master_key_history_distribution(
+                    scheme.to_u32(),
+                    *pubkey,
+                    my_ecdh_pubkey,
+                    data,
+                    iv,
+                ));
+        }
     }
 
     pub fn process_messages(&mut self, block: &BlockInfo<'_>) {
@@ -199,6 +503,8 @@ where
             return;
         }
 
+        self.activate_scheduled_tokenomic_params(block.block_number);
+
         let sum_share: FixedPoint = self
             .workers
             .values()
@@ -222,24 +528,118 @@ where
         }
     }
 
-    pub fn emit_random_number(&mut self, block_number: chain::BlockNumber) {
+    /// Switch to the scheduled tokenomic parameters if they take effect at the block
+    ///
+    /// It happens before processing any message of the block, so that all the gatekeepers switch
+    /// at exactly the same point.
+    fn activate_scheduled_tokenomic_params(&mut self, block_number: chain::BlockNumber) {
+        match self.scheduled_tokenomic_params.take() {
+            Some((activation_block, params)) if activation_block <= block_number => {
+                info!(
+                    "Tokenomic parameters scheduled at block {} activated at block {}: {:#?}",
+                    activation_block, block_number, &params
+                );
+                self.tokenomic_params = params;
+            }
+            scheduled => self.scheduled_tokenomic_params = scheduled,
+        }
+    }
+
+    /// The random number of the block computed with the full master key, along with its VRF proof
+    /// if the VRF beacon is enabled
+    fn random_number_at(
+        &self,
+        block_number: chain::BlockNumber,
+        last_random_number: RandomNumber,
+    ) -> (RandomNumber, Option<RandomNumberProof>) {
+        // the blocks before a rotation are replayed with the retired master key
+        let master_key = self.master_key_at(block_number);
+        match self.vrf_beacon_since {
+            Some(since) if block_number >= since => {
+                let (random_number, proof) =
+                    next_vrf_random_number(master_key, block_number, last_random_number);
+                (random_number, Some(proof))
+            }
+            _ => {
+                let random_number = next_random_number(
+                    master_key,
+                    &self.kdf_salt,
+                    block_number,
+                    last_random_number,
+                );
+                (random_number, None)
+            }
+        }
+    }
+
+    /// Emit the random number of the block if it's time to
+    ///
+    /// Returns the partial evaluation to be sent by this worker if the threshold master key is in
+    /// use. The random number is emitted once enough partial evaluations are received.
+    pub fn emit_random_number(
+        &mut self,
+        block_number: chain::BlockNumber,
+    ) -> Option<ThresholdEvent> {
         if block_number % VRF_INTERVAL != 0 {
-            return;
+            return None;
         }
 
-        let random_number =
-            next_random_number(&self.master_key, block_number, self.last_random_number);
+        if let Some(master_key_share) = self.master_key_share_at(block_number) {
+            let input = random_number_input(block_number, &self.last_random_number);
+            let partial = master_key_share
+                .share
+                .evaluate(&input)
+                .expect("the share is verified when received; qed.");
+            self.random_rounds.insert(
+                block_number,
+                RandomRound {
+                    last_random_number: self.last_random_number,
+                    partials: Vec::new(),
+                },
+            );
+            while self.random_rounds.len() > MAX_PENDING_RANDOM_ROUNDS {
+                let oldest = *self
+                    .random_rounds
+                    .keys()
+                    .next()
+                    .expect("rounds can not be empty; qed.");
+                warn!(
+                    "Gatekeeper: drop the random number round of block {}",
+                    oldest
+                );
+                self.random_rounds.remove(&oldest);
+            }
+            return Some(ThresholdEvent::PartialRandomNumber(
+                PartialRandomNumberEvent {
+                    block_number,
+                    last_random_number: self.last_random_number,
+                    partial: partial.encode(),
+                },
+            ));
+        }
+
+        let (random_number, proof) = self.random_number_at(block_number, self.last_random_number);
         info!(
             "Gatekeeper: emit random number {} in block {}",
             hex::encode(&random_number),
             block_number
         );
-        self.egress.push_message(GatekeeperEvent::new_random_number(
-            block_number,
-            random_number,
-            self.last_random_number,
-        ));
+        let event = match proof {
+            Some(proof) => GatekeeperEvent::new_vrf_random_number(
+                block_number,
+                random_number,
+                self.last_random_number,
+                proof,
+            ),
+            None => GatekeeperEvent::new_random_number(
+                block_number,
+                random_number,
+                self.last_random_number,
+            ),
+        };
+        self.egress.push_message(event);
         self.last_random_number = random_number;
+        None
     }
 
     pub fn worker_state(&self, pubkey: &WorkerPublicKey) -> Option<pb::WorkerState> {
@@ -273,6 +673,50 @@ where
             },
         })
     }
+
+    pub fn worker_tokenomic_events(
+        &self,
+        pubkey: &WorkerPublicKey,
+    ) -> Option<pb::WorkerTokenomicEvents> {
+        let info = self.workers.get(pubkey)?;
+        Some(pb::WorkerTokenomicEvents {
+            events: info.tokenomic_events.iter().map(Into::into).collect(),
+        })
+    }
+
+    /// Export the state every gatekeeper must agree on, i.e. the workers and the tokenomic
+    /// parameters which the `MiningInfoUpdateEvent`s are computed from.
+    ///
+    /// The digest is always filled. The per-worker entries are only filled when `dump` is set.
+    pub fn export_state(&self, dump: bool) -> pb::GatekeeperState {
+        let digest = hashing::blake2_256(&(&self.workers, &self.tokenomic_params).encode());
+        let workers = if dump {
+            self.workers
+                .iter()
+                .map(|(pubkey, info)| {
+                    let encoded_info = info.encode();
+                    pb::GatekeeperWorkerState {
+                        public_key: pubkey.to_vec(),
+                        digest: hashing::blake2_256(&encoded_info).to_vec(),
+                        state: self.worker_state(pubkey),
+                        // Unlike in `worker_state`, the tokenomic info is exported even if the
+                        // worker is not mining, because it is kept across the mining sessions.
+                        tokenomic_info: Some(info.tokenomic.into()),
+                        encoded_info,
+                    }
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        pb::GatekeeperState {
+            digest: digest.to_vec(),
+            tokenomic_params_digest: hashing::blake2_256(&self.tokenomic_params.encode()).to_vec(),
+            num_workers: self.workers.len() as _,
+            workers,
+            ..Default::default()
+        }
+    }
 }
 
 struct GKMessageProcesser<'a, MsgChan> {
@@ -317,6 +761,14 @@ where
                         error!("Read message failed: {:?}", e);
                     }
                 },
+                message = self.state.threshold_events => match message {
+                    Ok((_, event, origin)) => {
+                        self.process_threshold_event(origin, event);
+                    }
+                    Err(e) => {
+                        error!("Read message failed: {:?}", e);
+                    }
+                },
             };
             if ok.is_none() {
                 // All messages processed
@@ -367,6 +819,13 @@ where
                     worker_info.last_gk_responsive_event =
                         pb::ResponsiveEvent::ExitUnresponsive as _;
                     worker_info.last_gk_responsive_event_at_block = self.block.block_number;
+                    let v = worker_info.tokenomic.v;
+                    worker_info.record_tokenomic_event(
+                        tokenomic::EventKind::ExitUnresponsive,
+                        self.block.block_number,
+                        v,
+                        fp!(0),
+                    );
                 }
             } else if let Some(&hb_sent_at) = worker_info.waiting_heartbeats.get(0) {
                 if self.block.block_number - hb_sent_at
@@ -383,6 +842,13 @@ where
                     worker_info.last_gk_responsive_event =
                         pb::ResponsiveEvent::EnterUnresponsive as _;
                     worker_info.last_gk_responsive_event_at_block = self.block.block_number;
+                    let v = worker_info.tokenomic.v;
+                    worker_info.record_tokenomic_event(
+                        tokenomic::EventKind::EnterUnresponsive,
+                        self.block.block_number,
+                        v,
+                        fp!(0),
+                    );
                 }
             }
 
@@ -392,7 +858,15 @@ where
                     "[{}] case3/case4: Idle, heartbeat failed or Unresponsive, no event",
                     hex::encode(&worker_info.state.pubkey)
                 );
+                let v_before = worker_info.tokenomic.v;
                 worker_info.tokenomic.update_v_slash(params, self.block.block_number);
+                let slash = worker_info.tokenomic.last_slash;
+                worker_info.record_tokenomic_event(
+                    tokenomic::EventKind::Slash,
+                    self.block.block_number,
+                    v_before,
+                    slash,
+                );
             } else if !worker_info.heartbeat_flag {
                 debug!(
                     "[{}] case1: Idle, no event",
@@ -466,6 +940,13 @@ where
                 tokenomic.update_p_instant(self.block.now_ms, iterations);
                 tokenomic.challenge_time_last = challenge_time;
                 tokenomic.iteration_last = iterations;
+                let v = tokenomic.v;
+                worker_info.record_tokenomic_event(
+                    tokenomic::EventKind::HeartbeatAccepted,
+                    self.block.block_number,
+                    v,
+                    fp!(0),
+                );
 
                 if worker_info.unresponsive {
                     debug!(
@@ -474,12 +955,19 @@ where
                     );
                 } else {
                     debug!("[{}] heartbeat handling case2: Idle, successful heartbeat, report to pallet", hex::encode(&worker_info.state.pubkey));
+                    let v_before = worker_info.tokenomic.v;
                     let (payout, treasury) = worker_info.tokenomic.update_v_heartbeat(
                         &self.state.tokenomic_params,
                         self.sum_share,
                         self.block.now_ms,
                         self.block.block_number,
                     );
+                    worker_info.record_tokenomic_event(
+                        tokenomic::EventKind::Payout,
+                        self.block.block_number,
+                        v_before,
+                        payout,
+                    );
 
                     // NOTE: keep the reporting order (vs the one while mining stop).
                     self.report.settle.push(SettleInfo {
@@ -587,6 +1075,24 @@ where
                         }
                         WorkerEvent::MiningEnterUnresponsive => {}
                         WorkerEvent::MiningExitUnresponsive => {}
+                        WorkerEvent::AttestationInvalidated => {
+                            // The worker stops responding from now on. Report it offline right away
+                            // rather than waiting for the pending heartbeats to time out.
+                            if worker.state.mining_state.is_some() && !worker.unresponsive {
+                                self.report.offline.push(worker.state.pubkey);
+                                worker.unresponsive = true;
+                                worker.last_gk_responsive_event =
+                                    pb::ResponsiveEvent::EnterUnresponsive as _;
+                                worker.last_gk_responsive_event_at_block = self.block.block_number;
+                                let v = worker.tokenomic.v;
+                                worker.record_tokenomic_event(
+                                    tokenomic::EventKind::EnterUnresponsive,
+                                    self.block.block_number,
+                                    v,
+                                    fp!(0),
+                                );
+                            }
+                        }
                     }
                 }
             }
@@ -598,7 +1104,10 @@ where
         info!("Incoming gatekeeper event: {:?}", event);
         match event {
             GatekeeperEvent::NewRandomNumber(random_number_event) => {
-                self.process_random_number_event(origin, random_number_event)
+                self.process_random_number_event(origin, random_number_event, None)
+            }
+            GatekeeperEvent::NewVrfRandomNumber { event, proof } => {
+                self.process_random_number_event(origin, event, Some(proof))
             }
             GatekeeperEvent::TokenomicParametersChanged(params) => {
                 if origin.is_pallet() {
@@ -609,23 +1118,142 @@ where
                     );
                 }
             }
+            GatekeeperEvent::TokenomicParametersScheduled {
+                activation_block,
+                params,
+            } => {
+                if origin.is_pallet() {
+                    info!(
+                        "Tokenomic parameters scheduled at block {}: {:#?}",
+                        activation_block, &params
+                    );
+                    self.state.scheduled_tokenomic_params = Some((activation_block, params.into()));
+                }
+            }
+            GatekeeperEvent::VrfBeaconEnabled => {
+                if origin.is_pallet() && self.state.vrf_beacon_since.is_none() {
+                    info!(
+                        "Gatekeeper: VRF beacon enabled since block {}",
+                        self.block.block_number
+                    );
+                    self.state.vrf_beacon_since = Some(self.block.block_number);
+                }
+            }
+        }
+    }
+
+    fn process_threshold_event(&mut self, origin: MessageOrigin, event: ThresholdEvent) {
+        match event {
+            ThresholdEvent::PartialRandomNumber(partial_random_number_event) => {
+                self.process_partial_random_number_event(origin, partial_random_number_event)
+            }
+        }
+    }
+
+    /// Collect the partial evaluations, and emit the random number once there are enough
+    fn process_partial_random_number_event(
+        &mut self,
+        origin: MessageOrigin,
+        event: PartialRandomNumberEvent,
+    ) {
+        // borrow the field only, to keep the rounds mutable
+        let master_key_share = match &self.state.master_key_share {
+            Some((activated_at, master_key_share)) if event.block_number >= *activated_at => {
+                master_key_share
+            }
+            _ => return,
+        };
+        let holder = match &origin {
+            MessageOrigin::Worker(pubkey) => master_key_share
+                .share_holders
+                .iter()
+                .find(|holder| holder.pubkey == *pubkey),
+            _ => None,
+        };
+        let holder = match holder {
+            Some(holder) => holder,
+            None => {
+                error!("Invalid origin {:?} sent a {:?}", origin, event);
+                return;
+            }
+        };
+        let partial = match PartialEvaluation::decode(&mut &event.partial[..]) {
+            Ok(partial) if partial.index == holder.index => partial,
+            _ => {
+                error!("Invalid partial random number from {:?}", origin);
+                return;
+            }
+        };
+
+        let round = match self.state.random_rounds.get_mut(&event.block_number) {
+            Some(round) if round.last_random_number == event.last_random_number => round,
+            // finished, dropped or mismatched
+            _ => return,
+        };
+        if round.partials.iter().any(|p| p.index == partial.index) {
+            return;
+        }
+        let input = random_number_input(event.block_number, &event.last_random_number);
+        if let Err(err) = threshold::verify_partial(&holder.share_pubkey, &input, &partial) {
+            error!("Invalid partial random number from {:?}: {:?}", origin, err);
+            return;
+        }
+        round.partials.push(partial);
+        if (round.partials.len() as u32) < master_key_share.threshold {
+            return;
         }
+
+        let point =
+            threshold::combine(&round.partials).expect("partial evaluations are verified; qed.");
+        let random_number = hashing::blake2_256(&point);
+        info!(
+            "Gatekeeper: emit random number {} in block {}",
+            hex::encode(&random_number),
+            event.block_number
+        );
+        self.state.random_rounds.remove(&event.block_number);
+        self.state
+            .combined_random_numbers
+            .insert(event.block_number, random_number);
+        self.state
+            .egress
+            .push_message(GatekeeperEvent::new_random_number(
+                event.block_number,
+                random_number,
+                event.last_random_number,
+            ));
+        self.state.last_random_number = random_number;
     }
 
     /// Verify on-chain random number
-    fn process_random_number_event(&mut self, origin: MessageOrigin, event: RandomNumberEvent) {
+    fn process_random_number_event(
+        &mut self,
+        origin: MessageOrigin,
+        event: RandomNumberEvent,
+        proof: Option<RandomNumberProof>,
+    ) {
         if !origin.is_gatekeeper() {
             error!("Invalid origin {:?} sent a {:?}", origin, event);
             return;
         };
 
-        let expect_random = next_random_number(
-            &self.state.master_key,
-            event.block_number,
-            event.last_random_number,
-        );
+        if self.state.master_key_share_at(event.block_number).is_some() {
+            let expect_random = self
+                .state
+                .combined_random_numbers
+                .remove(&event.block_number);
+            if expect_random != Some(event.random_number) {
+                error!("Fatal error: Expect random number {:?}", expect_random);
+                panic!("GK state poisoned");
+            }
+            return;
+        }
+
+        let expect_random = self
+            .state
+            .random_number_at(event.block_number, event.last_random_number);
         // instead of checking the origin, we directly verify the random to avoid access storage
-        if expect_random != event.random_number {
+        if expect_random != (event.random_number, proof) {
             error!("Fatal error: Expect random number {:?}", expect_random);
             panic!("GK state poisoned");
         }
@@ -653,8 +1281,29 @@ mod tokenomic {
     pub use fixed::types::U64F64 as FixedPoint;
     use fixed_macro::types::U64F64 as fp;
     use fixed_sqrt::FixedSqrt as _;
+    use parity_scale_codec::{Decode, Encode, EncodeAsRef};
     use phala_types::messaging::TokenomicParameters;
 
+    /// SCALE codec helper to encode a `FixedPoint` as its raw bits
+    #[derive(Encode, Decode)]
+    pub struct FixedBits(u128);
+
+    impl From<&FixedPoint> for FixedBits {
+        fn from(v: &FixedPoint) -> Self {
+            FixedBits(v.to_bits())
+        }
+    }
+
+    impl From<FixedBits> for FixedPoint {
+        fn from(v: FixedBits) -> Self {
+            FixedPoint::from_bits(v.0)
+        }
+    }
+
+    impl EncodeAsRef<'_, FixedPoint> for FixedBits {
+        type RefType = FixedBits;
+    }
+
     fn square(v: FixedPoint) -> FixedPoint {
         v * v
     }
@@ -668,25 +1317,34 @@ mod tokenomic {
         }
     }
 
-    #[derive(Default, Clone, Copy)]
+    #[derive(Encode, Decode, Default, Clone, Copy)]
     pub struct TokenomicInfo {
+        #[codec(encoded_as = "FixedBits")]
         pub v: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
         pub v_init: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
         pub payable: FixedPoint,
         pub v_update_at: u64,
         pub v_update_block: u32,
         pub iteration_last: u64,
         pub challenge_time_last: u64,
+        #[codec(encoded_as = "FixedBits")]
         pub p_bench: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
         pub p_instant: FixedPoint,
         pub confidence_level: u8,
 
+        #[codec(encoded_as = "FixedBits")]
         pub last_payout: FixedPoint,
         pub last_payout_at_block: chain::BlockNumber,
+        #[codec(encoded_as = "FixedBits")]
         pub total_payout: FixedPoint,
         pub total_payout_count: chain::BlockNumber,
+        #[codec(encoded_as = "FixedBits")]
         pub last_slash: FixedPoint,
         pub last_slash_at_block: chain::BlockNumber,
+        #[codec(encoded_as = "FixedBits")]
         pub total_slash: FixedPoint,
         pub total_slash_count: chain::BlockNumber,
     }
@@ -716,15 +1374,72 @@ mod tokenomic {
         }
     }
 
-    #[derive(Debug)]
+    /// The max number of tokenomic events kept for each worker
+    pub const MAX_EVENTS: usize = 32;
+
+    #[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum EventKind {
+        HeartbeatAccepted,
+        Payout,
+        Slash,
+        EnterUnresponsive,
+        ExitUnresponsive,
+    }
+
+    /// A change of the tokenomic state of a worker, kept to explain its payouts and slashes
+    #[derive(Encode, Decode, Debug, Clone)]
+    pub struct Event {
+        pub kind: EventKind,
+        pub block_number: chain::BlockNumber,
+        /// The last block of the consecutive slashes merged into this event
+        pub last_block_number: chain::BlockNumber,
+        #[codec(encoded_as = "FixedBits")]
+        pub v_before: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
+        pub v_after: FixedPoint,
+        /// The amount paid out or slashed
+        #[codec(encoded_as = "FixedBits")]
+        pub amount: FixedPoint,
+    }
+
+    impl From<&Event> for super::pb::TokenomicEvent {
+        fn from(event: &Event) -> Self {
+            use super::pb::TokenomicEventKind as Kind;
+            let kind = match event.kind {
+                EventKind::HeartbeatAccepted => Kind::HeartbeatAccepted,
+                EventKind::Payout => Kind::Payout,
+                EventKind::Slash => Kind::Slash,
+                EventKind::EnterUnresponsive => Kind::EnterUnresponsive,
+                EventKind::ExitUnresponsive => Kind::ExitUnresponsive,
+            };
+            Self {
+                kind: kind as _,
+                block_number: event.block_number,
+                last_block_number: event.last_block_number,
+                v_before: event.v_before.to_string(),
+                v_after: event.v_after.to_string(),
+                amount: event.amount.to_string(),
+            }
+        }
+    }
+
+    #[derive(Encode, Decode, Debug, Clone)]
     pub struct Params {
+        #[codec(encoded_as = "FixedBits")]
         rho: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
         slash_rate: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
         budget_per_block: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
         v_max: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
         cost_k: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
         cost_b: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
         treasury_ration: FixedPoint,
+        #[codec(encoded_as = "FixedBits")]
         payout_ration: FixedPoint,
         pub heartbeat_window: u32,
     }
@@ -853,6 +1568,171 @@ mod tokenomic {
     }
 }
 
+/// Offline replay of the tokenomic computation, for economic analysis
+pub mod replay {
+    use super::{msg_trait::MessageChannel, FixedPoint, Gatekeeper, MiningInfoUpdateEvent};
+    use crate::{side_task::SideTaskManager, types::BlockInfo, Storage};
+    use parity_scale_codec::{Decode, Encode};
+    use phala_mq::{BindTopic, Message, MessageDispatcher};
+    use phala_types::messaging::{
+        GatekeeperEvent, MiningReportEvent, SystemEvent, TokenomicParameters,
+    };
+    use serde::Serialize;
+    use sp_core::crypto::Pair;
+    use std::cell::RefCell;
+
+    /// The egress of the replayed gatekeeper, collecting the `MiningInfoUpdateEvent`s
+    #[derive(Default)]
+    struct ReportCollector {
+        reports: RefCell<Vec<MiningInfoUpdateEvent<chain::BlockNumber>>>,
+    }
+
+    impl MessageChannel for ReportCollector {
+        fn push_message<M: Encode + BindTopic>(&self, message: M) {
+            if M::topic() == MiningInfoUpdateEvent::<chain::BlockNumber>::topic() {
+                let report = Decode::decode(&mut &message.encode()[..])
+                    .expect("the topic is bound to the type; qed.");
+                self.reports.borrow_mut().push(report);
+            }
+        }
+
+        fn set_dummy(&self, _dummy: bool) {}
+    }
+
+    /// The tokenomic state of a mining worker after a block
+    #[derive(Serialize, Debug, Clone)]
+    pub struct WorkerSample {
+        pub block_number: chain::BlockNumber,
+        pub pubkey: String,
+        pub paused: bool,
+        pub unresponsive: bool,
+        pub v: f64,
+        pub p_instant: f64,
+        /// The payout settled in this block
+        pub payout: f64,
+        /// The slash applied in this block
+        pub slash: f64,
+        pub total_payout: f64,
+        pub total_slash: f64,
+    }
+
+    /// Replays the recorded `SystemEvent`s and `MiningReportEvent`s through a real `Gatekeeper`
+    pub struct GatekeeperReplay {
+        gk: Gatekeeper<ReportCollector>,
+        recv_mq: MessageDispatcher,
+        storage: Storage,
+        side_task_man: SideTaskManager,
+    }
+
+    impl GatekeeperReplay {
+        /// Create a replay starting with the given tokenomic parameters, or the default ones of
+        /// the gatekeeper if `None`.
+        ///
+        /// The parameters are still updated by the `TokenomicParametersChanged` and
+        /// `TokenomicParametersScheduled` events replayed.
+        pub fn new(params: Option<TokenomicParameters>) -> Self {
+            let mut recv_mq = MessageDispatcher::new();
+            // The master key only affects the random numbers, which are not replayed
+            let master_key = sp_core::sr25519::Pair::from_seed(&[0; 32]);
+            let mut gk = Gatekeeper::new(
+                master_key,
+                vec![],
+                None,
+                Default::default(),
+                &mut recv_mq,
+                ReportCollector::default(),
+            );
+            gk.master_pubkey_on_chain = true;
+            if let Some(params) = params {
+                gk.tokenomic_params = params.into();
+            }
+            Self {
+                gk,
+                recv_mq,
+                storage: Default::default(),
+                side_task_man: Default::default(),
+            }
+        }
+
+        /// Queue a recorded message for the next block.
+        ///
+        /// Returns false if the message is ignored. Only the system events, the mining reports
+        /// and the tokenomic parameter changes are replayed. The random numbers are signed by the
+        /// master key of the recording chain and would poison the replayed gatekeeper.
+        pub fn dispatch(&mut self, message: Message) -> bool {
+            let path = message.destination.path();
+            let replayable = if path == &SystemEvent::topic() || path == &MiningReportEvent::topic()
+            {
+                true
+            } else if path == &GatekeeperEvent::topic() {
+                matches!(
+                    GatekeeperEvent::decode(&mut &message.payload[..]),
+                    Ok(GatekeeperEvent::TokenomicParametersChanged(_))
+                        | Ok(GatekeeperEvent::TokenomicParametersScheduled { .. })
+                )
+            } else {
+                false
+            };
+            if replayable {
+                let _ = self.recv_mq.dispatch(message);
+            }
+            replayable
+        }
+
+        /// Process the messages queued for the block.
+        ///
+        /// Returns the `MiningInfoUpdateEvent`s the gatekeeper would have sent.
+        pub fn process_block(
+            &mut self,
+            block_number: chain::BlockNumber,
+            now_ms: u64,
+        ) -> Vec<MiningInfoUpdateEvent<chain::BlockNumber>> {
+            let block = BlockInfo {
+                block_number,
+                now_ms,
+                storage: &self.storage,
+                recv_mq: &mut self.recv_mq,
+                side_task_man: &mut self.side_task_man,
+            };
+            self.gk.process_messages(&block);
+            self.gk.egress.reports.borrow_mut().drain(..).collect()
+        }
+
+        /// The tokenomic state of the mining workers after the given block
+        pub fn samples(&self, block_number: chain::BlockNumber) -> Vec<WorkerSample> {
+            let to_f64 = |v: FixedPoint| v.to_num::<f64>();
+            self.gk
+                .workers
+                .iter()
+                .filter_map(|(pubkey, info)| {
+                    let mining = info.state.mining_state.as_ref()?;
+                    let tokenomic = &info.tokenomic;
+                    Some(WorkerSample {
+                        block_number,
+                        pubkey: hex::encode(pubkey),
+                        paused: matches!(mining.state, super::super::MiningState::Paused),
+                        unresponsive: info.unresponsive,
+                        v: to_f64(tokenomic.v),
+                        p_instant: to_f64(tokenomic.p_instant),
+                        payout: if tokenomic.last_payout_at_block == block_number {
+                            to_f64(tokenomic.last_payout)
+                        } else {
+                            0.0
+                        },
+                        slash: if tokenomic.last_slash_at_block == block_number {
+                            to_f64(tokenomic.last_slash)
+                        } else {
+                            0.0
+                        },
+                        total_payout: to_f64(tokenomic.total_payout),
+                        total_slash: to_f64(tokenomic.total_slash),
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
 mod msg_trait {
     use parity_scale_codec::Encode;
     use phala_mq::{BindTopic, MessageSigner};
@@ -955,12 +1835,16 @@ pub mod tests {
 
     impl Roles {
         fn test_roles() -> Roles {
+            Self::on_network(Default::default())
+        }
+
+        fn on_network(genesis_block_hash: sp_core::H256) -> Roles {
             use sp_core::crypto::Pair;
 
             let mut mq = MessageDispatcher::new();
             let egress = CollectChannel::default();
             let key = sp_core::sr25519::Pair::from_seed(&[1u8; 32]);
-            let mut gk = Gatekeeper::new(key, &mut mq, egress);
+            let mut gk = Gatekeeper::new(key, vec![], None, genesis_block_hash, &mut mq, egress);
             gk.master_pubkey_on_chain = true;
             Roles {
                 mq,
@@ -1517,6 +2401,137 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn gk_should_report_workers_with_invalidated_attestation_offline() {
+        let mut r = Roles::test_roles();
+        let mut block_number = 1;
+
+        // Register worker
+        with_block(block_number, |block| {
+            let mut worker0 = r.for_worker(0);
+            worker0.pallet_say(msg::WorkerEvent::Registered(msg::WorkerInfo {
+                confidence_level: 2,
+            }));
+            r.gk.process_messages(block);
+        });
+
+        // Start mining
+        block_number += 1;
+        with_block(block_number, |block| {
+            let mut worker0 = r.for_worker(0);
+            worker0.pallet_say(msg::WorkerEvent::MiningStart {
+                session_id: 1,
+                init_v: fp!(1).to_bits(),
+                init_p: 100,
+            });
+            r.gk.process_messages(block);
+        });
+        r.gk.egress.clear();
+
+        // The attestation expired, report it offline without waiting for the heartbeat window
+        block_number += 1;
+        with_block(block_number, |block| {
+            let mut worker0 = r.for_worker(0);
+            worker0.pallet_say(msg::WorkerEvent::AttestationInvalidated);
+            r.gk.process_messages(block);
+        });
+        assert!(r.get_worker(0).unresponsive);
+        assert!(!r.get_worker(0).state.registered);
+        {
+            let messages = r.gk.egress.drain_mining_info_update_event();
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].offline, vec![r.workers[0].clone()]);
+        }
+
+        // The worker no longer responds to the challenges
+        r.gk.egress.clear();
+        block_number += 1;
+        with_block(block_number, |block| {
+            let mut worker0 = r.for_worker(0);
+            worker0.challenge();
+            r.gk.process_messages(block);
+        });
+        assert!(r.get_worker(0).waiting_heartbeats.is_empty());
+        assert_eq!(
+            r.gk.egress.drain_mining_info_update_event().len(),
+            0,
+            "Should not report offline workers again"
+        );
+    }
+
+    #[test]
+    fn gk_should_record_tokenomic_events() {
+        use super::tokenomic::EventKind;
+
+        let mut r = Roles::test_roles();
+        let mut block_number = 1;
+
+        // Register worker
+        with_block(block_number, |block| {
+            let mut worker0 = r.for_worker(0);
+            worker0.pallet_say(msg::WorkerEvent::Registered(msg::WorkerInfo {
+                confidence_level: 2,
+            }));
+            r.gk.process_messages(block);
+        });
+
+        // Start mining & send heartbeat challenge
+        block_number += 1;
+        with_block(block_number, |block| {
+            let mut worker0 = r.for_worker(0);
+            worker0.pallet_say(msg::WorkerEvent::MiningStart {
+                session_id: 1,
+                init_v: fp!(1).to_bits(),
+                init_p: 100,
+            });
+            worker0.challenge();
+            r.gk.process_messages(block);
+        });
+        let challenge_block = block_number;
+        assert!(r.get_worker(0).tokenomic_events.is_empty());
+
+        // Heartbeat timed out, and slashed in two blocks
+        block_number += r.gk.tokenomic_params.heartbeat_window + 1;
+        let timeout_block = block_number;
+        with_block(block_number, |block| {
+            r.gk.process_messages(block);
+        });
+        block_number += 1;
+        with_block(block_number, |block| {
+            r.gk.process_messages(block);
+        });
+
+        // Recovered
+        let v_snap = r.get_worker(0).tokenomic.v;
+        block_number += 1;
+        with_block(block_number, |block| {
+            r.for_worker(0).heartbeat(1, challenge_block, 10000000);
+            r.gk.process_messages(block);
+        });
+
+        let events = &r.get_worker(0).tokenomic_events;
+        let kinds: Vec<_> = events
+            .iter()
+            .map(|e| (e.kind, e.block_number, e.last_block_number))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (EventKind::EnterUnresponsive, timeout_block, timeout_block),
+                (EventKind::Slash, timeout_block, timeout_block + 1),
+                (EventKind::HeartbeatAccepted, block_number, block_number),
+                (EventKind::ExitUnresponsive, block_number, block_number),
+            ]
+        );
+        let slash = &events[1];
+        assert_eq!(slash.v_after, v_snap);
+        assert_eq!(slash.v_before - slash.v_after, slash.amount);
+        assert_eq!(slash.amount, r.get_worker(0).tokenomic.total_slash);
+
+        let state = r.gk.worker_tokenomic_events(&r.workers[0]).unwrap();
+        assert_eq!(state.events.len(), 4);
+    }
+
     #[test]
     fn check_tokenomic_numerics() {
         let mut r = Roles::test_roles();
@@ -1675,4 +2690,463 @@ pub mod tests {
         info.update_p_instant(200_000, 999);
         assert_eq!(info.p_instant, fp!(0));
     }
+
+    #[test]
+    fn gk_snapshot_restore() {
+        let mut r = Roles::test_roles();
+
+        with_block(1, |block| {
+            let mut worker0 = r.for_worker(0);
+            worker0.pallet_say(msg::WorkerEvent::Registered(msg::WorkerInfo {
+                confidence_level: 2,
+            }));
+            worker0.pallet_say(msg::WorkerEvent::MiningStart {
+                session_id: 1,
+                init_v: fp!(30000).to_bits(),
+                init_p: 3000,
+            });
+            r.gk.process_messages(block);
+        });
+        with_block(2, |block| {
+            r.gk.process_messages(block);
+        });
+
+        let encoded = r.gk.snapshot().encode();
+        let snapshot = super::GatekeeperSnapshot::decode(&mut &encoded[..]).unwrap();
+
+        let mut restored = Roles::test_roles();
+        restored.gk.master_pubkey_on_chain = false;
+        restored.gk.restore(snapshot);
+
+        assert!(restored.gk.master_pubkey_on_chain);
+        assert_eq!(restored.gk.workers.len(), 1);
+        let (worker, orig) = (restored.get_worker(0), r.get_worker(0));
+        assert!(worker.state.registered);
+        assert!(worker.state.mining_state.is_some());
+        assert_eq!(worker.tokenomic.v, orig.tokenomic.v);
+        assert_eq!(worker.tokenomic.v_init, orig.tokenomic.v_init);
+        assert_eq!(worker.tokenomic.p_bench, orig.tokenomic.p_bench);
+    }
+
+    #[test]
+    fn gk_should_switch_tokenomic_params_at_activation_block() {
+        let mut r = Roles::test_roles();
+        let pallet = MessageOrigin::Pallet(b"Pallet".to_vec());
+        let params = msg::TokenomicParameters {
+            pha_rate: 0,
+            rho: 0,
+            budget_per_block: 0,
+            v_max: 0,
+            cost_k: 0,
+            cost_b: 0,
+            slash_rate: 0,
+            treasury_ratio: 0,
+            heartbeat_window: 20,
+            rig_k: 0,
+            rig_b: 0,
+            re: 0,
+            k: 0,
+            kappa: 0,
+        };
+
+        with_block(1, |block| {
+            r.mq.dispatch_bound(
+                &pallet,
+                msg::GatekeeperEvent::TokenomicParametersScheduled {
+                    activation_block: 3,
+                    params,
+                },
+            );
+            r.gk.process_messages(block);
+        });
+        assert_eq!(r.gk.tokenomic_params.heartbeat_window, 10);
+
+        with_block(2, |block| {
+            r.gk.process_messages(block);
+        });
+        assert_eq!(r.gk.tokenomic_params.heartbeat_window, 10);
+
+        with_block(3, |block| {
+            r.gk.process_messages(block);
+        });
+        assert_eq!(r.gk.tokenomic_params.heartbeat_window, 20);
+        assert!(r.gk.scheduled_tokenomic_params.is_none());
+    }
+
+    #[test]
+    fn gk_should_agree_with_peers_of_another_runtime_data_salt() {
+        use phala_crypto::sr25519::{kdf_salt_from_genesis, KDF_SALT};
+
+        // Not PoC-4, where the genesis salt is the legacy one
+        let genesis_block_hash = sp_core::H256::repeat_byte(7);
+        assert_ne!(kdf_salt_from_genesis(&genesis_block_hash.0), KDF_SALT);
+        // The runtime data of the first one is upgraded from V1 with the legacy salt, and the
+        // other one is provisioned with the genesis salt. Neither salt reaches the gatekeeper.
+        let mut upgraded = Roles::on_network(genesis_block_hash);
+        let mut provisioned = Roles::on_network(genesis_block_hash);
+
+        for &block_number in &[5, 10] {
+            upgraded.gk.emit_random_number(block_number);
+            provisioned.gk.emit_random_number(block_number);
+        }
+        assert_eq!(
+            upgraded.gk.last_random_number,
+            provisioned.gk.last_random_number
+        );
+        assert_eq!(upgraded.gk.generate_iv(10), provisioned.gk.generate_iv(10));
+
+        // Each one verifies the random numbers emitted by the other
+        let events = upgraded.gk.egress.drain_decode::<msg::GatekeeperEvent>();
+        assert_eq!(events.len(), 2);
+        with_block(11, |block| {
+            for event in events {
+                provisioned
+                    .mq
+                    .dispatch_bound(&MessageOrigin::Gatekeeper, event);
+            }
+            provisioned.gk.process_messages(block);
+        });
+    }
+
+    #[test]
+    fn gk_should_emit_vrf_random_numbers() {
+        use phala_crypto::vrf;
+        use sp_core::crypto::Pair;
+
+        let mut r = Roles::test_roles();
+        r.gk.emit_random_number(5);
+        let legacy = r.gk.egress.drain_decode::<msg::GatekeeperEvent>();
+        assert!(matches!(
+            &legacy[..],
+            [msg::GatekeeperEvent::NewRandomNumber(_)]
+        ));
+
+        with_block(6, |block| {
+            let pallet = MessageOrigin::Pallet(b"Pallet".to_vec());
+            r.mq.dispatch_bound(&pallet, msg::GatekeeperEvent::VrfBeaconEnabled);
+            r.gk.process_messages(block);
+        });
+        r.gk.emit_random_number(10);
+        let events = r.gk.egress.drain_decode::<msg::GatekeeperEvent>();
+        let (event, proof) = match &events[..] {
+            [msg::GatekeeperEvent::NewVrfRandomNumber { event, proof }] => {
+                (event.clone(), proof.clone())
+            }
+            _ => panic!("Expect a VRF random number, got {:?}", events),
+        };
+        let random_number = vrf::vrf_verify(
+            &r.gk.master_key.public().0,
+            msg::RANDOM_NUMBER_VRF_CONTEXT,
+            &msg::random_number_vrf_message(10, &event.last_random_number),
+            msg::RANDOM_NUMBER_VRF_LABEL,
+            &proof.vrf_output,
+            &proof.vrf_proof,
+        )
+        .expect("VRF proof should be valid");
+        assert_eq!(random_number, event.random_number);
+
+        // The random numbers on chain are verified by the gatekeeper
+        with_block(11, |block| {
+            for event in legacy.into_iter().chain(events) {
+                r.mq.dispatch_bound(&MessageOrigin::Gatekeeper, event);
+            }
+            r.gk.process_messages(block);
+        });
+    }
+
+    /// Splits a 2-of-2 threshold master key between the workers, returning the share of the first
+    /// one and all the shares
+    fn threshold_master_key(
+        r: &Roles,
+    ) -> (
+        super::MasterKeyShare,
+        Vec<phala_crypto::threshold::KeyShare>,
+    ) {
+        use phala_crypto::{sr25519::Persistence, threshold};
+        use sp_core::crypto::Pair;
+
+        let master_key = sp_core::sr25519::Pair::from_seed(&[2u8; 32]);
+        let mut secret = [0u8; 32];
+        secret.copy_from_slice(&master_key.dump_secret_key()[..32]);
+        let shares = threshold::split(&secret, 2, 2, b"seed").unwrap();
+        let share_holders: Vec<_> = r
+            .workers
+            .iter()
+            .zip(shares.iter())
+            .map(|(pubkey, share)| msg::MasterKeyShareHolder {
+                pubkey: *pubkey,
+                index: share.index,
+                share_pubkey: share.public().unwrap(),
+            })
+            .collect();
+        let master_key_share = super::MasterKeyShare {
+            master_pubkey: master_key.public(),
+            threshold: 2,
+            share: shares[0].clone(),
+            share_holders,
+        };
+        (master_key_share, shares)
+    }
+
+    #[test]
+    fn gk_should_combine_threshold_random_numbers() {
+        use super::random_number_input;
+        use phala_crypto::threshold;
+
+        let mut r = Roles::test_roles();
+        let (master_key_share, shares) = threshold_master_key(&r);
+        r.gk.enable_threshold_master_key(master_key_share, 5, CollectChannel::default());
+
+        let partial = match r.gk.emit_random_number(5) {
+            Some(msg::ThresholdEvent::PartialRandomNumber(partial)) => partial,
+            _ => panic!("Partial random number expected"),
+        };
+        let input = random_number_input(5, &partial.last_random_number);
+        let other_partial = msg::PartialRandomNumberEvent {
+            partial: shares[1].evaluate(&input).unwrap().encode(),
+            ..partial.clone()
+        };
+
+        // Not enough partial evaluations
+        with_block(6, |block| {
+            r.for_worker(0)
+                .say(msg::ThresholdEvent::PartialRandomNumber(partial.clone()));
+            r.gk.process_messages(block);
+        });
+        assert!(r
+            .gk
+            .egress
+            .drain_decode::<msg::GatekeeperEvent>()
+            .is_empty());
+
+        with_block(7, |block| {
+            r.for_worker(1)
+                .say(msg::ThresholdEvent::PartialRandomNumber(other_partial));
+            r.gk.process_messages(block);
+        });
+        let expected = sp_core::blake2_256(
+            &threshold::combine(&[
+                shares[0].evaluate(&input).unwrap(),
+                shares[1].evaluate(&input).unwrap(),
+            ])
+            .unwrap(),
+        );
+        let events = r.gk.egress.drain_decode::<msg::GatekeeperEvent>();
+        assert_eq!(
+            events,
+            vec![msg::GatekeeperEvent::new_random_number(
+                5,
+                expected,
+                partial.last_random_number
+            )]
+        );
+        assert_eq!(r.gk.last_random_number, expected);
+
+        // The random number on chain is verified against the combined one
+        with_block(8, |block| {
+            r.mq.dispatch_bound(&MessageOrigin::Gatekeeper, events[0].clone());
+            r.gk.process_messages(block);
+        });
+        assert!(r.gk.combined_random_numbers.is_empty());
+    }
+
+    #[test]
+    fn gk_should_not_share_master_key_with_threshold_master_key() {
+        use super::MASTER_KEY_SCHEME_RUNTIME_VERSION as VERSION;
+        use phala_crypto::sr25519::KDF;
+        use sp_core::crypto::Pair;
+
+        let mut r = Roles::test_roles();
+        let (master_key_share, _) = threshold_master_key(&r);
+        r.gk.enable_threshold_master_key(master_key_share, 10, CollectChannel::default());
+        let receiver = sp_core::sr25519::Pair::from_seed(&[9u8; 32]);
+        let ecdh_pubkey = phala_types::EcdhPublicKey(receiver.derive_ecdh_key().unwrap().public());
+
+        // Still shared when replaying the blocks before the activation
+        r.gk.share_master_key(&receiver.public(), &ecdh_pubkey, VERSION, 0, 9);
+        assert_eq!(r.gk.egress.drain_decode::<msg::KeyDistribution>().len(), 1);
+
+        r.gk.share_master_key(&receiver.public(), &ecdh_pubkey, VERSION, 1, 10);
+        assert!(r
+            .gk
+            .egress
+            .drain_decode::<msg::KeyDistribution>()
+            .is_empty());
+    }
+
+    #[test]
+    fn gk_should_export_consistent_state() {
+        let mut r = Roles::test_roles();
+        let mut other = Roles::test_roles();
+        assert_eq!(r.gk.export_state(false), other.gk.export_state(false));
+
+        let register_worker0 = |roles: &mut Roles| {
+            with_block(1, |block| {
+                let mut worker0 = roles.for_worker(0);
+                worker0.pallet_say(msg::WorkerEvent::Registered(msg::WorkerInfo {
+                    confidence_level: 2,
+                }));
+                roles.gk.process_messages(block);
+            });
+        };
+        register_worker0(&mut r);
+        register_worker0(&mut other);
+        let state = r.gk.export_state(false);
+        assert_eq!(state.num_workers, 1);
+        assert!(state.workers.is_empty());
+        assert_eq!(state, other.gk.export_state(false));
+
+        with_block(2, |block| {
+            r.for_worker(0).pallet_say(msg::WorkerEvent::MiningStart {
+                session_id: 1,
+                init_v: 1,
+                init_p: 100,
+            });
+            r.gk.process_messages(block);
+        });
+        let state = r.gk.export_state(true);
+        let other_state = other.gk.export_state(true);
+        assert_ne!(state.digest, other_state.digest);
+        assert_eq!(
+            state.tokenomic_params_digest,
+            other_state.tokenomic_params_digest
+        );
+        assert_eq!(state.workers.len(), 1);
+        assert_eq!(state.workers[0].public_key, r.workers[0].to_vec());
+        assert_ne!(state.workers[0].digest, other_state.workers[0].digest);
+    }
+
+    #[test]
+    fn gk_should_share_retired_master_keys() {
+        use super::{SharedMasterKeyHistory, MASTER_KEY_SCHEME_RUNTIME_VERSION as VERSION};
+        use phala_crypto::{
+            aead, ecdh,
+            sr25519::{Persistence, KDF},
+        };
+        use sp_core::crypto::Pair;
+
+        let mut r = Roles::test_roles();
+        let receiver = sp_core::sr25519::Pair::from_seed(&[9u8; 32]);
+        let receiver_ecdh = receiver.derive_ecdh_key().unwrap();
+        let ecdh_pubkey = phala_types::EcdhPublicKey(receiver_ecdh.public());
+
+        // Shared as before without rotations
+        r.gk.share_master_key(&receiver.public(), &ecdh_pubkey, VERSION, 0, 1);
+        let messages: Vec<msg::KeyDistribution> = r.gk.egress.drain_decode();
+        assert!(matches!(
+            messages[..],
+            [msg::KeyDistribution::MasterKeyDistributionWithScheme { .. }]
+        ));
+
+        let retired = r.gk.master_key.clone();
+        let new_key = sp_core::sr25519::Pair::from_seed(&[2u8; 32]);
+        r.gk.rotate_master_key(new_key.clone(), 10, CollectChannel::default());
+        r.gk.share_master_key(&receiver.public(), &ecdh_pubkey, VERSION, 1, 20);
+        let messages: Vec<msg::KeyDistribution> = r.gk.egress.drain_decode();
+        let (scheme, event) = match &messages[..] {
+            [msg::KeyDistribution::MasterKeyHistoryDistribution { scheme, event }] => {
+                (*scheme, event)
+            }
+            _ => panic!("Unexpected messages {:?}", messages),
+        };
+        assert_eq!(event.dest, receiver.public());
+        let secret = ecdh::agree_with(
+            ecdh::EncryptionScheme::from_u32(scheme).unwrap(),
+            &receiver_ecdh,
+            &event.ecdh_pubkey.0,
+            ecdh::context::MASTER_KEY,
+        )
+        .unwrap();
+        let mut data = event.encrypted_master_key.clone();
+        let data = aead::decrypt(&event.iv, &secret, &mut data).unwrap();
+        let history = SharedMasterKeyHistory::decode(&mut &data[..]).unwrap();
+        assert_eq!(history.secret, new_key.dump_secret_key());
+        assert_eq!(history.rotation_id, 1);
+        assert_eq!(history.retired, vec![(10, retired.dump_secret_key())]);
+    }
+
+    #[test]
+    fn gk_should_share_raw_master_key_to_legacy_receivers() {
+        use phala_crypto::{
+            aead, ecdh,
+            sr25519::{Persistence, KDF},
+        };
+        use sp_core::crypto::Pair;
+
+        let mut r = Roles::test_roles();
+        let receiver = sp_core::sr25519::Pair::from_seed(&[9u8; 32]);
+        let receiver_ecdh = receiver.derive_ecdh_key().unwrap();
+        let ecdh_pubkey = phala_types::EcdhPublicKey(receiver_ecdh.public());
+        let legacy_version = super::MASTER_KEY_SCHEME_RUNTIME_VERSION - 1;
+
+        let decrypt_master_key = |messages: Vec<msg::KeyDistribution>| {
+            let event = match &messages[..] {
+                [msg::KeyDistribution::MasterKeyDistribution(event)] => event.clone(),
+                _ => panic!("Unexpected messages {:?}", messages),
+            };
+            assert_eq!(event.dest, receiver.public());
+            // The way the legacy pRuntimes decrypt it
+            let secret = ecdh::agree(&receiver_ecdh, &event.ecdh_pubkey.0).unwrap();
+            let mut data = event.encrypted_master_key.clone();
+            aead::decrypt(&event.iv, &secret, &mut data)
+                .unwrap()
+                .to_vec()
+        };
+
+        r.gk.share_master_key(&receiver.public(), &ecdh_pubkey, legacy_version, 0, 1);
+        let data = decrypt_master_key(r.gk.egress.drain_decode());
+        assert_eq!(data, r.gk.master_key.dump_secret_key().to_vec());
+
+        // Only the current master key once rotated, since the history can't be decoded
+        let new_key = sp_core::sr25519::Pair::from_seed(&[2u8; 32]);
+        r.gk.rotate_master_key(new_key.clone(), 10, CollectChannel::default());
+        r.gk.share_master_key(&receiver.public(), &ecdh_pubkey, legacy_version, 1, 20);
+        let data = decrypt_master_key(r.gk.egress.drain_decode());
+        assert_eq!(data, new_key.dump_secret_key().to_vec());
+    }
+
+    #[test]
+    fn gk_replay_should_sample_mining_workers() {
+        use super::replay::GatekeeperReplay;
+
+        let worker = WorkerPublicKey::from_raw([0x01u8; 32]);
+        let pallet = MessageOrigin::Pallet(b"Pallet".to_vec());
+        let mut replay = GatekeeperReplay::new(None);
+        assert!(replay.dispatch(mk_msg(
+            &pallet,
+            msg::SystemEvent::new_worker_event(
+                worker.clone(),
+                msg::WorkerEvent::Registered(msg::WorkerInfo {
+                    confidence_level: 2,
+                }),
+            ),
+        )));
+        assert!(replay.dispatch(mk_msg(
+            &pallet,
+            msg::SystemEvent::new_worker_event(
+                worker.clone(),
+                msg::WorkerEvent::MiningStart {
+                    session_id: 1,
+                    init_v: fp!(1).to_bits(),
+                    init_p: 100,
+                },
+            ),
+        )));
+        // Random numbers from the recording chain are not replayed
+        assert!(!replay.dispatch(mk_msg(
+            &MessageOrigin::Gatekeeper,
+            msg::GatekeeperEvent::new_random_number(5, [1; 32], [0; 32]),
+        )));
+        replay.process_block(1, block_ts(1));
+
+        let samples = replay.samples(1);
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].pubkey, hex::encode(&worker));
+        assert!(!samples[0].paused);
+        assert_eq!(samples[0].payout, 0.0);
+
+        // Idle workers are rewarded
+        replay.process_block(2, block_ts(2));
+        assert!(replay.samples(2)[0].v > samples[0].v);
+    }
 }
@@ -0,0 +1,174 @@
+//! A bounded history of the heartbeat challenges seen by the worker.
+//!
+//! It tracks each challenge from the local eligibility decision, to the heartbeat enqueued in
+//! the worker egress, to the block in which the heartbeat was accepted on chain. So that the
+//! operators can tell the relayer lag apart from the enclave issues when heartbeats are missed.
+
+use std::collections::VecDeque;
+
+use phactory_api::prpc as pb;
+
+/// Number of the most recent challenges to keep
+const MAX_RECORDS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HeartbeatEligibility {
+    NotRegistered,
+    NotMining,
+    Paused,
+    /// Mining but not hit by the challenge
+    NotSelected,
+    Selected,
+}
+
+impl HeartbeatEligibility {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::NotRegistered => "not-registered",
+            Self::NotMining => "not-mining",
+            Self::Paused => "paused",
+            Self::NotSelected => "not-selected",
+            Self::Selected => "selected",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HeartbeatEgress {
+    /// No heartbeat is required, or the channel is in dummy mode
+    NotSent,
+    /// Rejected by the full egress queue
+    Rejected,
+    Enqueued {
+        sequence: u64,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct ChallengeRecord {
+    challenge_block: chain::BlockNumber,
+    challenge_time: u64,
+    eligibility: HeartbeatEligibility,
+    egress: HeartbeatEgress,
+    /// The block in which the heartbeat was observed in `OffchainIngress`
+    landed_block: Option<chain::BlockNumber>,
+}
+
+#[derive(Default)]
+pub(crate) struct HeartbeatLog {
+    records: VecDeque<ChallengeRecord>,
+    /// The next sequence of the worker expected on chain, as of the last processed block
+    next_sequence_on_chain: u64,
+}
+
+impl HeartbeatLog {
+    pub fn on_challenge(
+        &mut self,
+        challenge_block: chain::BlockNumber,
+        challenge_time: u64,
+        eligibility: HeartbeatEligibility,
+    ) {
+        if self.records.len() >= MAX_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(ChallengeRecord {
+            challenge_block,
+            challenge_time,
+            eligibility,
+            egress: HeartbeatEgress::NotSent,
+            landed_block: None,
+        });
+    }
+
+    /// Records the heartbeat sent for the challenge at `challenge_block`
+    pub fn on_heartbeat(&mut self, challenge_block: chain::BlockNumber, egress: HeartbeatEgress) {
+        if let Some(record) = self
+            .records
+            .iter_mut()
+            .rev()
+            .find(|record| record.challenge_block == challenge_block)
+        {
+            record.egress = egress;
+        }
+    }
+
+    /// Marks the heartbeats accepted on chain by the end of `block_number`
+    pub fn on_block_processed(&mut self, block_number: chain::BlockNumber, next_sequence: u64) {
+        self.next_sequence_on_chain = next_sequence;
+        for record in self.records.iter_mut().rev() {
+            if let HeartbeatEgress::Enqueued { sequence } = record.egress {
+                if record.landed_block.is_some() {
+                    // The earlier ones must have been landed too
+                    break;
+                }
+                if sequence < next_sequence {
+                    record.landed_block = Some(block_number);
+                }
+            }
+        }
+    }
+
+    pub fn export(&self) -> pb::HeartbeatHistory {
+        let records = self
+            .records
+            .iter()
+            .map(|record| {
+                let (enqueued, rejected, sequence) = match record.egress {
+                    HeartbeatEgress::NotSent => (false, false, 0),
+                    HeartbeatEgress::Rejected => (false, true, 0),
+                    HeartbeatEgress::Enqueued { sequence } => (true, false, sequence),
+                };
+                pb::HeartbeatChallengeRecord {
+                    challenge_block: record.challenge_block,
+                    challenge_time: record.challenge_time,
+                    eligibility: record.eligibility.as_str().into(),
+                    enqueued,
+                    rejected,
+                    sequence,
+                    landed_block: record.landed_block.unwrap_or_default(),
+                }
+            })
+            .collect();
+        pb::HeartbeatHistory {
+            next_sequence_on_chain: self.next_sequence_on_chain,
+            records,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_log_should_track_landing_block() {
+        let mut log = HeartbeatLog::default();
+        log.on_challenge(10, 60_000, HeartbeatEligibility::Selected);
+        log.on_heartbeat(10, HeartbeatEgress::Enqueued { sequence: 3 });
+        log.on_challenge(11, 72_000, HeartbeatEligibility::NotSelected);
+        log.on_challenge(12, 84_000, HeartbeatEligibility::Selected);
+        log.on_heartbeat(12, HeartbeatEgress::Rejected);
+        log.on_block_processed(12, 3);
+        log.on_block_processed(13, 4);
+        log.on_block_processed(14, 4);
+
+        let history = log.export();
+        assert_eq!(history.next_sequence_on_chain, 4);
+        let landed: Vec<_> = history.records.iter().map(|r| r.landed_block).collect();
+        assert_eq!(landed, vec![13, 0, 0]);
+        assert!(history.records[0].enqueued);
+        assert!(history.records[2].rejected);
+        assert_eq!(history.records[1].eligibility, "not-selected");
+    }
+
+    #[test]
+    fn heartbeat_log_should_be_bounded() {
+        let mut log = HeartbeatLog::default();
+        for block in 0..(MAX_RECORDS as u32 + 10) {
+            log.on_challenge(block, 0, HeartbeatEligibility::NotMining);
+        }
+        let history = log.export();
+        assert_eq!(history.records.len(), MAX_RECORDS);
+        assert_eq!(history.records[0].challenge_block, 10);
+    }
+}
@@ -1,24 +1,86 @@
 use std::path::PathBuf;
 
+use anyhow::{anyhow, Context as _, Result};
 use parity_scale_codec::{Decode, Encode};
 use sp_core::sr25519;
 
-use phala_crypto::sr25519::{Persistence, Signature, Signing, Sr25519SecretKey};
+use phala_crypto::{
+    sr25519::{Persistence, Signature, Signing, Sr25519SecretKey},
+    threshold::KeyShare,
+};
+use phala_types::{messaging::MasterKeyShareHolder, MasterPublicKey};
 
 use crate::pal::Sealing;
 
 /// Master key filepath
 pub const MASTER_KEY_FILE: &str = "master_key.seal";
 
+/// The master keys beside the current one
+#[derive(Clone, Default)]
+pub struct KeyRotation {
+    /// The rotation id which activated the current master key, 0 for the genesis one
+    pub rotation_id: u32,
+    /// The master keys replaced by rotations, with the block numbers they were retired at
+    pub retired: Vec<(chain::BlockNumber, sr25519::Pair)>,
+    /// The next master key received but not activated yet, with its rotation id
+    pub pending: Option<(u32, sr25519::Pair)>,
+    /// The share of the next threshold master key not activated yet, with its rotation id
+    pub pending_share: Option<(u32, MasterKeyShare)>,
+    /// The share of the threshold master key, with the block number it was activated at
+    ///
+    /// The share only evaluates the random beacon. The current master key is kept to replay the
+    /// blocks before the activation, and is never shared again.
+    pub share: Option<(chain::BlockNumber, MasterKeyShare)>,
+}
+
+/// The share of a master key split among the gatekeepers
+#[derive(Debug, Encode, Decode, Clone)]
+pub struct MasterKeyShare {
+    pub master_pubkey: MasterPublicKey,
+    /// The number of shares required to use the master key
+    pub threshold: u32,
+    pub share: KeyShare,
+    pub share_holders: Vec<MasterKeyShareHolder>,
+}
+
+/// The master key shared to a new gatekeeper, along with the ones retired by rotations
+///
+/// The retired keys are required to replay the blocks before the rotations.
+#[derive(Debug, Encode, Decode, Clone)]
+pub struct SharedMasterKeyHistory {
+    pub secret: Sr25519SecretKey,
+    /// The rotation id which activated the master key
+    pub rotation_id: u32,
+    /// The retired master keys, with the block numbers they were retired at
+    pub retired: Vec<(chain::BlockNumber, Sr25519SecretKey)>,
+}
+
 #[derive(Debug, Encode, Decode, Clone)]
 struct PersistentMasterKey {
     secret: Sr25519SecretKey,
     signature: Signature,
 }
 
+#[derive(Debug, Encode, Decode, Clone)]
+struct MasterKeyHistory {
+    secret: Sr25519SecretKey,
+    rotation_id: u32,
+    retired: Vec<(chain::BlockNumber, Sr25519SecretKey)>,
+    pending: Option<(u32, Sr25519SecretKey)>,
+    pending_share: Option<(u32, MasterKeyShare)>,
+    share: Option<(chain::BlockNumber, MasterKeyShare)>,
+}
+
+#[derive(Debug, Encode, Decode, Clone)]
+struct PersistentMasterKeyHistory {
+    history: MasterKeyHistory,
+    signature: Signature,
+}
+
 #[derive(Debug, Encode, Decode)]
 enum MasterKeySeal {
     V1(PersistentMasterKey),
+    V2(PersistentMasterKeyHistory),
 }
 
 fn master_key_file_path(sealing_path: String) -> PathBuf {
@@ -29,13 +91,28 @@ fn master_key_file_path(sealing_path: String) -> PathBuf {
 pub fn seal(
     sealing_path: String,
     master_key: &sr25519::Pair,
+    rotation: &KeyRotation,
     identity_key: &sr25519::Pair,
     sys: &impl Sealing,
 ) {
-    let secret = master_key.dump_secret_key();
-    let signature = identity_key.sign_data(&secret);
+    let history = MasterKeyHistory {
+        secret: master_key.dump_secret_key(),
+        rotation_id: rotation.rotation_id,
+        retired: rotation
+            .retired
+            .iter()
+            .map(|(block, key)| (*block, key.dump_secret_key()))
+            .collect(),
+        pending: rotation
+            .pending
+            .as_ref()
+            .map(|(id, key)| (*id, key.dump_secret_key())),
+        pending_share: rotation.pending_share.clone(),
+        share: rotation.share.clone(),
+    };
+    let signature = identity_key.sign_data(&history.encode());
 
-    let data = MasterKeySeal::V1(PersistentMasterKey { secret, signature });
+    let data = MasterKeySeal::V2(PersistentMasterKeyHistory { history, signature });
     let filepath = master_key_file_path(sealing_path);
     info!("Seal master key to {}", filepath.as_path().display());
     sys.seal_data(filepath, &data.encode())
@@ -49,7 +126,7 @@ pub fn try_unseal(
     sealing_path: String,
     identity_key: &sr25519::Pair,
     sys: &impl Sealing,
-) -> Option<sr25519::Pair> {
+) -> Option<(sr25519::Pair, KeyRotation)> {
     let filepath = master_key_file_path(sealing_path);
     info!("Unseal master key from {}", filepath.as_path().display());
     let sealed_data = match sys
@@ -65,16 +142,81 @@ pub fn try_unseal(
 
     let versioned_data =
         MasterKeySeal::decode(&mut &sealed_data[..]).expect("Failed to decode sealed master key");
+    let history = open(versioned_data, identity_key).expect("Broken sealed master key");
 
-    #[allow(clippy::infallible_destructuring_match)]
-    let data = match versioned_data {
-        MasterKeySeal::V1(data) => data,
+    let restore = |secret: &Sr25519SecretKey| sr25519::Pair::restore_from_secret_key(secret);
+    let rotation = KeyRotation {
+        rotation_id: history.rotation_id,
+        retired: history
+            .retired
+            .iter()
+            .map(|(block, secret)| (*block, restore(secret)))
+            .collect(),
+        pending: history
+            .pending
+            .as_ref()
+            .map(|(id, secret)| (*id, restore(secret))),
+        pending_share: history.pending_share,
+        share: history.share,
     };
+    Some((restore(&history.secret), rotation))
+}
 
-    assert!(
-        identity_key.verify_data(&data.signature, &data.secret),
-        "Broken sealed master key"
-    );
+/// Verifies the signature of the sealed data and upgrades it to the latest format
+fn open(versioned_data: MasterKeySeal, identity_key: &sr25519::Pair) -> Option<MasterKeyHistory> {
+    match versioned_data {
+        MasterKeySeal::V1(data) => {
+            if !identity_key.verify_data(&data.signature, &data.secret) {
+                return None;
+            }
+            Some(MasterKeyHistory {
+                secret: data.secret,
+                rotation_id: 0,
+                retired: vec![],
+                pending: None,
+                pending_share: None,
+                share: None,
+            })
+        }
+        MasterKeySeal::V2(data) => {
+            if !identity_key.verify_data(&data.signature, &data.history.encode()) {
+                return None;
+            }
+            Some(data.history)
+        }
+    }
+}
 
-    Some(sr25519::Pair::restore_from_secret_key(&data.secret))
+/// Reads the signed master key history as is, to be handed over to another pRuntime
+///
+/// Returns None if there's no sealed master key.
+pub fn export(sealing_path: String, sys: &impl Sealing) -> Result<Option<Vec<u8>>> {
+    let filepath = master_key_file_path(sealing_path);
+    sys.unseal_data(&filepath)
+        .map_err(Into::into)
+        .context("Unseal master key")
+}
+
+/// Seals the master key history exported by another pRuntime with the same identity
+///
+/// The data is rejected unless it's signed by `identity_key`.
+pub fn import(
+    sealing_path: String,
+    data: &[u8],
+    identity_key: &sr25519::Pair,
+    sys: &impl Sealing,
+) -> Result<()> {
+    let versioned_data =
+        MasterKeySeal::decode(&mut &data[..]).map_err(|_| anyhow!("Bad master key data"))?;
+    if open(versioned_data, identity_key).is_none() {
+        return Err(anyhow!("Master key not signed by the identity key"));
+    }
+    let filepath = master_key_file_path(sealing_path);
+    info!(
+        "Seal handed over master key to {}",
+        filepath.as_path().display()
+    );
+    sys.seal_data(filepath, data)
+        .map_err(Into::into)
+        .context("Seal master key")
 }
@@ -1,5 +1,9 @@
 mod gk;
-mod master_key;
+mod heartbeat_log;
+pub mod master_key;
+
+use heartbeat_log::{HeartbeatEgress, HeartbeatEligibility, HeartbeatLog};
+use master_key::MasterKeyShare;
 
 use crate::{benchmark, types::BlockInfo};
 use anyhow::Result;
@@ -7,22 +11,30 @@ use core::fmt;
 use log::info;
 
 use crate::pal;
-use chain::pallet_registry::RegistryEvent;
+use chain::pallet_registry::{RegistryEvent, BENCH_SUITE_VERSION};
+pub use gk::replay::{GatekeeperReplay, WorkerSample};
+use phactory_api::prpc as pb;
 pub use phactory_api::prpc::{GatekeeperRole, GatekeeperStatus};
 use parity_scale_codec::{Decode, Encode};
-use phala_crypto::{aead, ecdh, sr25519::KDF};
+use phala_crypto::{
+    aead, ecdh,
+    sr25519::{Persistence, KDF},
+    threshold,
+};
 use phala_mq::{
-    BadOrigin, MessageDispatcher, MessageOrigin, MessageSendQueue, Sr25519MessageChannel,
-    TypedReceiveError, TypedReceiver,
+    BadOrigin, BindTopic, MessageDispatcher, MessageOrigin, MessageSendQueue,
+    Sr25519MessageChannel, TypedReceiveError, TypedReceiver,
 };
 use phala_types::{
     messaging::{
-        DispatchMasterKeyEvent, GatekeeperChange, GatekeeperLaunch, HeartbeatChallenge,
-        KeyDistribution, MiningReportEvent, NewGatekeeperEvent, SystemEvent, WorkerEvent,
+        DispatchMasterKeyEvent, DispatchMasterKeySharesEvent, GatekeeperChange, GatekeeperLaunch,
+        HeartbeatChallenge, KeyDistribution, MasterKeyRotatedEvent, MasterKeyRotationEvent,
+        MasterKeyShareHolder, MiningReportEvent, NewGatekeeperEvent, RemovedGatekeeperEvent,
+        RotateMasterKeyEvent, SystemEvent, ThresholdMasterKeyRotationEvent, WorkerEvent,
     },
-    MasterPublicKey, WorkerPublicKey,
+    EcdhPublicKey, GatekeeperUnregistration, MasterPublicKey, WorkerPublicKey,
 };
-use sp_core::{hashing::blake2_256, sr25519, Pair, U256};
+use sp_core::{hashing::blake2_256, sr25519, Pair, H256, U256};
 
 pub type TransactionResult = Result<(), TransactionError>;
 
@@ -65,7 +77,7 @@ impl From<BadOrigin> for TransactionError {
     }
 }
 
-#[derive(Debug)]
+#[derive(Encode, Decode, Debug, Clone)]
 struct BenchState {
     start_block: chain::BlockNumber,
     start_time: u64,
@@ -73,13 +85,13 @@ struct BenchState {
     duration: u32,
 }
 
-#[derive(Debug)]
+#[derive(Encode, Decode, Debug, Clone)]
 enum MiningState {
     Mining,
     Paused,
 }
 
-#[derive(Debug)]
+#[derive(Encode, Decode, Debug, Clone)]
 struct MiningInfo {
     session_id: u32,
     state: MiningState,
@@ -89,6 +101,7 @@ struct MiningInfo {
 
 // Minimum worker state machine can be reused to replay in GK.
 // TODO: shrink size
+#[derive(Encode, Decode, Clone)]
 struct WorkerState {
     pubkey: WorkerPublicKey,
     hashed_id: U256,
@@ -133,7 +146,11 @@ impl WorkerState {
                     Registered(_) => {
                         self.registered = true;
                     }
+                    AttestationInvalidated => {
+                        self.registered = false;
+                    }
                     BenchStart { duration } => {
+                        callback.bench_start();
                         self.bench_state = Some(BenchState {
                             start_block: block.block_number,
                             start_time: block.now_ms,
@@ -218,32 +235,43 @@ impl WorkerState {
             );
         }
 
+        let eligibility = self.heartbeat_eligibility(seed_info);
+        callback.heartbeat_challenge(block.block_number, block.now_ms, eligibility);
+
+        // Push queue when necessary
+        if let (HeartbeatEligibility::Selected, Some(mining_state)) =
+            (eligibility, &self.mining_state)
+        {
+            let iterations = callback.bench_iterations() - mining_state.start_iter;
+            callback.heartbeat(
+                mining_state.session_id,
+                block.block_number,
+                block.now_ms,
+                iterations,
+            );
+        }
+    }
+
+    fn heartbeat_eligibility(&self, seed_info: &HeartbeatChallenge) -> HeartbeatEligibility {
         if !self.registered {
-            return;
+            return HeartbeatEligibility::NotRegistered;
         }
 
-        let mining_state = if let Some(state) = &mut self.mining_state {
+        let mining_state = if let Some(state) = &self.mining_state {
             state
         } else {
-            return;
+            return HeartbeatEligibility::NotMining;
         };
 
         if matches!(mining_state.state, MiningState::Paused) {
-            return;
+            return HeartbeatEligibility::Paused;
         }
 
         let x = self.hashed_id ^ seed_info.seed;
-        let online_hit = x <= seed_info.online_target;
-
-        // Push queue when necessary
-        if online_hit {
-            let iterations = callback.bench_iterations() - mining_state.start_iter;
-            callback.heartbeat(
-                mining_state.session_id,
-                block.block_number,
-                block.now_ms,
-                iterations,
-            );
+        if x <= seed_info.online_target {
+            HeartbeatEligibility::Selected
+        } else {
+            HeartbeatEligibility::NotSelected
         }
     }
 
@@ -280,9 +308,17 @@ trait WorkerStateMachineCallback {
     fn bench_iterations(&self) -> u64 {
         0
     }
+    fn bench_start(&mut self) {}
     fn bench_resume(&mut self) {}
     fn bench_pause(&mut self) {}
     fn bench_report(&mut self, _start_time: u64, _iterations: u64) {}
+    fn heartbeat_challenge(
+        &mut self,
+        _challenge_block: chain::BlockNumber,
+        _challenge_time: u64,
+        _eligibility: HeartbeatEligibility,
+    ) {
+    }
     fn heartbeat(
         &mut self,
         _session_id: u32,
@@ -293,25 +329,44 @@ trait WorkerStateMachineCallback {
     }
 }
 
-struct WorkerSMDelegate<'a>(&'a Sr25519MessageChannel);
+struct WorkerSMDelegate<'a> {
+    egress: &'a Sr25519MessageChannel,
+    heartbeat_log: &'a mut HeartbeatLog,
+    chain_storage: &'a crate::Storage,
+}
 
 impl WorkerStateMachineCallback for WorkerSMDelegate<'_> {
     fn bench_iterations(&self) -> u64 {
         benchmark::iteration_counter()
     }
+    fn bench_start(&mut self) {
+        benchmark::reset_component_iterations();
+    }
     fn bench_resume(&mut self) {
+        benchmark::set_weights(&chain_state::benchmark_weights(self.chain_storage));
         benchmark::resume();
     }
     fn bench_pause(&mut self) {
         benchmark::pause();
     }
     fn bench_report(&mut self, start_time: u64, iterations: u64) {
-        let report = RegistryEvent::BenchReport {
+        let report = RegistryEvent::SuiteBenchReport {
             start_time,
             iterations,
+            suite_version: BENCH_SUITE_VERSION,
+            component_iterations: benchmark::component_iterations(),
         };
         info!("Reporting benchmark: {:?}", report);
-        self.0.send(&report);
+        self.egress.send(&report);
+    }
+    fn heartbeat_challenge(
+        &mut self,
+        challenge_block: chain::BlockNumber,
+        challenge_time: u64,
+        eligibility: HeartbeatEligibility,
+    ) {
+        self.heartbeat_log
+            .on_challenge(challenge_block, challenge_time, eligibility);
     }
     fn heartbeat(
         &mut self,
@@ -327,10 +382,32 @@ impl WorkerStateMachineCallback for WorkerSMDelegate<'_> {
             iterations,
         };
         info!("System: sending {:?}", event);
-        self.0.send(&event);
+        let mut sequence = None;
+        let result = self
+            .egress
+            .try_send_data_with(MiningReportEvent::topic(), |seq| {
+                sequence = Some(seq);
+                event.encode()
+            });
+        let egress = match (result, sequence) {
+            (Err(_), _) => {
+                error!("System: heartbeat rejected by the egress queue");
+                HeartbeatEgress::Rejected
+            }
+            (Ok(()), Some(sequence)) => HeartbeatEgress::Enqueued { sequence },
+            (Ok(()), None) => HeartbeatEgress::NotSent,
+        };
+        self.heartbeat_log.on_heartbeat(challenge_block, egress);
     }
 }
 
+/// The persistable part of `System`, used for checkpoints
+#[derive(Encode, Decode, Clone)]
+pub(crate) struct SystemSnapshot {
+    worker_state: WorkerState,
+    gatekeeper: Option<gk::GatekeeperSnapshot>,
+}
+
 pub struct System<Platform> {
     platform: Platform,
     // Configuration
@@ -345,8 +422,13 @@ pub struct System<Platform> {
     // Worker
     identity_key: sr25519::Pair,
     worker_state: WorkerState,
+    heartbeat_log: HeartbeatLog,
     // Gatekeeper
     master_key: Option<sr25519::Pair>,
+    /// The retired and pending master keys of the rotations
+    master_key_rotation: master_key::KeyRotation,
+    /// The genesis block hash of the network, from which the gatekeeper derives its KDF salt
+    genesis_block_hash: H256,
     pub(crate) gatekeeper: Option<gk::Gatekeeper<Sr25519MessageChannel>>,
 }
 
@@ -355,12 +437,17 @@ impl<Platform: pal::Platform> System<Platform> {
         platform: Platform,
         sealing_path: String,
         identity_key: &sr25519::Pair,
+        genesis_block_hash: H256,
         send_mq: &MessageSendQueue,
         recv_mq: &mut MessageDispatcher,
     ) -> Self {
         let pubkey = identity_key.clone().public();
         let sender = MessageOrigin::Worker(pubkey);
-        let master_key = master_key::try_unseal(sealing_path.clone(), identity_key, &platform);
+        let (master_key, master_key_rotation) =
+            match master_key::try_unseal(sealing_path.clone(), identity_key, &platform) {
+                Some((master_key, rotation)) => (Some(master_key), rotation),
+                None => (None, Default::default()),
+            };
 
         System {
             platform,
@@ -373,7 +460,10 @@ impl<Platform: pal::Platform> System<Platform> {
             key_distribution_events: recv_mq.subscribe_bound(),
             identity_key: identity_key.clone(),
             worker_state: WorkerState::new(pubkey),
+            heartbeat_log: Default::default(),
             master_key,
+            master_key_rotation,
+            genesis_block_hash,
             gatekeeper: None,
         }
     }
@@ -437,27 +527,48 @@ impl<Platform: pal::Platform> System<Platform> {
                 break;
             }
         }
-        self.worker_state
-            .on_block_processed(block, &mut WorkerSMDelegate(&self.egress));
+        self.worker_state.on_block_processed(
+            block,
+            &mut WorkerSMDelegate {
+                egress: &self.egress,
+                heartbeat_log: &mut self.heartbeat_log,
+                chain_storage: block.storage,
+            },
+        );
+        let next_sequence =
+            chain_state::offchain_ingress_sequence(self.egress.sender(), block.storage);
+        self.heartbeat_log
+            .on_block_processed(block.block_number, next_sequence);
 
         if let Some(gatekeeper) = &mut self.gatekeeper {
             gatekeeper.process_messages(block);
-            gatekeeper.emit_random_number(block.block_number);
+            if let Some(partial) = gatekeeper.emit_random_number(block.block_number) {
+                // the partial evaluations differ among the gatekeepers
+                self.egress.send(&partial);
+            }
         }
 
         Ok(())
     }
 
     fn process_system_event(&mut self, block: &BlockInfo, event: &SystemEvent) -> Result<()> {
-        self.worker_state
-            .process_event(block, event, &mut WorkerSMDelegate(&self.egress), true);
+        self.worker_state.process_event(
+            block,
+            event,
+            &mut WorkerSMDelegate {
+                egress: &self.egress,
+                heartbeat_log: &mut self.heartbeat_log,
+                chain_storage: block.storage,
+            },
+            true,
+        );
         Ok(())
     }
 
     fn set_master_key(&mut self, master_key: sr25519::Pair, need_restart: bool) {
         if self.master_key.is_none() {
-            master_key::seal(self.sealing_path.clone(), &master_key, &self.identity_key, &self.platform);
             self.master_key = Some(master_key);
+            self.seal_master_key();
 
             if need_restart {
                 panic!("Received master key, please restart pRuntime and pherry");
@@ -468,6 +579,18 @@ impl<Platform: pal::Platform> System<Platform> {
         }
     }
 
+    fn seal_master_key(&self) {
+        if let Some(master_key) = &self.master_key {
+            master_key::seal(
+                self.sealing_path.clone(),
+                master_key,
+                &self.master_key_rotation,
+                &self.identity_key,
+                &self.platform,
+            );
+        }
+    }
+
     fn init_gatekeeper(&mut self, recv_mq: &mut MessageDispatcher) {
         assert!(
             self.master_key.is_some(),
@@ -478,19 +601,25 @@ impl<Platform: pal::Platform> System<Platform> {
             "Duplicated gatekeeper initialization"
         );
 
+        // nobody holds the whole threshold master key to sign the gatekeeper messages
+        let signer = if self.master_key_rotation.share.is_some() {
+            self.identity_key.clone()
+        } else {
+            self.master_key
+                .as_ref()
+                .expect("checked master key above; qed.")
+                .clone()
+        };
         let gatekeeper = gk::Gatekeeper::new(
             self.master_key
                 .as_ref()
                 .expect("checked master key above; qed.")
                 .clone(),
+            self.master_key_rotation.retired.clone(),
+            self.master_key_rotation.share.clone(),
+            self.genesis_block_hash,
             recv_mq,
-            self.send_mq.channel(
-                MessageOrigin::Gatekeeper,
-                self.master_key
-                    .as_ref()
-                    .expect("checked master key above; qed.")
-                    .clone(),
-            ),
+            self.send_mq.channel(MessageOrigin::Gatekeeper, signer),
         );
         self.gatekeeper = Some(gatekeeper);
     }
@@ -583,6 +712,327 @@ impl<Platform: pal::Platform> System<Platform> {
             GatekeeperChange::GatekeeperRegistered(new_gatekeeper_event) => {
                 self.process_new_gatekeeper_event(block, origin, new_gatekeeper_event)
             }
+            GatekeeperChange::MasterKeyRotation(rotation_event) => {
+                self.process_master_key_rotation_event(origin, rotation_event)
+            }
+            GatekeeperChange::MasterKeyRotated(rotated_event) => {
+                self.process_master_key_rotated_event(block, origin, rotated_event)
+            }
+            GatekeeperChange::ThresholdMasterKeyRotation(rotation_event) => {
+                self.process_threshold_master_key_rotation_event(origin, rotation_event)
+            }
+            GatekeeperChange::GatekeeperUnregistered(removed_event) => {
+                self.process_gatekeeper_unregistered_event(block, origin, removed_event)
+            }
+        }
+    }
+
+    /// Stop working as a gatekeeper once unregistered on chain
+    ///
+    /// The master key is kept, so that the gatekeeper can be registered again.
+    fn process_gatekeeper_unregistered_event(
+        &mut self,
+        block: &BlockInfo,
+        origin: MessageOrigin,
+        event: RemovedGatekeeperEvent,
+    ) {
+        if !origin.is_pallet() {
+            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            return;
+        }
+
+        if event.pubkey != self.identity_key.public() {
+            return;
+        }
+        if let Some(mut gatekeeper) = self.gatekeeper.take() {
+            info!("Gatekeeper: unregistered in block {}", block.block_number);
+            gatekeeper.unregister_on_chain();
+        }
+    }
+
+    /// Generate the next master key and send it to the other gatekeepers if this is the lead
+    fn process_master_key_rotation_event(
+        &mut self,
+        origin: MessageOrigin,
+        event: MasterKeyRotationEvent,
+    ) {
+        if !origin.is_pallet() {
+            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            return;
+        }
+
+        let my_pubkey = self.identity_key.public();
+        if my_pubkey != event.lead || self.master_key.is_none() {
+            return;
+        }
+        if event.rotation_id <= self.master_key_rotation.rotation_id {
+            info!(
+                "Gatekeeper: master key rotation {} already activated",
+                event.rotation_id
+            );
+            return;
+        }
+
+        // a rebooted lead should re-send the key it generated before
+        let new_master_key = match &self.master_key_rotation.pending {
+            Some((rotation_id, key)) if *rotation_id == event.rotation_id => key.clone(),
+            _ => {
+                info!(
+                    "Gatekeeper: generate new master key for rotation {}",
+                    event.rotation_id
+                );
+                let key = crate::new_sr25519_key();
+                self.master_key_rotation.pending = Some((event.rotation_id, key.clone()));
+                self.seal_master_key();
+                key
+            }
+        };
+
+        let my_ecdh_key = self
+            .identity_key
+            .derive_ecdh_key()
+            .expect("Should never failed with valid identity key; qed.");
+        let my_ecdh_pubkey = EcdhPublicKey(my_ecdh_key.public());
+        let scheme = ecdh::EncryptionScheme::HkdfSha256;
+        let encrypted_keys = event
+            .gatekeepers
+            .iter()
+            .filter(|gatekeeper| gatekeeper.pubkey != my_pubkey)
+            .map(|gatekeeper| {
+                let secret = ecdh::agree_with(
+                    scheme,
+                    &my_ecdh_key,
+                    &gatekeeper.ecdh_pubkey.0,
+                    ecdh::context::MASTER_KEY,
+                )
+                .expect("Should never failed with valid ecdh key; qed.");
+                // only the lead encrypts, so there is no need for a deterministic iv
+                let iv = crate::generate_random_iv();
+                let mut data = new_master_key.dump_secret_key().to_vec();
+                aead::encrypt(&iv, &secret, &mut data).expect("Failed to encrypt master key");
+                DispatchMasterKeyEvent {
+                    dest: gatekeeper.pubkey,
+                    ecdh_pubkey: my_ecdh_pubkey.clone(),
+                    encrypted_master_key: data,
+                    iv,
+                }
+            })
+            .collect();
+
+        info!(
+            "Gatekeeper: upload new master key {} for rotation {}",
+            hex::encode(new_master_key.public()),
+            event.rotation_id
+        );
+        self.egress
+            .send(&KeyDistribution::MasterKeyRotation(RotateMasterKeyEvent {
+                rotation_id: event.rotation_id,
+                scheme: scheme.to_u32(),
+                encrypted_keys,
+            }));
+        self.egress.send(&RegistryEvent::RotatedMasterPubkey {
+            rotation_id: event.rotation_id,
+            master_pubkey: new_master_key.public(),
+        });
+    }
+
+    /// Generate the next master key and share it among the gatekeepers if this is the dealer
+    ///
+    /// The dealer keeps nothing but its own share, so it can't re-send the shares after reboot.
+    /// A new rotation is required if the shares are lost.
+    fn process_threshold_master_key_rotation_event(
+        &mut self,
+        origin: MessageOrigin,
+        event: ThresholdMasterKeyRotationEvent,
+    ) {
+        if !origin.is_pallet() {
+            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            return;
+        }
+
+        let my_pubkey = self.identity_key.public();
+        if my_pubkey != event.dealer || self.master_key.is_none() {
+            return;
+        }
+        let dealt = matches!(
+            &self.master_key_rotation.pending_share,
+            Some((rotation_id, _)) if *rotation_id == event.rotation_id
+        );
+        if dealt || event.rotation_id <= self.master_key_rotation.rotation_id {
+            info!(
+                "Gatekeeper: threshold master key rotation {} already dealt",
+                event.rotation_id
+            );
+            return;
+        }
+
+        info!(
+            "Gatekeeper: generate new threshold master key for rotation {}",
+            event.rotation_id
+        );
+        let new_master_key = crate::new_sr25519_key();
+        let mut secret = [0u8; 32];
+        // the first 32 bytes holds the canonical private key
+        secret.copy_from_slice(&new_master_key.dump_secret_key()[..32]);
+        let shares = threshold::split(
+            &secret,
+            event.threshold,
+            event.gatekeepers.len() as u32,
+            &crate::generate_random_info(),
+        )
+        .expect("Failed to split the master key");
+        let share_holders: Vec<_> = event
+            .gatekeepers
+            .iter()
+            .zip(shares.iter())
+            .map(|(gatekeeper, share)| MasterKeyShareHolder {
+                pubkey: gatekeeper.pubkey,
+                index: share.index,
+                share_pubkey: share.public().expect("Shares must be valid; qed."),
+            })
+            .collect();
+
+        let my_ecdh_key = self
+            .identity_key
+            .derive_ecdh_key()
+            .expect("Should never failed with valid identity key; qed.");
+        let my_ecdh_pubkey = EcdhPublicKey(my_ecdh_key.public());
+        let scheme = ecdh::EncryptionScheme::HkdfSha256;
+        let mut my_share = None;
+        let mut encrypted_shares = Vec::new();
+        for (gatekeeper, share) in event.gatekeepers.iter().zip(shares) {
+            if gatekeeper.pubkey == my_pubkey {
+                my_share = Some(share);
+                continue;
+            }
+            let key = ecdh::agree_with(
+                scheme,
+                &my_ecdh_key,
+                &gatekeeper.ecdh_pubkey.0,
+                ecdh::context::MASTER_KEY,
+            )
+            .expect("Should never failed with valid ecdh key; qed.");
+            let iv = crate::generate_random_iv();
+            let mut data = share.secret.to_vec();
+            aead::encrypt(&iv, &key, &mut data).expect("Failed to encrypt master key share");
+            encrypted_shares.push(DispatchMasterKeyEvent {
+                dest: gatekeeper.pubkey,
+                ecdh_pubkey: my_ecdh_pubkey.clone(),
+                encrypted_master_key: data,
+                iv,
+            });
+        }
+
+        let master_pubkey = new_master_key.public();
+        // the whole key is never used or kept
+        drop(new_master_key);
+        self.master_key_rotation.pending_share = Some((
+            event.rotation_id,
+            MasterKeyShare {
+                master_pubkey,
+                threshold: event.threshold,
+                share: my_share.expect("the dealer must be a gatekeeper; qed."),
+                share_holders: share_holders.clone(),
+            },
+        ));
+        self.seal_master_key();
+
+        info!(
+            "Gatekeeper: upload new threshold master key {} for rotation {}",
+            hex::encode(master_pubkey),
+            event.rotation_id
+        );
+        self.egress.send(&KeyDistribution::MasterKeyShares(
+            DispatchMasterKeySharesEvent {
+                rotation_id: event.rotation_id,
+                scheme: scheme.to_u32(),
+                threshold: event.threshold,
+                master_pubkey,
+                share_holders: share_holders.clone(),
+                encrypted_shares,
+            },
+        ));
+        self.egress
+            .send(&RegistryEvent::RotatedThresholdMasterPubkey {
+                rotation_id: event.rotation_id,
+                master_pubkey,
+                share_holders,
+            });
+    }
+
+    /// Switch to the pending master key once it's activated on chain
+    fn process_master_key_rotated_event(
+        &mut self,
+        block: &mut BlockInfo,
+        origin: MessageOrigin,
+        event: MasterKeyRotatedEvent,
+    ) {
+        if !origin.is_pallet() {
+            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            return;
+        }
+
+        if self.master_key.is_none() || event.rotation_id <= self.master_key_rotation.rotation_id {
+            return;
+        }
+
+        let pending_share =
+            self.master_key_rotation
+                .pending_share
+                .take()
+                .filter(|(rotation_id, share)| {
+                    *rotation_id == event.rotation_id && share.master_pubkey == event.master_pubkey
+                });
+        if let Some((_, master_key_share)) = pending_share {
+            // the current master key is kept to replay the blocks before
+            self.master_key_rotation.rotation_id = event.rotation_id;
+            self.master_key_rotation.pending = None;
+            self.master_key_rotation.share = Some((block.block_number, master_key_share.clone()));
+            self.seal_master_key();
+
+            if let Some(gatekeeper) = &mut self.gatekeeper {
+                let egress = self
+                    .send_mq
+                    .channel(MessageOrigin::Gatekeeper, self.identity_key.clone());
+                gatekeeper.enable_threshold_master_key(
+                    master_key_share,
+                    block.block_number,
+                    egress,
+                );
+            }
+            return;
+        }
+
+        let new_master_key = match self.master_key_rotation.pending.take() {
+            Some((rotation_id, key))
+                if rotation_id == event.rotation_id && key.public() == event.master_pubkey =>
+            {
+                key
+            }
+            _ => {
+                error!(
+                    "Missing the new master key of rotation {}, ignoring the rotation",
+                    event.rotation_id
+                );
+                return;
+            }
+        };
+
+        let retired_master_key = self
+            .master_key
+            .replace(new_master_key.clone())
+            .expect("checked master key above; qed.");
+        self.master_key_rotation.rotation_id = event.rotation_id;
+        self.master_key_rotation
+            .retired
+            .push((block.block_number, retired_master_key));
+        self.seal_master_key();
+
+        if let Some(gatekeeper) = &mut self.gatekeeper {
+            let egress = self
+                .send_mq
+                .channel(MessageOrigin::Gatekeeper, new_master_key.clone());
+            gatekeeper.rotate_master_key(new_master_key, block.block_number, egress);
         }
     }
 
@@ -609,7 +1059,15 @@ impl<Platform: pal::Platform> System<Platform> {
         }
 
         if let Some(gatekeeper) = &mut self.gatekeeper {
-            gatekeeper.share_master_key(&event.pubkey, &event.ecdh_pubkey, block.block_number);
+            let runtime_version =
+                chain_state::worker_runtime_version(&event.pubkey, block.storage).unwrap_or(0);
+            gatekeeper.share_master_key(
+                &event.pubkey,
+                &event.ecdh_pubkey,
+                runtime_version,
+                self.master_key_rotation.rotation_id,
+                block.block_number,
+            );
 
             let my_pubkey = self.identity_key.public();
             if my_pubkey == event.pubkey {
@@ -620,22 +1078,270 @@ impl<Platform: pal::Platform> System<Platform> {
 
     fn process_key_distribution_event(
         &mut self,
-        _block: &mut BlockInfo,
+        block: &mut BlockInfo,
         origin: MessageOrigin,
         event: KeyDistribution,
     ) {
         info!("Incoming key distribution event: {:?}", event);
         match event {
-            KeyDistribution::MasterKeyDistribution(dispatch_master_key_event) => {
-                self.process_master_key_distribution(origin, dispatch_master_key_event)
+            KeyDistribution::MasterKeyDistribution(dispatch_master_key_event) => self
+                .process_master_key_distribution(
+                    origin,
+                    ecdh::EncryptionScheme::Raw,
+                    dispatch_master_key_event,
+                ),
+            KeyDistribution::MasterKeyDistributionWithScheme { scheme, event } => {
+                let scheme = match ecdh::EncryptionScheme::from_u32(scheme) {
+                    Some(scheme) => scheme,
+                    None => {
+                        error!("Unknown encryption scheme {} of the master key", scheme);
+                        return;
+                    }
+                };
+                self.process_master_key_distribution(origin, scheme, event)
+            }
+            KeyDistribution::MasterKeyHistoryDistribution { scheme, event } => {
+                let scheme = match ecdh::EncryptionScheme::from_u32(scheme) {
+                    Some(scheme) => scheme,
+                    None => {
+                        error!("Unknown encryption scheme {} of the master key", scheme);
+                        return;
+                    }
+                };
+                self.process_master_key_history_distribution(origin, scheme, event)
+            }
+            KeyDistribution::MasterKeyRotation(rotate_master_key_event) => {
+                self.process_rotate_master_key_event(block, origin, rotate_master_key_event)
+            }
+            KeyDistribution::MasterKeyShares(master_key_shares_event) => {
+                self.process_master_key_shares_event(block, origin, master_key_shares_event)
             }
         }
     }
 
+    /// Keep the share of the next master key sent by the dealer until it's activated
+    fn process_master_key_shares_event(
+        &mut self,
+        block: &mut BlockInfo,
+        origin: MessageOrigin,
+        event: DispatchMasterKeySharesEvent,
+    ) {
+        match &origin {
+            MessageOrigin::Worker(pubkey) if chain_state::is_gatekeeper(pubkey, block.storage) => {}
+            _ => {
+                error!("Invalid origin {:?} sent a {:?}", origin, event);
+                return;
+            }
+        };
+
+        if self.master_key.is_none() || event.rotation_id <= self.master_key_rotation.rotation_id {
+            return;
+        }
+
+        let scheme = match ecdh::EncryptionScheme::from_u32(event.scheme) {
+            Some(scheme) => scheme,
+            None => {
+                error!(
+                    "Unknown encryption scheme {} of the master key share",
+                    event.scheme
+                );
+                return;
+            }
+        };
+
+        let my_pubkey = self.identity_key.public();
+        let holder = match event
+            .share_holders
+            .iter()
+            .find(|holder| holder.pubkey == my_pubkey)
+        {
+            Some(holder) => holder,
+            None => return,
+        };
+        let encrypted_share = match event
+            .encrypted_shares
+            .iter()
+            .find(|share| share.dest == my_pubkey)
+        {
+            Some(share) => share,
+            None => return,
+        };
+
+        let my_ecdh_key = self
+            .identity_key
+            .derive_ecdh_key()
+            .expect("Should never failed with valid identity key; qed.");
+        let key = ecdh::agree_with(
+            scheme,
+            &my_ecdh_key,
+            &encrypted_share.ecdh_pubkey.0,
+            ecdh::context::MASTER_KEY,
+        )
+        .expect("Should never failed with valid ecdh key; qed.");
+        let mut share_buff = encrypted_share.encrypted_master_key.clone();
+        let secret = match aead::decrypt(&encrypted_share.iv, &key, &mut share_buff[..]) {
+            Ok(secret) if secret.len() == 32 => secret,
+            _ => {
+                error!("Failed to decrypt the master key share from {:?}", origin);
+                return;
+            }
+        };
+        let mut share = threshold::KeyShare {
+            index: holder.index,
+            secret: [0u8; 32],
+        };
+        share.secret.copy_from_slice(secret);
+
+        // the share must match its public share, and the public shares must match the master
+        // pubkey
+        let threshold = event.threshold as usize;
+        let public_shares: Vec<_> = event
+            .share_holders
+            .iter()
+            .take(threshold)
+            .map(|holder| (holder.index, holder.share_pubkey))
+            .collect();
+        let consistent = share.public().ok() == Some(holder.share_pubkey)
+            && public_shares.len() == threshold
+            && threshold::recover_public(&public_shares).ok() == Some(event.master_pubkey.0);
+        if !consistent {
+            error!(
+                "Inconsistent master key share of rotation {} from {:?}",
+                event.rotation_id, origin
+            );
+            return;
+        }
+
+        info!(
+            "Gatekeeper: received share {} of new threshold master key {} for rotation {}",
+            share.index,
+            hex::encode(event.master_pubkey),
+            event.rotation_id
+        );
+        // the share is only trusted once the master pubkey gets activated on chain
+        self.master_key_rotation.pending_share = Some((
+            event.rotation_id,
+            MasterKeyShare {
+                master_pubkey: event.master_pubkey,
+                threshold: event.threshold,
+                share,
+                share_holders: event.share_holders,
+            },
+        ));
+        self.seal_master_key();
+    }
+
+    /// Keep the next master key sent by the lead gatekeeper until it's activated
+    fn process_rotate_master_key_event(
+        &mut self,
+        block: &mut BlockInfo,
+        origin: MessageOrigin,
+        event: RotateMasterKeyEvent,
+    ) {
+        let sender = match &origin {
+            MessageOrigin::Worker(pubkey) if chain_state::is_gatekeeper(pubkey, block.storage) => {
+                pubkey
+            }
+            _ => {
+                error!("Invalid origin {:?} sent a {:?}", origin, event);
+                return;
+            }
+        };
+
+        if self.master_key.is_none() || event.rotation_id <= self.master_key_rotation.rotation_id {
+            return;
+        }
+
+        let scheme = match ecdh::EncryptionScheme::from_u32(event.scheme) {
+            Some(scheme) => scheme,
+            None => {
+                error!(
+                    "Unknown encryption scheme {} of the master key",
+                    event.scheme
+                );
+                return;
+            }
+        };
+
+        let my_pubkey = self.identity_key.public();
+        let encrypted_key = match event
+            .encrypted_keys
+            .iter()
+            .find(|key| key.dest == my_pubkey)
+        {
+            Some(key) => key,
+            None => return,
+        };
+
+        let my_ecdh_key = self
+            .identity_key
+            .derive_ecdh_key()
+            .expect("Should never failed with valid identity key; qed.");
+        let secret = ecdh::agree_with(
+            scheme,
+            &my_ecdh_key,
+            &encrypted_key.ecdh_pubkey.0,
+            ecdh::context::MASTER_KEY,
+        )
+        .expect("Should never failed with valid ecdh key; qed.");
+
+        let mut master_key_buff = encrypted_key.encrypted_master_key.clone();
+        let master_key = match aead::decrypt(&encrypted_key.iv, &secret, &mut master_key_buff[..]) {
+            Ok(master_key) => master_key,
+            Err(err) => {
+                error!(
+                    "Failed to decrypt the new master key from {}: {:?}",
+                    hex::encode(sender),
+                    err
+                );
+                return;
+            }
+        };
+        let master_pair = sr25519::Pair::from_seed_slice(master_key)
+            .expect("Master key seed must be correct; qed.");
+        info!(
+            "Gatekeeper: received new master key {} for rotation {}",
+            hex::encode(master_pair.public()),
+            event.rotation_id
+        );
+        // the key is only trusted once its pubkey gets activated on chain
+        self.master_key_rotation.pending = Some((event.rotation_id, master_pair));
+        self.seal_master_key();
+    }
+
+    /// Decrypt the master key data sent to this worker, or None if sent to others
+    fn decrypt_master_key_distribution(
+        &self,
+        scheme: ecdh::EncryptionScheme,
+        event: &DispatchMasterKeyEvent,
+    ) -> Option<Vec<u8>> {
+        let my_pubkey = self.identity_key.public();
+        if my_pubkey != event.dest {
+            return None;
+        }
+        let my_ecdh_key = self
+            .identity_key
+            .derive_ecdh_key()
+            .expect("Should never failed with valid identity key; qed.");
+        let secret = ecdh::agree_with(
+            scheme,
+            &my_ecdh_key,
+            &event.ecdh_pubkey.0,
+            ecdh::context::MASTER_KEY,
+        )
+        .expect("Should never failed with valid ecdh key; qed.");
+
+        let mut master_key_buff = event.encrypted_master_key.clone();
+        let data = aead::decrypt(&event.iv, &secret, &mut master_key_buff[..])
+            .expect("Failed to decrypt dispatched master key");
+        Some(data.to_vec())
+    }
+
     /// Process encrypted master key from mq
     fn process_master_key_distribution(
         &mut self,
         origin: MessageOrigin,
+        scheme: ecdh::EncryptionScheme,
         event: DispatchMasterKeyEvent,
     ) {
         if !origin.is_gatekeeper() {
@@ -643,30 +1349,129 @@ impl<Platform: pal::Platform> System<Platform> {
             return;
         };
 
-        let my_pubkey = self.identity_key.public();
-        if my_pubkey == event.dest {
-            let my_ecdh_key = self
-                .identity_key
-                .derive_ecdh_key()
-                .expect("Should never failed with valid identity key; qed.");
-            let secret = ecdh::agree(&my_ecdh_key, &event.ecdh_pubkey.0)
-                .expect("Should never failed with valid ecdh key; qed.");
-
-            let mut master_key_buff = event.encrypted_master_key.clone();
-            let master_key = aead::decrypt(&event.iv, &secret, &mut master_key_buff[..])
-                .expect("Failed to decrypt dispatched master key");
-
-            let master_pair = sr25519::Pair::from_seed_slice(master_key)
+        if let Some(master_key) = self.decrypt_master_key_distribution(scheme, &event) {
+            let master_pair = sr25519::Pair::from_seed_slice(&master_key)
                 .expect("Master key seed must be correct; qed.");
             info!("Gatekeeper: successfully decrypt received master key");
             self.set_master_key(master_pair, true);
         }
     }
 
+    /// Process encrypted master key along with the retired ones from mq
+    fn process_master_key_history_distribution(
+        &mut self,
+        origin: MessageOrigin,
+        scheme: ecdh::EncryptionScheme,
+        event: DispatchMasterKeyEvent,
+    ) {
+        if !origin.is_gatekeeper() {
+            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            return;
+        };
+
+        if let Some(data) = self.decrypt_master_key_distribution(scheme, &event) {
+            let history = master_key::SharedMasterKeyHistory::decode(&mut &data[..])
+                .expect("Failed to decode dispatched master key history");
+            let master_pair = sr25519::Pair::restore_from_secret_key(&history.secret);
+            info!(
+                "Gatekeeper: successfully decrypt received master key with {} retired",
+                history.retired.len()
+            );
+            if self.master_key.is_none() {
+                self.master_key_rotation.rotation_id = history.rotation_id;
+                self.master_key_rotation.retired = history
+                    .retired
+                    .iter()
+                    .map(|(retired_at, secret)| {
+                        (*retired_at, sr25519::Pair::restore_from_secret_key(secret))
+                    })
+                    .collect();
+            }
+            self.set_master_key(master_pair, true);
+        }
+    }
+
     pub fn is_registered(&self) -> bool {
         self.worker_state.registered
     }
 
+    /// The lifecycle phase of the worker, for the metrics
+    pub fn worker_phase(&self) -> &'static str {
+        let state = &self.worker_state;
+        match &state.mining_state {
+            Some(MiningInfo {
+                state: MiningState::Mining,
+                ..
+            }) => "mining",
+            Some(MiningInfo {
+                state: MiningState::Paused,
+                ..
+            }) => "paused",
+            None if state.bench_state.is_some() => "benchmarking",
+            None if state.registered => "idle",
+            None => "unregistered",
+        }
+    }
+
+    /// Signs the unregistration of this gatekeeper, to be submitted in
+    /// `PhalaRegistry::unregister_gatekeeper`
+    pub fn sign_gatekeeper_unregistration(
+        &self,
+        chain_storage: &crate::Storage,
+    ) -> Result<(GatekeeperUnregistration, sr25519::Signature)> {
+        let pubkey = self.identity_key.public();
+        if !chain_state::is_gatekeeper(&pubkey, chain_storage) {
+            return Err(anyhow::anyhow!("Not a gatekeeper on chain"));
+        }
+        let unregistration = GatekeeperUnregistration {
+            pubkey,
+            nonce: chain_state::gatekeeper_unregistration_nonce(&pubkey, chain_storage),
+        };
+        let signature = self.identity_key.sign(&unregistration.data_be_signed());
+        Ok((unregistration, signature))
+    }
+
+    /// The recent heartbeat challenges and how they were answered
+    pub fn heartbeat_history(&self) -> pb::HeartbeatHistory {
+        self.heartbeat_log.export()
+    }
+
+    pub(crate) fn snapshot(&self) -> SystemSnapshot {
+        SystemSnapshot {
+            worker_state: self.worker_state.clone(),
+            gatekeeper: self.gatekeeper.as_ref().map(|gk| gk.snapshot()),
+        }
+    }
+
+    /// Restore the state from a snapshot. Must be called before processing any block.
+    pub(crate) fn restore(
+        &mut self,
+        snapshot: SystemSnapshot,
+        recv_mq: &mut MessageDispatcher,
+    ) -> Result<()> {
+        if snapshot.worker_state.pubkey != self.worker_state.pubkey {
+            return Err(anyhow::anyhow!("Worker pubkey mismatch"));
+        }
+        if snapshot.gatekeeper.is_some() && self.master_key.is_none() {
+            return Err(anyhow::anyhow!("Gatekeeper snapshot without master key"));
+        }
+        self.worker_state = snapshot.worker_state;
+        if !self.worker_state.need_pause() {
+            // The benchmark was running when the snapshot was taken
+            benchmark::resume();
+        }
+        if let Some(gk_snapshot) = snapshot.gatekeeper {
+            if self.gatekeeper.is_none() {
+                self.init_gatekeeper(recv_mq);
+            }
+            self.gatekeeper
+                .as_mut()
+                .expect("gatekeeper initialized above; qed.")
+                .restore(gk_snapshot);
+        }
+        Ok(())
+    }
+
     pub fn gatekeeper_status(&self) -> GatekeeperStatus {
         let active = match &self.gatekeeper {
             Some(gk) => gk.registered_on_chain(),
@@ -717,8 +1522,12 @@ pub enum Response {
 
 pub mod chain_state {
     use super::*;
-    use crate::light_validation::utils::storage_prefix;
-    use crate::storage::Storage;
+    use crate::light_validation::utils::{storage_map_prefix_twox_64_concat, storage_prefix};
+    use crate::storage::{Storage, StorageExt};
+    use chain::pallet_registry::{
+        AttestationProvider, AttestationProviderConfig, BenchComponent, DcapCollateral, WorkerInfo,
+        DEFAULT_BENCH_COMPONENT_WEIGHT,
+    };
     use parity_scale_codec::Decode;
 
     pub fn is_gatekeeper(pubkey: &WorkerPublicKey, chain_storage: &Storage) -> bool {
@@ -734,6 +1543,77 @@ pub mod chain_state {
         gatekeepers.contains(pubkey)
     }
 
+    /// The pRuntime version the worker registered with, or None if it's not registered
+    pub fn worker_runtime_version(
+        pubkey: &WorkerPublicKey,
+        chain_storage: &Storage,
+    ) -> Option<u32> {
+        let key = storage_map_prefix_twox_64_concat(b"PhalaRegistry", b"Workers", pubkey);
+        chain_storage
+            .get_decoded::<WorkerInfo<chain::AccountId>>(&key)
+            .map(|info| info.runtime_version())
+    }
+
+    /// The next sequence of the sender expected by the chain
+    pub fn offchain_ingress_sequence(sender: &MessageOrigin, chain_storage: &Storage) -> u64 {
+        let key = storage_map_prefix_twox_64_concat(b"PhalaMq", b"OffchainIngress", sender);
+        chain_storage.get_decoded(&key).unwrap_or(0)
+    }
+
+    pub fn gatekeeper_unregistration_nonce(
+        pubkey: &WorkerPublicKey,
+        chain_storage: &Storage,
+    ) -> u32 {
+        let key = storage_map_prefix_twox_64_concat(
+            b"PhalaRegistry",
+            b"GatekeeperUnregistrationNonce",
+            pubkey,
+        );
+        chain_storage.get_decoded(&key).unwrap_or(0)
+    }
+
+    /// The policy of the attestation provider, or None if it's not accepted by the chain
+    pub fn attestation_provider_config(
+        provider: AttestationProvider,
+        chain_storage: &Storage,
+    ) -> Option<AttestationProviderConfig> {
+        let key =
+            storage_map_prefix_twox_64_concat(b"PhalaRegistry", b"AttestationProviders", &provider);
+        chain_storage.get_decoded(&key)
+    }
+
+    pub fn pruntime_allowlist(
+        provider: AttestationProvider,
+        chain_storage: &Storage,
+    ) -> Vec<Vec<u8>> {
+        let key =
+            storage_map_prefix_twox_64_concat(b"PhalaRegistry", b"PRuntimeAllowList", &provider);
+        chain_storage.get_decoded(&key).unwrap_or_default()
+    }
+
+    /// The units of each benchmark component in a round of the suite, in per mille
+    pub fn benchmark_weights(chain_storage: &Storage) -> Vec<(BenchComponent, u32)> {
+        BenchComponent::ALL
+            .iter()
+            .map(|component| {
+                let key = storage_map_prefix_twox_64_concat(
+                    b"PhalaRegistry",
+                    b"BenchmarkWeights",
+                    component,
+                );
+                let weight = chain_storage
+                    .get_decoded(&key)
+                    .unwrap_or(DEFAULT_BENCH_COMPONENT_WEIGHT);
+                (*component, weight)
+            })
+            .collect()
+    }
+
+    pub fn dcap_collateral(chain_storage: &Storage) -> Option<DcapCollateral> {
+        let key = storage_prefix("PhalaRegistry", "DcapQuoteCollateral");
+        chain_storage.get_decoded(&key)
+    }
+
     #[allow(dead_code)]
     pub fn read_master_pubkey(chain_storage: &Storage) -> Option<MasterPublicKey> {
         let key = storage_prefix("PhalaRegistry", "GatekeeperMasterPubkey");
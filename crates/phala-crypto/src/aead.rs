@@ -25,11 +25,22 @@ fn load_key(raw: &[u8]) -> Result<AeadKey, CryptoError> {
 
 // Encrypts the data in-place and appends a 128bit auth tag
 pub fn encrypt(iv: &IV, secret: &[u8], in_out: &mut Vec<u8>) -> Result<(), CryptoError> {
+    encrypt_with_aad(iv, secret, &[], in_out)
+}
+
+// Encrypts the data in-place and appends a 128bit auth tag covering both the data and the
+// associated data. The associated data is not included in the output.
+pub fn encrypt_with_aad(
+    iv: &IV,
+    secret: &[u8],
+    aad: &[u8],
+    in_out: &mut Vec<u8>,
+) -> Result<(), CryptoError> {
     let nonce = ring::aead::Nonce::assume_unique_for_key(*iv);
     let key = load_key(secret)?;
 
     key.0
-        .seal_in_place_append_tag(nonce, ring::aead::Aad::empty(), in_out)
+        .seal_in_place_append_tag(nonce, ring::aead::Aad::from(aad), in_out)
         .map_err(|_| CryptoError::AeadEncryptError)?;
     Ok(())
 }
@@ -40,13 +51,27 @@ pub fn decrypt<'in_out>(
     secret: &[u8],
     in_out: &'in_out mut [u8],
 ) -> Result<&'in_out mut [u8], CryptoError> {
+    decrypt_with_aad(iv, secret, &[], in_out)
+}
+
+// Decrypts the cipher in-place like `decrypt`. Fails if the associated data differs from the one
+// used to encrypt.
+pub fn decrypt_with_aad<'in_out>(
+    iv: &[u8],
+    secret: &[u8],
+    aad: &[u8],
+    in_out: &'in_out mut [u8],
+) -> Result<&'in_out mut [u8], CryptoError> {
+    if iv.len() < IV_BYTES {
+        return Err(CryptoError::AeadDecryptError);
+    }
     let mut iv_arr = [0_u8; IV_BYTES];
     iv_arr.copy_from_slice(&iv[..IV_BYTES]);
     let key = load_key(secret)?;
     let nonce = ring::aead::Nonce::assume_unique_for_key(iv_arr);
 
     key.0
-        .open_in_place(nonce, ring::aead::Aad::empty(), in_out)
+        .open_in_place(nonce, ring::aead::Aad::from(aad), in_out)
         .map_err(|_| CryptoError::AeadDecryptError)
 }
 
@@ -76,4 +101,23 @@ mod test {
 
         assert_eq!(decrypted_messgae, message);
     }
+
+    #[test]
+    fn encrypt_and_decrypt_with_aad() {
+        let iv = generate_random_iv();
+        let secret = [233_u8; 32];
+        let message = [233_u8; 64];
+
+        let mut encrypted_message = message.to_vec();
+        encrypt_with_aad(&iv, &secret, b"topic", &mut encrypted_message).unwrap();
+
+        let mut tmp = encrypted_message.clone();
+        assert!(decrypt_with_aad(&iv, &secret, b"other topic", &mut tmp[..]).is_err());
+        let mut tmp = encrypted_message.clone();
+        assert!(decrypt(&iv, &secret, &mut tmp[..]).is_err());
+
+        let decrypted =
+            decrypt_with_aad(&iv, &secret, b"topic", &mut encrypted_message[..]).unwrap();
+        assert_eq!(decrypted, message);
+    }
 }
@@ -1,7 +1,9 @@
 use crate::CryptoError;
 
-use alloc::vec::Vec;
+use alloc::{vec, vec::Vec};
 use curve25519_dalek::scalar::Scalar;
+use parity_scale_codec::{Decode, Encode};
+use ring::hkdf;
 use schnorrkel::keys::{ExpansionMode, Keypair, MiniSecretKey, PublicKey, SecretKey};
 use schnorrkel::{MINI_SECRET_KEY_LENGTH, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH};
 
@@ -52,6 +54,84 @@ pub fn agree(sk: &EcdhKey, pk: &[u8]) -> Result<Vec<u8>, CryptoError> {
     Ok((key * public.as_point()).compress().0.to_vec())
 }
 
+/// Domain separation labels for the keys derived with `EncryptionScheme::HkdfSha256`
+pub mod context {
+    /// Payloads of the secret message channels
+    pub const MQ_PAYLOAD: &[u8] = b"phala/ecdh/mq-payload";
+    /// Contract queries and their responses
+    pub const CONTRACT_QUERY: &[u8] = b"phala/ecdh/contract-query";
+    /// Master key distributed by the gatekeepers
+    pub const MASTER_KEY: &[u8] = b"phala/ecdh/master-key";
+    /// Secrets handed over to another pRuntime build
+    pub const HANDOVER: &[u8] = b"phala/ecdh/handover";
+}
+
+const HKDF_SALT: &[u8] = b"phala/ecdh/hkdf-sha256/v1";
+
+/// The way to derive the symmetric key from the ECDH shared secret.
+///
+/// The scheme is tagged along with the encrypted data, so that the receiver can pick the same one.
+/// New schemes must be appended at the end.
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncryptionScheme {
+    /// The shared secret is used as the key directly. Only kept for the existing clients.
+    Raw,
+    /// HKDF-SHA256 over the shared secret, with the context label and the both public keys as the
+    /// info.
+    HkdfSha256,
+}
+
+impl EncryptionScheme {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(EncryptionScheme::Raw),
+            1 => Some(EncryptionScheme::HkdfSha256),
+            _ => None,
+        }
+    }
+
+    pub fn to_u32(self) -> u32 {
+        match self {
+            EncryptionScheme::Raw => 0,
+            EncryptionScheme::HkdfSha256 => 1,
+        }
+    }
+}
+
+/// Derives a 256-bit secret key for symmetric encryption with the given scheme
+///
+/// `context` is ignored by `EncryptionScheme::Raw`. Both sides get the same key.
+pub fn agree_with(
+    scheme: EncryptionScheme,
+    sk: &EcdhKey,
+    pk: &[u8],
+    context: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    let shared = agree(sk, pk)?;
+    match scheme {
+        EncryptionScheme::Raw => Ok(shared),
+        EncryptionScheme::HkdfSha256 => {
+            // Order the public keys so that both sides build the same info
+            let my_pk = sk.public();
+            let (pk_lo, pk_hi) = if my_pk[..] <= pk[..] {
+                (&my_pk[..], pk)
+            } else {
+                (pk, &my_pk[..])
+            };
+            let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, HKDF_SALT);
+            let prk = salt.extract(&shared);
+            let info = [context, pk_lo, pk_hi];
+            let okm = prk
+                .expand(&info, hkdf::HKDF_SHA256)
+                .map_err(|_| CryptoError::HkdfExpandError)?;
+            let mut key = vec![0_u8; 32];
+            okm.fill(&mut key)
+                .map_err(|_| CryptoError::HkdfExpandError)?;
+            Ok(key)
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -96,4 +176,19 @@ mod test {
             agree(&key2, key1.public().as_ref()).unwrap(),
         )
     }
+
+    #[test]
+    fn ecdh_agree_hkdf() {
+        let key1 = generate_key();
+        let key2 = generate_key();
+        let derive = |sk: &EcdhKey, pk: &EcdhKey, context: &[u8]| {
+            agree_with(EncryptionScheme::HkdfSha256, sk, &pk.public(), context).unwrap()
+        };
+
+        let key = derive(&key1, &key2, context::MQ_PAYLOAD);
+        assert_eq!(key.len(), 32);
+        assert_eq!(key, derive(&key2, &key1, context::MQ_PAYLOAD));
+        assert_ne!(key, derive(&key1, &key2, context::CONTRACT_QUERY));
+        assert_ne!(key, agree(&key1, &key2.public()).unwrap());
+    }
 }
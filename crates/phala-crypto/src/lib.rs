@@ -6,9 +6,15 @@ extern crate alloc;
 #[macro_use]
 extern crate std;
 
-pub mod ecdh;
+#[cfg(feature = "full_crypto")]
 pub mod aead;
+#[cfg(feature = "full_crypto")]
+pub mod ecdh;
+#[cfg(feature = "full_crypto")]
 pub mod sr25519;
+#[cfg(feature = "full_crypto")]
+pub mod threshold;
+pub mod vrf;
 
 #[derive(Debug)]
 pub enum CryptoError {
@@ -20,4 +26,13 @@ pub enum CryptoError {
     AeadInvalidKey,
     AeadEncryptError,
     AeadDecryptError,
+    // Threshold errors
+    ThresholdInvalidParameters,
+    ThresholdDuplicatedShare,
+    ThresholdInvalidShare,
+    ThresholdInvalidPoint,
+    ThresholdInvalidProof,
+    // Vrf errors
+    VrfInvalidPublicKey,
+    VrfInvalidProof,
 }
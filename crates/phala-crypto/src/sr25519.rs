@@ -20,8 +20,15 @@ pub trait Signing {
     fn verify_data(&self, sig: &Signature, data: &[u8]) -> bool;
 }
 
+/// The HKDF salt, which should be unique to each network
+pub type KdfSalt = [u8; 32];
+
 pub trait KDF {
-    fn derive_sr25519_pair(&self, info: &[&[u8]]) -> Result<sr25519::Pair, CryptoError>;
+    fn derive_sr25519_pair(
+        &self,
+        salt: &KdfSalt,
+        info: &[&[u8]],
+    ) -> Result<sr25519::Pair, CryptoError>;
 
     fn derive_ecdh_key(&self) -> Result<EcdhKey, CryptoError>;
 }
@@ -46,12 +53,21 @@ impl Signing for sr25519::Pair {
     }
 }
 
-// Phala Network Poc-4 Genesis Block Hash
-const KDF_SALT: [u8; 32] = [
+/// The salt of the keys derived before it's persisted with the runtime data, which is the Phala
+/// Network PoC-4 genesis block hash
+pub const KDF_SALT: KdfSalt = [
     0x18, 0xe8, 0x76, 0xad, 0xfa, 0x74, 0xcc, 0x74, 0x3b, 0x4b, 0x7d, 0x7f, 0x92, 0xc9, 0x6e, 0x03,
     0xde, 0x55, 0x9a, 0x3c, 0x15, 0x27, 0x05, 0x81, 0xfc, 0xe4, 0x45, 0x96, 0x3d, 0x90, 0xf8, 0x5e,
 ];
 
+/// Derives the KDF salt of a network from its genesis block hash, so that the keys derived on
+/// different networks never collide.
+///
+/// The genesis block hash is used as is, which keeps the keys derived on Phala PoC-4 unchanged.
+pub fn kdf_salt_from_genesis(genesis_block_hash: &[u8; 32]) -> KdfSalt {
+    *genesis_block_hash
+}
+
 /// Generic newtype wrapper that lets us implement traits for externally-defined
 /// types.
 ///
@@ -74,9 +90,12 @@ impl From<hkdf::Okm<'_, My<usize>>> for My<Vec<u8>> {
 }
 
 impl KDF for sr25519::Pair {
-    // TODO.shelven: allow to specify the salt from pruntime (instead of hard code)
-    fn derive_sr25519_pair(&self, info: &[&[u8]]) -> Result<sr25519::Pair, CryptoError> {
-        let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, &KDF_SALT);
+    fn derive_sr25519_pair(
+        &self,
+        salt: &KdfSalt,
+        info: &[&[u8]],
+    ) -> Result<sr25519::Pair, CryptoError> {
+        let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, salt);
         let prk = salt.extract(&self.as_ref().secret.to_bytes());
         let okm = prk
             .expand(info, My(SEED_BYTES))
@@ -152,9 +171,19 @@ mod test {
         let (sr25519_key, _) = generate_key();
         // this should not panic
         sr25519_key
-            .derive_sr25519_pair(&[&[255], &[255, 255], &[255, 255, 255]])
+            .derive_sr25519_pair(&[0; 32], &[&[255], &[255, 255], &[255, 255, 255]])
             .unwrap();
 
+        // keys derived with different salts never collide
+        let derive = |salt: &KdfSalt| {
+            sr25519_key
+                .derive_sr25519_pair(salt, &[b"info"])
+                .unwrap()
+                .to_raw_vec()
+        };
+        assert_ne!(derive(&[0; 32]), derive(&[1; 32]));
+        assert_eq!(derive(&[1; 32]), derive(&[1; 32]));
+
         let ecdh_key = sr25519_key.derive_ecdh_key().unwrap();
         // this should not panic
         ecdh_key.public();
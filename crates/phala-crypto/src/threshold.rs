@@ -0,0 +1,336 @@
+//! Shamir secret sharing of a Ristretto scalar, and a threshold PRF on top of it.
+//!
+//! The secret `s` is split into `n` shares `s_i = f(i)`, where `f` is a random polynomial of
+//! degree `t - 1` and `f(0) = s`. A holder evaluates the PRF by publishing `s_i * H(input)`
+//! together with a DLEQ proof that ties it to its public share `s_i * G`. Any `t` valid
+//! evaluations combine into `s * H(input)` by Lagrange interpolation in the exponent, so the PRF
+//! can be evaluated without ever reconstructing `s`.
+//!
+//! `s * G` equals the sr25519 public key when `s` is the scalar of an sr25519 secret key.
+
+use crate::CryptoError;
+
+use alloc::vec::Vec;
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::Identity,
+};
+use parity_scale_codec::{Decode, Encode};
+use ring::digest;
+
+/// The 1-based x-coordinate of a share
+pub type ShareIndex = u32;
+pub type ScalarBytes = [u8; 32];
+pub type PointBytes = [u8; 32];
+
+/// The share of the secret held by a single party
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct KeyShare {
+    pub index: ShareIndex,
+    pub secret: ScalarBytes,
+}
+
+/// A proof that `log_G(public) == log_H(point)`
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct DleqProof {
+    pub challenge: ScalarBytes,
+    pub response: ScalarBytes,
+}
+
+/// The PRF evaluated with a single share
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct PartialEvaluation {
+    pub index: ShareIndex,
+    pub point: PointBytes,
+    pub proof: DleqProof,
+}
+
+fn hash_to_scalar(domain: &[u8], parts: &[&[u8]]) -> Scalar {
+    let mut ctx = digest::Context::new(&digest::SHA512);
+    ctx.update(domain);
+    for part in parts {
+        ctx.update(part);
+    }
+    let mut wide = [0u8; 64];
+    wide.copy_from_slice(ctx.finish().as_ref());
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+fn hash_to_point(input: &[u8]) -> RistrettoPoint {
+    let mut ctx = digest::Context::new(&digest::SHA512);
+    ctx.update(b"phala/threshold/hash-to-point");
+    ctx.update(input);
+    let mut wide = [0u8; 64];
+    wide.copy_from_slice(ctx.finish().as_ref());
+    RistrettoPoint::from_uniform_bytes(&wide)
+}
+
+fn decode_scalar(bytes: &ScalarBytes) -> Result<Scalar, CryptoError> {
+    Scalar::from_canonical_bytes(*bytes).ok_or(CryptoError::ThresholdInvalidShare)
+}
+
+fn decode_point(bytes: &PointBytes) -> Result<RistrettoPoint, CryptoError> {
+    CompressedRistretto(*bytes)
+        .decompress()
+        .ok_or(CryptoError::ThresholdInvalidPoint)
+}
+
+/// The Lagrange coefficient of `index` to interpolate `f(0)` from the shares at `indexes`
+fn lagrange_coefficient(index: ShareIndex, indexes: &[ShareIndex]) -> Scalar {
+    let xi = Scalar::from(index);
+    let mut numerator = Scalar::one();
+    let mut denominator = Scalar::one();
+    for &j in indexes.iter().filter(|&&j| j != index) {
+        let xj = Scalar::from(j);
+        numerator *= xj;
+        denominator *= xj - xi;
+    }
+    numerator * denominator.invert()
+}
+
+fn check_indexes(indexes: &[ShareIndex]) -> Result<(), CryptoError> {
+    if indexes.is_empty() || indexes.contains(&0) {
+        return Err(CryptoError::ThresholdInvalidParameters);
+    }
+    for (i, index) in indexes.iter().enumerate() {
+        if indexes[..i].contains(index) {
+            return Err(CryptoError::ThresholdDuplicatedShare);
+        }
+    }
+    Ok(())
+}
+
+fn interpolate_points(
+    points: &[(ShareIndex, RistrettoPoint)],
+) -> Result<RistrettoPoint, CryptoError> {
+    let indexes: Vec<ShareIndex> = points.iter().map(|(index, _)| *index).collect();
+    check_indexes(&indexes)?;
+    Ok(points
+        .iter()
+        .fold(RistrettoPoint::identity(), |acc, (index, point)| {
+            acc + lagrange_coefficient(*index, &indexes) * point
+        }))
+}
+
+/// Splits `secret` into `shares` shares, `threshold` of which are needed to recover it
+///
+/// The polynomial coefficients are derived from `seed`, which must be kept secret and never be
+/// reused.
+pub fn split(
+    secret: &ScalarBytes,
+    threshold: u32,
+    shares: u32,
+    seed: &[u8],
+) -> Result<Vec<KeyShare>, CryptoError> {
+    if threshold == 0 || threshold > shares {
+        return Err(CryptoError::ThresholdInvalidParameters);
+    }
+    let mut coefficients = Vec::with_capacity(threshold as usize);
+    coefficients.push(decode_scalar(secret)?);
+    for j in 1..threshold {
+        coefficients.push(hash_to_scalar(
+            b"phala/threshold/coefficient",
+            &[seed, &j.to_be_bytes()],
+        ));
+    }
+    Ok((1..=shares)
+        .map(|index| {
+            let x = Scalar::from(index);
+            // Horner's method
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::zero(), |acc, coefficient| acc * x + coefficient);
+            KeyShare {
+                index,
+                secret: y.to_bytes(),
+            }
+        })
+        .collect())
+}
+
+/// Recovers the secret from at least `threshold` shares
+pub fn recover(shares: &[KeyShare]) -> Result<ScalarBytes, CryptoError> {
+    let indexes: Vec<ShareIndex> = shares.iter().map(|share| share.index).collect();
+    check_indexes(&indexes)?;
+    let mut secret = Scalar::zero();
+    for share in shares {
+        secret += lagrange_coefficient(share.index, &indexes) * decode_scalar(&share.secret)?;
+    }
+    Ok(secret.to_bytes())
+}
+
+/// Interpolates the public key `s * G` from at least `threshold` public shares
+pub fn recover_public(shares: &[(ShareIndex, PointBytes)]) -> Result<PointBytes, CryptoError> {
+    let points = shares
+        .iter()
+        .map(|(index, public)| Ok((*index, decode_point(public)?)))
+        .collect::<Result<Vec<_>, CryptoError>>()?;
+    Ok(interpolate_points(&points)?.compress().to_bytes())
+}
+
+fn dleq_challenge(
+    public: &RistrettoPoint,
+    base: &RistrettoPoint,
+    point: &RistrettoPoint,
+    commitment_g: &RistrettoPoint,
+    commitment_h: &RistrettoPoint,
+) -> Scalar {
+    hash_to_scalar(
+        b"phala/threshold/dleq",
+        &[
+            public.compress().as_bytes(),
+            base.compress().as_bytes(),
+            point.compress().as_bytes(),
+            commitment_g.compress().as_bytes(),
+            commitment_h.compress().as_bytes(),
+        ],
+    )
+}
+
+impl KeyShare {
+    /// The public share `s_i * G`
+    pub fn public(&self) -> Result<PointBytes, CryptoError> {
+        let secret = decode_scalar(&self.secret)?;
+        Ok((secret * RISTRETTO_BASEPOINT_POINT).compress().to_bytes())
+    }
+
+    /// Evaluates the PRF on `input` with this share
+    ///
+    /// The proof nonce is derived from the share and the input, so the evaluation is
+    /// deterministic.
+    pub fn evaluate(&self, input: &[u8]) -> Result<PartialEvaluation, CryptoError> {
+        let secret = decode_scalar(&self.secret)?;
+        let public = secret * RISTRETTO_BASEPOINT_POINT;
+        let base = hash_to_point(input);
+        let point = secret * base;
+
+        let nonce = hash_to_scalar(b"phala/threshold/nonce", &[&self.secret, input]);
+        let commitment_g = nonce * RISTRETTO_BASEPOINT_POINT;
+        let commitment_h = nonce * base;
+        let challenge = dleq_challenge(&public, &base, &point, &commitment_g, &commitment_h);
+        let response = nonce + challenge * secret;
+
+        Ok(PartialEvaluation {
+            index: self.index,
+            point: point.compress().to_bytes(),
+            proof: DleqProof {
+                challenge: challenge.to_bytes(),
+                response: response.to_bytes(),
+            },
+        })
+    }
+}
+
+/// Verifies a partial evaluation on `input` against the public share of its holder
+pub fn verify_partial(
+    public: &PointBytes,
+    input: &[u8],
+    partial: &PartialEvaluation,
+) -> Result<(), CryptoError> {
+    let public = decode_point(public)?;
+    let point = decode_point(&partial.point)?;
+    let challenge = Scalar::from_canonical_bytes(partial.proof.challenge)
+        .ok_or(CryptoError::ThresholdInvalidProof)?;
+    let response = Scalar::from_canonical_bytes(partial.proof.response)
+        .ok_or(CryptoError::ThresholdInvalidProof)?;
+    let base = hash_to_point(input);
+
+    let commitment_g = response * RISTRETTO_BASEPOINT_POINT - challenge * public;
+    let commitment_h = response * base - challenge * point;
+    if dleq_challenge(&public, &base, &point, &commitment_g, &commitment_h) != challenge {
+        return Err(CryptoError::ThresholdInvalidProof);
+    }
+    Ok(())
+}
+
+/// Combines at least `threshold` verified partial evaluations into `s * H(input)`
+pub fn combine(partials: &[PartialEvaluation]) -> Result<PointBytes, CryptoError> {
+    let points = partials
+        .iter()
+        .map(|partial| Ok((partial.index, decode_point(&partial.point)?)))
+        .collect::<Result<Vec<_>, CryptoError>>()?;
+    Ok(interpolate_points(&points)?.compress().to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    fn random_secret() -> ScalarBytes {
+        let mut wide = [0u8; 64];
+        rand::thread_rng().fill_bytes(&mut wide);
+        Scalar::from_bytes_mod_order_wide(&wide).to_bytes()
+    }
+
+    #[test]
+    fn split_and_recover() {
+        let secret = random_secret();
+        let shares = split(&secret, 3, 5, b"seed").unwrap();
+        assert_eq!(shares.len(), 5);
+
+        assert_eq!(recover(&shares[..3]).unwrap(), secret);
+        assert_eq!(recover(&shares[2..]).unwrap(), secret);
+        assert_eq!(recover(&shares).unwrap(), secret);
+        assert_ne!(recover(&shares[..2]).unwrap(), secret);
+
+        let publics: Vec<_> = shares
+            .iter()
+            .map(|share| (share.index, share.public().unwrap()))
+            .collect();
+        let expected = (decode_scalar(&secret).unwrap() * RISTRETTO_BASEPOINT_POINT)
+            .compress()
+            .to_bytes();
+        assert_eq!(recover_public(&publics[1..4]).unwrap(), expected);
+
+        assert!(split(&secret, 0, 5, b"seed").is_err());
+        assert!(split(&secret, 6, 5, b"seed").is_err());
+        let duplicated = [shares[0].clone(), shares[0].clone(), shares[1].clone()];
+        assert!(recover(&duplicated).is_err());
+    }
+
+    #[test]
+    fn threshold_evaluation() {
+        let secret = random_secret();
+        let shares = split(&secret, 2, 3, b"seed").unwrap();
+        let input = b"block 10";
+
+        let partials: Vec<_> = shares
+            .iter()
+            .map(|share| share.evaluate(input).unwrap())
+            .collect();
+        for (share, partial) in shares.iter().zip(partials.iter()) {
+            verify_partial(&share.public().unwrap(), input, partial).unwrap();
+            // bound to both the holder and the input
+            assert!(verify_partial(&share.public().unwrap(), b"block 11", partial).is_err());
+        }
+        assert!(verify_partial(&shares[1].public().unwrap(), input, &partials[0]).is_err());
+
+        let expected = (decode_scalar(&secret).unwrap() * hash_to_point(input))
+            .compress()
+            .to_bytes();
+        assert_eq!(combine(&partials[..2]).unwrap(), expected);
+        assert_eq!(combine(&partials[1..]).unwrap(), expected);
+    }
+
+    #[test]
+    fn sr25519_public_key() {
+        use schnorrkel::{ExpansionMode, MiniSecretKey};
+
+        let pair = MiniSecretKey::from_bytes(&[7u8; 32])
+            .unwrap()
+            .expand_to_keypair(ExpansionMode::Ed25519);
+        let mut secret = [0u8; 32];
+        secret.copy_from_slice(&pair.secret.to_bytes()[..32]);
+
+        let shares = split(&secret, 2, 2, b"seed").unwrap();
+        let publics: Vec<_> = shares
+            .iter()
+            .map(|share| (share.index, share.public().unwrap()))
+            .collect();
+        assert_eq!(recover_public(&publics).unwrap(), pair.public.to_bytes());
+    }
+}
@@ -0,0 +1,120 @@
+//! Deterministic schnorrkel VRF on sr25519 keys.
+//!
+//! The proofs are derandomized: the nonce is derived from the secret key and the transcript only,
+//! so that every holder of the same key produces exactly the same proof for the same message.
+//! This is safe for the plain DLEQ proofs of the VRF, though it must never be used for
+//! multi-signatures.
+
+use crate::CryptoError;
+
+use merlin::Transcript;
+use rand_core::{CryptoRng, RngCore};
+use schnorrkel::{
+    context::attach_rng,
+    signing_context,
+    vrf::{VRFOutput, VRFProof},
+    Keypair, PublicKey,
+};
+
+pub type VrfOutput = [u8; 32];
+pub type VrfProof = [u8; 64];
+
+/// An RNG providing no randomness at all, to make the proofs deterministic
+struct NoRng;
+
+impl RngCore for NoRng {
+    fn next_u32(&mut self) -> u32 {
+        0
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        0
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        dest.iter_mut().for_each(|b| *b = 0);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for NoRng {}
+
+/// Evaluates the VRF on `message`, returning the randomness derived with `label`, the VRF output
+/// and its proof
+pub fn vrf_sign(
+    keypair: &Keypair,
+    context: &[u8],
+    message: &[u8],
+    label: &[u8],
+) -> ([u8; 32], VrfOutput, VrfProof) {
+    let (inout, proof, _) = keypair.vrf_sign_extra(
+        signing_context(context).bytes(message),
+        attach_rng(Transcript::new(b"VRF"), NoRng),
+    );
+    (
+        inout.make_bytes(label),
+        inout.to_output().to_bytes(),
+        proof.to_bytes(),
+    )
+}
+
+/// Verifies the VRF output of `message`, returning the randomness derived with `label`
+pub fn vrf_verify(
+    public: &[u8; 32],
+    context: &[u8],
+    message: &[u8],
+    label: &[u8],
+    output: &VrfOutput,
+    proof: &VrfProof,
+) -> Result<[u8; 32], CryptoError> {
+    let public = PublicKey::from_bytes(public).or(Err(CryptoError::VrfInvalidPublicKey))?;
+    let output = VRFOutput::from_bytes(output).or(Err(CryptoError::VrfInvalidProof))?;
+    let proof = VRFProof::from_bytes(proof).or(Err(CryptoError::VrfInvalidProof))?;
+    let (inout, _) = public
+        .vrf_verify(signing_context(context).bytes(message), &output, &proof)
+        .or(Err(CryptoError::VrfInvalidProof))?;
+    Ok(inout.make_bytes(label))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schnorrkel::MiniSecretKey;
+
+    const CONTEXT: &[u8] = b"test context";
+    const LABEL: &[u8] = b"test label";
+
+    fn keypair(seed: u8) -> Keypair {
+        MiniSecretKey::from_bytes(&[seed; 32])
+            .unwrap()
+            .expand_to_keypair(MiniSecretKey::UNIFORM_MODE)
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let key = keypair(1);
+        let (randomness, output, proof) = vrf_sign(&key, CONTEXT, b"message", LABEL);
+        let public = key.public.to_bytes();
+        assert_eq!(
+            vrf_verify(&public, CONTEXT, b"message", LABEL, &output, &proof).unwrap(),
+            randomness
+        );
+
+        // Deterministic
+        assert_eq!(
+            vrf_sign(&key, CONTEXT, b"message", LABEL),
+            (randomness, output, proof)
+        );
+
+        assert!(vrf_verify(&public, CONTEXT, b"other message", LABEL, &output, &proof).is_err());
+        let other_public = keypair(2).public.to_bytes();
+        assert!(vrf_verify(&other_public, CONTEXT, b"message", LABEL, &output, &proof).is_err());
+        let mut bad_proof = proof;
+        bad_proof[0] ^= 1;
+        assert!(vrf_verify(&public, CONTEXT, b"message", LABEL, &output, &bad_proof).is_err());
+    }
+}
@@ -1,11 +1,14 @@
 use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{Context, Poll};
 
 use alloc::{collections::BTreeMap, vec::Vec};
 
-use crate::simple_mpsc::{channel, ReceiveError, Receiver as RawReceiver, Sender, Seq};
+use crate::simple_mpsc::{channel, ReceiveError, Receiver as RawReceiver, SendError, Sender, Seq};
 use crate::types::{Message, Path};
 use crate::{BindTopic, MessageOrigin};
 use derive_more::Display;
+use futures_core::Stream;
 use parity_scale_codec::{Decode, Error as CodecError};
 
 impl Seq for (u64, Message) {
@@ -14,19 +17,50 @@ impl Seq for (u64, Message) {
     }
 }
 
+impl Seq for (u64, Message, Option<u64>) {
+    fn seq(&self) -> u64 {
+        self.0
+    }
+}
+
+enum Subscriber {
+    Plain(Sender<(u64, Message)>),
+    Sequenced(Sender<(u64, Message, Option<u64>)>),
+}
+
+impl Subscriber {
+    fn send(&self, sn: u64, message: &Message, sequence: Option<u64>) -> Result<(), SendError> {
+        match self {
+            Self::Plain(sender) => sender.send((sn, message.clone())),
+            Self::Sequenced(sender) => sender.send((sn, message.clone(), sequence)),
+        }
+    }
+
+    fn clear(&self) -> usize {
+        match self {
+            Self::Plain(sender) => sender.clear(),
+            Self::Sequenced(sender) => sender.clear(),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct MessageDispatcher {
-    subscribers: BTreeMap<Path, Vec<Sender<(u64, Message)>>>,
+    subscribers: BTreeMap<Path, Vec<Subscriber>>,
+    prefix_subscribers: BTreeMap<Path, Vec<Subscriber>>,
     local_index: u64,
-    //match_subscribers: Vec<Matcher, Vec<Sender<Message>>>,
 }
 
 pub type Receiver<T> = RawReceiver<(u64, T)>;
 
+/// The receiving end of the dispatched messages, with the egress sequence of the sender if known.
+pub type SequencedReceiver<T> = RawReceiver<(u64, T, Option<u64>)>;
+
 impl MessageDispatcher {
     pub fn new() -> Self {
         MessageDispatcher {
             subscribers: Default::default(),
+            prefix_subscribers: Default::default(),
             local_index: 0,
         }
     }
@@ -36,34 +70,64 @@ impl MessageDispatcher {
     pub fn subscribe(&mut self, path: impl Into<Path>) -> Receiver<Message> {
         let (rx, tx) = channel();
         let entry = self.subscribers.entry(path.into()).or_default();
-        entry.push(tx);
+        entry.push(Subscriber::Plain(tx));
+        rx
+    }
+
+    /// Same as `subscribe`, but the messages come with the egress sequences of their senders.
+    pub fn subscribe_with_sequence(&mut self, path: impl Into<Path>) -> SequencedReceiver<Message> {
+        let (rx, tx) = channel();
+        let entry = self.subscribers.entry(path.into()).or_default();
+        entry.push(Subscriber::Sequenced(tx));
+        rx
+    }
+
+    /// Subscribe messages which are sent to any path starting with `prefix`.
+    /// Returns a Receiver channel end.
+    ///
+    /// A message matching both exact and prefix subscriptions is delivered to each of them, with
+    /// the same sequence number.
+    pub fn subscribe_prefix(&mut self, prefix: impl Into<Path>) -> Receiver<Message> {
+        let (rx, tx) = channel();
+        let entry = self.prefix_subscribers.entry(prefix.into()).or_default();
+        entry.push(Subscriber::Plain(tx));
         rx
     }
 
+    /// Subscribe all dispatched messages, regardless of the destination.
+    /// Returns a Receiver channel end.
+    pub fn tap(&mut self) -> Receiver<Message> {
+        self.subscribe_prefix(Path::new())
+    }
+
     /// Subscribe messages which implementing BindTopic
     /// Returns a TypedReceiver channel end.
     pub fn subscribe_bound<T: Decode + BindTopic>(&mut self) -> TypedReceiver<T> {
-        self.subscribe(<T as BindTopic>::topic()).into()
+        self.subscribe_with_sequence(<T as BindTopic>::topic())
+            .into()
     }
 
     /// Dispatch a message.
     /// Returns number of receivers dispatched to.
     pub fn dispatch(&mut self, message: Message) -> usize {
+        self.dispatch_with_sequence(message, None)
+    }
+
+    /// Dispatch a message with the egress sequence assigned by its sender, which is only known
+    /// for the messages synced from offchain.
+    /// Returns number of receivers dispatched to.
+    pub fn dispatch_with_sequence(&mut self, message: Message, sequence: Option<u64>) -> usize {
         let mut count = 0;
         let sn = self.local_index;
         self.local_index += 1;
-        if let Some(receivers) = self.subscribers.get_mut(message.destination.path()) {
-            receivers.retain(|receiver| {
-                if let Err(error) = receiver.send((sn, message.clone())) {
-                    use crate::simple_mpsc::SendError::*;
-                    match error {
-                        ReceiverGone => false,
-                    }
-                } else {
-                    count += 1;
-                    true
-                }
-            });
+        let path = message.destination.path();
+        if let Some(receivers) = self.subscribers.get_mut(path) {
+            count += send_to(receivers, sn, &message, sequence);
+        }
+        for (prefix, receivers) in self.prefix_subscribers.iter_mut() {
+            if path.starts_with(prefix) {
+                count += send_to(receivers, sn, &message, sequence);
+            }
         }
         count
     }
@@ -75,13 +139,41 @@ impl MessageDispatcher {
     /// Drop all unhandled messages.
     pub fn clear(&mut self) -> usize {
         let mut count = 0;
-        for subscriber in self.subscribers.values_mut().flatten() {
+        for subscriber in self
+            .subscribers
+            .values_mut()
+            .chain(self.prefix_subscribers.values_mut())
+            .flatten()
+        {
             count += subscriber.clear();
         }
         count
     }
 }
 
+/// Sends the message to the receivers and drops the gone ones.
+/// Returns number of receivers sent to.
+fn send_to(
+    receivers: &mut Vec<Subscriber>,
+    sn: u64,
+    message: &Message,
+    sequence: Option<u64>,
+) -> usize {
+    let mut count = 0;
+    receivers.retain(|receiver| {
+        if let Err(error) = receiver.send(sn, message, sequence) {
+            use crate::simple_mpsc::SendError::*;
+            match error {
+                ReceiverGone => false,
+            }
+        } else {
+            count += 1;
+            true
+        }
+    });
+    count
+}
+
 #[derive(Display, Debug)]
 pub enum TypedReceiveError {
     #[display(fmt = "All senders of the channel have gone")]
@@ -96,22 +188,63 @@ impl From<CodecError> for TypedReceiveError {
     }
 }
 
+enum Queue {
+    Plain(Receiver<Message>),
+    Sequenced(SequencedReceiver<Message>),
+}
+
+impl Queue {
+    fn try_next(&mut self) -> Result<Option<(u64, Message, Option<u64>)>, ReceiveError> {
+        match self {
+            Self::Plain(queue) => Ok(queue.try_next()?.map(|(sn, msg)| (sn, msg, None))),
+            Self::Sequenced(queue) => queue.try_next(),
+        }
+    }
+
+    fn peek_ind(&self) -> Result<Option<u64>, ReceiveError> {
+        match self {
+            Self::Plain(queue) => queue.peek_ind(),
+            Self::Sequenced(queue) => queue.peek_ind(),
+        }
+    }
+
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<(u64, Message)>> {
+        match self {
+            Self::Plain(queue) => Pin::new(queue).poll_next(cx),
+            Self::Sequenced(queue) => Pin::new(queue)
+                .poll_next(cx)
+                .map(|message| message.map(|(sn, msg, _)| (sn, msg))),
+        }
+    }
+}
+
 pub struct TypedReceiver<T> {
-    queue: Receiver<Message>,
+    queue: Queue,
     _t: PhantomData<T>,
 }
 
 impl<T: Decode> TypedReceiver<T> {
     pub fn try_next(&mut self) -> Result<Option<(u64, T, MessageOrigin)>, TypedReceiveError> {
+        Ok(self
+            .try_next_with_sequence()?
+            .map(|(sn, typed, origin, _)| (sn, typed, origin)))
+    }
+
+    /// Same as `try_next`, but also returns the egress sequence of the sender if known.
+    ///
+    /// The sequences are only known by the receivers created from `SequencedReceiver`s.
+    pub fn try_next_with_sequence(
+        &mut self,
+    ) -> Result<Option<(u64, T, MessageOrigin, Option<u64>)>, TypedReceiveError> {
         let message = self.queue.try_next().map_err(|e| match e {
             ReceiveError::SenderGone => TypedReceiveError::SenderGone,
         })?;
-        let (sn, msg) = match message {
+        let (sn, msg, sequence) = match message {
             None => return Ok(None),
             Some(m) => m,
         };
         let typed = Decode::decode(&mut &msg.payload[..])?;
-        Ok(Some((sn, typed, msg.sender)))
+        Ok(Some((sn, typed, msg.sender, sequence)))
     }
 
     pub fn peek_ind(&self) -> Result<Option<u64>, ReceiveError> {
@@ -119,10 +252,36 @@ impl<T: Decode> TypedReceiver<T> {
     }
 }
 
+// The receiver doesn't hold any T.
+impl<T> Unpin for TypedReceiver<T> {}
+
+/// The stream ends when all senders have gone. Messages failing to decode are yielded as errors.
+impl<T: Decode> Stream for TypedReceiver<T> {
+    type Item = Result<(u64, T, MessageOrigin), TypedReceiveError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.queue.poll_next(cx).map(|message| {
+            message.map(|(sn, msg)| {
+                let typed = Decode::decode(&mut &msg.payload[..])?;
+                Ok((sn, typed, msg.sender))
+            })
+        })
+    }
+}
+
 impl<T: Decode> From<Receiver<Message>> for TypedReceiver<T> {
     fn from(queue: Receiver<Message>) -> Self {
         Self {
-            queue,
+            queue: Queue::Plain(queue),
+            _t: Default::default(),
+        }
+    }
+}
+
+impl<T: Decode> From<SequencedReceiver<Message>> for TypedReceiver<T> {
+    fn from(queue: SequencedReceiver<Message>) -> Self {
+        Self {
+            queue: Queue::Sequenced(queue),
             _t: Default::default(),
         }
     }
@@ -1,5 +1,8 @@
 #![no_std]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 #[cfg(feature = "sgx")]
 pub extern crate serde_sgx as serde;
 
@@ -18,7 +21,10 @@ mod simple_mpsc;
 #[cfg(feature = "dispatcher")]
 pub use dispatcher::{MessageDispatcher, TypedReceiveError, TypedReceiver};
 #[cfg(feature = "queue")]
-pub use send_queue::{MessageChannel, MessageSendQueue};
+pub use send_queue::{
+    MessageChannel, MessageSendQueue, MessageSendQueueSnapshot, OverflowPolicy, QueueLimit,
+    SendError, SenderQueueStats,
+};
 #[cfg(any(feature = "queue", feature = "dispatcher"))]
 pub use simple_mpsc::{ReceiveError, Receiver};
 
@@ -26,12 +32,40 @@ pub use signer::MessageSigner;
 
 pub use types::*;
 
-// TODO.kevin: use std::sync::Mutex instead.
+// Spinlocks are only used when there is no std available.
 // See:
 //    https://matklad.github.io/2020/01/02/spinlocks-considered-harmful.html
 //    https://matklad.github.io/2020/01/04/mutexes-are-faster-than-spinlocks.html
-#[cfg(any(feature = "queue", feature = "dispatcher"))]
+#[cfg(all(
+    any(feature = "queue", feature = "dispatcher"),
+    not(feature = "std"),
+    not(feature = "spin")
+))]
+compile_error!("The `spin` feature is required for the queue and the dispatcher without std");
+#[cfg(all(any(feature = "queue", feature = "dispatcher"), not(feature = "std")))]
 use spin::mutex::Mutex;
+#[cfg(all(any(feature = "queue", feature = "dispatcher"), feature = "std"))]
+use std_mutex::Mutex;
+
+#[cfg(all(any(feature = "queue", feature = "dispatcher"), feature = "std"))]
+mod std_mutex {
+    use std::sync::{self, MutexGuard};
+
+    /// A std Mutex with the same interface as spin::mutex::Mutex
+    #[derive(Default)]
+    pub struct Mutex<T>(sync::Mutex<T>);
+
+    impl<T> Mutex<T> {
+        pub fn new(value: T) -> Self {
+            Self(sync::Mutex::new(value))
+        }
+
+        pub fn lock(&self) -> MutexGuard<'_, T> {
+            // The queues are never left half-updated by a panic, so the poison can be ignored.
+            self.0.lock().unwrap_or_else(|err| err.into_inner())
+        }
+    }
+}
 
 #[cfg(all(feature = "queue", feature = "signers"))]
 pub use alias::*;
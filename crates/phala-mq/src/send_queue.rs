@@ -1,18 +1,84 @@
-use crate::types::{Message, MessageToBeSigned, SignedMessage};
+use crate::types::{Message, MessageToBeSigned, Path, SignedMessage};
 use crate::{MessageOrigin, MessageSigner, Mutex, SenderId};
 use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use derive_more::Display;
+use parity_scale_codec::{Decode, Encode};
 
-
-#[derive(Default)]
+#[derive(Encode, Decode, Clone, Default)]
 struct Channel {
     sequence: u64,
     messages: Vec<SignedMessage>,
     dummy: bool,
+    /// Number of messages rejected or dropped due to the queue limit
+    #[codec(skip)]
+    dropped: u64,
+}
+
+/// What to do when a message is sent to a full queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Refuse the new message. Its sequence number is not consumed.
+    Reject,
+    /// Drop the oldest pending message to make room.
+    ///
+    /// The dropped sequence number never reaches the chain, so the later messages from the same
+    /// sender would not be accepted. Only use it for senders that are not synced to the chain.
+    DropOldest,
+    /// Hold the producer until the queue gets purged, without blocking the thread.
+    ///
+    /// The producer is expected to check `MessageChannel::is_blocked` and stop producing while it
+    /// returns true. The messages sent anyway are refused as with `Reject`.
+    Block,
+}
+
+/// The capacity limit of the pending messages of a sender.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueLimit {
+    pub capacity: usize,
+    pub policy: OverflowPolicy,
+}
+
+#[derive(Display, Debug)]
+pub enum SendError {
+    #[display(fmt = "The send queue of the sender is full")]
+    QueueFull,
+}
+
+/// Queue depth of a sender.
+#[derive(Clone, Debug)]
+pub struct SenderQueueStats {
+    pub sender: SenderId,
+    /// Number of pending messages
+    pub depth: usize,
+    pub limit: Option<QueueLimit>,
+    /// Number of messages rejected or dropped due to the limit
+    pub dropped: u64,
+}
+
+/// A snapshot of the sequences and pending messages in a `MessageSendQueue`.
+///
+/// The queue limits are configuration rather than state, so they are not included.
+#[derive(Encode, Decode, Clone, Default)]
+pub struct MessageSendQueueSnapshot(BTreeMap<SenderId, Channel>);
+
+#[derive(Default)]
+struct Inner {
+    channels: BTreeMap<SenderId, Channel>,
+    default_limit: Option<QueueLimit>,
+    limits: BTreeMap<SenderId, QueueLimit>,
+    /// Number of messages enqueued to each topic, regardless of the sender
+    sent_by_topic: BTreeMap<Path, u64>,
+}
+
+impl Inner {
+    fn limit_of(&self, sender: &SenderId) -> Option<QueueLimit> {
+        self.limits.get(sender).copied().or(self.default_limit)
+    }
 }
 
 #[derive(Clone, Default)]
 pub struct MessageSendQueue {
-    inner: Arc<Mutex<BTreeMap<SenderId, Channel>>>,
+    inner: Arc<Mutex<Inner>>,
 }
 
 impl MessageSendQueue {
@@ -30,25 +96,96 @@ impl MessageSendQueue {
         &self,
         sender: SenderId,
         constructor: impl FnOnce(u64) -> SignedMessage,
-    ) {
+    ) -> Result<(), SendError> {
         let mut inner = self.inner.lock();
-        let entry = inner.entry(sender).or_default();
+        let inner = &mut *inner;
+        let limit = inner.limit_of(&sender);
+        let entry = inner.channels.entry(sender).or_default();
+        if let Some(limit) = limit {
+            if !entry.dummy && entry.messages.len() >= limit.capacity {
+                match limit.policy {
+                    OverflowPolicy::Reject | OverflowPolicy::Block => {
+                        entry.dropped += 1;
+                        return Err(SendError::QueueFull);
+                    }
+                    OverflowPolicy::DropOldest => {
+                        if !entry.messages.is_empty() {
+                            entry.messages.remove(0);
+                            entry.dropped += 1;
+                        }
+                    }
+                }
+            }
+        }
         if !entry.dummy {
             let message = constructor(entry.sequence);
+            *inner
+                .sent_by_topic
+                .entry(message.message.destination.path().clone())
+                .or_default() += 1;
             entry.messages.push(message);
         }
         entry.sequence += 1;
+        Ok(())
+    }
+
+    /// Whether the sender should stop producing messages, i.e. its queue is full with the `Block`
+    /// policy.
+    pub fn is_blocked(&self, sender: &SenderId) -> bool {
+        let inner = self.inner.lock();
+        match (inner.limit_of(sender), inner.channels.get(sender)) {
+            (Some(limit), Some(entry)) => {
+                limit.policy == OverflowPolicy::Block
+                    && !entry.dummy
+                    && entry.messages.len() >= limit.capacity
+            }
+            _ => false,
+        }
     }
 
     pub fn set_dummy_mode(&self, sender: SenderId, dummy: bool) {
         let mut inner = self.inner.lock();
-        let entry = inner.entry(sender).or_default();
+        let entry = inner.channels.entry(sender).or_default();
         entry.dummy = dummy;
     }
 
+    /// Set the limit for the senders without a limit of their own. None for unlimited.
+    pub fn set_default_limit(&self, limit: Option<QueueLimit>) {
+        self.inner.lock().default_limit = limit;
+    }
+
+    /// Set the limit of a sender, overriding the default limit. None to use the default limit.
+    pub fn set_limit(&self, sender: SenderId, limit: Option<QueueLimit>) {
+        let mut inner = self.inner.lock();
+        match limit {
+            Some(limit) => inner.limits.insert(sender, limit),
+            None => inner.limits.remove(&sender),
+        };
+    }
+
+    pub fn stats(&self) -> Vec<SenderQueueStats> {
+        let inner = self.inner.lock();
+        inner
+            .channels
+            .iter()
+            .map(|(sender, ch)| SenderQueueStats {
+                sender: sender.clone(),
+                depth: ch.messages.len(),
+                limit: inner.limit_of(sender),
+                dropped: ch.dropped,
+            })
+            .collect()
+    }
+
+    /// Number of messages enqueued to each topic since the queue was created
+    pub fn sent_by_topic(&self) -> BTreeMap<Path, u64> {
+        self.inner.lock().sent_by_topic.clone()
+    }
+
     pub fn all_messages(&self) -> Vec<SignedMessage> {
         let inner = self.inner.lock();
         inner
+            .channels
             .iter()
             .flat_map(|(_k, v)| v.messages.iter().cloned())
             .collect()
@@ -57,6 +194,7 @@ impl MessageSendQueue {
     pub fn all_messages_grouped(&self) -> BTreeMap<MessageOrigin, Vec<SignedMessage>> {
         let inner = self.inner.lock();
         inner
+            .channels
             .iter()
             .map(|(k, v)| (k.clone(), v.messages.clone()))
             .collect()
@@ -64,11 +202,16 @@ impl MessageSendQueue {
 
     pub fn messages(&self, sender: &SenderId) -> Vec<SignedMessage> {
         let inner = self.inner.lock();
-        inner.get(sender).map(|x| x.messages.clone()).unwrap_or_default()
+        inner
+            .channels
+            .get(sender)
+            .map(|x| x.messages.clone())
+            .unwrap_or_default()
     }
 
     pub fn count_messages(&self) -> usize {
         self.inner.lock()
+            .channels
             .iter()
             .map(|(_k, v)| v.messages.len())
             .sum()
@@ -77,11 +220,22 @@ impl MessageSendQueue {
     /// Purge the messages which are aready accepted on chain.
     pub fn purge(&self, next_sequence_for: impl Fn(&SenderId) -> u64) {
         let mut inner = self.inner.lock();
-        for (k, v) in inner.iter_mut() {
+        for (k, v) in inner.channels.iter_mut() {
             let seq = next_sequence_for(k);
             v.messages.retain(|msg| msg.sequence >= seq);
         }
     }
+
+    pub fn snapshot(&self) -> MessageSendQueueSnapshot {
+        MessageSendQueueSnapshot(self.inner.lock().channels.clone())
+    }
+
+    /// Overwrite the queue state with a snapshot.
+    ///
+    /// The existing channels keep working, since they share the same underlying queue.
+    pub fn restore(&self, snapshot: MessageSendQueueSnapshot) {
+        self.inner.lock().channels = snapshot.0;
+    }
 }
 
 pub use msg_channel::*;
@@ -106,7 +260,35 @@ mod msg_channel {
             }
         }
 
+        pub fn sender(&self) -> &SenderId {
+            &self.sender
+        }
+
+        /// Send the message, logging it if the queue is full.
+        ///
+        /// The rejected messages are counted in the queue stats.
         pub fn send_data(&self, payload: Vec<u8>, to: impl Into<Path>) {
+            if let Err(err) = self.try_send_data(payload, to) {
+                log::warn!("Message from {} refused: {}", self.sender, err);
+            }
+        }
+
+        pub fn try_send_data(
+            &self,
+            payload: Vec<u8>,
+            to: impl Into<Path>,
+        ) -> Result<(), SendError> {
+            self.try_send_data_with(to, move |_sequence| payload)
+        }
+
+        /// Send the payload built from the sequence number assigned to the message.
+        ///
+        /// The builder is not called if the message is rejected or the channel is in dummy mode.
+        pub fn try_send_data_with(
+            &self,
+            to: impl Into<Path>,
+            build_payload: impl FnOnce(u64) -> Vec<u8>,
+        ) -> Result<(), SendError> {
             let sender = self.sender.clone();
             let signer = &self.signer;
 
@@ -114,7 +296,7 @@ mod msg_channel {
                 let message = Message {
                     sender,
                     destination: to.into().into(),
-                    payload,
+                    payload: build_payload(sequence),
                 };
                 let be_signed = MessageToBeSigned {
                     message: &message,
@@ -136,10 +318,27 @@ mod msg_channel {
             self.send_data(message.encode(), to)
         }
 
+        pub fn try_sendto<M: Encode>(
+            &self,
+            message: &M,
+            to: impl Into<Path>,
+        ) -> Result<(), SendError> {
+            self.try_send_data(message.encode(), to)
+        }
+
         pub fn send<M: Encode + BindTopic>(&self, message: &M) {
             self.sendto(message, <M as BindTopic>::topic())
         }
 
+        pub fn try_send<M: Encode + BindTopic>(&self, message: &M) -> Result<(), SendError> {
+            self.try_sendto(message, <M as BindTopic>::topic())
+        }
+
+        /// Whether the sender should hold on producing messages until the queue gets purged.
+        pub fn is_blocked(&self) -> bool {
+            self.queue.is_blocked(&self.sender)
+        }
+
         /// Set the channel to dummy mode which increasing the sequence but dropping the message.
         pub fn set_dummy(&self, dummy: bool) {
             self.queue.set_dummy_mode(self.sender.clone(), dummy);
@@ -2,12 +2,16 @@ use crate::Mutex;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
 use derive_more::Display;
+use futures_core::Stream;
 
 struct Channel<T> {
     deque: VecDeque<T>,
     sender_count: usize,
     receiver_gone: bool,
+    receiver_waker: Option<Waker>,
 }
 
 impl<T> Channel<T> {
@@ -20,10 +24,17 @@ impl<T> Channel<T> {
             deque: VecDeque::with_capacity(cap),
             sender_count: 1,
             receiver_gone: false,
+            receiver_waker: None,
         }
     }
 }
 
+fn wake(waker: Option<Waker>) {
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
 type ArcCh<T> = Arc<Mutex<Channel<T>>>;
 pub struct Sender<T>(ArcCh<T>);
 
@@ -40,7 +51,9 @@ impl<T> Sender<T> {
             Err(SendError::ReceiverGone)
         } else {
             ch.deque.push_back(value);
-            // TODO.kevin: awake the receiver task
+            let waker = ch.receiver_waker.take();
+            drop(ch);
+            wake(waker);
             Ok(())
         }
     }
@@ -63,7 +76,10 @@ impl<T> Drop for Sender<T> {
         let mut inner = self.0.lock();
         inner.sender_count -= 1;
         if inner.sender_count == 0 {
-            // TODO.kevin: awake the receiver task
+            // Let the receiver task see the end of the stream
+            let waker = inner.receiver_waker.take();
+            drop(inner);
+            wake(waker);
         }
     }
 }
@@ -110,6 +126,22 @@ impl<T: Seq> Receiver<T> {
     }
 }
 
+/// The stream ends when all senders have gone and the queued values are consumed.
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut ch = self.0.lock();
+        if let Some(value) = ch.deque.pop_front() {
+            return Poll::Ready(Some(value));
+        } else if ch.sender_count == 0 {
+            return Poll::Ready(None);
+        }
+        ch.receiver_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
 impl<T> Drop for Receiver<T> {
     fn drop(&mut self) {
         self.0.lock().receiver_gone = true;
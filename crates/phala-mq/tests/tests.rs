@@ -70,6 +70,49 @@ fn test_send_message() {
         assert_eq!(runtime_msgs.len(), 2);
         assert_eq!(contract1_msgs.len(), 3);
     }
+
+    {
+        // The purged messages are still counted
+        let sent = queue.sent_by_topic();
+        assert_eq!(sent.get(&b"phala.network/test0".to_vec()), Some(&1));
+        assert_eq!(sent.get(&b"phala.network/test1".to_vec()), Some(&2));
+        assert_eq!(sent.get(&b"/the/hole".to_vec()), Some(&3));
+    }
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_send_queue_snapshot() {
+    use parity_scale_codec::{Decode, Encode};
+    use phala_mq::{MessageSendQueue, MessageSendQueueSnapshot, MessageSigner};
+
+    struct TestSigner;
+
+    impl MessageSigner for TestSigner {
+        fn sign(&self, _data: &[u8]) -> Vec<u8> {
+            b"sig".to_vec()
+        }
+    }
+
+    let sender = MessageOrigin::Pallet(b"p0".to_vec());
+    let queue = MessageSendQueue::new();
+    let handle = queue.channel(sender.clone(), TestSigner);
+    handle.send_data(b"payload0".to_vec(), b"path".to_vec());
+    handle.send_data(b"payload1".to_vec(), b"path".to_vec());
+
+    let encoded = queue.snapshot().encode();
+    let snapshot = MessageSendQueueSnapshot::decode(&mut &encoded[..]).unwrap();
+
+    let restored = MessageSendQueue::new();
+    let handle = restored.channel(sender.clone(), TestSigner);
+    restored.restore(snapshot);
+    assert_eq!(restored.messages(&sender), queue.messages(&sender));
+
+    // The sequence continues from the snapshot
+    handle.send_data(b"payload2".to_vec(), b"path".to_vec());
+    let messages = restored.messages(&sender);
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[2].sequence, 2);
 }
 
 #[cfg(feature = "dispatcher")]
@@ -172,3 +215,227 @@ fn test_select_order() {
     }
     assert_eq!(payloads, [0, 1, 2, 3, 4]);
 }
+
+#[cfg(feature = "dispatcher")]
+#[test]
+fn test_prefix_subscription() {
+    use phala_mq::{Message, MessageDispatcher};
+
+    let sender = MessageOrigin::Pallet(b"sender".to_vec());
+    let mut dispatcher = MessageDispatcher::new();
+
+    let mut exact = dispatcher.subscribe(*b"phala/gatekeeper/event");
+    let mut gk = dispatcher.subscribe_prefix(*b"phala/gatekeeper/");
+    let mut tap = dispatcher.tap();
+
+    let n = dispatcher.dispatch(Message::new(
+        sender.clone(),
+        *b"phala/gatekeeper/event",
+        b"0".to_vec(),
+    ));
+    assert_eq!(n, 3);
+    let n = dispatcher.dispatch(Message::new(
+        sender.clone(),
+        *b"phala/gatekeeper/launch",
+        b"1".to_vec(),
+    ));
+    assert_eq!(n, 2);
+    let n = dispatcher.dispatch(Message::new(
+        sender.clone(),
+        *b"phala/system",
+        b"2".to_vec(),
+    ));
+    assert_eq!(n, 1);
+
+    let payloads = |rx: &mut phala_mq::Receiver<(u64, Message)>| -> Vec<(u64, Vec<u8>)> {
+        rx.drain().map(|(sn, msg)| (sn, msg.payload)).collect()
+    };
+    assert_eq!(payloads(&mut exact), [(0, b"0".to_vec())]);
+    assert_eq!(payloads(&mut gk), [(0, b"0".to_vec()), (1, b"1".to_vec())]);
+    assert_eq!(
+        payloads(&mut tap),
+        [(0, b"0".to_vec()), (1, b"1".to_vec()), (2, b"2".to_vec())]
+    );
+
+    drop(gk);
+    let n = dispatcher.dispatch(Message::new(
+        sender.clone(),
+        *b"phala/gatekeeper/launch",
+        b"3".to_vec(),
+    ));
+    assert_eq!(n, 1);
+}
+
+#[cfg(feature = "dispatcher")]
+#[test]
+fn test_receiver_stream() {
+    use futures::{executor::block_on, StreamExt};
+    use parity_scale_codec::Encode;
+    use phala_mq::{Message, MessageDispatcher, TypedReceiver};
+
+    let sender = MessageOrigin::Pallet(b"sender".to_vec());
+    let mut dispatcher = MessageDispatcher::new();
+    let mut rx: TypedReceiver<u32> = dispatcher.subscribe(*b"path").into();
+
+    dispatcher.dispatch(Message::new(sender.clone(), *b"path", 1u32.encode()));
+    let (sn, value, origin) = block_on(rx.next()).unwrap().unwrap();
+    assert_eq!((sn, value), (0, 1));
+    assert_eq!(origin, sender);
+
+    // The pending receiver is woken up by the sender on another thread
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        dispatcher.dispatch(Message::new(sender, *b"path", 2u32.encode()));
+    });
+    let (sn, value, _) = block_on(rx.next()).unwrap().unwrap();
+    assert_eq!((sn, value), (1, 2));
+    handle.join().unwrap();
+
+    // The stream ends after the dispatcher has gone
+    assert!(block_on(rx.next()).is_none());
+}
+
+#[cfg(feature = "dispatcher")]
+#[test]
+fn test_dispatch_with_sequence() {
+    use parity_scale_codec::Encode;
+    use phala_mq::{Message, MessageDispatcher, TypedReceiver};
+
+    let sender = MessageOrigin::Gatekeeper;
+    let mut dispatcher = MessageDispatcher::new();
+    let mut rx: TypedReceiver<u32> = dispatcher.subscribe_with_sequence(*b"path").into();
+    let mut plain: TypedReceiver<u32> = dispatcher.subscribe(*b"path").into();
+
+    dispatcher.dispatch_with_sequence(
+        Message::new(sender.clone(), *b"path", 1u32.encode()),
+        Some(5),
+    );
+    dispatcher.dispatch(Message::new(sender.clone(), *b"path", 2u32.encode()));
+    assert_eq!(
+        rx.try_next_with_sequence().unwrap(),
+        Some((0, 1, sender.clone(), Some(5)))
+    );
+    assert_eq!(
+        rx.try_next_with_sequence().unwrap(),
+        Some((1, 2, sender.clone(), None))
+    );
+    assert!(rx.try_next_with_sequence().unwrap().is_none());
+    // The sequences are not known by the plain subscribers
+    assert_eq!(
+        plain.try_next_with_sequence().unwrap(),
+        Some((0, 1, sender.clone(), None))
+    );
+    assert_eq!(plain.try_next().unwrap(), Some((1, 2, sender)));
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_send_queue_limit() {
+    use phala_mq::{MessageSendQueue, MessageSigner, OverflowPolicy, QueueLimit};
+
+    struct TestSigner;
+
+    impl MessageSigner for TestSigner {
+        fn sign(&self, _data: &[u8]) -> Vec<u8> {
+            vec![]
+        }
+    }
+
+    let queue = MessageSendQueue::new();
+    let contract = MessageOrigin::native_contract(1);
+    let system = MessageOrigin::Pallet(b"system".to_vec());
+    queue.set_default_limit(Some(QueueLimit {
+        capacity: 2,
+        policy: OverflowPolicy::Reject,
+    }));
+    queue.set_limit(
+        system.clone(),
+        Some(QueueLimit {
+            capacity: 2,
+            policy: OverflowPolicy::DropOldest,
+        }),
+    );
+
+    let contract_mq = queue.channel(contract.clone(), TestSigner);
+    assert!(contract_mq
+        .try_send_data(b"0".to_vec(), b"topic".to_vec())
+        .is_ok());
+    assert!(contract_mq
+        .try_send_data(b"1".to_vec(), b"topic".to_vec())
+        .is_ok());
+    assert!(contract_mq
+        .try_send_data(b"2".to_vec(), b"topic".to_vec())
+        .is_err());
+    // The rejected message doesn't consume a sequence
+    let sequences: Vec<_> = queue
+        .messages(&contract)
+        .iter()
+        .map(|m| m.sequence)
+        .collect();
+    assert_eq!(sequences, [0, 1]);
+
+    let system_mq = queue.channel(system.clone(), TestSigner);
+    for i in 0..3u8 {
+        system_mq.send_data(vec![i], b"topic".to_vec());
+    }
+    let sequences: Vec<_> = queue.messages(&system).iter().map(|m| m.sequence).collect();
+    assert_eq!(sequences, [1, 2]);
+
+    let stats = queue.stats();
+    let contract_stats = stats.iter().find(|s| s.sender == contract).unwrap();
+    assert_eq!((contract_stats.depth, contract_stats.dropped), (2, 1));
+    let system_stats = stats.iter().find(|s| s.sender == system).unwrap();
+    assert_eq!((system_stats.depth, system_stats.dropped), (2, 1));
+
+    // Room is made by purging
+    queue.purge(|_| 1);
+    assert!(contract_mq
+        .try_send_data(b"2".to_vec(), b"topic".to_vec())
+        .is_ok());
+    let sequences: Vec<_> = queue
+        .messages(&contract)
+        .iter()
+        .map(|m| m.sequence)
+        .collect();
+    assert_eq!(sequences, [1, 2]);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_send_queue_block_policy() {
+    use phala_mq::{MessageSendQueue, MessageSigner, OverflowPolicy, QueueLimit};
+
+    struct TestSigner;
+
+    impl MessageSigner for TestSigner {
+        fn sign(&self, _data: &[u8]) -> Vec<u8> {
+            vec![]
+        }
+    }
+
+    let queue = MessageSendQueue::new();
+    let contract = MessageOrigin::native_contract(1);
+    queue.set_limit(
+        contract.clone(),
+        Some(QueueLimit {
+            capacity: 2,
+            policy: OverflowPolicy::Block,
+        }),
+    );
+
+    let contract_mq = queue.channel(contract.clone(), TestSigner);
+    assert!(!contract_mq.is_blocked());
+    contract_mq.send_data(b"0".to_vec(), b"topic".to_vec());
+    assert!(!contract_mq.is_blocked());
+    contract_mq.send_data(b"1".to_vec(), b"topic".to_vec());
+    assert!(contract_mq.is_blocked());
+    // The producer ignoring the signal gets its messages refused without blocking the thread
+    assert!(contract_mq
+        .try_send_data(b"2".to_vec(), b"topic".to_vec())
+        .is_err());
+    assert_eq!(queue.messages(&contract).len(), 2);
+
+    // Unblocked by purging
+    queue.purge(|_| 1);
+    assert!(!contract_mq.is_blocked());
+}
@@ -8,7 +8,7 @@ pub mod ser;
 use alloc::vec::Vec;
 
 use parity_scale_codec::Codec;
-use sp_core::storage::ChildInfo;
+use sp_core::storage::{well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX, ChildInfo};
 use sp_core::Hasher;
 use sp_state_machine::{Backend, TrieBackend};
 use sp_trie::{trie_types::TrieDBMut, MemoryDB, TrieMut};
@@ -25,6 +25,13 @@ pub type StorageCollection = Vec<(StorageKey, Option<StorageValue>)>;
 /// In memory arrays of storage values for multiple child tries.
 pub type ChildStorageCollection = Vec<(StorageKey, StorageCollection)>;
 
+/// All the key/value pairs in a trie, with the child tries (keyed by their unprefixed child
+/// storage key) separated from the main trie.
+pub type StorageDump = (
+    Vec<(StorageKey, StorageValue)>,
+    Vec<(StorageKey, Vec<(StorageKey, StorageValue)>)>,
+);
+
 pub struct TrieStorage<H: Hasher>(TrieBackend<MemoryDB<H>, H>);
 
 impl<H: Hasher> Default for TrieStorage<H>
@@ -115,4 +122,50 @@ where
             })
             .collect()
     }
+
+    /// Dump all the key/value pairs in the trie DB, including the child tries.
+    pub fn dump(&self) -> StorageDump {
+        let mut main = Vec::new();
+        let mut children = Vec::new();
+        for (key, value) in self.pairs(&[]) {
+            if let Some(child_key) = key.strip_prefix(DEFAULT_CHILD_STORAGE_KEY_PREFIX) {
+                let child_info = ChildInfo::new_default(child_key);
+                let pairs = self
+                    .0
+                    .child_keys(&child_info, &[])
+                    .into_iter()
+                    .map(|key| {
+                        let value = self
+                            .0
+                            .child_storage(&child_info, &key)
+                            .ok()
+                            .flatten()
+                            .expect("Reflected child key should exists");
+                        (key, value)
+                    })
+                    .collect();
+                children.push((child_key.to_vec(), pairs));
+            } else {
+                main.push((key, value));
+            }
+        }
+        (main, children)
+    }
+
+    /// Rebuild a trie DB from the output of `dump`.
+    pub fn restore(dump: StorageDump) -> Self {
+        let (main, children) = dump;
+        let delta: StorageCollection = main.into_iter().map(|(k, v)| (k, Some(v))).collect();
+        let child_deltas: ChildStorageCollection = children
+            .into_iter()
+            .map(|(child_key, pairs)| {
+                let pairs = pairs.into_iter().map(|(k, v)| (k, Some(v))).collect();
+                (child_key, pairs)
+            })
+            .collect();
+        let mut storage = Self::default();
+        let (root, transaction) = storage.calc_root_if_changes(&delta, &child_deltas);
+        storage.apply_changes(root, transaction);
+        storage
+    }
 }
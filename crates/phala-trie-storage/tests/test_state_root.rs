@@ -124,3 +124,24 @@ fn test_apply_main_changes() {
         assert_eq!(format!("{:?}", trie.root()), roots[number + 1]);
     }
 }
+
+#[test]
+fn test_dump_restore() {
+    let mut trie = load_genesis_trie();
+    let changes = load_changes();
+
+    for change in changes.into_iter().skip(1).take(10) {
+        let main_storage_changes = map_storage_collection(change.main_storage_changes);
+        let child_storage_changes: Vec<_> = change
+            .child_storage_changes
+            .into_iter()
+            .map(|(k, v)| (k.0, map_storage_collection(v)))
+            .collect();
+
+        let (root, trans) = trie.calc_root_if_changes(&main_storage_changes, &child_storage_changes);
+        trie.apply_changes(root, trans);
+    }
+
+    let restored = TrieStorage::<NativeBlakeTwo256>::restore(trie.dump());
+    assert_eq!(restored.root(), trie.root());
+}
@@ -293,6 +293,10 @@ pub mod messaging {
         ///  When a miner recovered to MiningIdle state from Unresponsive, push this message to the worker to
         ///  resume the subsequent heartbeat responses.
         MiningExitUnresponsive,
+        /// pallet-registry --> worker
+        ///  When the attestation of a worker expired or its pRuntime was revoked, push this message to the worker
+        ///  to suppress the heartbeat responses until it registers again.
+        AttestationInvalidated,
     }
 
     bind_topic!(SystemEvent, b"phala/system/event");
@@ -401,6 +405,12 @@ pub mod messaging {
         pub ecdh_pubkey: EcdhPublicKey,
     }
 
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+    pub struct RemovedGatekeeperEvent {
+        /// The public key of the unregistered gatekeeper
+        pub pubkey: WorkerPublicKey,
+    }
+
     #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
     pub struct MasterPubkeyEvent {
         pub master_pubkey: MasterPublicKey,
@@ -411,6 +421,15 @@ pub mod messaging {
     #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
     pub enum GatekeeperChange {
         GatekeeperRegistered(NewGatekeeperEvent),
+        /// The lead gatekeeper should generate a new master key and send it to the others
+        MasterKeyRotation(MasterKeyRotationEvent),
+        /// The new master key takes effect since the current block
+        MasterKeyRotated(MasterKeyRotatedEvent),
+        /// The dealer should generate a new master key and share it among the gatekeepers as a
+        /// threshold random beacon
+        ThresholdMasterKeyRotation(ThresholdMasterKeyRotationEvent),
+        /// The gatekeeper is removed and should stop working as a gatekeeper
+        GatekeeperUnregistered(RemovedGatekeeperEvent),
     }
 
     impl GatekeeperChange {
@@ -423,13 +442,92 @@ pub mod messaging {
                 ecdh_pubkey,
             })
         }
+
+        pub fn master_key_rotation(
+            rotation_id: u32,
+            lead: WorkerPublicKey,
+            gatekeepers: Vec<NewGatekeeperEvent>,
+        ) -> GatekeeperChange {
+            GatekeeperChange::MasterKeyRotation(MasterKeyRotationEvent {
+                rotation_id,
+                lead,
+                gatekeepers,
+            })
+        }
+
+        pub fn master_key_rotated(
+            rotation_id: u32,
+            master_pubkey: MasterPublicKey,
+        ) -> GatekeeperChange {
+            GatekeeperChange::MasterKeyRotated(MasterKeyRotatedEvent {
+                rotation_id,
+                master_pubkey,
+            })
+        }
+
+        pub fn gatekeeper_unregistered(pubkey: WorkerPublicKey) -> GatekeeperChange {
+            GatekeeperChange::GatekeeperUnregistered(RemovedGatekeeperEvent { pubkey })
+        }
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+    pub struct MasterKeyRotationEvent {
+        pub rotation_id: u32,
+        /// The gatekeeper to generate the new master key
+        pub lead: WorkerPublicKey,
+        /// All the gatekeepers to receive the new master key
+        pub gatekeepers: Vec<NewGatekeeperEvent>,
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+    pub struct ThresholdMasterKeyRotationEvent {
+        pub rotation_id: u32,
+        /// The gatekeeper to generate and split the new master key
+        pub dealer: WorkerPublicKey,
+        /// The number of shares required to evaluate the random beacon
+        pub threshold: u32,
+        /// All the gatekeepers to receive a share, in the order of the share indexes
+        pub gatekeepers: Vec<NewGatekeeperEvent>,
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+    pub struct MasterKeyShareHolder {
+        pub pubkey: WorkerPublicKey,
+        /// The 1-based index of the share
+        pub index: u32,
+        /// The public share, a compressed Ristretto point
+        pub share_pubkey: [u8; 32],
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+    pub struct MasterKeyRotatedEvent {
+        pub rotation_id: u32,
+        pub master_pubkey: MasterPublicKey,
     }
 
     // Messages: Distribution of master key and contract keys
     bind_topic!(KeyDistribution, b"phala/gatekeeper/key");
     #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
     pub enum KeyDistribution {
+        /// The master key encrypted with the raw ECDH shared secret
         MasterKeyDistribution(DispatchMasterKeyEvent),
+        /// The master key encrypted with the key derived by the tagged scheme
+        MasterKeyDistributionWithScheme {
+            /// The `phala_crypto::ecdh::EncryptionScheme` in its u32 form
+            scheme: u32,
+            event: DispatchMasterKeyEvent,
+        },
+        /// The next master key generated by the lead gatekeeper of a rotation
+        MasterKeyRotation(RotateMasterKeyEvent),
+        /// The shares of the next master key generated by the dealer of a threshold rotation
+        MasterKeyShares(DispatchMasterKeySharesEvent),
+        /// The master key along with the ones retired by rotations, encrypted with the key
+        /// derived by the tagged scheme
+        MasterKeyHistoryDistribution {
+            /// The `phala_crypto::ecdh::EncryptionScheme` in its u32 form
+            scheme: u32,
+            event: DispatchMasterKeyEvent,
+        },
     }
 
     impl KeyDistribution {
@@ -446,6 +544,63 @@ pub mod messaging {
                 iv,
             })
         }
+
+        pub fn master_key_distribution_with_scheme(
+            scheme: u32,
+            dest: WorkerPublicKey,
+            ecdh_pubkey: EcdhPublicKey,
+            encrypted_master_key: Vec<u8>,
+            iv: AeadIV,
+        ) -> KeyDistribution {
+            KeyDistribution::MasterKeyDistributionWithScheme {
+                scheme,
+                event: DispatchMasterKeyEvent {
+                    dest,
+                    ecdh_pubkey,
+                    encrypted_master_key,
+                    iv,
+                },
+            }
+        }
+
+        pub fn master_key_history_distribution(
+            scheme: u32,
+            dest: WorkerPublicKey,
+            ecdh_pubkey: EcdhPublicKey,
+            encrypted_master_key_history: Vec<u8>,
+            iv: AeadIV,
+        ) -> KeyDistribution {
+            KeyDistribution::MasterKeyHistoryDistribution {
+                scheme,
+                event: DispatchMasterKeyEvent {
+                    dest,
+                    ecdh_pubkey,
+                    encrypted_master_key: encrypted_master_key_history,
+                    iv,
+                },
+            }
+        }
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+    pub struct RotateMasterKeyEvent {
+        pub rotation_id: u32,
+        /// The `phala_crypto::ecdh::EncryptionScheme` in its u32 form
+        pub scheme: u32,
+        /// The new master key encrypted to each of the other gatekeepers
+        pub encrypted_keys: Vec<DispatchMasterKeyEvent>,
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+    pub struct DispatchMasterKeySharesEvent {
+        pub rotation_id: u32,
+        /// The `phala_crypto::ecdh::EncryptionScheme` in its u32 form
+        pub scheme: u32,
+        pub threshold: u32,
+        pub master_pubkey: MasterPublicKey,
+        pub share_holders: Vec<MasterKeyShareHolder>,
+        /// The secret of each share encrypted to its holder
+        pub encrypted_shares: Vec<DispatchMasterKeyEvent>,
     }
 
     type AeadIV = [u8; 12];
@@ -467,6 +622,21 @@ pub mod messaging {
     pub enum GatekeeperEvent {
         NewRandomNumber(RandomNumberEvent),
         TokenomicParametersChanged(TokenomicParameters),
+        /// The random numbers of the blocks since now are VRF outputs of the master key
+        VrfBeaconEnabled,
+        /// The tokenomic parameters taking effect at `activation_block`
+        ///
+        /// Replaces the previously scheduled parameters not activated yet.
+        TokenomicParametersScheduled {
+            activation_block: u32,
+            params: TokenomicParameters,
+        },
+        /// A random number produced by the VRF beacon, with its VRF proof verifiable against the
+        /// master pubkey
+        NewVrfRandomNumber {
+            event: RandomNumberEvent,
+            proof: RandomNumberProof,
+        },
     }
 
     impl GatekeeperEvent {
@@ -481,6 +651,22 @@ pub mod messaging {
                 last_random_number,
             })
         }
+
+        pub fn new_vrf_random_number(
+            block_number: u32,
+            random_number: RandomNumber,
+            last_random_number: RandomNumber,
+            proof: RandomNumberProof,
+        ) -> GatekeeperEvent {
+            GatekeeperEvent::NewVrfRandomNumber {
+                event: RandomNumberEvent {
+                    block_number,
+                    random_number,
+                    last_random_number,
+                },
+                proof,
+            }
+        }
     }
 
     pub type RandomNumber = [u8; 32];
@@ -491,6 +677,43 @@ pub mod messaging {
         pub last_random_number: RandomNumber,
     }
 
+    /// The schnorrkel signing context of the random number VRF
+    pub const RANDOM_NUMBER_VRF_CONTEXT: &[u8] = b"phala/gatekeeper/random_number";
+    /// The label to derive the random number from the VRF output
+    pub const RANDOM_NUMBER_VRF_LABEL: &[u8] = b"random_number";
+
+    /// The message signed by the VRF to produce the random number of a block
+    pub fn random_number_vrf_message(
+        block_number: u32,
+        last_random_number: &RandomNumber,
+    ) -> Vec<u8> {
+        let mut buf = last_random_number.to_vec();
+        buf.extend_from_slice(&block_number.to_be_bytes());
+        buf
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+    pub struct RandomNumberProof {
+        /// The VRF output, a compressed Ristretto point
+        pub vrf_output: [u8; 32],
+        pub vrf_proof: [u8; 64],
+    }
+
+    // Messages: Threshold random beacon evaluated with the shares of the master key
+    bind_topic!(ThresholdEvent, b"phala/gatekeeper/threshold");
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+    pub enum ThresholdEvent {
+        PartialRandomNumber(PartialRandomNumberEvent),
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+    pub struct PartialRandomNumberEvent {
+        pub block_number: u32,
+        pub last_random_number: RandomNumber,
+        /// The SCALE-encoded `phala_crypto::threshold::PartialEvaluation`
+        pub partial: Vec<u8>,
+    }
+
     #[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
     #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
     pub struct TokenomicParameters {
@@ -601,6 +824,21 @@ pub struct WorkerRegistrationInfo<AccountId> {
     pub operator: Option<AccountId>,
 }
 
+/// The message signed by a gatekeeper to unregister itself
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct GatekeeperUnregistration {
+    pub pubkey: WorkerPublicKey,
+    /// The unregistration nonce of the gatekeeper on chain, against replaying the signature
+    pub nonce: u32,
+}
+
+impl GatekeeperUnregistration {
+    /// The data to sign, separated from the other messages signed by the worker identity key
+    pub fn data_be_signed(&self) -> Vec<u8> {
+        (b"phala/gatekeeper/unregister", self).encode()
+    }
+}
+
 #[derive(Encode, Decode, Debug, Default)]
 pub struct RoundInfo<BlockNumber> {
     pub round: u32,
@@ -70,14 +70,75 @@ const types = {
     },
     "Attestation": {
         "_enum": {
-            "SgxIas": "AttestationSgxIas"
+            "SgxIas": "AttestationSgxIas",
+            "SgxDcap": "AttestationSgxDcap",
+            "None": null
         }
     },
+    "AttestationProvider": {
+        "_enum": [
+            "Ias",
+            "Dcap",
+            "None"
+        ]
+    },
+    "AttestationProviderConfig": {
+        "confidenceLevels": "Vec<(u8, u8)>"
+    },
+    "WorkerAttestation": {
+        "provider": "AttestationProvider",
+        "pruntimeHash": "Vec<u8>"
+    },
+    "AttestationInvalidation": {
+        "_enum": [
+            "Expired",
+            "PRuntimeRevoked"
+        ]
+    },
     "AttestationSgxIas": {
         "raReport": "Vec<u8>",
         "signature": "Vec<u8>",
         "rawSigningCert": "Vec<u8>"
     },
+    "AttestationSgxDcap": {
+        "quote": "Vec<u8>"
+    },
+    "DcapCollateral": {
+        "rootCa": "Vec<u8>",
+        "qeIdentity": "QeIdentity",
+        "tcbInfo": "Vec<TcbInfo>"
+    },
+    "QeIdentity": {
+        "mrSigner": "[u8; 32]",
+        "isvProdId": "u16",
+        "minIsvSvn": "u16",
+        "miscSelect": "u32",
+        "miscSelectMask": "u32",
+        "attributes": "[u8; 16]",
+        "attributesMask": "[u8; 16]"
+    },
+    "TcbInfo": {
+        "fmspc": "[u8; 6]",
+        "nextUpdate": "u64",
+        "levels": "Vec<TcbLevel>"
+    },
+    "TcbLevel": {
+        "sgxTcbComponents": "[u8; 16]",
+        "pceSvn": "u16",
+        "status": "TcbStatus",
+        "advisoryIds": "Vec<Vec<u8>>"
+    },
+    "TcbStatus": {
+        "_enum": [
+            "UpToDate",
+            "SwHardeningNeeded",
+            "ConfigurationNeeded",
+            "ConfigurationAndSwHardeningNeeded",
+            "OutOfDate",
+            "OutOfDateConfigurationNeeded",
+            "Revoked"
+        ]
+    },
     "SenderId": "MessageOrigin",
     "Path": "Vec<u8>",
     "Topic": "Path",
@@ -164,6 +225,10 @@ const types = {
     "KeyDistribution": {
         "_enum": {
             "MasterKeyDistribution": "DispatchMasterKeyEvent",
+            "MasterKeyDistributionWithScheme": "DispatchMasterKeyWithSchemeEvent",
+            "MasterKeyRotation": "RotateMasterKeyEvent",
+            "MasterKeyShares": "DispatchMasterKeySharesEvent",
+            "MasterKeyHistoryDistribution": "DispatchMasterKeyWithSchemeEvent",
         }
     },
     "GatekeeperLaunch": {
@@ -175,29 +240,84 @@ const types = {
     "GatekeeperChange": {
         "_enum": {
             "GatekeeperRegistered": "NewGatekeeperEvent",
+            "MasterKeyRotation": "MasterKeyRotationEvent",
+            "MasterKeyRotated": "MasterKeyRotatedEvent",
+            "ThresholdMasterKeyRotation": "ThresholdMasterKeyRotationEvent",
+            "GatekeeperUnregistered": "RemovedGatekeeperEvent",
         }
     },
     "GatekeeperEvent": {
         "_enum": {
             "NewRandomNumber": "RandomNumberEvent",
-            "TokenomicParametersChanged": "TokenomicParameters"
+            "TokenomicParametersChanged": "TokenomicParameters",
+            "VrfBeaconEnabled": null,
+            "TokenomicParametersScheduled": "ScheduledTokenomicParameters",
+            "NewVrfRandomNumber": "VrfRandomNumberEvent"
         }
     },
     "NewGatekeeperEvent": {
         "pubkey": "WorkerPublicKey",
         "ecdhPubkey": "EcdhPublicKey",
     },
+    "RemovedGatekeeperEvent": {
+        "pubkey": "WorkerPublicKey"
+    },
     "DispatchMasterKeyEvent": {
         "dest": "WorkerPublicKey",
         "ecdhPubkey": "EcdhPublicKey",
         "encryptedMasterKey": "Vec<u8>",
         "iv": "[u8; 12]"
     },
+    "DispatchMasterKeyWithSchemeEvent": {
+        "scheme": "u32",
+        "event": "DispatchMasterKeyEvent"
+    },
+    "MasterKeyRotationEvent": {
+        "rotationId": "u32",
+        "lead": "WorkerPublicKey",
+        "gatekeepers": "Vec<NewGatekeeperEvent>"
+    },
+    "MasterKeyRotatedEvent": {
+        "rotationId": "u32",
+        "masterPubkey": "MasterPublicKey"
+    },
+    "RotateMasterKeyEvent": {
+        "rotationId": "u32",
+        "scheme": "u32",
+        "encryptedKeys": "Vec<DispatchMasterKeyEvent>"
+    },
+    "ThresholdMasterKeyRotationEvent": {
+        "rotationId": "u32",
+        "dealer": "WorkerPublicKey",
+        "threshold": "u32",
+        "gatekeepers": "Vec<NewGatekeeperEvent>"
+    },
+    "MasterKeyShareHolder": {
+        "pubkey": "WorkerPublicKey",
+        "index": "u32",
+        "sharePubkey": "[u8; 32]"
+    },
+    "DispatchMasterKeySharesEvent": {
+        "rotationId": "u32",
+        "scheme": "u32",
+        "threshold": "u32",
+        "masterPubkey": "MasterPublicKey",
+        "shareHolders": "Vec<MasterKeyShareHolder>",
+        "encryptedShares": "Vec<DispatchMasterKeyEvent>"
+    },
     "RandomNumberEvent": {
         "blockNumber": "u32",
         "randomNumber": "[u8; 32]",
         "lastRandomNumber": "[u8; 32]"
     },
+    "RandomNumberProof": {
+        "vrfOutput": "[u8; 32]",
+        "vrfProof": "[u8; 64]"
+    },
+    "VrfRandomNumberEvent": {
+        "event": "RandomNumberEvent",
+        "proof": "RandomNumberProof"
+    },
     "TokenomicParameters": {
         "phaRate": "U64F64Bits",
         "rho": "U64F64Bits",
@@ -215,6 +335,10 @@ const types = {
         "kappa": "U64F64Bits"
     },
     "TokenomicParams": "TokenomicParameters",
+    "ScheduledTokenomicParameters": {
+        "activationBlock": "u32",
+        "params": "TokenomicParameters"
+    },
     "U64F64Bits": "u128",
     "UserStakeInfo": {
         "user": "AccountId",
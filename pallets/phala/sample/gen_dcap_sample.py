@@ -0,0 +1,198 @@
+#!/usr/bin/env python3
+"""Generates the DCAP quote samples used by the attestation tests.
+
+The quote is laid out exactly as a v3 ECDSA quote produced by the Intel DCAP Quoting Enclave, but
+the PCK certificate chain is issued by a test root CA instead of the Intel SGX Root CA, so that
+the whole verification path can be exercised offline.
+
+Outputs (in the directory of this script):
+- dcap_quote.bin: the quote
+- dcap_root_ca.der: the root CA to put in the collateral
+
+Requires the `cryptography` package.
+"""
+
+import datetime
+import hashlib
+import os
+import struct
+
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec
+from cryptography.hazmat.primitives.asymmetric.utils import decode_dss_signature
+from cryptography.x509.oid import NameOID
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+NOT_BEFORE = datetime.datetime(2021, 1, 1)
+NOT_AFTER = datetime.datetime(2031, 1, 1)
+
+SGX_EXTENSION_OID = "1.2.840.113741.1.13.1"
+FMSPC = bytes.fromhex("00906ea10000")
+PCE_SVN = 10
+CPU_SVN = bytes([3, 3, 2, 4, 1, 128, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+
+QE_MR_SIGNER = bytes.fromhex("8c4f5775d796503e96137f77c68a829a0056ac8ded70140b081b094490c57bff")
+QE_ISV_PROD_ID = 1
+QE_ISV_SVN = 6
+QE_ATTRIBUTES = bytes.fromhex("11000000000000000000000000000000")
+
+MR_ENCLAVE = bytes(range(32))
+MR_SIGNER = bytes(range(32, 64))
+ISV_PROD_ID = 0
+ISV_SVN = 0
+USER_DATA = hashlib.sha256(b"phala dcap sample").digest()
+
+
+def der_len(n):
+    if n < 0x80:
+        return bytes([n])
+    if n < 0x100:
+        return bytes([0x81, n])
+    return bytes([0x82, n >> 8, n & 0xFF])
+
+
+def der(tag, value):
+    return bytes([tag]) + der_len(len(value)) + value
+
+
+def der_oid(oid):
+    parts = [int(p) for p in oid.split(".")]
+    body = bytes([parts[0] * 40 + parts[1]])
+    for part in parts[2:]:
+        chunk = [part & 0x7F]
+        part >>= 7
+        while part:
+            chunk.insert(0, 0x80 | (part & 0x7F))
+            part >>= 7
+        body += bytes(chunk)
+    return der(0x06, body)
+
+
+def der_int(value):
+    body = value.to_bytes((value.bit_length() + 8) // 8 or 1, "big")
+    return der(0x02, body)
+
+
+def sgx_extension():
+    tcb = b""
+    for i, svn in enumerate(CPU_SVN):
+        tcb += der(0x30, der_oid(f"{SGX_EXTENSION_OID}.2.{i + 1}") + der_int(svn))
+    tcb += der(0x30, der_oid(f"{SGX_EXTENSION_OID}.2.17") + der_int(PCE_SVN))
+    tcb += der(0x30, der_oid(f"{SGX_EXTENSION_OID}.2.18") + der(0x04, CPU_SVN))
+    entries = [
+        der(0x30, der_oid(f"{SGX_EXTENSION_OID}.1") + der(0x04, bytes(16))),
+        der(0x30, der_oid(f"{SGX_EXTENSION_OID}.2") + der(0x30, tcb)),
+        der(0x30, der_oid(f"{SGX_EXTENSION_OID}.3") + der(0x04, bytes([0, 0]))),
+        der(0x30, der_oid(f"{SGX_EXTENSION_OID}.4") + der(0x04, FMSPC)),
+        der(0x30, der_oid(f"{SGX_EXTENSION_OID}.5") + der(0x0A, bytes([0]))),
+    ]
+    return der(0x30, b"".join(entries))
+
+
+def name(cn):
+    return x509.Name(
+        [
+            x509.NameAttribute(NameOID.COMMON_NAME, cn),
+            x509.NameAttribute(NameOID.ORGANIZATION_NAME, "Phala Test"),
+        ]
+    )
+
+
+def issue(subject, key, issuer, issuer_key, ca, extra=None):
+    builder = (
+        x509.CertificateBuilder()
+        .subject_name(name(subject))
+        .issuer_name(name(issuer))
+        .public_key(key.public_key())
+        .serial_number(x509.random_serial_number())
+        .not_valid_before(NOT_BEFORE)
+        .not_valid_after(NOT_AFTER)
+        .add_extension(x509.BasicConstraints(ca=ca, path_length=None), critical=True)
+    )
+    if extra is not None:
+        builder = builder.add_extension(extra, critical=False)
+    return builder.sign(issuer_key, hashes.SHA256())
+
+
+def raw_sign(key, data):
+    r, s = decode_dss_signature(key.sign(data, ec.ECDSA(hashes.SHA256())))
+    return r.to_bytes(32, "big") + s.to_bytes(32, "big")
+
+
+def raw_pubkey(key):
+    point = key.public_key().public_bytes(
+        serialization.Encoding.X962, serialization.PublicFormat.UncompressedPoint
+    )
+    return point[1:]
+
+
+def report_body(mr_enclave, mr_signer, attributes, isv_prod_id, isv_svn, report_data):
+    body = bytearray(384)
+    body[0:16] = CPU_SVN
+    body[48:64] = attributes
+    body[64:96] = mr_enclave
+    body[128:160] = mr_signer
+    body[256:258] = struct.pack("<H", isv_prod_id)
+    body[258:260] = struct.pack("<H", isv_svn)
+    body[320:384] = report_data
+    return bytes(body)
+
+
+def main():
+    root_key = ec.generate_private_key(ec.SECP256R1())
+    platform_key = ec.generate_private_key(ec.SECP256R1())
+    pck_key = ec.generate_private_key(ec.SECP256R1())
+    attestation_key = ec.generate_private_key(ec.SECP256R1())
+
+    root = issue("Test SGX Root CA", root_key, "Test SGX Root CA", root_key, True)
+    platform = issue("Test SGX PCK Platform CA", platform_key, "Test SGX Root CA", root_key, True)
+    pck = issue(
+        "Test SGX PCK Certificate",
+        pck_key,
+        "Test SGX PCK Platform CA",
+        platform_key,
+        False,
+        x509.UnrecognizedExtension(
+            x509.ObjectIdentifier(SGX_EXTENSION_OID), sgx_extension()
+        ),
+    )
+
+    header = struct.pack("<HHIHH", 3, 2, 0, QE_ISV_SVN, PCE_SVN)
+    header += bytes.fromhex("939a7233f79c4ca9940a0db3957f0607") + bytes(20)
+    enclave_report = report_body(
+        MR_ENCLAVE, MR_SIGNER, bytes(16), ISV_PROD_ID, ISV_SVN, USER_DATA + bytes(32)
+    )
+    signed = header + enclave_report
+
+    attestation_pubkey = raw_pubkey(attestation_key)
+    qe_auth_data = bytes(range(32))
+    qe_report = report_body(
+        bytes(32),
+        QE_MR_SIGNER,
+        QE_ATTRIBUTES,
+        QE_ISV_PROD_ID,
+        QE_ISV_SVN,
+        hashlib.sha256(attestation_pubkey + qe_auth_data).digest() + bytes(32),
+    )
+    cert_chain = b"".join(
+        cert.public_bytes(serialization.Encoding.PEM) for cert in [pck, platform, root]
+    )
+
+    signature_data = raw_sign(attestation_key, signed)
+    signature_data += attestation_pubkey
+    signature_data += qe_report
+    signature_data += raw_sign(pck_key, qe_report)
+    signature_data += struct.pack("<H", len(qe_auth_data)) + qe_auth_data
+    signature_data += struct.pack("<HI", 5, len(cert_chain)) + cert_chain
+
+    quote = signed + struct.pack("<I", len(signature_data)) + signature_data
+    with open(os.path.join(HERE, "dcap_quote.bin"), "wb") as f:
+        f.write(quote)
+    with open(os.path.join(HERE, "dcap_root_ca.der"), "wb") as f:
+        f.write(root.public_bytes(serialization.Encoding.DER))
+
+
+if __name__ == "__main__":
+    main()
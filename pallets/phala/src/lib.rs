@@ -9,7 +9,10 @@
 //! - `registry`: Manages the public key of offchain components (i.e. workers and contracts)
 //! - `mining`: Manages mining lifecycle, reward and slashes
 //! - `stakepool`: Pool for collaboratively mining staking
+//! - `random_beacon`: The random numbers produced by the gatekeepers, verified by VRF proofs
 
+#[cfg(target_arch = "wasm32")]
+extern crate ring_wasm as ring;
 #[cfg(target_arch = "wasm32")]
 extern crate webpki_wasm as webpki;
 
@@ -21,6 +24,7 @@ mod utils;
 pub mod mining;
 pub mod mq;
 pub mod ott;
+pub mod random_beacon;
 pub mod registry;
 pub mod stakepool;
 
@@ -28,6 +32,7 @@ pub mod stakepool;
 pub use mining as pallet_mining;
 pub use mq as pallet_mq;
 pub use ott as pallet_ott;
+pub use random_beacon as pallet_random_beacon;
 pub use registry as pallet_registry;
 pub use stakepool as pallet_stakepool;
 
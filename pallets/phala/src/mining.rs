@@ -34,6 +34,12 @@ pub mod pallet {
 	use fixed_sqrt::FixedSqrt;
 
 	const DEFAULT_EXPECTED_HEARTBEAT_COUNT: u32 = 20;
+	/// The max slash rate of V per block
+	const MAX_SLASH_RATE: FixedPoint = fp!(0.01);
+	/// The max heartbeat window (in blocks)
+	const MAX_HEARTBEAT_WINDOW: u32 = 300;
+	/// The max upper bound of V
+	const MAX_V_MAX: FixedPoint = fp!(1000000);
 	const MINING_PALLETID: PalletId = PalletId(*b"phala/pp");
 
 	#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
@@ -214,6 +220,18 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type TokenomicParameters<T> = StorageValue<_, TokenomicParams>;
 
+	/// The tokenomic parameters to take effect at the block, not activated yet
+	#[pallet::storage]
+	#[pallet::getter(fn scheduled_tokenomic_parameters)]
+	pub type ScheduledTokenomicParameters<T: Config> =
+		StorageValue<_, (T::BlockNumber, TokenomicParams)>;
+
+	/// The tokenomic parameters ever applied, by the block they took effect at
+	#[pallet::storage]
+	#[pallet::getter(fn tokenomic_parameters_history)]
+	pub type TokenomicParametersHistory<T: Config> =
+		StorageMap<_, Twox64Concat, T::BlockNumber, TokenomicParams>;
+
 	/// Total online miners
 	///
 	/// Increased when a miner is turned to MininIdle; decreased when turned to CoolingDown
@@ -259,7 +277,11 @@ pub mod pallet {
 
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
-	#[pallet::metadata(T::AccountId = "AccountId", BalanceOf<T> = "Balance")]
+	#[pallet::metadata(
+		T::AccountId = "AccountId",
+		BalanceOf<T> = "Balance",
+		T::BlockNumber = "BlockNumber"
+	)]
 	pub enum Event<T: Config> {
 		/// Cool down expiration changed. \[period\]
 		CoolDownExpirationChanged(u64),
@@ -281,6 +303,10 @@ pub mod pallet {
 		MinerSettled(T::AccountId, u128, u128),
 		/// Some internal error happened when settling a miner's ledger. \[worker\]
 		InternalErrorMinerSettleFailed(WorkerPublicKey),
+		/// Tokenomic parameters are scheduled. \[activation_block\]
+		TokenomicParametersScheduled(T::BlockNumber),
+		/// Tokenomic parameters take effect. \[block\]
+		TokenomicParametersActivated(T::BlockNumber),
 	}
 
 	#[pallet::error]
@@ -303,6 +329,18 @@ pub mod pallet {
 		DuplicateBoundWorker,
 		/// Indicating the initial benchmark score is too low to start mining.
 		BenchmarkTooLow,
+		/// The slash rate in the tokenomic parameters is out of bound.
+		InvalidSlashRate,
+		/// The heartbeat window in the tokenomic parameters is out of bound.
+		InvalidHeartbeatWindow,
+		/// The max V in the tokenomic parameters is out of bound.
+		InvalidVMax,
+		/// The tokenomic parameters can only be scheduled for a future block.
+		InvalidActivationBlock,
+		/// The worker is forced out for its attestation until it registers again.
+		WorkerAttestationInvalidated,
+		/// The tokenomic parameters can't be updated while some are scheduled.
+		TokenomicParametersPending,
 	}
 
 	type BalanceOf<T> =
@@ -402,15 +440,48 @@ pub mod pallet {
 		}
 
 		/// Updates the tokenomic parameters
+		///
+		/// Rejected while some parameters are scheduled, which would override the update at the
+		/// activation block. Schedule the parameters again to replace them instead.
 		#[pallet::weight(1)]
 		pub fn update_tokenomic(
 			origin: OriginFor<T>,
 			new_params: TokenomicParams,
 		) -> DispatchResult {
 			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			Self::validate_tokenomic_parameters(&new_params)?;
+			ensure!(
+				ScheduledTokenomicParameters::<T>::get().is_none(),
+				Error::<T>::TokenomicParametersPending
+			);
 			Self::update_tokenomic_parameters(new_params);
 			Ok(())
 		}
+
+		/// Schedules the tokenomic parameters to take effect at `activation_block`
+		///
+		/// Replaces the previously scheduled parameters not activated yet. The Gatekeepers are
+		/// notified immediately, but switch to the new parameters exactly at the activation block.
+		#[pallet::weight(1)]
+		pub fn schedule_tokenomic(
+			origin: OriginFor<T>,
+			new_params: TokenomicParams,
+			activation_block: T::BlockNumber,
+		) -> DispatchResult {
+			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			Self::validate_tokenomic_parameters(&new_params)?;
+			ensure!(
+				activation_block > frame_system::Pallet::<T>::block_number(),
+				Error::<T>::InvalidActivationBlock
+			);
+			ScheduledTokenomicParameters::<T>::put((activation_block, new_params.clone()));
+			Self::push_message(GatekeeperEvent::TokenomicParametersScheduled {
+				activation_block: activation_block.saturated_into(),
+				params: new_params,
+			});
+			Self::deposit_event(Event::<T>::TokenomicParametersScheduled(activation_block));
+			Ok(())
+		}
 	}
 
 	#[pallet::hooks]
@@ -418,6 +489,17 @@ pub mod pallet {
 	where
 		BalanceOf<T>: FixedPointConvert,
 	{
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			let mut w = T::DbWeight::get().reads(1);
+			if let Some((activation_block, _)) = ScheduledTokenomicParameters::<T>::get() {
+				if activation_block <= n {
+					Self::activate_scheduled_tokenomic_parameters(n);
+					w += T::DbWeight::get().writes(3);
+				}
+			}
+			w
+		}
+
 		fn on_finalize(_n: T::BlockNumber) {
 			Self::heartbeat_challenge();
 		}
@@ -682,6 +764,10 @@ pub mod pallet {
 
 			let worker_info =
 				registry::Workers::<T>::get(&worker).expect("Bounded worker must exist; qed.");
+			ensure!(
+				!registry::InvalidatedWorkers::<T>::contains_key(&worker),
+				Error::<T>::WorkerAttestationInvalidated
+			);
 			let p = worker_info
 				.initial_score
 				.ok_or(Error::<T>::BenchmarkMissing)?;
@@ -769,10 +855,40 @@ pub mod pallet {
 		}
 
 		fn update_tokenomic_parameters(params: TokenomicParams) {
+			let now = frame_system::Pallet::<T>::block_number();
 			TokenomicParameters::<T>::put(params.clone());
+			TokenomicParametersHistory::<T>::insert(now, params.clone());
 			Self::push_message(GatekeeperEvent::TokenomicParametersChanged(params));
 		}
 
+		/// Applies the scheduled tokenomic parameters
+		///
+		/// The Gatekeepers switch by themselves, so no message is sent.
+		fn activate_scheduled_tokenomic_parameters(now: T::BlockNumber) {
+			if let Some((_, params)) = ScheduledTokenomicParameters::<T>::take() {
+				TokenomicParameters::<T>::put(params.clone());
+				TokenomicParametersHistory::<T>::insert(now, params);
+				Self::deposit_event(Event::<T>::TokenomicParametersActivated(now));
+			}
+		}
+
+		fn validate_tokenomic_parameters(params: &TokenomicParams) -> DispatchResult {
+			ensure!(
+				FixedPoint::from_bits(params.slash_rate) <= MAX_SLASH_RATE,
+				Error::<T>::InvalidSlashRate
+			);
+			ensure!(
+				params.heartbeat_window > 0 && params.heartbeat_window <= MAX_HEARTBEAT_WINDOW,
+				Error::<T>::InvalidHeartbeatWindow
+			);
+			let v_max = FixedPoint::from_bits(params.v_max);
+			ensure!(
+				v_max > fp!(0) && v_max <= MAX_V_MAX,
+				Error::<T>::InvalidVMax
+			);
+			Ok(())
+		}
+
 		pub fn withdraw_subsidy_pool(target: &T::AccountId, value: BalanceOf<T>) -> DispatchResult {
 			let wallet = Self::account_id();
 			T::Currency::transfer(&wallet, target, value, KeepAlive)
@@ -938,6 +1054,10 @@ pub mod pallet {
 		fn build(&self) {
 			CoolDownPeriod::<T>::put(self.cool_down_period_sec as u64);
 			TokenomicParameters::<T>::put(self.tokenomic_parameters.clone());
+			TokenomicParametersHistory::<T>::insert(
+				T::BlockNumber::default(),
+				self.tokenomic_parameters.clone(),
+			);
 			Pallet::<T>::queue_message(GatekeeperEvent::TokenomicParametersChanged(
 				self.tokenomic_parameters.clone(),
 			));
@@ -1018,12 +1138,18 @@ pub mod pallet {
 		type Config = T;
 	}
 
+	impl<T: Config> registry::MiningWorkers for Pallet<T> {
+		fn online_workers() -> u32 {
+			OnlineMiners::<T>::get()
+		}
+	}
+
 	#[cfg(test)]
 	mod test {
 		use super::*;
 		use crate::mock::{
 			elapse_seconds, new_test_ext, set_block_1, setup_workers, take_events, take_messages,
-			worker_pubkey, Event as TestEvent, Origin, Test, DOLLARS,
+			teleport_to_block, worker_pubkey, Event as TestEvent, Origin, Test, DOLLARS,
 		};
 		// Pallets
 		use crate::mock::{PhalaMining, PhalaRegistry, System};
@@ -1345,5 +1471,79 @@ pub mod pallet {
 				}
 			);
 		}
+
+		#[test]
+		fn test_schedule_tokenomic() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let params = TokenomicParameters::<Test>::get().unwrap();
+				let mut new_params = params.clone();
+				new_params.heartbeat_window = 20;
+
+				// Out of bounds
+				let mut bad_params = new_params.clone();
+				bad_params.slash_rate = fp!(0.5).to_bits();
+				assert_noop!(
+					PhalaMining::schedule_tokenomic(Origin::root(), bad_params, 10),
+					Error::<Test>::InvalidSlashRate
+				);
+				let mut bad_params = new_params.clone();
+				bad_params.heartbeat_window = 0;
+				assert_noop!(
+					PhalaMining::schedule_tokenomic(Origin::root(), bad_params, 10),
+					Error::<Test>::InvalidHeartbeatWindow
+				);
+				let mut bad_params = new_params.clone();
+				bad_params.v_max = 0;
+				assert_noop!(
+					PhalaMining::schedule_tokenomic(Origin::root(), bad_params, 10),
+					Error::<Test>::InvalidVMax
+				);
+				assert_noop!(
+					PhalaMining::schedule_tokenomic(Origin::root(), new_params.clone(), 1),
+					Error::<Test>::InvalidActivationBlock
+				);
+
+				assert_ok!(PhalaMining::schedule_tokenomic(
+					Origin::root(),
+					new_params.clone(),
+					10
+				));
+				let messages: Vec<GatekeeperEvent> = take_messages()
+					.iter()
+					.filter_map(|m| m.decode_payload())
+					.collect();
+				assert_eq!(
+					messages,
+					vec![GatekeeperEvent::TokenomicParametersScheduled {
+						activation_block: 10,
+						params: new_params.clone(),
+					}]
+				);
+
+				// Not overridden by the scheduled ones silently
+				assert_noop!(
+					PhalaMining::update_tokenomic(Origin::root(), params.clone()),
+					Error::<Test>::TokenomicParametersPending
+				);
+
+				teleport_to_block(9);
+				assert_eq!(TokenomicParameters::<Test>::get(), Some(params.clone()));
+				teleport_to_block(10);
+				assert_eq!(TokenomicParameters::<Test>::get(), Some(new_params.clone()));
+				assert_eq!(
+					PhalaMining::tokenomic_parameters_history(10),
+					Some(new_params)
+				);
+				assert_eq!(PhalaMining::scheduled_tokenomic_parameters(), None);
+
+				// Updatable once activated
+				assert_ok!(PhalaMining::update_tokenomic(
+					Origin::root(),
+					params.clone()
+				));
+				assert_eq!(TokenomicParameters::<Test>::get(), Some(params));
+			});
+		}
 	}
 }
@@ -1,6 +1,9 @@
 use crate::{
-	attestation::{Attestation, AttestationValidator, Error as AttestationError, IasFields},
-	mining, mq, ott, registry, stakepool,
+	attestation::{
+		Attestation, AttestationProvider, AttestationValidator, DcapCollateral,
+		Error as AttestationError, IasFields,
+	},
+	mining, mq, ott, random_beacon, registry, stakepool,
 };
 
 use frame_support::{
@@ -38,6 +41,7 @@ frame_support::construct_runtime!(
 		PhalaMining: mining::{Pallet, Event<T>, Storage, Config},
 		PhalaStakePool: stakepool::{Pallet, Event<T>},
 		PhalaOneshotTransfer: ott::{Pallet, Event<T>},
+		PhalaRandomBeacon: random_beacon::{Pallet, Event<T>, Storage},
 	}
 );
 
@@ -126,6 +130,7 @@ impl registry::Config for Test {
 	type VerifyPRuntime = VerifyPRuntime;
 	type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
 	type GovernanceOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type MiningWorkers = PhalaMining;
 }
 
 impl mining::Config for Test {
@@ -158,6 +163,10 @@ impl ott::Config for Test {
 	type Currency = Balances;
 }
 
+impl random_beacon::Config for Test {
+	type Event = Event;
+}
+
 pub struct MockValidator;
 impl AttestationValidator for MockValidator {
 	fn validate(
@@ -166,6 +175,7 @@ impl AttestationValidator for MockValidator {
 		_now: u64,
 		_verify_pruntime: bool,
 		_pruntime_allowlist: Vec<Vec<u8>>,
+		_dcap_collateral: Option<DcapCollateral>,
 	) -> Result<IasFields, AttestationError> {
 		Ok(IasFields {
 			mr_enclave: [0u8; 32],
@@ -202,6 +212,11 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
 		workers: vec![(zero_pubkey.clone(), zero_ecdh_pubkey, None)],
 		gatekeepers: vec![(zero_pubkey.clone())],
 		benchmark_duration: 0u32,
+		attestation_providers: vec![
+			AttestationProvider::Ias,
+			AttestationProvider::Dcap,
+			AttestationProvider::None,
+		],
 	}
 	.assimilate_storage(&mut t)
 	.unwrap();
@@ -228,6 +243,7 @@ pub fn take_messages() -> Vec<Message> {
 	let messages = PhalaMq::messages();
 	println!("messages(): {:?}", messages);
 	mq::OutboundMessages::<Test>::kill();
+	mq::OutboundMessageSequences::<Test>::kill();
 	messages
 }
 
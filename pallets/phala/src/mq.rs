@@ -43,6 +43,12 @@ pub mod pallet {
 	#[pallet::getter(fn messages)]
 	pub type OutboundMessages<T> = StorageValue<_, Vec<Message>, ValueQuery>;
 
+	/// The egress sequences of the `OutboundMessages` synced from offchain, in the same order.
+	///
+	/// It will be cleared at the beginning of every block.
+	#[pallet::storage]
+	pub type OutboundMessageSequences<T> = StorageValue<_, Vec<Option<u64>>, ValueQuery>;
+
 	#[pallet::error]
 	pub enum Error<T> {
 		BadSender,
@@ -81,11 +87,14 @@ pub mod pallet {
 				Error::<T>::BadSequence
 			);
 			// Validate signature
-			crate::registry::Pallet::<T>::check_message(&signed_message)?;
+			if !crate::registry::Pallet::<T>::check_message(&signed_message)? {
+				// Waiting for more share holders to approve it
+				return Ok(());
+			}
 			// Update ingress
 			OffchainIngress::<T>::insert(sender.clone(), expected_seq + 1);
 			// Call dispatch_message
-			Self::dispatch_message(signed_message.message);
+			Self::do_dispatch_message(signed_message.message, Some(expected_seq));
 			Ok(())
 		}
 
@@ -108,6 +117,10 @@ pub mod pallet {
 	impl<T: Config> Pallet<T> {
 		/// Push a validated message to the queue
 		pub fn dispatch_message(message: Message) {
+			Self::do_dispatch_message(message, None)
+		}
+
+		fn do_dispatch_message(message: Message, sequence: Option<u64>) {
 			// Notify subcribers
 			if let Err(_err) = T::QueueNotifyConfig::on_message_received(&message) {
 				// TODO: Consider to emit a message as warning. We can't stop dispatching message in any situation.
@@ -115,6 +128,7 @@ pub mod pallet {
 			// Notify the off-chain components
 			if T::QueueNotifyConfig::should_push_message(&message) {
 				OutboundMessages::<T>::append(message);
+				OutboundMessageSequences::<T>::append(sequence);
 			}
 		}
 
@@ -146,6 +160,7 @@ pub mod pallet {
 		fn on_initialize(_now: BlockNumberFor<T>) -> Weight {
 			// Clear the previously pushed offchain messages
 			OutboundMessages::<T>::kill();
+			OutboundMessageSequences::<T>::kill();
 
 			// Send out queued message from the previous block
 			if let Some(msgs) = QueuedOutboundMessage::<T>::take() {
@@ -0,0 +1,342 @@
+//! # RandomBeacon Pallet
+//!
+//! This pallet stores the random numbers produced by the gatekeepers. Once the VRF beacon is
+//! enabled, the gatekeepers attach a schnorrkel VRF proof to each random number, so that the
+//! chain can verify it against the gatekeeper master pubkey before accepting it. The latest
+//! verified random number is exposed through the `Randomness` trait for the other pallets.
+
+pub use self::pallet::*;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use crate::mq::{self, MessageOriginInfo};
+	use crate::registry;
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{Randomness, StorageVersion},
+	};
+	use frame_system::pallet_prelude::*;
+	use phala_crypto::vrf;
+	use phala_types::{
+		messaging::{
+			random_number_vrf_message, DecodedMessage, GatekeeperEvent, MessageOrigin,
+			RandomNumber, RandomNumberEvent, RandomNumberProof, RANDOM_NUMBER_VRF_CONTEXT,
+			RANDOM_NUMBER_VRF_LABEL,
+		},
+		MasterPublicKey,
+	};
+	use sp_runtime::traits::{Hash, Zero};
+	use sp_std::vec::Vec;
+
+	/// The number of verified random numbers kept on chain
+	pub const RANDOM_NUMBER_HISTORY_SIZE: usize = 64;
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config + mq::Config + registry::Config {
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+	}
+
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(0);
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	#[pallet::storage_version(STORAGE_VERSION)]
+	pub struct Pallet<T>(_);
+
+	#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+	pub struct RandomNumberRecord<BlockNumber> {
+		/// The block the random number was produced for by the gatekeepers
+		pub block_number: u32,
+		pub random_number: RandomNumber,
+		/// The block the random number was accepted at
+		pub stored_at: BlockNumber,
+	}
+
+	/// The block the VRF beacon was enabled at
+	#[pallet::storage]
+	#[pallet::getter(fn vrf_beacon_enabled_at)]
+	pub type VrfBeaconEnabledAt<T: Config> = StorageValue<_, T::BlockNumber>;
+
+	/// The latest verified random numbers, the oldest first
+	#[pallet::storage]
+	#[pallet::getter(fn random_numbers)]
+	pub type RandomNumbers<T: Config> =
+		StorageValue<_, Vec<RandomNumberRecord<T::BlockNumber>>, ValueQuery>;
+
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	#[pallet::metadata(T::BlockNumber = "BlockNumber")]
+	pub enum Event<T: Config> {
+		/// The gatekeepers are requested to attach VRF proofs to the random numbers. \[block\]
+		VrfBeaconEnabled(T::BlockNumber),
+		/// A random number is verified and stored. \[gk_block, random_number\]
+		RandomNumberStored(u32, RandomNumber),
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		VrfBeaconAlreadyEnabled,
+		MasterKeyUninitialized,
+		InvalidRandomNumberProof,
+		StaleRandomNumber,
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T>
+	where
+		T: mq::Config,
+	{
+		/// Requests the gatekeepers to produce the random numbers with the VRF
+		///
+		/// Can only be called by root.
+		#[pallet::weight(0)]
+		pub fn enable_vrf_beacon(origin: OriginFor<T>) -> DispatchResult {
+			ensure_root(origin)?;
+			ensure!(
+				VrfBeaconEnabledAt::<T>::get().is_none(),
+				Error::<T>::VrfBeaconAlreadyEnabled
+			);
+			ensure!(
+				registry::GatekeeperMasterPubkey::<T>::get().is_some(),
+				Error::<T>::MasterKeyUninitialized
+			);
+			let now = frame_system::Pallet::<T>::block_number();
+			VrfBeaconEnabledAt::<T>::put(now);
+			Self::push_message(GatekeeperEvent::VrfBeaconEnabled);
+			Self::deposit_event(Event::<T>::VrfBeaconEnabled(now));
+			Ok(())
+		}
+	}
+
+	impl<T: Config> Pallet<T>
+	where
+		T: mq::Config,
+	{
+		pub fn on_gk_message_received(message: DecodedMessage<GatekeeperEvent>) -> DispatchResult {
+			// The pallets also talk to the gatekeepers on this topic
+			if !matches!(message.sender, MessageOrigin::Gatekeeper) {
+				return Ok(());
+			}
+			// The ones produced with the hash chain or the threshold master key are not verifiable
+			let (event, proof) = match message.payload {
+				GatekeeperEvent::NewVrfRandomNumber { event, proof } => (event, proof),
+				_ => return Ok(()),
+			};
+
+			let master_pubkey = registry::GatekeeperMasterPubkey::<T>::get()
+				.ok_or(Error::<T>::MasterKeyUninitialized)?;
+			let verified = verify_random_number(&master_pubkey, &event, &proof)
+				|| registry::Pallet::<T>::recently_retired_master_pubkey()
+					.map(|retired| verify_random_number(&retired, &event, &proof))
+					.unwrap_or(false);
+			ensure!(verified, Error::<T>::InvalidRandomNumberProof);
+
+			let mut records = RandomNumbers::<T>::get();
+			if let Some(last) = records.last() {
+				ensure!(
+					event.block_number > last.block_number,
+					Error::<T>::StaleRandomNumber
+				);
+			}
+			records.push(RandomNumberRecord {
+				block_number: event.block_number,
+				random_number: event.random_number,
+				stored_at: frame_system::Pallet::<T>::block_number(),
+			});
+			if records.len() > RANDOM_NUMBER_HISTORY_SIZE {
+				records.remove(0);
+			}
+			RandomNumbers::<T>::put(records);
+			Self::deposit_event(Event::<T>::RandomNumberStored(
+				event.block_number,
+				event.random_number,
+			));
+			Ok(())
+		}
+
+		/// The latest verified random number
+		pub fn latest_random_number() -> Option<RandomNumberRecord<T::BlockNumber>> {
+			RandomNumbers::<T>::get().pop()
+		}
+	}
+
+	/// Verifies the VRF proof of the random number against the master pubkey
+	fn verify_random_number(
+		master_pubkey: &MasterPublicKey,
+		event: &RandomNumberEvent,
+		proof: &RandomNumberProof,
+	) -> bool {
+		let message = random_number_vrf_message(event.block_number, &event.last_random_number);
+		match vrf::vrf_verify(
+			&master_pubkey.0,
+			RANDOM_NUMBER_VRF_CONTEXT,
+			&message,
+			RANDOM_NUMBER_VRF_LABEL,
+			&proof.vrf_output,
+			&proof.vrf_proof,
+		) {
+			Ok(random_number) => random_number == event.random_number,
+			Err(_) => false,
+		}
+	}
+
+	impl<T: Config> Randomness<T::Hash, T::BlockNumber> for Pallet<T> {
+		/// Mixes the latest verified random number with the subject
+		///
+		/// Returns the block the random number was accepted at, or zero if there's none yet.
+		fn random(subject: &[u8]) -> (T::Hash, T::BlockNumber) {
+			match RandomNumbers::<T>::get().pop() {
+				Some(record) => (
+					T::Hashing::hash(&(subject, record.random_number).encode()),
+					record.stored_at,
+				),
+				None => (T::Hashing::hash(subject), Zero::zero()),
+			}
+		}
+	}
+
+	impl<T: Config> MessageOriginInfo for Pallet<T> {
+		type Config = T;
+	}
+
+	#[cfg(test)]
+	mod test {
+		use super::*;
+		use crate::mock::{new_test_ext, set_block_1, take_messages, Origin, Test};
+		// Pallets
+		use crate::mock::PhalaRandomBeacon;
+		use frame_support::{assert_noop, assert_ok};
+		use phala_types::messaging::Topic;
+		use schnorrkel::{ExpansionMode, Keypair, MiniSecretKey};
+
+		fn master_key() -> Keypair {
+			MiniSecretKey::from_bytes(&[1u8; 32])
+				.unwrap()
+				.expand_to_keypair(ExpansionMode::Ed25519)
+		}
+
+		fn random_number_message(
+			key: &Keypair,
+			block_number: u32,
+			last_random_number: RandomNumber,
+		) -> DecodedMessage<GatekeeperEvent> {
+			let message = random_number_vrf_message(block_number, &last_random_number);
+			let (random_number, vrf_output, vrf_proof) = vrf::vrf_sign(
+				key,
+				RANDOM_NUMBER_VRF_CONTEXT,
+				&message,
+				RANDOM_NUMBER_VRF_LABEL,
+			);
+			DecodedMessage {
+				sender: MessageOrigin::Gatekeeper,
+				destination: Topic::new(*b"phala/gatekeeper/event"),
+				payload: GatekeeperEvent::new_vrf_random_number(
+					block_number,
+					random_number,
+					last_random_number,
+					RandomNumberProof {
+						vrf_output,
+						vrf_proof,
+					},
+				),
+			}
+		}
+
+		#[test]
+		fn enable_vrf_beacon_once() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				assert_noop!(
+					PhalaRandomBeacon::enable_vrf_beacon(Origin::root()),
+					Error::<Test>::MasterKeyUninitialized
+				);
+				registry::GatekeeperMasterPubkey::<Test>::put(MasterPublicKey::from_raw(
+					master_key().public.to_bytes(),
+				));
+				assert_ok!(PhalaRandomBeacon::enable_vrf_beacon(Origin::root()));
+				assert_eq!(PhalaRandomBeacon::vrf_beacon_enabled_at(), Some(1));
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert_eq!(
+					messages[0].decode_payload::<GatekeeperEvent>(),
+					Some(GatekeeperEvent::VrfBeaconEnabled)
+				);
+				assert_noop!(
+					PhalaRandomBeacon::enable_vrf_beacon(Origin::root()),
+					Error::<Test>::VrfBeaconAlreadyEnabled
+				);
+			});
+		}
+
+		#[test]
+		fn verify_random_numbers() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let key = master_key();
+				registry::GatekeeperMasterPubkey::<Test>::put(MasterPublicKey::from_raw(
+					key.public.to_bytes(),
+				));
+
+				let message = random_number_message(&key, 10, [0u8; 32]);
+				let random_number = match &message.payload {
+					GatekeeperEvent::NewVrfRandomNumber { event, .. } => event.random_number,
+					_ => unreachable!(),
+				};
+				assert_ok!(PhalaRandomBeacon::on_gk_message_received(message));
+				assert_eq!(
+					PhalaRandomBeacon::latest_random_number(),
+					Some(RandomNumberRecord {
+						block_number: 10,
+						random_number,
+						stored_at: 1,
+					})
+				);
+				// Replayed random number
+				assert_noop!(
+					PhalaRandomBeacon::on_gk_message_received(random_number_message(
+						&key, 10, [0u8; 32]
+					)),
+					Error::<Test>::StaleRandomNumber
+				);
+
+				// Signed by another key
+				let other_key = MiniSecretKey::from_bytes(&[2u8; 32])
+					.unwrap()
+					.expand_to_keypair(ExpansionMode::Ed25519);
+				assert_noop!(
+					PhalaRandomBeacon::on_gk_message_received(random_number_message(
+						&other_key,
+						15,
+						random_number
+					)),
+					Error::<Test>::InvalidRandomNumberProof
+				);
+				// Tampered random number
+				let mut message = random_number_message(&key, 15, random_number);
+				if let GatekeeperEvent::NewVrfRandomNumber { event, .. } = &mut message.payload {
+					event.random_number[0] ^= 1;
+				}
+				assert_noop!(
+					PhalaRandomBeacon::on_gk_message_received(message),
+					Error::<Test>::InvalidRandomNumberProof
+				);
+
+				// Random numbers without proofs are ignored
+				assert_ok!(PhalaRandomBeacon::on_gk_message_received(DecodedMessage {
+					sender: MessageOrigin::Gatekeeper,
+					destination: Topic::new(*b"phala/gatekeeper/event"),
+					payload: GatekeeperEvent::new_random_number(15, [1u8; 32], random_number),
+				}));
+				assert_eq!(PhalaRandomBeacon::random_numbers().len(), 1);
+
+				assert_ok!(PhalaRandomBeacon::on_gk_message_received(
+					random_number_message(&key, 15, random_number)
+				));
+				assert_eq!(PhalaRandomBeacon::random_numbers().len(), 2);
+				let (_, stored_at) = PhalaRandomBeacon::random(b"subject");
+				assert_eq!(stored_at, 1);
+			});
+		}
+	}
+}
@@ -15,17 +15,21 @@ pub mod pallet {
 	use sp_std::prelude::*;
 	use sp_std::{convert::TryFrom, vec};
 
-	use crate::attestation::{AttestationValidator, Error as AttestationError};
 	use crate::mq::MessageOriginInfo;
 	// Re-export
-	pub use crate::attestation::{Attestation, IasValidator};
+	pub use crate::attestation::{
+		Attestation, AttestationProvider, AttestationProviderConfig, AttestationValidator,
+		DcapCollateral, Error as AttestationError, IasFields, IasValidator, QeIdentity, TcbInfo,
+		TcbLevel, TcbStatus,
+	};
 
 	use phala_types::{
 		messaging::{
-			self, bind_topic, DecodedMessage, GatekeeperChange, GatekeeperLaunch, MessageOrigin,
-			SignedMessage, SystemEvent, WorkerEvent,
+			self, bind_topic, DecodedMessage, GatekeeperChange, GatekeeperLaunch,
+			MasterKeyShareHolder, MessageOrigin, SignedMessage, SystemEvent, WorkerEvent,
 		},
-		ContractPublicKey, EcdhPublicKey, MasterPublicKey, WorkerPublicKey, WorkerRegistrationInfo,
+		ContractPublicKey, EcdhPublicKey, GatekeeperUnregistration, MasterPublicKey,
+		WorkerPublicKey, WorkerRegistrationInfo,
 	};
 
 	bind_topic!(RegistryEvent, b"^phala/registry/event");
@@ -33,6 +37,91 @@ pub mod pallet {
 	pub enum RegistryEvent {
 		BenchReport { start_time: u64, iterations: u64 },
 		MasterPubkey { master_pubkey: MasterPublicKey },
+		/// The master pubkey generated by the lead gatekeeper of a rotation
+		RotatedMasterPubkey {
+			rotation_id: u32,
+			master_pubkey: MasterPublicKey,
+		},
+		/// The master pubkey and the public shares generated by the dealer of a threshold
+		/// rotation
+		RotatedThresholdMasterPubkey {
+			rotation_id: u32,
+			master_pubkey: MasterPublicKey,
+			share_holders: Vec<MasterKeyShareHolder>,
+		},
+		/// The report of the composite benchmark suite
+		///
+		/// `iterations` counts the rounds of the suite, each running the components as many units
+		/// as their weights. `component_iterations` are the units of work done by the components,
+		/// in the order of `BenchComponent::ALL`.
+		SuiteBenchReport {
+			start_time: u64,
+			iterations: u64,
+			suite_version: u32,
+			component_iterations: Vec<(BenchComponent, u64)>,
+		},
+	}
+
+	/// The version of the benchmark suite accepted by the chain
+	pub const BENCH_SUITE_VERSION: u32 = 1;
+
+	/// The default weight of a benchmark component in the composite score, in per mille
+	pub const DEFAULT_BENCH_COMPONENT_WEIGHT: u32 = 1000;
+
+	/// The max weight of a benchmark component, in per mille
+	pub const MAX_BENCH_COMPONENT_WEIGHT: u32 = 1_000_000;
+
+	/// The confidence level of the workers registered without a validated attestation
+	pub const UNATTESTED_CONFIDENCE_LEVEL: u8 = 128;
+
+	/// The granularity of the attestation expiry check, in seconds
+	pub const ATTESTATION_EXPIRY_BUCKET_SECS: u64 = 600;
+
+	/// Max number of `AttestationExpiryQueue` buckets to check in a block
+	const MAX_ATTESTATION_EXPIRY_BUCKETS_PER_BLOCK: u32 = 16;
+
+	/// The components of the composite benchmark suite
+	#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+	pub enum BenchComponent {
+		/// sr25519 signing
+		Sr25519Sign,
+		/// AES-GCM encryption throughput
+		AesGcm,
+		/// Trie insertion and lookup
+		Trie,
+		/// Memory copy throughput
+		MemoryBandwidth,
+	}
+
+	impl BenchComponent {
+		pub const ALL: [BenchComponent; 4] = [
+			BenchComponent::Sr25519Sign,
+			BenchComponent::AesGcm,
+			BenchComponent::Trie,
+			BenchComponent::MemoryBandwidth,
+		];
+	}
+
+	/// Number of blocks the retired master pubkey is still accepted to verify the gatekeeper
+	/// messages, which may be signed before the gatekeepers switch to the new key.
+	pub const RETIRED_MASTER_PUBKEY_GRACE_BLOCKS: u32 = 100;
+
+	/// A master key rotation waiting for activation
+	#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+	pub struct MasterKeyRotation<BlockNumber> {
+		pub rotation_id: u32,
+		/// The gatekeeper to generate the new master key
+		pub lead: WorkerPublicKey,
+		/// The new master pubkey, once uploaded by the lead gatekeeper
+		pub master_pubkey: Option<MasterPublicKey>,
+		/// The new master key takes effect at this block if the pubkey has been uploaded, or
+		/// otherwise right after uploaded
+		pub activation_block: BlockNumber,
+		/// The number of shares required to use the new master key, if it's split among the
+		/// gatekeepers
+		pub threshold: Option<u32>,
+		/// The share holders of the new master key, once uploaded by the dealer
+		pub share_holders: Vec<MasterKeyShareHolder>,
 	}
 
 	#[pallet::config]
@@ -52,9 +141,23 @@ pub mod pallet {
 
 		/// Origin used to administer the pallet
 		type GovernanceOrigin: EnsureOrigin<Self::Origin>;
+
+		/// The mining workers, which need a gatekeeper to work
+		type MiningWorkers: MiningWorkers;
+	}
+
+	/// Provides the number of the mining workers
+	pub trait MiningWorkers {
+		fn online_workers() -> u32;
 	}
 
-	const STORAGE_VERSION: StorageVersion = StorageVersion::new(1);
+	impl MiningWorkers for () {
+		fn online_workers() -> u32 {
+			0
+		}
+	}
+
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(3);
 
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
@@ -69,6 +172,46 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type GatekeeperMasterPubkey<T: Config> = StorageValue<_, MasterPublicKey>;
 
+	/// The master key rotation in progress
+	#[pallet::storage]
+	pub type PendingGatekeeperMasterPubkey<T: Config> =
+		StorageValue<_, MasterKeyRotation<T::BlockNumber>>;
+
+	/// The master pubkeys replaced by rotations, with the blocks they were retired at
+	///
+	/// Kept to verify the historical gatekeeper messages.
+	#[pallet::storage]
+	pub type RetiredGatekeeperMasterPubkeys<T: Config> =
+		StorageValue<_, Vec<(MasterPublicKey, T::BlockNumber)>, ValueQuery>;
+
+	/// The id of the last requested master key rotation
+	#[pallet::storage]
+	pub type MasterKeyRotationId<T: Config> = StorageValue<_, u32, ValueQuery>;
+
+	/// The number of shares required to evaluate the random beacon, if the gatekeepers switched to
+	/// a threshold master key
+	#[pallet::storage]
+	pub type MasterKeyThreshold<T: Config> = StorageValue<_, u32>;
+
+	/// The gatekeepers holding a share of the threshold master key
+	#[pallet::storage]
+	pub type MasterKeyShareHolders<T: Config> =
+		StorageValue<_, Vec<MasterKeyShareHolder>, ValueQuery>;
+
+	/// The ingress sequence of each sender with the message approved by each share holder at it,
+	/// as the hash of the signed data
+	///
+	/// A gatekeeper message signed by the share holders is only accepted with `MasterKeyThreshold`
+	/// approvals. The approvals are dropped once the sequence advances.
+	#[pallet::storage]
+	pub type GatekeeperMessageApprovals<T: Config> =
+		StorageMap<_, Twox64Concat, MessageOrigin, (u64, Vec<(WorkerPublicKey, [u8; 32])>)>;
+
+	/// The number of the unregistrations of each gatekeeper, signed in the next unregistration
+	#[pallet::storage]
+	pub type GatekeeperUnregistrationNonce<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, u32, ValueQuery>;
+
 	/// Mapping from worker pubkey to WorkerInfo
 	#[pallet::storage]
 	pub type Workers<T: Config> =
@@ -85,12 +228,77 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type BenchmarkDuration<T: Config> = StorageValue<_, u32>;
 
-	/// Allow list of pRuntime binary digest
+	/// The units of each benchmark component run in a round of the suite, in per mille
+	///
+	/// A component weighs `DEFAULT_BENCH_COMPONENT_WEIGHT` if not set.
+	#[pallet::storage]
+	pub type BenchmarkWeights<T: Config> = StorageMap<_, Twox64Concat, BenchComponent, u32>;
+
+	/// The attestation providers accepted by the chain, with their policies
+	///
+	/// The workers attested by other providers are rejected.
+	#[pallet::storage]
+	pub type AttestationProviders<T: Config> =
+		StorageMap<_, Twox64Concat, AttestationProvider, AttestationProviderConfig>;
+
+	/// Allow list of pRuntime binary digest of each attestation provider
 	///
 	/// Only pRuntime within the list can register.
 	#[pallet::storage]
 	#[pallet::getter(fn pruntime_allowlist)]
-	pub type PRuntimeAllowList<T: Config> = StorageValue<_, Vec<Vec<u8>>, ValueQuery>;
+	pub type PRuntimeAllowList<T: Config> =
+		StorageMap<_, Twox64Concat, AttestationProvider, Vec<Vec<u8>>, ValueQuery>;
+
+	/// The max age of the worker attestations, in seconds
+	///
+	/// The workers are forced out once their attestations get older, until they register again.
+	/// The attestations never expire if not set.
+	#[pallet::storage]
+	pub type MaxAttestationAge<T: Config> = StorageValue<_, u64>;
+
+	/// The workers to check for the attestation expiry, bucketed by their registration time
+	///
+	/// The key is the registration time divided by `ATTESTATION_EXPIRY_BUCKET_SECS`.
+	#[pallet::storage]
+	pub type AttestationExpiryQueue<T: Config> =
+		StorageMap<_, Twox64Concat, u64, Vec<WorkerPublicKey>, ValueQuery>;
+
+	/// The next bucket in `AttestationExpiryQueue` to check
+	#[pallet::storage]
+	pub type NextAttestationExpiryBucket<T: Config> = StorageValue<_, u64>;
+
+	/// The time of the last registration of each worker, in seconds
+	///
+	/// Unlike `WorkerInfo::last_updated`, it's not touched by the benchmark reports.
+	#[pallet::storage]
+	pub type WorkerRegistrationTime<T: Config> = StorageMap<_, Twox64Concat, WorkerPublicKey, u64>;
+
+	/// The attested pRuntime of each worker
+	#[pallet::storage]
+	pub type WorkerAttestations<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, WorkerAttestation>;
+
+	/// The workers attested to run each pRuntime, indexed by `(provider, pruntime_hash)`
+	#[pallet::storage]
+	pub type PRuntimeWorkers<T: Config> = StorageDoubleMap<
+		_,
+		Blake2_128Concat,
+		(AttestationProvider, Vec<u8>),
+		Twox64Concat,
+		WorkerPublicKey,
+		(),
+	>;
+
+	/// The workers forced out for their attestations, until they register again
+	#[pallet::storage]
+	pub type InvalidatedWorkers<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, AttestationInvalidation>;
+
+	/// The collateral to verify the DCAP quotes
+	///
+	/// The DCAP attestations are rejected until it's set.
+	#[pallet::storage]
+	pub type DcapQuoteCollateral<T: Config> = StorageValue<_, DcapCollateral>;
 
 	/// Allow list of relaychain genesis
 	///
@@ -104,6 +312,30 @@ pub mod pallet {
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event {
 		GatekeeperAdded(WorkerPublicKey),
+		/// A gatekeeper unregistered itself. \[gatekeeper\]
+		GatekeeperRemoved(WorkerPublicKey),
+		/// A master key rotation is requested. \[rotation_id, lead_gatekeeper\]
+		MasterKeyRotationRequested(u32, WorkerPublicKey),
+		/// The new master pubkey is uploaded. \[rotation_id, master_pubkey\]
+		RotatedMasterPubkeyUploaded(u32, MasterPublicKey),
+		/// The new master key takes effect. \[rotation_id, master_pubkey\]
+		MasterKeyRotated(u32, MasterPublicKey),
+		/// A threshold master key rotation is requested. \[rotation_id, dealer, threshold\]
+		ThresholdMasterKeyRotationRequested(u32, WorkerPublicKey, u32),
+		/// The weight of a benchmark component is updated. \[component, weight\]
+		BenchmarkWeightUpdated(BenchComponent, u32),
+		/// The DCAP collateral is updated by the governance.
+		DcapCollateralUpdated,
+		/// A worker is registered or has refreshed its registration. \[worker, provider\]
+		WorkerRegistered(WorkerPublicKey, AttestationProvider),
+		/// An attestation provider is enabled or reconfigured. \[provider\]
+		AttestationProviderEnabled(AttestationProvider),
+		/// An attestation provider is disabled. \[provider\]
+		AttestationProviderDisabled(AttestationProvider),
+		/// The max attestation age is updated. \[max_age\]
+		MaxAttestationAgeUpdated(Option<u64>),
+		/// A worker is forced out until it registers again. \[worker, reason\]
+		WorkerAttestationInvalidated(WorkerPublicKey, AttestationInvalidation),
 	}
 
 	#[pallet::error]
@@ -122,17 +354,40 @@ pub mod pallet {
 		BadIASReport,
 		OutdatedIASReport,
 		UnknownQuoteBodyFormat,
+		// Attestation provider related
+		UnsupportedAttestationProvider,
+		AttestationProviderNotFound,
+		ConfidenceLevelRejected,
+		InvalidMaxAttestationAge,
+		// DCAP related
+		DcapCollateralMissing,
+		InvalidDcapQuote,
+		InvalidPckCertChain,
+		InvalidQuoteSignature,
+		QeIdentityMismatch,
+		UnknownTcbLevel,
+		OutdatedDcapCollateral,
 		// Report validation
 		InvalidRuntimeInfoHash,
 		InvalidRuntimeInfo,
 		InvalidInput,
 		InvalidBenchReport,
+		UnsupportedBenchSuite,
+		InvalidBenchmarkWeight,
 		WorkerNotFound,
 		// Gatekeeper related
 		InvalidGatekeeper,
 		InvalidMasterPubkey,
 		MasterKeyMismatch,
 		MasterKeyUninitialized,
+		MasterKeyRotationInProgress,
+		NoMasterKeyRotation,
+		InvalidActivationBlock,
+		InvalidThreshold,
+		InvalidShareHolders,
+		ThresholdMasterKeyEnabled,
+		/// The last gatekeeper can't leave while the workers are mining
+		LastGatekeeperInUse,
 		// GenesisBlockHash related
 		GenesisBlockHashRejected,
 		GenesisBlockHashAlreadyExists,
@@ -155,6 +410,28 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Sets the units of the benchmark components run in a round of the suite, in per mille
+		///
+		/// Only affects the later benchmark reports.
+		#[pallet::weight(10_000 + T::DbWeight::get().writes(weights.len() as u64))]
+		pub fn set_benchmark_weights(
+			origin: OriginFor<T>,
+			weights: Vec<(BenchComponent, u32)>,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				weights
+					.iter()
+					.all(|(_, weight)| *weight > 0 && *weight <= MAX_BENCH_COMPONENT_WEIGHT),
+				Error::<T>::InvalidBenchmarkWeight
+			);
+			for (component, weight) in weights {
+				BenchmarkWeights::<T>::insert(component, weight);
+				Self::deposit_event(Event::BenchmarkWeightUpdated(component, weight));
+			}
+			Ok(())
+		}
+
 		/// Force register a worker with the given pubkey with sudo permission
 		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
 		pub fn force_register_worker(
@@ -170,7 +447,7 @@ pub mod pallet {
 				runtime_version: 0,
 				last_updated: 0,
 				operator,
-				confidence_level: 128u8,
+				confidence_level: UNATTESTED_CONFIDENCE_LEVEL,
 				initial_score: None,
 				features: vec![1, 4],
 			};
@@ -225,6 +502,16 @@ pub mod pallet {
 				gatekeepers.is_empty() || GatekeeperMasterPubkey::<T>::get().is_some(),
 				Error::<T>::MasterKeyUninitialized
 			);
+			// the new gatekeeper would miss the next master key
+			ensure!(
+				PendingGatekeeperMasterPubkey::<T>::get().is_none(),
+				Error::<T>::MasterKeyRotationInProgress
+			);
+			// nobody can share the threshold master key to the new gatekeeper
+			ensure!(
+				MasterKeyThreshold::<T>::get().is_none(),
+				Error::<T>::ThresholdMasterKeyEnabled
+			);
 
 			if !gatekeepers.contains(&gatekeeper) {
 				let worker_info =
@@ -248,19 +535,132 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Rotate the master key of the gatekeepers.
+		///
+		/// The `lead` gatekeeper generates the new master key, uploads its pubkey and sends the
+		/// key to the other gatekeepers. The new key takes effect at `activation_block`, or right
+		/// after the pubkey is uploaded if it's late. Overrides the pending rotation if any.
+		///
+		/// Must be called by the Root origin.
+		#[pallet::weight(10_000 + T::DbWeight::get().writes(2))]
+		pub fn rotate_master_key(
+			origin: OriginFor<T>,
+			lead: WorkerPublicKey,
+			activation_block: T::BlockNumber,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+
+			let (rotation_id, gatekeepers) =
+				Self::new_master_key_rotation(lead, activation_block, None)?;
+			Self::push_message(GatekeeperChange::master_key_rotation(
+				rotation_id,
+				lead,
+				gatekeepers,
+			));
+			Self::deposit_event(Event::MasterKeyRotationRequested(rotation_id, lead));
+			Ok(())
+		}
+
+		/// Rotate to a new master key split among the gatekeepers, as a threshold random beacon.
+		///
+		/// The `dealer` gatekeeper generates the new master key, splits it into a share for each
+		/// gatekeeper, `threshold` of which are required to use the key, and then discards it.
+		/// The dealer uploads the master pubkey together with the public shares. The activation
+		/// works as `rotate_master_key`.
+		///
+		/// Once activated, the random numbers are evaluated jointly by the share holders, and the
+		/// gatekeeper messages need the approvals of `threshold` share holders. Nothing else is
+		/// derived from the new key. The gatekeepers still hold the previous master keys in full to
+		/// replay the blocks before, but never share them again: no more gatekeeper can be
+		/// registered and the master key can't be rotated again.
+		///
+		/// Must be called by the Root origin.
+		#[pallet::weight(10_000 + T::DbWeight::get().writes(2))]
+		pub fn rotate_threshold_master_key(
+			origin: OriginFor<T>,
+			dealer: WorkerPublicKey,
+			threshold: u32,
+			activation_block: T::BlockNumber,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+
+			let (rotation_id, gatekeepers) =
+				Self::new_master_key_rotation(dealer, activation_block, Some(threshold))?;
+			Self::push_message(GatekeeperChange::ThresholdMasterKeyRotation(
+				messaging::ThresholdMasterKeyRotationEvent {
+					rotation_id,
+					dealer,
+					threshold,
+					gatekeepers,
+				},
+			));
+			Self::deposit_event(Event::ThresholdMasterKeyRotationRequested(
+				rotation_id,
+				dealer,
+				threshold,
+			));
+			Ok(())
+		}
+
 		/// Unregister a gatekeeper, must be called by gatekeeper himself
 		///
 		/// Requirements:
 		//  1. `sig` is the valid signature of specific unregister message
-		#[allow(unused_variables)]
-		#[pallet::weight(0)]
+		//  2. No master key rotation is in progress
+		//  3. Enough share holders of the threshold master key are left, if enabled
+		//  4. Some gatekeeper is left if any worker is mining
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(5, 2))]
 		pub fn unregister_gatekeeper(
 			origin: OriginFor<T>,
 			gatekeeper: WorkerPublicKey,
 			sig: [u8; 64],
 		) -> DispatchResult {
-			// TODO.shelven
-			panic!("unimpleneted");
+			ensure_signed(origin)?;
+
+			let mut gatekeepers = Gatekeeper::<T>::get();
+			ensure!(
+				gatekeepers.contains(&gatekeeper),
+				Error::<T>::InvalidGatekeeper
+			);
+			let unregistration = GatekeeperUnregistration {
+				pubkey: gatekeeper,
+				nonce: GatekeeperUnregistrationNonce::<T>::get(&gatekeeper),
+			};
+			ensure!(
+				sp_io::crypto::sr25519_verify(
+					&sp_core::sr25519::Signature::from_raw(sig),
+					&unregistration.data_be_signed(),
+					&gatekeeper
+				),
+				Error::<T>::InvalidSignature
+			);
+
+			// the leaving gatekeeper would miss the next master key
+			ensure!(
+				PendingGatekeeperMasterPubkey::<T>::get().is_none(),
+				Error::<T>::MasterKeyRotationInProgress
+			);
+			gatekeepers.retain(|pubkey| *pubkey != gatekeeper);
+			if let Some(threshold) = MasterKeyThreshold::<T>::get() {
+				let holders_left = MasterKeyShareHolders::<T>::get()
+					.iter()
+					.filter(|holder| gatekeepers.contains(&holder.pubkey))
+					.count();
+				ensure!(
+					holders_left as u32 >= threshold,
+					Error::<T>::InvalidShareHolders
+				);
+			}
+			ensure!(
+				!gatekeepers.is_empty() || T::MiningWorkers::online_workers() == 0,
+				Error::<T>::LastGatekeeperInUse
+			);
+
+			Gatekeeper::<T>::put(gatekeepers);
+			GatekeeperUnregistrationNonce::<T>::insert(&gatekeeper, unregistration.nonce + 1);
+			Self::push_message(GatekeeperChange::gatekeeper_unregistered(gatekeeper));
+			Self::deposit_event(Event::GatekeeperRemoved(gatekeeper));
+			Ok(())
 		}
 
 		/// (called by anyone on behalf of a worker)
@@ -274,14 +674,31 @@ pub mod pallet {
 			// Validate RA report & embedded user data
 			let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
 			let runtime_info_hash = crate::hashing::blake2_256(&Encode::encode(&pruntime_info));
-			let fields = T::AttestationValidator::validate(
-				&attestation,
-				&runtime_info_hash,
-				now,
-				T::VerifyPRuntime::get(),
-				PRuntimeAllowList::<T>::get(),
-			)
-			.map_err(Into::<Error<T>>::into)?;
+			let provider = attestation.provider();
+			let provider_config = AttestationProviders::<T>::get(provider)
+				.ok_or(Error::<T>::UnsupportedAttestationProvider)?;
+			let (judged_level, pruntime_hash) = match attestation {
+				Attestation::None => (UNATTESTED_CONFIDENCE_LEVEL, None),
+				_ => {
+					let dcap_collateral = match attestation {
+						Attestation::SgxDcap { .. } => DcapQuoteCollateral::<T>::get(),
+						_ => None,
+					};
+					let fields = T::AttestationValidator::validate(
+						&attestation,
+						&runtime_info_hash,
+						now,
+						T::VerifyPRuntime::get(),
+						PRuntimeAllowList::<T>::get(provider),
+						dcap_collateral,
+					)
+					.map_err(Into::<Error<T>>::into)?;
+					(fields.confidence_level, Some(fields.pruntime_hash()))
+				}
+			};
+			let confidence_level = provider_config
+				.confidence_level(judged_level)
+				.ok_or(Error::<T>::ConfidenceLevelRejected)?;
 
 			if T::VerifyRelaychainGenesisBlockHash::get() {
 				let genesis_block_hash = pruntime_info.genesis_block_hash;
@@ -300,11 +717,10 @@ pub mod pallet {
 						// Case 1 - Refresh the RA report, optionally update the operator, and redo benchmark
 						worker_info.last_updated = now;
 						worker_info.operator = pruntime_info.operator;
+						worker_info.runtime_version = pruntime_info.version;
 						Self::push_message(SystemEvent::new_worker_event(
 							pubkey,
-							WorkerEvent::Registered(messaging::WorkerInfo {
-								confidence_level: fields.confidence_level,
-							}),
+							WorkerEvent::Registered(messaging::WorkerInfo { confidence_level }),
 						));
 					}
 					None => {
@@ -315,50 +731,104 @@ pub mod pallet {
 							runtime_version: pruntime_info.version,
 							last_updated: now,
 							operator: pruntime_info.operator,
-							confidence_level: fields.confidence_level,
+							confidence_level,
 							initial_score: None,
 							features: pruntime_info.features,
 						});
 						Self::push_message(SystemEvent::new_worker_event(
 							pubkey,
-							WorkerEvent::Registered(messaging::WorkerInfo {
-								confidence_level: fields.confidence_level,
-							}),
+							WorkerEvent::Registered(messaging::WorkerInfo { confidence_level }),
 						));
 					}
 				}
 			});
+			if let Some(old) = WorkerAttestations::<T>::take(pubkey) {
+				PRuntimeWorkers::<T>::remove((old.provider, old.pruntime_hash), pubkey);
+			}
+			if let Some(pruntime_hash) = pruntime_hash {
+				PRuntimeWorkers::<T>::insert((provider, pruntime_hash.clone()), pubkey, ());
+				WorkerAttestations::<T>::insert(
+					pubkey,
+					WorkerAttestation {
+						provider,
+						pruntime_hash,
+					},
+				);
+			}
+			WorkerRegistrationTime::<T>::insert(pubkey, now);
+			InvalidatedWorkers::<T>::remove(pubkey);
+			Self::schedule_attestation_expiry(pubkey, now);
 			// Trigger benchmark anyway
 			let duration = BenchmarkDuration::<T>::get().unwrap_or_default();
 			Self::push_message(SystemEvent::new_worker_event(
 				pubkey,
 				WorkerEvent::BenchStart { duration },
 			));
+			Self::deposit_event(Event::WorkerRegistered(pubkey, provider));
+			Ok(())
+		}
+
+		/// Accepts the attestations of `provider`, or updates its policy if already accepted.
+		#[pallet::weight(0)]
+		pub fn enable_attestation_provider(
+			origin: OriginFor<T>,
+			provider: AttestationProvider,
+			config: AttestationProviderConfig,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			AttestationProviders::<T>::insert(provider, config);
+			Self::deposit_event(Event::AttestationProviderEnabled(provider));
+			Ok(())
+		}
+
+		/// Stops accepting the attestations of `provider`
+		///
+		/// The registered workers are not affected until they refresh their registrations.
+		#[pallet::weight(0)]
+		pub fn disable_attestation_provider(
+			origin: OriginFor<T>,
+			provider: AttestationProvider,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				AttestationProviders::<T>::contains_key(provider),
+				Error::<T>::AttestationProviderNotFound
+			);
+			AttestationProviders::<T>::remove(provider);
+			Self::deposit_event(Event::AttestationProviderDisabled(provider));
 			Ok(())
 		}
 
 		/// Registers a pRuntime image as the canonical runtime with its digest.
 		#[pallet::weight(0)]
-		pub fn add_pruntime(origin: OriginFor<T>, pruntime_hash: Vec<u8>) -> DispatchResult {
+		pub fn add_pruntime(
+			origin: OriginFor<T>,
+			provider: AttestationProvider,
+			pruntime_hash: Vec<u8>,
+		) -> DispatchResult {
 			T::GovernanceOrigin::ensure_origin(origin)?;
 
-			let mut allowlist = PRuntimeAllowList::<T>::get();
+			let mut allowlist = PRuntimeAllowList::<T>::get(provider);
 			ensure!(
 				!allowlist.contains(&pruntime_hash),
 				Error::<T>::PRuntimeAlreadyExists
 			);
 
 			allowlist.push(pruntime_hash);
-			PRuntimeAllowList::<T>::put(allowlist);
+			PRuntimeAllowList::<T>::insert(provider, allowlist);
 
 			Ok(())
 		}
 
 		#[pallet::weight(0)]
-		pub fn remove_pruntime(origin: OriginFor<T>, pruntime_hash: Vec<u8>) -> DispatchResult {
+		pub fn remove_pruntime(
+			origin: OriginFor<T>,
+			provider: AttestationProvider,
+			pruntime_hash: Vec<u8>,
+		) -> DispatchResult {
 			T::GovernanceOrigin::ensure_origin(origin)?;
 
-			let allowlist = PRuntimeAllowList::<T>::get();
+			let allowlist = PRuntimeAllowList::<T>::get(provider);
 			ensure!(
 				allowlist.contains(&pruntime_hash),
 				Error::<T>::PRuntimeNotFound
@@ -368,8 +838,48 @@ pub mod pallet {
 				.into_iter()
 				.filter(|h| *h != pruntime_hash)
 				.collect();
-			PRuntimeAllowList::<T>::put(filtered);
+			PRuntimeAllowList::<T>::insert(provider, filtered);
+
+			// Force out the workers running the revoked pRuntime
+			for (worker, ()) in PRuntimeWorkers::<T>::drain_prefix((provider, pruntime_hash)) {
+				if !InvalidatedWorkers::<T>::contains_key(&worker) {
+					Self::invalidate_worker(worker, AttestationInvalidation::PRuntimeRevoked);
+				}
+			}
+
+			Ok(())
+		}
+
+		/// Sets the max age of the worker attestations in seconds, or `None` to never expire them
+		///
+		/// The workers are expected to register again before their attestations expire.
+		#[pallet::weight(0)]
+		pub fn set_max_attestation_age(
+			origin: OriginFor<T>,
+			max_age: Option<u64>,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			match max_age {
+				Some(0) => return Err(Error::<T>::InvalidMaxAttestationAge.into()),
+				Some(max_age) => MaxAttestationAge::<T>::put(max_age),
+				None => MaxAttestationAge::<T>::kill(),
+			}
+			Self::deposit_event(Event::MaxAttestationAgeUpdated(max_age));
+			Ok(())
+		}
 
+		/// Sets the collateral to verify the DCAP quotes
+		///
+		/// The collateral is published by Intel. It's up to the governance to verify it before
+		/// putting it on chain.
+		#[pallet::weight(0)]
+		pub fn set_dcap_collateral(
+			origin: OriginFor<T>,
+			collateral: DcapCollateral,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			DcapQuoteCollateral::<T>::put(collateral);
+			Self::deposit_event(Event::DcapCollateralUpdated);
 			Ok(())
 		}
 
@@ -420,7 +930,11 @@ pub mod pallet {
 	where
 		T: crate::mq::Config,
 	{
-		pub fn check_message(message: &SignedMessage) -> DispatchResult {
+		/// Checks the signature of an offchain message
+		///
+		/// Returns false if the message is approved by a share holder of the threshold master key
+		/// but not by enough of them yet. It must not be dispatched until then.
+		pub fn check_message(message: &SignedMessage) -> Result<bool, DispatchError> {
 			let pubkey_copy: ContractPublicKey;
 			let pubkey = match &message.message.sender {
 				MessageOrigin::Worker(pubkey) => pubkey,
@@ -432,11 +946,214 @@ pub mod pallet {
 					// GatekeeperMasterPubkey should not be None
 					pubkey_copy = GatekeeperMasterPubkey::<T>::get()
 						.ok_or(Error::<T>::MasterKeyUninitialized)?;
-					&pubkey_copy
+					let result = Self::verify_signature(&pubkey_copy, message);
+					if result.is_err() {
+						// Nobody holds the whole threshold master key, so the gatekeepers sign
+						// with their own identity keys
+						if let Some(threshold) = MasterKeyThreshold::<T>::get() {
+							if let Some(approved) =
+								Self::approve_gatekeeper_message(message, threshold)
+							{
+								return Ok(approved);
+							}
+						}
+						// The message may be signed before the last rotation took effect
+						if let Some(retired) = Self::recently_retired_master_pubkey() {
+							return Self::verify_signature(&retired, message).map(|_| true);
+						}
+					}
+					return result.map(|_| true);
 				}
 				_ => return Err(Error::<T>::CannotHandleUnknownMessage.into()),
 			};
-			Self::verify_signature(pubkey, message)
+			Self::verify_signature(pubkey, message).map(|_| true)
+		}
+
+		/// Records the approval of a gatekeeper message by the share holder who signed it
+		///
+		/// Returns None if it's not signed by any share holder still being a gatekeeper, otherwise
+		/// whether the message is approved by `threshold` share holders.
+		fn approve_gatekeeper_message(message: &SignedMessage, threshold: u32) -> Option<bool> {
+			// The unregistered gatekeepers keep their shares but have no say any more
+			let gatekeepers = Gatekeeper::<T>::get();
+			let signer = MasterKeyShareHolders::<T>::get()
+				.into_iter()
+				.map(|holder| holder.pubkey)
+				.filter(|pubkey| gatekeepers.contains(pubkey))
+				.find(|pubkey| Self::verify_signature(pubkey, message).is_ok())?;
+			let message_hash = crate::hashing::blake2_256(&message.data_be_signed());
+			let sender = &message.message.sender;
+			// Only the expected sequence is accepted, so the approvals of any other sequence are
+			// stale
+			let mut approvals = match GatekeeperMessageApprovals::<T>::get(sender) {
+				Some((sequence, approvals)) if sequence == message.sequence => approvals,
+				_ => Vec::new(),
+			};
+			// A holder approves only one message at a sequence, which bounds the approvals
+			approvals.retain(|(holder, _)| *holder != signer);
+			approvals.push((signer, message_hash));
+			let num_approvals = approvals
+				.iter()
+				.filter(|(holder, hash)| gatekeepers.contains(holder) && *hash == message_hash)
+				.count();
+			if num_approvals as u32 >= threshold {
+				GatekeeperMessageApprovals::<T>::remove(sender);
+				Some(true)
+			} else {
+				GatekeeperMessageApprovals::<T>::insert(sender, (message.sequence, approvals));
+				Some(false)
+			}
+		}
+
+		/// The last retired master pubkey, if retired within the grace period
+		pub(crate) fn recently_retired_master_pubkey() -> Option<MasterPublicKey> {
+			let (pubkey, retired_at) = RetiredGatekeeperMasterPubkeys::<T>::get().pop()?;
+			let now = frame_system::Pallet::<T>::block_number();
+			if now <= retired_at + RETIRED_MASTER_PUBKEY_GRACE_BLOCKS.into() {
+				Some(pubkey)
+			} else {
+				None
+			}
+		}
+
+		/// Validates and saves a new master key rotation, returning its id and the gatekeepers
+		fn new_master_key_rotation(
+			lead: WorkerPublicKey,
+			activation_block: T::BlockNumber,
+			threshold: Option<u32>,
+		) -> Result<(u32, Vec<messaging::NewGatekeeperEvent>), DispatchError> {
+			ensure!(
+				GatekeeperMasterPubkey::<T>::get().is_some(),
+				Error::<T>::MasterKeyUninitialized
+			);
+			ensure!(
+				MasterKeyThreshold::<T>::get().is_none(),
+				Error::<T>::ThresholdMasterKeyEnabled
+			);
+			ensure!(
+				activation_block > frame_system::Pallet::<T>::block_number(),
+				Error::<T>::InvalidActivationBlock
+			);
+			let gatekeepers = Gatekeeper::<T>::get();
+			ensure!(gatekeepers.contains(&lead), Error::<T>::InvalidGatekeeper);
+			if let Some(threshold) = threshold {
+				ensure!(
+					threshold > 0 && threshold as usize <= gatekeepers.len(),
+					Error::<T>::InvalidThreshold
+				);
+			}
+			let gatekeepers = gatekeepers
+				.into_iter()
+				.map(|pubkey| {
+					let worker_info =
+						Workers::<T>::try_get(&pubkey).or(Err(Error::<T>::WorkerNotFound))?;
+					Ok(messaging::NewGatekeeperEvent {
+						pubkey,
+						ecdh_pubkey: worker_info.ecdh_pubkey,
+					})
+				})
+				.collect::<Result<Vec<_>, Error<T>>>()?;
+
+			let rotation_id = MasterKeyRotationId::<T>::get() + 1;
+			MasterKeyRotationId::<T>::put(rotation_id);
+			PendingGatekeeperMasterPubkey::<T>::put(MasterKeyRotation {
+				rotation_id,
+				lead,
+				master_pubkey: None,
+				activation_block,
+				threshold,
+				share_holders: Vec::new(),
+			});
+			Ok((rotation_id, gatekeepers))
+		}
+
+		/// Activates the pending master key rotation if it's ready
+		fn maybe_activate_master_key(now: T::BlockNumber) {
+			let rotation = match PendingGatekeeperMasterPubkey::<T>::get() {
+				Some(rotation) => rotation,
+				None => return,
+			};
+			let master_pubkey = match rotation.master_pubkey {
+				Some(pubkey) if now >= rotation.activation_block => pubkey,
+				_ => return,
+			};
+			if let Some(retired) = GatekeeperMasterPubkey::<T>::get() {
+				RetiredGatekeeperMasterPubkeys::<T>::append((retired, now));
+			}
+			GatekeeperMasterPubkey::<T>::put(master_pubkey);
+			if let Some(threshold) = rotation.threshold {
+				MasterKeyThreshold::<T>::put(threshold);
+				MasterKeyShareHolders::<T>::put(rotation.share_holders);
+			}
+			PendingGatekeeperMasterPubkey::<T>::kill();
+			Self::push_message(GatekeeperChange::master_key_rotated(
+				rotation.rotation_id,
+				master_pubkey,
+			));
+			Self::deposit_event(Event::MasterKeyRotated(rotation.rotation_id, master_pubkey));
+		}
+
+		fn schedule_attestation_expiry(worker: WorkerPublicKey, registered_at: u64) {
+			let bucket = registered_at / ATTESTATION_EXPIRY_BUCKET_SECS;
+			AttestationExpiryQueue::<T>::mutate(bucket, |workers| {
+				if !workers.contains(&worker) {
+					workers.push(worker);
+				}
+			});
+			NextAttestationExpiryBucket::<T>::mutate(|next| {
+				if next.map_or(true, |next| bucket < next) {
+					*next = Some(bucket);
+				}
+			});
+		}
+
+		/// Forces out the workers whose attestations expired
+		///
+		/// A bucket is checked once all the registrations in it expired, so the workers may be
+		/// forced out up to `ATTESTATION_EXPIRY_BUCKET_SECS` late.
+		fn check_attestation_expiry() {
+			let max_age = match MaxAttestationAge::<T>::get() {
+				Some(max_age) => max_age,
+				None => return,
+			};
+			let mut bucket = match NextAttestationExpiryBucket::<T>::get() {
+				Some(bucket) => bucket,
+				None => return,
+			};
+			let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
+			let mut checked = 0;
+			while checked < MAX_ATTESTATION_EXPIRY_BUCKETS_PER_BLOCK
+				&& (bucket + 1)
+					.saturating_mul(ATTESTATION_EXPIRY_BUCKET_SECS)
+					.saturating_add(max_age)
+					<= now
+			{
+				for worker in AttestationExpiryQueue::<T>::take(bucket) {
+					let registered_at = match WorkerRegistrationTime::<T>::get(&worker) {
+						Some(registered_at) => registered_at,
+						None => continue,
+					};
+					// Registered again since then
+					if registered_at / ATTESTATION_EXPIRY_BUCKET_SECS != bucket {
+						continue;
+					}
+					if !InvalidatedWorkers::<T>::contains_key(&worker) {
+						Self::invalidate_worker(worker, AttestationInvalidation::Expired);
+					}
+				}
+				bucket += 1;
+				checked += 1;
+			}
+			NextAttestationExpiryBucket::<T>::put(bucket);
+		}
+
+		fn invalidate_worker(worker: WorkerPublicKey, reason: AttestationInvalidation) {
+			InvalidatedWorkers::<T>::insert(worker, reason);
+			Self::push_message(SystemEvent::new_worker_event(
+				worker,
+				WorkerEvent::AttestationInvalidated,
+			));
+			Self::deposit_event(Event::WorkerAttestationInvalidated(worker, reason));
 		}
 
 		fn verify_signature(pubkey: &WorkerPublicKey, message: &SignedMessage) -> DispatchResult {
@@ -469,22 +1186,29 @@ pub mod pallet {
 						return Err(Error::<T>::InvalidBenchReport.into());
 					}
 
-					const MAX_SCORE: u32 = 6000;
 					let score = iterations / ((now - start_time) / 1000);
 					let score = score * 6; // iterations per 6s
-					let score = MAX_SCORE.min(score as u32);
-
-					Workers::<T>::mutate(worker_pubkey, |val| {
-						if let Some(val) = val {
-							val.initial_score = Some(score);
-							val.last_updated = now;
-						}
-					});
-
-					Self::push_message(SystemEvent::new_worker_event(
-						*worker_pubkey,
-						WorkerEvent::BenchScore(score),
-					));
+					Self::update_bench_score(worker_pubkey, score, now);
+				}
+				RegistryEvent::SuiteBenchReport {
+					start_time,
+					iterations,
+					suite_version,
+					component_iterations,
+				} => {
+					ensure!(
+						suite_version == BENCH_SUITE_VERSION,
+						Error::<T>::UnsupportedBenchSuite
+					);
+					let now = T::UnixTime::now().as_millis().saturated_into::<u64>();
+					ensure!(now > start_time, Error::<T>::InvalidBenchReport);
+					let score = Self::composite_bench_score(
+						now - start_time,
+						iterations,
+						&component_iterations,
+					)
+					.ok_or(Error::<T>::InvalidBenchReport)?;
+					Self::update_bench_score(worker_pubkey, score, now);
 				}
 				RegistryEvent::MasterPubkey { master_pubkey } => {
 					let gatekeepers = Gatekeeper::<T>::get();
@@ -507,19 +1231,132 @@ pub mod pallet {
 						}
 					}
 				}
+				RegistryEvent::RotatedMasterPubkey {
+					rotation_id,
+					master_pubkey,
+				} => {
+					let mut rotation = PendingGatekeeperMasterPubkey::<T>::get()
+						.filter(|rotation| rotation.rotation_id == rotation_id)
+						.ok_or(Error::<T>::NoMasterKeyRotation)?;
+					ensure!(
+						rotation.lead == *worker_pubkey,
+						Error::<T>::InvalidGatekeeper
+					);
+					ensure!(rotation.threshold.is_none(), Error::<T>::InvalidThreshold);
+					match rotation.master_pubkey {
+						Some(saved_pubkey) => {
+							ensure!(saved_pubkey == master_pubkey, Error::<T>::MasterKeyMismatch);
+						}
+						None => {
+							rotation.master_pubkey = Some(master_pubkey);
+							PendingGatekeeperMasterPubkey::<T>::put(rotation);
+							Self::deposit_event(Event::RotatedMasterPubkeyUploaded(
+								rotation_id,
+								master_pubkey,
+							));
+						}
+					}
+				}
+				RegistryEvent::RotatedThresholdMasterPubkey {
+					rotation_id,
+					master_pubkey,
+					share_holders,
+				} => {
+					let mut rotation = PendingGatekeeperMasterPubkey::<T>::get()
+						.filter(|rotation| rotation.rotation_id == rotation_id)
+						.ok_or(Error::<T>::NoMasterKeyRotation)?;
+					ensure!(
+						rotation.lead == *worker_pubkey,
+						Error::<T>::InvalidGatekeeper
+					);
+					ensure!(rotation.threshold.is_some(), Error::<T>::InvalidThreshold);
+					if let Some(saved_pubkey) = rotation.master_pubkey {
+						ensure!(saved_pubkey == master_pubkey, Error::<T>::MasterKeyMismatch);
+						return Ok(());
+					}
+					// one share for each gatekeeper, indexed from 1
+					let gatekeepers = Gatekeeper::<T>::get();
+					ensure!(
+						share_holders.len() == gatekeepers.len()
+							&& share_holders.iter().enumerate().all(|(i, holder)| {
+								holder.index as usize == i + 1
+									&& gatekeepers.contains(&holder.pubkey)
+									&& share_holders[..i]
+										.iter()
+										.all(|other| other.pubkey != holder.pubkey)
+							}),
+						Error::<T>::InvalidShareHolders
+					);
+					rotation.master_pubkey = Some(master_pubkey);
+					rotation.share_holders = share_holders;
+					PendingGatekeeperMasterPubkey::<T>::put(rotation);
+					Self::deposit_event(Event::RotatedMasterPubkeyUploaded(
+						rotation_id,
+						master_pubkey,
+					));
+				}
 			}
 			Ok(())
 		}
 
-		#[cfg(test)]
-		pub(crate) fn internal_set_benchmark(worker: &WorkerPublicKey, score: Option<u32>) {
-			Workers::<T>::mutate(worker, |w| {
-				if let Some(w) = w {
-					w.initial_score = score;
+		fn update_bench_score(worker_pubkey: &WorkerPublicKey, score: u64, now: u64) {
+			const MAX_SCORE: u32 = 6000;
+			let score = MAX_SCORE.min(score.saturated_into::<u32>());
+
+			Workers::<T>::mutate(worker_pubkey, |val| {
+				if let Some(val) = val {
+					val.initial_score = Some(score);
+					val.last_updated = now;
 				}
 			});
-		}
-	}
+
+			Self::push_message(SystemEvent::new_worker_event(
+				*worker_pubkey,
+				WorkerEvent::BenchScore(score),
+			));
+		}
+
+		/// Calculates the score of a composite benchmark report, in rounds per 6s
+		///
+		/// The rounds are counted by the units of work done by the components, so a component
+		/// with the default weight counts a round per unit. The rounds are in the same unit as the
+		/// heartbeat iterations.
+		fn composite_bench_score(
+			elapsed_ms: u64,
+			iterations: u64,
+			component_iterations: &[(BenchComponent, u64)],
+		) -> Option<u64> {
+			// Every component must be reported once
+			let reported = component_iterations.iter().map(|(component, _)| component);
+			if !reported.eq(BenchComponent::ALL.iter()) {
+				return None;
+			}
+			// A round is only done if every component has done its units
+			let rounds = component_iterations
+				.iter()
+				.map(|(component, units)| {
+					let weight = BenchmarkWeights::<T>::get(component)
+						.unwrap_or(DEFAULT_BENCH_COMPONENT_WEIGHT);
+					*units as u128 * 1_000 / weight as u128
+				})
+				.min()?
+				.min(iterations as u128);
+			if elapsed_ms == 0 {
+				return None;
+			}
+			let score = rounds * 6_000 / elapsed_ms as u128;
+			Some(score.saturated_into())
+		}
+
+		#[cfg(test)]
+		pub(crate) fn internal_set_benchmark(worker: &WorkerPublicKey, score: Option<u32>) {
+			Workers::<T>::mutate(worker, |w| {
+				if let Some(w) = w {
+					w.initial_score = score;
+				}
+			});
+		}
+	}
 
 	// Genesis config build
 
@@ -531,6 +1368,8 @@ pub mod pallet {
 		/// [identity]
 		pub gatekeepers: Vec<WorkerPublicKey>,
 		pub benchmark_duration: u32,
+		/// The attestation providers accepted with the default policy
+		pub attestation_providers: Vec<AttestationProvider>,
 	}
 
 	#[cfg(feature = "std")]
@@ -540,6 +1379,7 @@ pub mod pallet {
 				workers: Default::default(),
 				gatekeepers: Default::default(),
 				benchmark_duration: 8u32,
+				attestation_providers: vec![AttestationProvider::Ias],
 			}
 		}
 	}
@@ -560,7 +1400,7 @@ pub mod pallet {
 						runtime_version: 0,
 						last_updated: 0,
 						operator: operator.clone(),
-						confidence_level: 128u8,
+						confidence_level: UNATTESTED_CONFIDENCE_LEVEL,
 						initial_score: None,
 						features: vec![1, 4],
 					},
@@ -568,7 +1408,7 @@ pub mod pallet {
 				Pallet::<T>::queue_message(SystemEvent::new_worker_event(
 					*pubkey,
 					WorkerEvent::Registered(messaging::WorkerInfo {
-						confidence_level: 128u8,
+						confidence_level: UNATTESTED_CONFIDENCE_LEVEL,
 					}),
 				));
 				Pallet::<T>::queue_message(SystemEvent::new_worker_event(
@@ -598,11 +1438,22 @@ pub mod pallet {
 					}
 				}
 			}
+			for provider in &self.attestation_providers {
+				AttestationProviders::<T>::insert(provider, AttestationProviderConfig::default());
+			}
 		}
 	}
 
 	#[pallet::hooks]
-	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T>
+	where
+		T: crate::mq::Config,
+	{
+		fn on_finalize(now: T::BlockNumber) {
+			Self::maybe_activate_master_key(now);
+			Self::check_attestation_expiry();
+		}
+
 		fn on_runtime_upgrade() -> Weight {
 			let mut w = 0;
 			let old = Self::on_chain_storage_version();
@@ -610,6 +1461,14 @@ pub mod pallet {
 
 			if old == 0 {
 				w += migrations::initialize::<T>();
+			}
+			if old < 2 {
+				w += migrations::migrate_attestation_providers::<T>();
+			}
+			if old < 3 {
+				w += migrations::schedule_attestation_expiry::<T>();
+			}
+			if old < STORAGE_VERSION {
 				STORAGE_VERSION.put::<super::Pallet<T>>();
 				w += T::DbWeight::get().writes(1);
 			}
@@ -618,14 +1477,62 @@ pub mod pallet {
 	}
 
 	mod migrations {
-		use super::{BenchmarkDuration, Config};
-		use frame_support::pallet_prelude::*;
+		use super::{
+			AttestationProvider, AttestationProviderConfig, AttestationProviders,
+			BenchmarkDuration, Config, PRuntimeAllowList, Pallet, WorkerRegistrationTime, Workers,
+		};
+		use frame_support::{
+			pallet_prelude::*, storage::migration::take_storage_value, traits::PalletInfoAccess,
+		};
+		use sp_std::vec::Vec;
 
 		pub fn initialize<T: Config>() -> Weight {
 			log::info!("phala_pallet::registry: initialize()");
 			BenchmarkDuration::<T>::put(50);
 			T::DbWeight::get().writes(1)
 		}
+
+		/// Moves the pRuntime allowlist under IAS, the only provider accepted so far
+		pub fn migrate_attestation_providers<T: Config>() -> Weight {
+			log::info!("phala_pallet::registry: migrate_attestation_providers()");
+			let pallet_name = <Pallet<T> as PalletInfoAccess>::name();
+			let allowlist: Vec<Vec<u8>> =
+				take_storage_value(pallet_name.as_bytes(), b"PRuntimeAllowList", &[])
+					.unwrap_or_default();
+			PRuntimeAllowList::<T>::insert(AttestationProvider::Ias, allowlist);
+			AttestationProviders::<T>::insert(
+				AttestationProvider::Ias,
+				AttestationProviderConfig::default(),
+			);
+			T::DbWeight::get().reads_writes(1, 3)
+		}
+
+		/// Schedules the expiry check of the workers registered before
+		///
+		/// Their attested pRuntime is unknown, so they are not affected by the pRuntime revocation
+		/// until they register again. Neither is their registration time, so the last update is
+		/// taken instead, which is never earlier.
+		pub fn schedule_attestation_expiry<T: Config>() -> Weight
+		where
+			T: crate::mq::Config,
+		{
+			// Year 2286 in seconds, or still early 1970 in milliseconds
+			const MAX_UNIX_SECS: u64 = 10_000_000_000;
+			log::info!("phala_pallet::registry: schedule_attestation_expiry()");
+			let mut count = 0u64;
+			for (worker, info) in Workers::<T>::iter() {
+				// Benchmark reports used to update it in milliseconds
+				let registered_at = if info.last_updated > MAX_UNIX_SECS {
+					info.last_updated / 1000
+				} else {
+					info.last_updated
+				};
+				WorkerRegistrationTime::<T>::insert(worker, registered_at);
+				Pallet::<T>::schedule_attestation_expiry(worker, registered_at);
+				count += 1;
+			}
+			T::DbWeight::get().reads_writes(count * 3, count * 3)
+		}
 	}
 
 	impl<T: Config + crate::mq::Config> MessageOriginInfo for Pallet<T> {
@@ -648,6 +1555,30 @@ pub mod pallet {
 		features: Vec<u32>,
 	}
 
+	impl<AccountId> WorkerInfo<AccountId> {
+		/// The version of the pRuntime the worker registered with
+		pub fn runtime_version(&self) -> u32 {
+			self.runtime_version
+		}
+	}
+
+	/// The attested pRuntime of a worker
+	#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+	pub struct WorkerAttestation {
+		pub provider: AttestationProvider,
+		/// The pRuntime hash as in `PRuntimeAllowList`
+		pub pruntime_hash: Vec<u8>,
+	}
+
+	/// Why a worker is forced out
+	#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+	pub enum AttestationInvalidation {
+		/// The attestation is older than `MaxAttestationAge`
+		Expired,
+		/// The attested pRuntime is removed from `PRuntimeAllowList`
+		PRuntimeRevoked,
+	}
+
 	impl<T: Config> From<AttestationError> for Error<T> {
 		fn from(err: AttestationError) -> Self {
 			match err {
@@ -659,6 +1590,13 @@ pub mod pallet {
 				AttestationError::OutdatedIASReport => Self::OutdatedIASReport,
 				AttestationError::UnknownQuoteBodyFormat => Self::UnknownQuoteBodyFormat,
 				AttestationError::InvalidUserDataHash => Self::InvalidRuntimeInfoHash,
+				AttestationError::MissingDcapCollateral => Self::DcapCollateralMissing,
+				AttestationError::InvalidDcapQuote => Self::InvalidDcapQuote,
+				AttestationError::InvalidPckCertChain => Self::InvalidPckCertChain,
+				AttestationError::InvalidQuoteSignature => Self::InvalidQuoteSignature,
+				AttestationError::QeIdentityMismatch => Self::QeIdentityMismatch,
+				AttestationError::UnknownTcbLevel => Self::UnknownTcbLevel,
+				AttestationError::OutdatedDcapCollateral => Self::OutdatedDcapCollateral,
 			}
 		}
 	}
@@ -670,7 +1608,8 @@ pub mod pallet {
 		use super::*;
 		use crate::mock::{
 			ecdh_pubkey, elapse_seconds, new_test_ext, set_block_1,
-			setup_relaychain_genesis_allowlist, worker_pubkey, Origin, Test,
+			setup_relaychain_genesis_allowlist, setup_workers, take_messages, teleport_to_block,
+			worker_pubkey, Origin, Test,
 		};
 		// Pallets
 		use crate::mock::PhalaRegistry;
@@ -728,7 +1667,7 @@ pub mod pallet {
 				assert_ok!(PhalaRegistry::register_worker(
 					Origin::signed(1),
 					WorkerRegistrationInfo::<u64> {
-						version: 1,
+						version: 2,
 						machine_id: Default::default(),
 						pubkey: worker_pubkey(1),
 						ecdh_pubkey: ecdh_pubkey(1),
@@ -745,6 +1684,419 @@ pub mod pallet {
 				let worker = Workers::<Test>::get(worker_pubkey(1)).unwrap();
 				assert_eq!(worker.last_updated, 100);
 				assert_eq!(worker.operator, Some(2));
+				// Upgraded pRuntime
+				assert_eq!(worker.runtime_version(), 2);
+			});
+		}
+
+		#[test]
+		fn test_master_key_rotation() {
+			use crate::mock::System;
+			use phala_types::messaging::{BindTopic, MasterKeyRotatedEvent, Topic};
+
+			fn registry_message(
+				sender: WorkerPublicKey,
+				payload: RegistryEvent,
+			) -> DecodedMessage<RegistryEvent> {
+				DecodedMessage {
+					sender: MessageOrigin::Worker(sender),
+					destination: Topic::new(*b"^phala/registry/event"),
+					payload,
+				}
+			}
+
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				let genesis_gk = WorkerPublicKey::from_raw([0u8; 32]);
+				let old_pubkey = MasterPublicKey::from_raw([1u8; 32]);
+				let new_pubkey = MasterPublicKey::from_raw([2u8; 32]);
+
+				assert_noop!(
+					PhalaRegistry::rotate_master_key(Origin::root(), genesis_gk, 10),
+					Error::<Test>::MasterKeyUninitialized
+				);
+				assert_ok!(PhalaRegistry::on_message_received(registry_message(
+					genesis_gk,
+					RegistryEvent::MasterPubkey {
+						master_pubkey: old_pubkey
+					}
+				)));
+				assert_noop!(
+					PhalaRegistry::rotate_master_key(Origin::root(), worker_pubkey(1), 10),
+					Error::<Test>::InvalidGatekeeper
+				);
+				assert_ok!(PhalaRegistry::rotate_master_key(
+					Origin::root(),
+					genesis_gk,
+					10
+				));
+				assert_noop!(
+					PhalaRegistry::register_gatekeeper(Origin::root(), worker_pubkey(1)),
+					Error::<Test>::MasterKeyRotationInProgress
+				);
+				// Only the lead can upload the new pubkey
+				assert_noop!(
+					PhalaRegistry::on_message_received(registry_message(
+						worker_pubkey(1),
+						RegistryEvent::RotatedMasterPubkey {
+							rotation_id: 1,
+							master_pubkey: new_pubkey,
+						}
+					)),
+					Error::<Test>::InvalidGatekeeper
+				);
+				assert_ok!(PhalaRegistry::on_message_received(registry_message(
+					genesis_gk,
+					RegistryEvent::RotatedMasterPubkey {
+						rotation_id: 1,
+						master_pubkey: new_pubkey,
+					}
+				)));
+
+				// Not activated before the activation block
+				teleport_to_block(9);
+				assert_eq!(GatekeeperMasterPubkey::<Test>::get(), Some(old_pubkey));
+				take_messages();
+				teleport_to_block(10);
+				PhalaRegistry::on_finalize(10);
+				assert_eq!(GatekeeperMasterPubkey::<Test>::get(), Some(new_pubkey));
+				assert_eq!(PendingGatekeeperMasterPubkey::<Test>::get(), None);
+				assert_eq!(
+					RetiredGatekeeperMasterPubkeys::<Test>::get(),
+					vec![(old_pubkey, 10)]
+				);
+				let rotated: Vec<_> = take_messages()
+					.iter()
+					.filter(|m| m.destination.path() == &GatekeeperChange::topic())
+					.filter_map(|m| m.decode_payload::<GatekeeperChange>())
+					.collect();
+				assert_eq!(
+					rotated,
+					vec![GatekeeperChange::MasterKeyRotated(MasterKeyRotatedEvent {
+						rotation_id: 1,
+						master_pubkey: new_pubkey,
+					})]
+				);
+				// The retired pubkey is accepted for a while
+				assert_eq!(
+					PhalaRegistry::recently_retired_master_pubkey(),
+					Some(old_pubkey)
+				);
+				System::set_block_number(10 + RETIRED_MASTER_PUBKEY_GRACE_BLOCKS as u64 + 1);
+				assert_eq!(PhalaRegistry::recently_retired_master_pubkey(), None);
+			});
+		}
+
+		#[test]
+		fn test_threshold_master_key_rotation() {
+			use phala_types::messaging::Topic;
+
+			fn registry_message(
+				sender: WorkerPublicKey,
+				payload: RegistryEvent,
+			) -> DecodedMessage<RegistryEvent> {
+				DecodedMessage {
+					sender: MessageOrigin::Worker(sender),
+					destination: Topic::new(*b"^phala/registry/event"),
+					payload,
+				}
+			}
+
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(2);
+				let genesis_gk = WorkerPublicKey::from_raw([0u8; 32]);
+				let new_pubkey = MasterPublicKey::from_raw([2u8; 32]);
+				assert_ok!(PhalaRegistry::on_message_received(registry_message(
+					genesis_gk,
+					RegistryEvent::MasterPubkey {
+						master_pubkey: MasterPublicKey::from_raw([1u8; 32])
+					}
+				)));
+				assert_ok!(PhalaRegistry::register_gatekeeper(
+					Origin::root(),
+					worker_pubkey(1)
+				));
+
+				assert_noop!(
+					PhalaRegistry::rotate_threshold_master_key(Origin::root(), genesis_gk, 3, 10),
+					Error::<Test>::InvalidThreshold
+				);
+				assert_ok!(PhalaRegistry::rotate_threshold_master_key(
+					Origin::root(),
+					genesis_gk,
+					2,
+					10
+				));
+				// The plain pubkey upload doesn't fit a threshold rotation
+				assert_noop!(
+					PhalaRegistry::on_message_received(registry_message(
+						genesis_gk,
+						RegistryEvent::RotatedMasterPubkey {
+							rotation_id: 1,
+							master_pubkey: new_pubkey,
+						}
+					)),
+					Error::<Test>::InvalidThreshold
+				);
+				let holder = |pubkey, index| MasterKeyShareHolder {
+					pubkey,
+					index,
+					share_pubkey: [index as u8; 32],
+				};
+				// A share for each gatekeeper is required
+				assert_noop!(
+					PhalaRegistry::on_message_received(registry_message(
+						genesis_gk,
+						RegistryEvent::RotatedThresholdMasterPubkey {
+							rotation_id: 1,
+							master_pubkey: new_pubkey,
+							share_holders: vec![holder(genesis_gk, 1), holder(genesis_gk, 2)],
+						}
+					)),
+					Error::<Test>::InvalidShareHolders
+				);
+				let share_holders = vec![holder(genesis_gk, 1), holder(worker_pubkey(1), 2)];
+				assert_ok!(PhalaRegistry::on_message_received(registry_message(
+					genesis_gk,
+					RegistryEvent::RotatedThresholdMasterPubkey {
+						rotation_id: 1,
+						master_pubkey: new_pubkey,
+						share_holders: share_holders.clone(),
+					}
+				)));
+
+				teleport_to_block(10);
+				PhalaRegistry::on_finalize(10);
+				assert_eq!(GatekeeperMasterPubkey::<Test>::get(), Some(new_pubkey));
+				assert_eq!(MasterKeyThreshold::<Test>::get(), Some(2));
+				assert_eq!(MasterKeyShareHolders::<Test>::get(), share_holders);
+				// Frozen once the master key is split
+				assert_noop!(
+					PhalaRegistry::register_gatekeeper(Origin::root(), worker_pubkey(2)),
+					Error::<Test>::ThresholdMasterKeyEnabled
+				);
+				assert_noop!(
+					PhalaRegistry::rotate_master_key(Origin::root(), genesis_gk, 20),
+					Error::<Test>::ThresholdMasterKeyEnabled
+				);
+			});
+		}
+
+		#[test]
+		fn test_threshold_gatekeeper_message() {
+			use crate::mq::OffchainIngress;
+			use phala_types::messaging::Message;
+			use sp_core::Pair;
+
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let holders: Vec<_> = (1u8..=3)
+					.map(|i| sp_core::sr25519::Pair::from_seed(&[i; 32]))
+					.collect();
+				let outsider = sp_core::sr25519::Pair::from_seed(&[4u8; 32]);
+				GatekeeperMasterPubkey::<Test>::put(MasterPublicKey::from_raw([1u8; 32]));
+				Gatekeeper::<Test>::put(holders.iter().map(|p| p.public()).collect::<Vec<_>>());
+				MasterKeyThreshold::<Test>::put(2);
+				MasterKeyShareHolders::<Test>::put(
+					holders
+						.iter()
+						.zip(1u32..)
+						.map(|(pair, index)| MasterKeyShareHolder {
+							pubkey: pair.public(),
+							index,
+							share_pubkey: [index as u8; 32],
+						})
+						.collect::<Vec<_>>(),
+				);
+				take_messages();
+
+				let sign = |pair: &sp_core::sr25519::Pair, payload: Vec<u8>| {
+					let mut message = SignedMessage {
+						message: Message::new(MessageOrigin::Gatekeeper, *b"phala/test", payload),
+						sequence: 0,
+						signature: vec![],
+					};
+					message.signature = pair.sign(&message.data_be_signed()).0.to_vec();
+					message
+				};
+				let sync = |message| {
+					crate::mq::Pallet::<Test>::sync_offchain_message(Origin::signed(1), message)
+				};
+
+				assert_noop!(
+					sync(sign(&outsider, vec![1])),
+					Error::<Test>::InvalidSignature
+				);
+				// A single share holder can't speak for the gatekeepers
+				assert_ok!(sync(sign(&holders[0], vec![1])));
+				assert_ok!(sync(sign(&holders[0], vec![1])));
+				assert_eq!(
+					OffchainIngress::<Test>::get(MessageOrigin::Gatekeeper),
+					None
+				);
+				assert!(take_messages().is_empty());
+				// Approvals of different messages don't add up
+				assert_ok!(sync(sign(&holders[1], vec![2])));
+				assert_eq!(
+					OffchainIngress::<Test>::get(MessageOrigin::Gatekeeper),
+					None
+				);
+				assert!(take_messages().is_empty());
+				// Dispatched once approved by the threshold
+				assert_ok!(sync(sign(&holders[2], vec![1])));
+				assert_eq!(
+					OffchainIngress::<Test>::get(MessageOrigin::Gatekeeper),
+					Some(1)
+				);
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert_eq!(messages[0].payload, vec![1]);
+				assert!(
+					GatekeeperMessageApprovals::<Test>::get(MessageOrigin::Gatekeeper).is_none()
+				);
+				// The approvals are bound to the sequence
+				assert_noop!(
+					sync(sign(&holders[1], vec![2])),
+					crate::mq::Error::<Test>::BadSequence
+				);
+			});
+		}
+
+		#[test]
+		fn test_removed_share_holder_message_rejected() {
+			use crate::mq::OffchainIngress;
+			use phala_types::messaging::Message;
+			use sp_core::Pair;
+
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let holders: Vec<_> = (1u8..=3)
+					.map(|i| sp_core::sr25519::Pair::from_seed(&[i; 32]))
+					.collect();
+				GatekeeperMasterPubkey::<Test>::put(MasterPublicKey::from_raw([1u8; 32]));
+				Gatekeeper::<Test>::put(holders.iter().map(|p| p.public()).collect::<Vec<_>>());
+				MasterKeyThreshold::<Test>::put(2);
+				MasterKeyShareHolders::<Test>::put(
+					holders
+						.iter()
+						.zip(1u32..)
+						.map(|(pair, index)| MasterKeyShareHolder {
+							pubkey: pair.public(),
+							index,
+							share_pubkey: [index as u8; 32],
+						})
+						.collect::<Vec<_>>(),
+				);
+				take_messages();
+
+				let sign = |pair: &sp_core::sr25519::Pair| {
+					let mut message = SignedMessage {
+						message: Message::new(MessageOrigin::Gatekeeper, *b"phala/test", vec![1]),
+						sequence: 0,
+						signature: vec![],
+					};
+					message.signature = pair.sign(&message.data_be_signed()).0.to_vec();
+					message
+				};
+				let sync = |message| {
+					crate::mq::Pallet::<Test>::sync_offchain_message(Origin::signed(1), message)
+				};
+
+				// Approved by a holder before it's removed
+				assert_ok!(sync(sign(&holders[0])));
+				Gatekeeper::<Test>::put(vec![holders[1].public(), holders[2].public()]);
+				// The removed holder can't sign any more
+				assert_noop!(sync(sign(&holders[0])), Error::<Test>::InvalidSignature);
+				// And its approval doesn't count
+				assert_ok!(sync(sign(&holders[1])));
+				assert_eq!(
+					OffchainIngress::<Test>::get(MessageOrigin::Gatekeeper),
+					None
+				);
+				assert!(take_messages().is_empty());
+				assert_ok!(sync(sign(&holders[2])));
+				assert_eq!(
+					OffchainIngress::<Test>::get(MessageOrigin::Gatekeeper),
+					Some(1)
+				);
+				assert_eq!(take_messages().len(), 1);
+			});
+		}
+
+		#[test]
+		fn test_interleaved_gatekeeper_messages() {
+			use crate::mq::OffchainIngress;
+			use phala_types::messaging::Message;
+			use sp_core::Pair;
+
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let holders: Vec<_> = (1u8..=3)
+					.map(|i| sp_core::sr25519::Pair::from_seed(&[i; 32]))
+					.collect();
+				GatekeeperMasterPubkey::<Test>::put(MasterPublicKey::from_raw([1u8; 32]));
+				Gatekeeper::<Test>::put(holders.iter().map(|p| p.public()).collect::<Vec<_>>());
+				MasterKeyThreshold::<Test>::put(2);
+				MasterKeyShareHolders::<Test>::put(
+					holders
+						.iter()
+						.zip(1u32..)
+						.map(|(pair, index)| MasterKeyShareHolder {
+							pubkey: pair.public(),
+							index,
+							share_pubkey: [index as u8; 32],
+						})
+						.collect::<Vec<_>>(),
+				);
+				take_messages();
+
+				let sign = |pair: &sp_core::sr25519::Pair, sequence: u64| {
+					let payload = vec![sequence as u8];
+					let mut message = SignedMessage {
+						message: Message::new(MessageOrigin::Gatekeeper, *b"phala/test", payload),
+						sequence,
+						signature: vec![],
+					};
+					message.signature = pair.sign(&message.data_be_signed()).0.to_vec();
+					message
+				};
+				let sync = |message| {
+					crate::mq::Pallet::<Test>::sync_offchain_message(Origin::signed(1), message)
+				};
+				let pending_sequence = || {
+					GatekeeperMessageApprovals::<Test>::get(MessageOrigin::Gatekeeper)
+						.map(|(sequence, approvals)| (sequence, approvals.len()))
+				};
+
+				// The holders run ahead of each other
+				assert_ok!(sync(sign(&holders[0], 0)));
+				assert_noop!(
+					sync(sign(&holders[0], 1)),
+					crate::mq::Error::<Test>::BadSequence
+				);
+				assert_ok!(sync(sign(&holders[1], 0)));
+				assert_eq!(
+					OffchainIngress::<Test>::get(MessageOrigin::Gatekeeper),
+					Some(1)
+				);
+				assert_eq!(pending_sequence(), None);
+				// The late approval of the dispatched message doesn't count for the next one
+				assert_noop!(
+					sync(sign(&holders[2], 0)),
+					crate::mq::Error::<Test>::BadSequence
+				);
+				assert_ok!(sync(sign(&holders[0], 1)));
+				assert_eq!(pending_sequence(), Some((1, 1)));
+				assert_ok!(sync(sign(&holders[2], 1)));
+				assert_eq!(
+					OffchainIngress::<Test>::get(MessageOrigin::Gatekeeper),
+					Some(2)
+				);
+				assert_eq!(pending_sequence(), None);
+				let payloads: Vec<_> = take_messages().into_iter().map(|m| m.payload).collect();
+				assert_eq!(payloads, vec![vec![0], vec![1]]);
 			});
 		}
 
@@ -754,22 +2106,381 @@ pub mod pallet {
 				// Set block number to 1 to test the events
 				set_block_1();
 
+				let ias = AttestationProvider::Ias;
 				let sample: Vec<u8> = [1, 2, 3, 4].to_vec();
-				assert_ok!(PhalaRegistry::add_pruntime(Origin::root(), sample.clone()));
+				assert_ok!(PhalaRegistry::add_pruntime(
+					Origin::root(),
+					ias,
+					sample.clone()
+				));
 				assert_noop!(
-					PhalaRegistry::add_pruntime(Origin::root(), sample.clone()),
+					PhalaRegistry::add_pruntime(Origin::root(), ias, sample.clone()),
 					Error::<Test>::PRuntimeAlreadyExists
 				);
-				assert_eq!(PRuntimeAllowList::<Test>::get().len(), 1);
+				assert_eq!(PRuntimeAllowList::<Test>::get(ias).len(), 1);
+				// The allowlists are separated by providers
+				assert_eq!(
+					PRuntimeAllowList::<Test>::get(AttestationProvider::Dcap).len(),
+					0
+				);
+				assert_noop!(
+					PhalaRegistry::remove_pruntime(
+						Origin::root(),
+						AttestationProvider::Dcap,
+						sample.clone()
+					),
+					Error::<Test>::PRuntimeNotFound
+				);
 				assert_ok!(PhalaRegistry::remove_pruntime(
 					Origin::root(),
+					ias,
 					sample.clone()
 				));
 				assert_noop!(
-					PhalaRegistry::remove_pruntime(Origin::root(), sample.clone()),
+					PhalaRegistry::remove_pruntime(Origin::root(), ias, sample.clone()),
 					Error::<Test>::PRuntimeNotFound
 				);
-				assert_eq!(PRuntimeAllowList::<Test>::get().len(), 0);
+				assert_eq!(PRuntimeAllowList::<Test>::get(ias).len(), 0);
+			});
+		}
+
+		#[test]
+		fn test_attestation_providers() {
+			use crate::mock::{take_events, Event as TestEvent};
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_relaychain_genesis_allowlist();
+				let registration = |i: u8| WorkerRegistrationInfo::<u64> {
+					version: 1,
+					machine_id: Default::default(),
+					pubkey: worker_pubkey(i),
+					ecdh_pubkey: ecdh_pubkey(i),
+					genesis_block_hash: H256::repeat_byte(1),
+					features: vec![4, 1],
+					operator: Some(1),
+				};
+				let ias_attestation = || Attestation::SgxIas {
+					ra_report: Vec::new(),
+					signature: Vec::new(),
+					raw_signing_cert: Vec::new(),
+				};
+
+				// Only the governance can manage the providers
+				assert_noop!(
+					PhalaRegistry::disable_attestation_provider(
+						Origin::signed(1),
+						AttestationProvider::Ias
+					),
+					sp_runtime::DispatchError::BadOrigin
+				);
+				assert_ok!(PhalaRegistry::disable_attestation_provider(
+					Origin::root(),
+					AttestationProvider::Ias
+				));
+				assert_noop!(
+					PhalaRegistry::disable_attestation_provider(
+						Origin::root(),
+						AttestationProvider::Ias
+					),
+					Error::<Test>::AttestationProviderNotFound
+				);
+				assert_noop!(
+					PhalaRegistry::register_worker(
+						Origin::signed(1),
+						registration(1),
+						ias_attestation()
+					),
+					Error::<Test>::UnsupportedAttestationProvider
+				);
+
+				// Reject the unknown confidence level judged by the mock validator
+				assert_ok!(PhalaRegistry::enable_attestation_provider(
+					Origin::root(),
+					AttestationProvider::Ias,
+					AttestationProviderConfig {
+						confidence_levels: vec![(UNATTESTED_CONFIDENCE_LEVEL, 0)],
+					}
+				));
+				assert_noop!(
+					PhalaRegistry::register_worker(
+						Origin::signed(1),
+						registration(1),
+						ias_attestation()
+					),
+					Error::<Test>::ConfidenceLevelRejected
+				);
+
+				// Map the unattested workers to a lower confidence level
+				assert_ok!(PhalaRegistry::enable_attestation_provider(
+					Origin::root(),
+					AttestationProvider::None,
+					AttestationProviderConfig {
+						confidence_levels: vec![(UNATTESTED_CONFIDENCE_LEVEL, 5)],
+					}
+				));
+				take_events();
+				assert_ok!(PhalaRegistry::register_worker(
+					Origin::signed(1),
+					registration(2),
+					Attestation::None
+				));
+				let worker = Workers::<Test>::get(worker_pubkey(2)).unwrap();
+				assert_eq!(worker.confidence_level, 5);
+				assert_eq!(
+					take_events().as_slice(),
+					[TestEvent::PhalaRegistry(Event::WorkerRegistered(
+						worker_pubkey(2),
+						AttestationProvider::None
+					))]
+				);
+			});
+		}
+
+		#[test]
+		fn test_migrate_attestation_providers() {
+			use frame_support::storage::migration::put_storage_value;
+			new_test_ext().execute_with(|| {
+				AttestationProviders::<Test>::remove(AttestationProvider::Ias);
+				let allowlist: Vec<Vec<u8>> = vec![vec![1, 2, 3, 4]];
+				put_storage_value(
+					b"PhalaRegistry",
+					b"PRuntimeAllowList",
+					&[],
+					allowlist.clone(),
+				);
+
+				migrations::migrate_attestation_providers::<Test>();
+				assert_eq!(
+					PRuntimeAllowList::<Test>::get(AttestationProvider::Ias),
+					allowlist
+				);
+				assert_eq!(
+					AttestationProviders::<Test>::get(AttestationProvider::Ias),
+					Some(Default::default())
+				);
+			});
+		}
+
+		#[test]
+		fn test_attestation_expiry() {
+			use crate::mock::{take_events, Event as TestEvent};
+			use phala_types::messaging::{BindTopic, WorkerEventWithKey};
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_relaychain_genesis_allowlist();
+				let register = |i: u8| {
+					assert_ok!(PhalaRegistry::register_worker(
+						Origin::signed(1),
+						WorkerRegistrationInfo::<u64> {
+							version: 1,
+							machine_id: Default::default(),
+							pubkey: worker_pubkey(i),
+							ecdh_pubkey: ecdh_pubkey(i),
+							genesis_block_hash: H256::repeat_byte(1),
+							features: vec![4, 1],
+							operator: Some(1),
+						},
+						Attestation::None
+					));
+				};
+				let invalidated_workers = || -> Vec<_> {
+					take_messages()
+						.iter()
+						.filter(|m| m.destination.path() == &SystemEvent::topic())
+						.filter_map(|m| m.decode_payload::<SystemEvent>())
+						.filter_map(|event| match event {
+							SystemEvent::WorkerEvent(WorkerEventWithKey {
+								pubkey,
+								event: WorkerEvent::AttestationInvalidated,
+							}) => Some(pubkey),
+							_ => None,
+						})
+						.collect()
+				};
+
+				assert_noop!(
+					PhalaRegistry::set_max_attestation_age(Origin::root(), Some(0)),
+					Error::<Test>::InvalidMaxAttestationAge
+				);
+				assert_ok!(PhalaRegistry::set_max_attestation_age(
+					Origin::root(),
+					Some(3600)
+				));
+				register(1);
+				register(2);
+				take_messages();
+				// Worker 2 refreshes its registration in the next bucket
+				elapse_seconds(ATTESTATION_EXPIRY_BUCKET_SECS);
+				register(2);
+				take_messages();
+
+				// The whole bucket is not expired yet
+				elapse_seconds(3600 - 1);
+				teleport_to_block(2);
+				assert!(invalidated_workers().is_empty());
+
+				elapse_seconds(1);
+				take_events();
+				teleport_to_block(3);
+				assert_eq!(invalidated_workers(), vec![worker_pubkey(1)]);
+				assert_eq!(
+					InvalidatedWorkers::<Test>::get(worker_pubkey(1)),
+					Some(AttestationInvalidation::Expired)
+				);
+				assert_eq!(InvalidatedWorkers::<Test>::get(worker_pubkey(2)), None);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaRegistry(
+						Event::WorkerAttestationInvalidated(
+							worker_pubkey(1),
+							AttestationInvalidation::Expired
+						)
+					)]
+				);
+
+				// Worker 2 expires in the next bucket
+				elapse_seconds(ATTESTATION_EXPIRY_BUCKET_SECS);
+				teleport_to_block(4);
+				assert_eq!(invalidated_workers(), vec![worker_pubkey(2)]);
+
+				// Registering again takes the worker back
+				register(1);
+				assert_eq!(InvalidatedWorkers::<Test>::get(worker_pubkey(1)), None);
+			});
+		}
+
+		#[test]
+		fn test_attestation_expiry_after_bench_report() {
+			use phala_types::messaging::Topic;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_relaychain_genesis_allowlist();
+				assert_ok!(PhalaRegistry::set_max_attestation_age(
+					Origin::root(),
+					Some(3600)
+				));
+				assert_ok!(PhalaRegistry::register_worker(
+					Origin::signed(1),
+					WorkerRegistrationInfo::<u64> {
+						version: 1,
+						machine_id: Default::default(),
+						pubkey: worker_pubkey(1),
+						ecdh_pubkey: ecdh_pubkey(1),
+						genesis_block_hash: H256::repeat_byte(1),
+						features: vec![4, 1],
+						operator: Some(1),
+					},
+					Attestation::None
+				));
+				// The benchmark reports in the next bucket
+				elapse_seconds(ATTESTATION_EXPIRY_BUCKET_SECS);
+				assert_ok!(PhalaRegistry::on_message_received(DecodedMessage {
+					sender: MessageOrigin::Worker(worker_pubkey(1)),
+					destination: Topic::new(*b"^phala/registry/event"),
+					payload: RegistryEvent::BenchReport {
+						start_time: 0,
+						iterations: 1000,
+					},
+				}));
+				assert!(Workers::<Test>::get(worker_pubkey(1))
+					.unwrap()
+					.initial_score
+					.is_some());
+
+				// Still expires by the registration time
+				elapse_seconds(3600);
+				teleport_to_block(2);
+				assert_eq!(
+					InvalidatedWorkers::<Test>::get(worker_pubkey(1)),
+					Some(AttestationInvalidation::Expired)
+				);
+			});
+		}
+
+		#[test]
+		fn test_pruntime_revocation() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_relaychain_genesis_allowlist();
+				// The pRuntime hash judged by the mock validator
+				let pruntime_hash = vec![0u8; 68];
+				assert_ok!(PhalaRegistry::add_pruntime(
+					Origin::root(),
+					AttestationProvider::Ias,
+					pruntime_hash.clone()
+				));
+				assert_ok!(PhalaRegistry::add_pruntime(
+					Origin::root(),
+					AttestationProvider::Dcap,
+					pruntime_hash.clone()
+				));
+				assert_ok!(PhalaRegistry::register_worker(
+					Origin::signed(1),
+					WorkerRegistrationInfo::<u64> {
+						version: 1,
+						machine_id: Default::default(),
+						pubkey: worker_pubkey(1),
+						ecdh_pubkey: ecdh_pubkey(1),
+						genesis_block_hash: H256::repeat_byte(1),
+						features: vec![4, 1],
+						operator: Some(1),
+					},
+					Attestation::SgxIas {
+						ra_report: Vec::new(),
+						signature: Vec::new(),
+						raw_signing_cert: Vec::new(),
+					}
+				));
+				assert_eq!(
+					WorkerAttestations::<Test>::get(worker_pubkey(1)),
+					Some(WorkerAttestation {
+						provider: AttestationProvider::Ias,
+						pruntime_hash: pruntime_hash.clone(),
+					})
+				);
+
+				// Revoked for another provider
+				assert_ok!(PhalaRegistry::remove_pruntime(
+					Origin::root(),
+					AttestationProvider::Dcap,
+					pruntime_hash.clone()
+				));
+				assert_eq!(InvalidatedWorkers::<Test>::get(worker_pubkey(1)), None);
+
+				assert_ok!(PhalaRegistry::remove_pruntime(
+					Origin::root(),
+					AttestationProvider::Ias,
+					pruntime_hash.clone()
+				));
+				assert_eq!(
+					InvalidatedWorkers::<Test>::get(worker_pubkey(1)),
+					Some(AttestationInvalidation::PRuntimeRevoked)
+				);
+				assert_eq!(
+					PRuntimeWorkers::<Test>::iter_prefix((
+						AttestationProvider::Ias,
+						pruntime_hash.clone()
+					))
+					.count(),
+					0
+				);
+			});
+		}
+
+		#[test]
+		fn test_dcap_collateral_works() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+
+				assert_noop!(
+					PhalaRegistry::set_dcap_collateral(Origin::signed(1), Default::default()),
+					sp_runtime::DispatchError::BadOrigin
+				);
+				assert_ok!(PhalaRegistry::set_dcap_collateral(
+					Origin::root(),
+					Default::default()
+				));
+				assert_eq!(DcapQuoteCollateral::<Test>::get(), Some(Default::default()));
 			});
 		}
 
@@ -806,5 +2517,193 @@ pub mod pallet {
 				assert_eq!(RelaychainGenesisBlockHashAllowList::<Test>::get().len(), 0);
 			});
 		}
+
+		#[test]
+		fn test_unregister_gatekeeper() {
+			use crate::mining::OnlineMiners;
+			use crate::mock::{take_events, Event as TestEvent};
+			use phala_types::messaging::{BindTopic, Topic};
+			use sp_core::Pair;
+
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let genesis_gk = WorkerPublicKey::from_raw([0u8; 32]);
+				let pair = sp_core::sr25519::Pair::from_seed(&[1u8; 32]);
+				let gatekeeper = pair.public();
+				let sign = |nonce: u32| {
+					let unregistration = GatekeeperUnregistration {
+						pubkey: gatekeeper,
+						nonce,
+					};
+					pair.sign(&unregistration.data_be_signed()).0
+				};
+
+				assert_ok!(PhalaRegistry::on_message_received(DecodedMessage {
+					sender: MessageOrigin::Worker(genesis_gk),
+					destination: Topic::new(*b"^phala/registry/event"),
+					payload: RegistryEvent::MasterPubkey {
+						master_pubkey: MasterPublicKey::from_raw([1u8; 32]),
+					},
+				}));
+				assert_ok!(PhalaRegistry::force_register_worker(
+					Origin::root(),
+					gatekeeper,
+					ecdh_pubkey(1),
+					None
+				));
+				assert_ok!(PhalaRegistry::register_gatekeeper(
+					Origin::root(),
+					gatekeeper
+				));
+				take_messages();
+				take_events();
+
+				// Signed by someone else, or for another nonce
+				let mut bad_sig = sign(0);
+				bad_sig[0] ^= 1;
+				assert_noop!(
+					PhalaRegistry::unregister_gatekeeper(Origin::signed(1), gatekeeper, bad_sig),
+					Error::<Test>::InvalidSignature
+				);
+				assert_noop!(
+					PhalaRegistry::unregister_gatekeeper(Origin::signed(1), gatekeeper, sign(1)),
+					Error::<Test>::InvalidSignature
+				);
+
+				assert_ok!(PhalaRegistry::unregister_gatekeeper(
+					Origin::signed(1),
+					gatekeeper,
+					sign(0)
+				));
+				assert_eq!(Gatekeeper::<Test>::get(), vec![genesis_gk]);
+				assert_eq!(GatekeeperUnregistrationNonce::<Test>::get(&gatekeeper), 1);
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert_eq!(messages[0].destination.path(), &GatekeeperChange::topic());
+				assert_eq!(
+					messages[0].decode_payload::<GatekeeperChange>(),
+					Some(GatekeeperChange::gatekeeper_unregistered(gatekeeper))
+				);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaRegistry(Event::GatekeeperRemoved(
+						gatekeeper
+					))]
+				);
+				assert_noop!(
+					PhalaRegistry::unregister_gatekeeper(Origin::signed(1), gatekeeper, sign(0)),
+					Error::<Test>::InvalidGatekeeper
+				);
+
+				// The old signature can't be replayed after registered again
+				assert_ok!(PhalaRegistry::register_gatekeeper(
+					Origin::root(),
+					gatekeeper
+				));
+				assert_noop!(
+					PhalaRegistry::unregister_gatekeeper(Origin::signed(1), gatekeeper, sign(0)),
+					Error::<Test>::InvalidSignature
+				);
+
+				// The last gatekeeper can't leave while the workers are mining
+				Gatekeeper::<Test>::put(vec![gatekeeper]);
+				OnlineMiners::<Test>::put(1);
+				assert_noop!(
+					PhalaRegistry::unregister_gatekeeper(Origin::signed(1), gatekeeper, sign(1)),
+					Error::<Test>::LastGatekeeperInUse
+				);
+				OnlineMiners::<Test>::put(0);
+				assert_ok!(PhalaRegistry::unregister_gatekeeper(
+					Origin::signed(1),
+					gatekeeper,
+					sign(1)
+				));
+				assert!(Gatekeeper::<Test>::get().is_empty());
+			});
+		}
+
+		#[test]
+		fn test_suite_bench_report() {
+			use phala_types::messaging::Topic;
+
+			fn bench_report(
+				suite_version: u32,
+				component_iterations: Vec<(BenchComponent, u64)>,
+			) -> DecodedMessage<RegistryEvent> {
+				DecodedMessage {
+					sender: MessageOrigin::Worker(worker_pubkey(1)),
+					destination: Topic::new(*b"^phala/registry/event"),
+					payload: RegistryEvent::SuiteBenchReport {
+						start_time: 0,
+						iterations: 1000,
+						suite_version,
+						component_iterations,
+					},
+				}
+			}
+			let units = |units: u64| {
+				BenchComponent::ALL
+					.iter()
+					.map(|component| (*component, units))
+					.collect::<Vec<_>>()
+			};
+			let score = || {
+				Workers::<Test>::get(worker_pubkey(1))
+					.unwrap()
+					.initial_score
+			};
+
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				elapse_seconds(60);
+
+				assert_noop!(
+					PhalaRegistry::on_message_received(bench_report(2, units(1000))),
+					Error::<Test>::UnsupportedBenchSuite
+				);
+				// Missing components
+				assert_noop!(
+					PhalaRegistry::on_message_received(bench_report(1, units(1000)[1..].to_vec())),
+					Error::<Test>::InvalidBenchReport
+				);
+
+				// 1000 rounds in 60s with the default weights
+				assert_ok!(PhalaRegistry::on_message_received(bench_report(
+					1,
+					units(1000)
+				)));
+				assert_eq!(score(), Some(100));
+				// The rounds are capped by the least done component
+				let mut partial = units(1000);
+				partial[2].1 = 600;
+				assert_ok!(PhalaRegistry::on_message_received(bench_report(1, partial)));
+				assert_eq!(score(), Some(60));
+				// The reported rounds can't be exceeded
+				assert_ok!(PhalaRegistry::on_message_received(bench_report(
+					1,
+					units(2000)
+				)));
+				assert_eq!(score(), Some(100));
+
+				assert_noop!(
+					PhalaRegistry::set_benchmark_weights(
+						Origin::root(),
+						vec![(BenchComponent::Trie, 0)]
+					),
+					Error::<Test>::InvalidBenchmarkWeight
+				);
+				// A round takes 3 units of signing, so 1000 units only make 333 rounds
+				assert_ok!(PhalaRegistry::set_benchmark_weights(
+					Origin::root(),
+					vec![(BenchComponent::Sr25519Sign, 3000)]
+				));
+				assert_ok!(PhalaRegistry::on_message_received(bench_report(
+					1,
+					units(1000)
+				)));
+				assert_eq!(score(), Some(33));
+			});
+		}
 	}
 }
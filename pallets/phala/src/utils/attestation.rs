@@ -1,12 +1,17 @@
 use crate::constants::*;
 
 use codec::{Decode, Encode};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
 use sp_std::{
 	borrow::ToOwned,
 	convert::{TryFrom, TryInto},
 	vec::Vec,
 };
 
+mod dcap;
+pub use dcap::validate_dcap_quote;
+
 #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
 pub enum Attestation {
 	SgxIas {
@@ -14,6 +19,54 @@ pub enum Attestation {
 		signature: Vec<u8>,
 		raw_signing_cert: Vec<u8>,
 	},
+	/// An ECDSA quote (v3) with the PCK certificate chain embedded
+	SgxDcap { quote: Vec<u8> },
+	/// No attestation at all, only accepted by the test chains
+	None,
+}
+
+impl Attestation {
+	pub fn provider(&self) -> AttestationProvider {
+		match self {
+			Attestation::SgxIas { .. } => AttestationProvider::Ias,
+			Attestation::SgxDcap { .. } => AttestationProvider::Dcap,
+			Attestation::None => AttestationProvider::None,
+		}
+	}
+}
+
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum AttestationProvider {
+	Ias,
+	Dcap,
+	None,
+}
+
+/// The policy of an accepted attestation provider
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq, Default)]
+pub struct AttestationProviderConfig {
+	/// Overrides the confidence levels judged by the validator, as `(judged, recorded)` pairs
+	///
+	/// Recording 0 rejects the judged level. The levels not listed are recorded as is.
+	pub confidence_levels: Vec<(u8, u8)>,
+}
+
+impl AttestationProviderConfig {
+	/// Returns the confidence level to record, or `None` if the judged level is rejected
+	pub fn confidence_level(&self, judged: u8) -> Option<u8> {
+		let recorded = self
+			.confidence_levels
+			.iter()
+			.find(|(level, _)| *level == judged)
+			.map(|(_, recorded)| *recorded)
+			.unwrap_or(judged);
+		if recorded == 0 {
+			None
+		} else {
+			Some(recorded)
+		}
+	}
 }
 
 pub trait AttestationValidator {
@@ -24,6 +77,7 @@ pub trait AttestationValidator {
 		now: u64,
 		verify_pruntime_hash: bool,
 		pruntime_allowlist: Vec<Vec<u8>>,
+		dcap_collateral: Option<DcapCollateral>,
 	) -> Result<IasFields, Error>;
 }
 
@@ -37,6 +91,14 @@ pub enum Error {
 	OutdatedIASReport,
 	UnknownQuoteBodyFormat,
 	InvalidUserDataHash,
+	// DCAP related
+	MissingDcapCollateral,
+	InvalidDcapQuote,
+	InvalidPckCertChain,
+	InvalidQuoteSignature,
+	QeIdentityMismatch,
+	UnknownTcbLevel,
+	OutdatedDcapCollateral,
 }
 
 #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
@@ -49,7 +111,70 @@ pub struct IasFields {
 	pub confidence_level: u8,
 }
 
-/// Attestation validator implementation for IAS
+impl IasFields {
+	/// The pRuntime hash as in the allowlist
+	pub fn pruntime_hash(&self) -> Vec<u8> {
+		extend_mrenclave(
+			&self.mr_enclave,
+			&self.mr_signer,
+			&self.isv_prod_id,
+			&self.isv_svn,
+		)
+	}
+}
+
+/// The reference values to verify the DCAP quotes against, maintained by the governance
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq, Default)]
+pub struct DcapCollateral {
+	/// The DER encoded root CA of the PCK certificate chains (i.e. the Intel SGX Root CA)
+	pub root_ca: Vec<u8>,
+	/// The identity of the Quoting Enclave
+	pub qe_identity: QeIdentity,
+	/// The TCB info of each platform family
+	pub tcb_info: Vec<TcbInfo>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq, Default)]
+pub struct QeIdentity {
+	pub mr_signer: [u8; 32],
+	pub isv_prod_id: u16,
+	pub min_isv_svn: u16,
+	pub misc_select: u32,
+	pub misc_select_mask: u32,
+	pub attributes: [u8; 16],
+	pub attributes_mask: [u8; 16],
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct TcbInfo {
+	pub fmspc: [u8; 6],
+	/// When the TCB info is expected to be updated, in unix time. It's rejected afterwards.
+	pub next_update: u64,
+	/// The TCB levels, in descending order
+	pub levels: Vec<TcbLevel>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct TcbLevel {
+	/// The minimal SVN of each SGX TCB component
+	pub sgx_tcb_components: [u8; 16],
+	pub pce_svn: u16,
+	pub status: TcbStatus,
+	pub advisory_ids: Vec<Vec<u8>>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcbStatus {
+	UpToDate,
+	SwHardeningNeeded,
+	ConfigurationNeeded,
+	ConfigurationAndSwHardeningNeeded,
+	OutOfDate,
+	OutOfDateConfigurationNeeded,
+	Revoked,
+}
+
+/// Attestation validator implementation for IAS reports and DCAP quotes
 pub struct IasValidator;
 impl AttestationValidator for IasValidator {
 	fn validate(
@@ -58,6 +183,7 @@ impl AttestationValidator for IasValidator {
 		now: u64,
 		verify_pruntime: bool,
 		pruntime_allowlist: Vec<Vec<u8>>,
+		dcap_collateral: Option<DcapCollateral>,
 	) -> Result<IasFields, Error> {
 		let fields = match attestation {
 			Attestation::SgxIas {
@@ -72,6 +198,15 @@ impl AttestationValidator for IasValidator {
 				verify_pruntime,
 				pruntime_allowlist,
 			),
+			Attestation::SgxDcap { quote } => validate_dcap_quote(
+				quote,
+				&dcap_collateral.ok_or(Error::MissingDcapCollateral)?,
+				now,
+				verify_pruntime,
+				pruntime_allowlist,
+			),
+			// Nothing to validate. It's up to the caller to accept it or not.
+			Attestation::None => Err(Error::InvalidReport),
 		}?;
 		let commit = &fields.report_data[..32];
 		if commit != user_data_hash {
@@ -197,11 +332,18 @@ pub fn validate_ias_report(
 mod test {
 	use super::*;
 	use frame_support::assert_ok;
+	use hex_literal::hex;
 
 	pub const ATTESTATION_SAMPLE: &[u8] = include_bytes!("../../sample/ias_attestation.json");
 	pub const ATTESTATION_TIMESTAMP: u64 = 1631441180; // 2021-09-12T18:06:20.402478
 	pub const PRUNTIME_HASH: &str = "518422fa769d2d55982015a0e0417c6a8521fdfc7308f5ec18aaa1b6924bd0f300000000815f42f11cf64430c30bab7816ba596a1da0130c3b028b673133a66cf9a3e0e6";
 
+	// Generated by `sample/gen_dcap_sample.py`, with a test PCK certificate hierarchy
+	pub const DCAP_QUOTE_SAMPLE: &[u8] = include_bytes!("../../sample/dcap_quote.bin");
+	pub const DCAP_ROOT_CA_SAMPLE: &[u8] = include_bytes!("../../sample/dcap_root_ca.der");
+	pub const DCAP_QUOTE_TIMESTAMP: u64 = 1640995200; // 2022-01-01T00:00:00
+	pub const DCAP_PRUNTIME_HASH: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f00000000202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f";
+
 	#[test]
 	fn test_ias_validator() {
 		let sample: serde_json::Value = serde_json::from_slice(ATTESTATION_SAMPLE).unwrap();
@@ -244,4 +386,156 @@ mod test {
 			vec![hex::decode(PRUNTIME_HASH).unwrap()]
 		));
 	}
+
+	fn dcap_collateral() -> DcapCollateral {
+		DcapCollateral {
+			root_ca: DCAP_ROOT_CA_SAMPLE.to_vec(),
+			qe_identity: QeIdentity {
+				mr_signer: hex!("8c4f5775d796503e96137f77c68a829a0056ac8ded70140b081b094490c57bff"),
+				isv_prod_id: 1,
+				min_isv_svn: 6,
+				misc_select: 0,
+				misc_select_mask: 0xffffffff,
+				attributes: hex!("11000000000000000000000000000000"),
+				attributes_mask: hex!("fbffffffffffffff0000000000000000"),
+			},
+			tcb_info: vec![TcbInfo {
+				fmspc: hex!("00906ea10000"),
+				next_update: DCAP_QUOTE_TIMESTAMP + 3600,
+				levels: vec![
+					TcbLevel {
+						sgx_tcb_components: [4, 4, 2, 4, 1, 128, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+						pce_svn: 11,
+						status: TcbStatus::UpToDate,
+						advisory_ids: vec![],
+					},
+					TcbLevel {
+						sgx_tcb_components: [3, 3, 2, 4, 1, 128, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+						pce_svn: 10,
+						status: TcbStatus::SwHardeningNeeded,
+						advisory_ids: vec![b"INTEL-SA-00615".to_vec()],
+					},
+				],
+			}],
+		}
+	}
+
+	#[test]
+	fn test_dcap_validator() {
+		let collateral = dcap_collateral();
+		let allowlist = vec![hex::decode(DCAP_PRUNTIME_HASH).unwrap()];
+
+		let fields = validate_dcap_quote(
+			DCAP_QUOTE_SAMPLE,
+			&collateral,
+			DCAP_QUOTE_TIMESTAMP,
+			true,
+			allowlist.clone(),
+		)
+		.unwrap();
+		assert_eq!(
+			fields.report_data[..32],
+			hex!("eddaf8d224627d3c36bc5e60cabac73aa91685dbb23389392604da4dd26ba599")[..]
+		);
+		// SW hardening needed, with an unknown advisory
+		assert_eq!(fields.confidence_level, 4);
+
+		assert_eq!(
+			validate_dcap_quote(
+				DCAP_QUOTE_SAMPLE,
+				&collateral,
+				DCAP_QUOTE_TIMESTAMP,
+				true,
+				vec![]
+			),
+			Err(Error::PRuntimeRejected)
+		);
+		assert_eq!(
+			validate_dcap_quote(
+				DCAP_QUOTE_SAMPLE,
+				&collateral,
+				DCAP_QUOTE_TIMESTAMP + 3600,
+				false,
+				vec![]
+			),
+			Err(Error::OutdatedDcapCollateral)
+		);
+
+		// Tampered report data
+		let mut quote = DCAP_QUOTE_SAMPLE.to_vec();
+		quote[48 + 320] ^= 1;
+		assert_eq!(
+			validate_dcap_quote(&quote, &collateral, DCAP_QUOTE_TIMESTAMP, false, vec![]),
+			Err(Error::InvalidQuoteSignature)
+		);
+		assert_eq!(
+			validate_dcap_quote(
+				&DCAP_QUOTE_SAMPLE[..1000],
+				&collateral,
+				DCAP_QUOTE_TIMESTAMP,
+				false,
+				vec![]
+			),
+			Err(Error::InvalidDcapQuote)
+		);
+
+		let mut untrusted_root = collateral.clone();
+		untrusted_root.root_ca = hex::decode(
+			serde_json::from_slice::<serde_json::Value>(ATTESTATION_SAMPLE).unwrap()
+				["rawSigningCert"]
+				.as_str()
+				.unwrap(),
+		)
+		.unwrap();
+		assert_eq!(
+			validate_dcap_quote(
+				DCAP_QUOTE_SAMPLE,
+				&untrusted_root,
+				DCAP_QUOTE_TIMESTAMP,
+				false,
+				vec![]
+			),
+			Err(Error::InvalidPckCertChain)
+		);
+
+		let mut newer_qe = collateral.clone();
+		newer_qe.qe_identity.min_isv_svn = 7;
+		assert_eq!(
+			validate_dcap_quote(
+				DCAP_QUOTE_SAMPLE,
+				&newer_qe,
+				DCAP_QUOTE_TIMESTAMP,
+				false,
+				vec![]
+			),
+			Err(Error::QeIdentityMismatch)
+		);
+
+		let mut newer_tcb = collateral.clone();
+		newer_tcb.tcb_info[0].levels.truncate(1);
+		assert_eq!(
+			validate_dcap_quote(
+				DCAP_QUOTE_SAMPLE,
+				&newer_tcb,
+				DCAP_QUOTE_TIMESTAMP,
+				false,
+				vec![]
+			),
+			Err(Error::UnknownTcbLevel)
+		);
+
+		assert_eq!(
+			IasValidator::validate(
+				&Attestation::SgxDcap {
+					quote: DCAP_QUOTE_SAMPLE.to_vec()
+				},
+				&[0u8; 32],
+				DCAP_QUOTE_TIMESTAMP,
+				false,
+				vec![],
+				None,
+			),
+			Err(Error::MissingDcapCollateral)
+		);
+	}
 }
@@ -0,0 +1,439 @@
+//! Verification of the SGX ECDSA quotes (DCAP)
+//!
+//! A v3 quote is verified against the collateral supplied by the governance:
+//!
+//! 1. The PCK certificate embedded in the quote chains up to the root CA in the collateral
+//! 2. The QE report is signed by the PCK key, and it commits to the attestation key
+//! 3. The enclave report is signed by the attestation key
+//! 4. The QE matches the QE identity
+//! 5. The TCB level of the platform is looked up in the TCB info by the FMSPC
+
+use super::{extend_mrenclave, DcapCollateral, Error, IasFields, QeIdentity, TcbStatus};
+use crate::constants::*;
+
+use sp_std::{
+	convert::{TryFrom, TryInto},
+	vec::Vec,
+};
+
+const QUOTE_VERSION: u16 = 3;
+const ATTESTATION_KEY_TYPE_ECDSA_P256: u16 = 2;
+const HEADER_LEN: usize = 48;
+const REPORT_BODY_LEN: usize = 384;
+const ECDSA_SIGNATURE_LEN: usize = 64;
+const ECDSA_PUBKEY_LEN: usize = 64;
+/// Certification data type of the concatenated PEM PCK certificate chain
+const CERT_TYPE_PCK_CERT_CHAIN: u16 = 5;
+
+/// DER encoded OID of the SGX extension of the PCK certificates (1.2.840.113741.1.13.1)
+const SGX_EXTENSION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf8, 0x4d, 0x01, 0x0d, 0x01];
+const SGX_EXTENSION_TCB: u8 = 2;
+const SGX_EXTENSION_FMSPC: u8 = 4;
+/// The TCB entry of the PCE SVN. The entries 1 to 16 are the SGX TCB components.
+const SGX_TCB_PCE_SVN: u8 = 17;
+
+const DER_TAG_OID: u8 = 0x06;
+const DER_TAG_OCTET_STRING: u8 = 0x04;
+const DER_TAG_BOOLEAN: u8 = 0x01;
+
+/// The report of an enclave, as defined in `sgx_report_body_t`
+struct ReportBody<'a>(&'a [u8]);
+
+impl<'a> ReportBody<'a> {
+	fn misc_select(&self) -> u32 {
+		u32::from_le_bytes(self.0[16..20].try_into().unwrap())
+	}
+	fn attributes(&self) -> &'a [u8] {
+		&self.0[48..64]
+	}
+	fn mr_enclave(&self) -> &'a [u8] {
+		&self.0[64..96]
+	}
+	fn mr_signer(&self) -> &'a [u8] {
+		&self.0[128..160]
+	}
+	fn isv_prod_id(&self) -> &'a [u8] {
+		&self.0[256..258]
+	}
+	fn isv_svn(&self) -> &'a [u8] {
+		&self.0[258..260]
+	}
+	fn report_data(&self) -> &'a [u8] {
+		&self.0[320..384]
+	}
+}
+
+struct Quote<'a> {
+	/// The header and the enclave report, signed by the attestation key
+	signed_data: &'a [u8],
+	enclave_report: ReportBody<'a>,
+	enclave_report_signature: &'a [u8],
+	attestation_key: &'a [u8],
+	qe_report: ReportBody<'a>,
+	qe_report_signature: &'a [u8],
+	qe_auth_data: &'a [u8],
+	pck_cert_chain: &'a [u8],
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+		if self.0.len() < len {
+			return Err(Error::InvalidDcapQuote);
+		}
+		let (head, rest) = self.0.split_at(len);
+		self.0 = rest;
+		Ok(head)
+	}
+	fn u16(&mut self) -> Result<u16, Error> {
+		Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+	}
+	fn u32(&mut self) -> Result<u32, Error> {
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+}
+
+impl<'a> Quote<'a> {
+	fn parse(quote: &'a [u8]) -> Result<Self, Error> {
+		let mut reader = Reader(quote);
+		let signed_data = reader.take(HEADER_LEN + REPORT_BODY_LEN)?;
+		let mut header = Reader(signed_data);
+		if header.u16()? != QUOTE_VERSION || header.u16()? != ATTESTATION_KEY_TYPE_ECDSA_P256 {
+			return Err(Error::InvalidDcapQuote);
+		}
+		let enclave_report = ReportBody(&signed_data[HEADER_LEN..]);
+
+		let signature_data_len = reader.u32()? as usize;
+		let mut reader = Reader(reader.take(signature_data_len)?);
+		let enclave_report_signature = reader.take(ECDSA_SIGNATURE_LEN)?;
+		let attestation_key = reader.take(ECDSA_PUBKEY_LEN)?;
+		let qe_report = ReportBody(reader.take(REPORT_BODY_LEN)?);
+		let qe_report_signature = reader.take(ECDSA_SIGNATURE_LEN)?;
+		let qe_auth_data_len = reader.u16()? as usize;
+		let qe_auth_data = reader.take(qe_auth_data_len)?;
+		if reader.u16()? != CERT_TYPE_PCK_CERT_CHAIN {
+			return Err(Error::InvalidDcapQuote);
+		}
+		let cert_data_len = reader.u32()? as usize;
+		let pck_cert_chain = reader.take(cert_data_len)?;
+		Ok(Quote {
+			signed_data,
+			enclave_report,
+			enclave_report_signature,
+			attestation_key,
+			qe_report,
+			qe_report_signature,
+			qe_auth_data,
+			pck_cert_chain,
+		})
+	}
+}
+
+pub fn validate_dcap_quote(
+	quote: &[u8],
+	collateral: &DcapCollateral,
+	now: u64,
+	verify_pruntime: bool,
+	pruntime_allowlist: Vec<Vec<u8>>,
+) -> Result<IasFields, Error> {
+	let quote = Quote::parse(quote)?;
+
+	// Validate the PCK certificate chain
+	let certs = parse_pem_certs(quote.pck_cert_chain)?;
+	let (raw_pck_cert, intermediates) = certs.split_first().ok_or(Error::InvalidPckCertChain)?;
+	let pck_cert =
+		webpki::EndEntityCert::try_from(&raw_pck_cert[..]).or(Err(Error::InvalidPckCertChain))?;
+	let root_ca = webpki::TrustAnchor::try_from_cert_der(&collateral.root_ca)
+		.or(Err(Error::InvalidPckCertChain))?;
+	let chain: Vec<&[u8]> = intermediates.iter().map(|cert| &cert[..]).collect();
+	let time_now = webpki::Time::from_seconds_since_unix_epoch(now);
+	pck_cert
+		.verify_is_valid_tls_server_cert(
+			DCAP_SUPPORTED_SIG_ALGS,
+			&webpki::TlsServerTrustAnchors(&[root_ca]),
+			&chain,
+			time_now,
+		)
+		.or(Err(Error::InvalidPckCertChain))?;
+
+	// Validate the QE report and the attestation key it commits to
+	pck_cert
+		.verify_signature(
+			&webpki::ECDSA_P256_SHA256,
+			quote.qe_report.0,
+			&der_encode_signature(quote.qe_report_signature),
+		)
+		.or(Err(Error::InvalidQuoteSignature))?;
+	let mut key_data = quote.attestation_key.to_vec();
+	key_data.extend_from_slice(quote.qe_auth_data);
+	let key_hash = sp_io::hashing::sha2_256(&key_data);
+	let qe_report_data = quote.qe_report.report_data();
+	if qe_report_data[..32] != key_hash || qe_report_data[32..].iter().any(|b| *b != 0) {
+		return Err(Error::InvalidQuoteSignature);
+	}
+
+	// Validate the enclave report
+	let mut attestation_key = Vec::with_capacity(1 + ECDSA_PUBKEY_LEN);
+	attestation_key.push(0x04); // Uncompressed point
+	attestation_key.extend_from_slice(quote.attestation_key);
+	ring::signature::UnparsedPublicKey::new(
+		&ring::signature::ECDSA_P256_SHA256_FIXED,
+		&attestation_key,
+	)
+	.verify(quote.signed_data, quote.enclave_report_signature)
+	.or(Err(Error::InvalidQuoteSignature))?;
+
+	validate_qe_identity(&quote.qe_report, &collateral.qe_identity)?;
+
+	// Validate the TCB level
+	let sgx_extension = SgxExtension::parse(raw_pck_cert)?;
+	let tcb_info = collateral
+		.tcb_info
+		.iter()
+		.find(|info| info.fmspc == sgx_extension.fmspc)
+		.ok_or(Error::UnknownTcbLevel)?;
+	if now >= tcb_info.next_update {
+		return Err(Error::OutdatedDcapCollateral);
+	}
+	let tcb_level = tcb_info
+		.levels
+		.iter()
+		.find(|level| {
+			sgx_extension.pce_svn >= level.pce_svn
+				&& sgx_extension
+					.sgx_tcb_components
+					.iter()
+					.zip(level.sgx_tcb_components.iter())
+					.all(|(svn, min_svn)| svn >= min_svn)
+		})
+		.ok_or(Error::UnknownTcbLevel)?;
+
+	// Validate PRuntime
+	let report = &quote.enclave_report;
+	if verify_pruntime {
+		let t_mrenclave = extend_mrenclave(
+			report.mr_enclave(),
+			report.mr_signer(),
+			report.isv_prod_id(),
+			report.isv_svn(),
+		);
+		if !pruntime_allowlist.contains(&t_mrenclave) {
+			return Err(Error::PRuntimeRejected);
+		}
+	}
+
+	// Same as the IAS quote status levels
+	let mut confidence_level: u8 = match tcb_level.status {
+		TcbStatus::UpToDate => 1,
+		TcbStatus::SwHardeningNeeded => 2,
+		TcbStatus::ConfigurationNeeded | TcbStatus::ConfigurationAndSwHardeningNeeded => 3,
+		TcbStatus::OutOfDate | TcbStatus::OutOfDateConfigurationNeeded => 5,
+		TcbStatus::Revoked => return Err(Error::InvalidQuoteStatus),
+	};
+	if confidence_level < 5 {
+		for advisory_id in tcb_level.advisory_ids.iter() {
+			if !IAS_QUOTE_ADVISORY_ID_WHITELIST
+				.iter()
+				.any(|id| id.as_bytes() == &advisory_id[..])
+			{
+				confidence_level = 4;
+			}
+		}
+	}
+
+	Ok(IasFields {
+		mr_enclave: report.mr_enclave().try_into().unwrap(),
+		mr_signer: report.mr_signer().try_into().unwrap(),
+		isv_prod_id: report.isv_prod_id().try_into().unwrap(),
+		isv_svn: report.isv_svn().try_into().unwrap(),
+		report_data: report.report_data().try_into().unwrap(),
+		confidence_level,
+	})
+}
+
+fn validate_qe_identity(qe_report: &ReportBody, identity: &QeIdentity) -> Result<(), Error> {
+	let isv_prod_id = u16::from_le_bytes(qe_report.isv_prod_id().try_into().unwrap());
+	let isv_svn = u16::from_le_bytes(qe_report.isv_svn().try_into().unwrap());
+	let attributes_match = qe_report
+		.attributes()
+		.iter()
+		.zip(identity.attributes_mask.iter())
+		.map(|(attr, mask)| attr & mask)
+		.eq(identity.attributes.iter().cloned());
+	if qe_report.mr_signer() != identity.mr_signer
+		|| isv_prod_id != identity.isv_prod_id
+		|| isv_svn < identity.min_isv_svn
+		|| qe_report.misc_select() & identity.misc_select_mask != identity.misc_select
+		|| !attributes_match
+	{
+		return Err(Error::QeIdentityMismatch);
+	}
+	Ok(())
+}
+
+/// Decodes the concatenated PEM certificates
+fn parse_pem_certs(data: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+	const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+	const END: &str = "-----END CERTIFICATE-----";
+	let mut rest = core::str::from_utf8(data).or(Err(Error::InvalidPckCertChain))?;
+	let mut certs = Vec::new();
+	while let Some(begin) = rest.find(BEGIN) {
+		let body = &rest[begin + BEGIN.len()..];
+		let end = body.find(END).ok_or(Error::InvalidPckCertChain)?;
+		let encoded: Vec<u8> = body[..end]
+			.bytes()
+			.filter(|b| !b.is_ascii_whitespace())
+			.collect();
+		certs.push(base64::decode(&encoded).or(Err(Error::InvalidPckCertChain))?);
+		rest = &body[end + END.len()..];
+	}
+	if certs.is_empty() {
+		return Err(Error::InvalidPckCertChain);
+	}
+	Ok(certs)
+}
+
+/// Converts a raw `r || s` ECDSA signature to the ASN.1 DER form
+fn der_encode_signature(raw: &[u8]) -> Vec<u8> {
+	fn der_integer(out: &mut Vec<u8>, bytes: &[u8]) {
+		let start = bytes
+			.iter()
+			.position(|b| *b != 0)
+			.unwrap_or(bytes.len() - 1);
+		let bytes = &bytes[start..];
+		let pad = bytes[0] & 0x80 != 0;
+		out.push(0x02);
+		out.push((bytes.len() + pad as usize) as u8);
+		if pad {
+			out.push(0);
+		}
+		out.extend_from_slice(bytes);
+	}
+	let (r, s) = raw.split_at(raw.len() / 2);
+	let mut integers = Vec::new();
+	der_integer(&mut integers, r);
+	der_integer(&mut integers, s);
+	let mut out = Vec::with_capacity(integers.len() + 2);
+	out.push(0x30);
+	out.push(integers.len() as u8);
+	out.extend_from_slice(&integers);
+	out
+}
+
+/// Reads a DER TLV, returning the tag, the value and the remaining input
+fn der_read(input: &[u8]) -> Result<(u8, &[u8], &[u8]), Error> {
+	let err = Error::InvalidPckCertChain;
+	let (&tag, rest) = input.split_first().ok_or(err.clone())?;
+	let (&len_byte, mut rest) = rest.split_first().ok_or(err.clone())?;
+	let len = if len_byte < 0x80 {
+		len_byte as usize
+	} else {
+		let num_bytes = (len_byte & 0x7f) as usize;
+		if num_bytes == 0 || num_bytes > 3 || rest.len() < num_bytes {
+			return Err(err);
+		}
+		let (len_bytes, remaining) = rest.split_at(num_bytes);
+		rest = remaining;
+		len_bytes
+			.iter()
+			.fold(0usize, |len, b| (len << 8) | *b as usize)
+	};
+	if rest.len() < len {
+		return Err(err);
+	}
+	let (value, rest) = rest.split_at(len);
+	Ok((tag, value, rest))
+}
+
+/// Reads a small non-negative DER INTEGER value
+fn der_uint(value: &[u8]) -> Result<u32, Error> {
+	if value.is_empty() || value.len() > 4 || value[0] & 0x80 != 0 {
+		return Err(Error::InvalidPckCertChain);
+	}
+	Ok(value.iter().fold(0u32, |n, b| (n << 8) | *b as u32))
+}
+
+/// The platform info in the SGX extension of a PCK certificate
+struct SgxExtension {
+	sgx_tcb_components: [u8; 16],
+	pce_svn: u16,
+	fmspc: [u8; 6],
+}
+
+impl SgxExtension {
+	/// Extracts the SGX extension from a DER encoded PCK certificate
+	///
+	/// The extension is located by its OID. It's fine to skip a full X.509 parsing since the
+	/// certificate has been verified, thus the content is trusted.
+	fn parse(cert: &[u8]) -> Result<Self, Error> {
+		let err = Error::InvalidPckCertChain;
+		let mut oid_tlv = Vec::with_capacity(2 + SGX_EXTENSION_OID.len());
+		oid_tlv.push(DER_TAG_OID);
+		oid_tlv.push(SGX_EXTENSION_OID.len() as u8);
+		oid_tlv.extend_from_slice(SGX_EXTENSION_OID);
+		let pos = cert
+			.windows(oid_tlv.len())
+			.position(|window| window == &oid_tlv[..])
+			.ok_or(err.clone())?;
+		let (mut tag, mut value, rest) = der_read(&cert[pos + oid_tlv.len()..])?;
+		// Skip the optional `critical` flag
+		if tag == DER_TAG_BOOLEAN {
+			let (next_tag, next_value, _) = der_read(rest)?;
+			tag = next_tag;
+			value = next_value;
+		}
+		if tag != DER_TAG_OCTET_STRING {
+			return Err(err);
+		}
+		// extnValue: SEQUENCE OF SEQUENCE { OID, value }
+		let (_, mut entries, _) = der_read(value)?;
+		let mut sgx_tcb_components = None;
+		let mut pce_svn = None;
+		let mut fmspc = None;
+		while !entries.is_empty() {
+			let (_, entry, next) = der_read(entries)?;
+			entries = next;
+			let (_, oid, entry) = der_read(entry)?;
+			let (_, value, _) = der_read(entry)?;
+			match sub_oid(oid) {
+				Some([SGX_EXTENSION_TCB]) => {
+					let mut components = [0u8; 16];
+					let mut tcb = value;
+					while !tcb.is_empty() {
+						let (_, item, next) = der_read(tcb)?;
+						tcb = next;
+						let (_, oid, item) = der_read(item)?;
+						let (_, value, _) = der_read(item)?;
+						match sub_oid(oid) {
+							Some([SGX_EXTENSION_TCB, n]) if (1..=16).contains(n) => {
+								let svn = der_uint(value)?;
+								components[*n as usize - 1] =
+									svn.try_into().or(Err(err.clone()))?;
+							}
+							Some([SGX_EXTENSION_TCB, SGX_TCB_PCE_SVN]) => {
+								pce_svn = Some(der_uint(value)?.try_into().or(Err(err.clone()))?);
+							}
+							_ => (),
+						}
+					}
+					sgx_tcb_components = Some(components);
+				}
+				Some([SGX_EXTENSION_FMSPC]) => {
+					fmspc = Some(value.try_into().or(Err(err.clone()))?);
+				}
+				_ => (),
+			}
+		}
+		Ok(SgxExtension {
+			sgx_tcb_components: sgx_tcb_components.ok_or(err.clone())?,
+			pce_svn: pce_svn.ok_or(err.clone())?,
+			fmspc: fmspc.ok_or(err)?,
+		})
+	}
+}
+
+/// Returns the arcs of an OID under the SGX extension OID
+fn sub_oid(oid: &[u8]) -> Option<&[u8]> {
+	oid.strip_prefix(SGX_EXTENSION_OID)
+}
@@ -67,7 +67,27 @@ enum RpcCommand {
     GetWorkerState {
         pubkey: String,
     },
+    /// List the recent heartbeats, payouts, slashes and responsiveness changes of a worker
+    GetWorkerTokenomicEvents {
+        pubkey: String,
+    },
     GetInfo,
+    GetContractStateDigests,
+    GetGatekeeperState {
+        /// Dump the state of every worker besides the digest
+        #[structopt(long)]
+        dump: bool,
+    },
+    /// Compare the state of this gatekeeper with another one, worker by worker
+    DiffGatekeeperStates {
+        other_url: String,
+    },
+    /// Show the recent heartbeat challenges and whether the heartbeats landed on chain
+    GetHeartbeatHistory,
+    /// Hand the identity of this pRuntime over to a new pRuntime build which has no identity yet
+    Handover {
+        to_url: String,
+    },
 }
 
 
@@ -192,14 +212,128 @@ async fn handle_rpc_command(command: RpcCommand, url: String) {
             let rv = client.get_worker_state(phactory_api::prpc::GetWorkerStateRequest { public_key }).await;
             print_result(rv);
         },
+        RpcCommand::GetWorkerTokenomicEvents { pubkey } => {
+            use phactory_api::prpc::TokenomicEventKind;
+
+            let public_key = try_decode_hex(&pubkey).expect("Failed to decode pubkey");
+            let request = phactory_api::prpc::GetWorkerTokenomicEventsRequest { public_key };
+            match client.get_worker_tokenomic_events(request).await {
+                Ok(resp) => {
+                    for e in resp.events {
+                        let kind = match TokenomicEventKind::from_i32(e.kind) {
+                            Some(kind) => format!("{:?}", kind),
+                            None => format!("Unknown({})", e.kind),
+                        };
+                        let blocks = if e.last_block_number != e.block_number {
+                            format!("{}-{}", e.block_number, e.last_block_number)
+                        } else {
+                            e.block_number.to_string()
+                        };
+                        println!("{}: {} v: {} -> {} amount: {}", blocks, kind, e.v_before, e.v_after, e.amount);
+                    }
+                }
+                Err(err) => println!("Error: {:?}", err),
+            }
+        },
         RpcCommand::GetInfo => {
             let rv = client.get_info(()).await;
             print_result(rv);
         },
+        RpcCommand::GetContractStateDigests => {
+            match client.get_contract_state_digests(()).await {
+                Ok(resp) => {
+                    for d in resp.digests {
+                        println!("0x{}: 0x{}", hex::encode(&d.contract_id), hex::encode(&d.digest));
+                    }
+                }
+                Err(err) => println!("Error: {:?}", err),
+            }
+        },
+        RpcCommand::GetGatekeeperState { dump } => {
+            let rv = client.get_gatekeeper_state(phactory_api::prpc::GetGatekeeperStateRequest { dump }).await;
+            print_result(rv);
+        },
+        RpcCommand::DiffGatekeeperStates { other_url } => {
+            let other_client = phactory_api::pruntime_client::new_pruntime_client(other_url);
+            let request = || phactory_api::prpc::GetGatekeeperStateRequest { dump: true };
+            let state = client.get_gatekeeper_state(request()).await.expect("Failed to get the gatekeeper state");
+            let other_state = other_client.get_gatekeeper_state(request()).await.expect("Failed to get the other gatekeeper state");
+            diff_gatekeeper_states(state, other_state);
+        },
+        RpcCommand::GetHeartbeatHistory => {
+            let rv = client.get_heartbeat_history(()).await;
+            print_result(rv);
+        },
+        RpcCommand::Handover { to_url } => {
+            let to_client = phactory_api::pruntime_client::new_pruntime_client(to_url);
+            let request = phactory_api::prpc::HandoverChallengeRequest { skip_ra: false };
+            let challenge = to_client.handover_create_challenge(request).await.expect("Failed to create the challenge");
+            let package = client.handover_start(challenge).await.expect("Failed to start the handover");
+            let rv = to_client.handover_accept(package).await;
+            match rv {
+                Ok(resp) => println!("Handed over the identity 0x{}", hex::encode(&resp.public_key)),
+                Err(err) => println!("Error: {:?}", err),
+            }
+        },
     }
 
 }
 
+fn diff_gatekeeper_states(state: phactory_api::prpc::GatekeeperState, other: phactory_api::prpc::GatekeeperState) {
+    use std::collections::BTreeMap;
+
+    if state.next_block_number != other.next_block_number {
+        println!(
+            "Warning: the gatekeepers are at different blocks ({} vs {}), the states are not comparable",
+            state.next_block_number, other.next_block_number
+        );
+    }
+    if state.digest == other.digest {
+        println!("Consistent: 0x{}", hex::encode(&state.digest));
+        return;
+    }
+    println!("Inconsistent: 0x{} vs 0x{}", hex::encode(&state.digest), hex::encode(&other.digest));
+    if state.tokenomic_params_digest != other.tokenomic_params_digest {
+        println!("Tokenomic parameters differ");
+    }
+
+    let mut workers: BTreeMap<_, _> = state.workers.into_iter().map(|w| (w.public_key.clone(), w)).collect();
+    for other_worker in other.workers {
+        let worker = match workers.remove(&other_worker.public_key) {
+            Some(worker) => worker,
+            None => {
+                println!(
+                    "Worker 0x{}: only in the other gatekeeper",
+                    hex::encode(&other_worker.public_key)
+                );
+                continue;
+            }
+        };
+        if worker.digest == other_worker.digest {
+            continue;
+        }
+        println!("Worker 0x{}: differs", hex::encode(&other_worker.public_key));
+        let fields = |w: &phactory_api::prpc::GatekeeperWorkerState| {
+            format!("{:#?}\n{:#?}", w.state, w.tokenomic_info)
+        };
+        let (lines, other_lines) = (fields(&worker), fields(&other_worker));
+        let mut shown = false;
+        for (line, other_line) in lines.lines().zip(other_lines.lines()) {
+            if line != other_line {
+                println!("  - {}\n  + {}", line.trim(), other_line.trim());
+                shown = true;
+            }
+        }
+        if !shown {
+            println!("  encoded: 0x{}", hex::encode(&worker.encoded_info));
+            println!("  other:   0x{}", hex::encode(&other_worker.encoded_info));
+        }
+    }
+    for pubkey in workers.keys() {
+        println!("Worker 0x{}: only in this gatekeeper", hex::encode(pubkey));
+    }
+}
+
 fn try_decode_hex(hex_str: &str) -> Result<Vec<u8>, hex::FromHexError> {
     hex::decode(hex_str.strip_prefix("0x").unwrap_or(hex_str))
 }
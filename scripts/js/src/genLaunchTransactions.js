@@ -49,7 +49,14 @@ function propose(api, call) {
 
 async function main() {
     const wsProvider = new WsProvider(process.env.ENDPOINT);
-    const api = await ApiPromise.create({ provider: wsProvider, types: typedefs });
+    const api = await ApiPromise.create({
+        provider: wsProvider,
+        types: {
+            ...typedefs,
+            // Not in the published typedefs yet
+            AttestationProvider: { _enum: ['Ias', 'Dcap', 'None'] },
+        },
+    });
 
     // We use the Khala parameter now.
     const params = khala;
@@ -57,8 +64,8 @@ async function main() {
     // Motion 2:
 
     const motion2 = api.tx.utility.batchAll([
-        // 1. Register the whitelisted pRuntime hashes
-        ... params.pruntimeHashes.map(h => api.tx.phalaRegistry.addPruntime(h)),
+        // 1. Register the whitelisted pRuntime hashes, attested by IAS
+        ... params.pruntimeHashes.map(h => api.tx.phalaRegistry.addPruntime('Ias', h)),
         // 2. Register the whitelisted relayc chain genesis hashes
         ... params.relayGenesisHashes.map(([_n, blockHash]) =>
             api.tx.phalaRegistry.addRelaychainGenesisBlockHash(blockHash)
@@ -26,7 +26,7 @@ use node_runtime::{
 	DemocracyConfig,GrandpaConfig, ImOnlineConfig, SessionConfig, SessionKeys, StakerStatus,
 	StakingConfig, ElectionsConfig, IndicesConfig, SocietyConfig, SudoConfig, SystemConfig,
 	TechnicalCommitteeConfig, wasm_binary_unwrap, KittyStorageConfig,
-	PhalaRegistryConfig,
+	PhalaRegistryConfig, pallet_registry::AttestationProvider,
 };
 use node_runtime::Block;
 use node_runtime::constants::{currency::*, time::*};
@@ -302,11 +302,17 @@ pub fn testnet_genesis(
 			],
 			gatekeepers: Vec::new(),
 			benchmark_duration: 1,
+			attestation_providers: vec![
+				AttestationProvider::Ias,
+				AttestationProvider::Dcap,
+				AttestationProvider::None,
+			],
 		},
 		false => PhalaRegistryConfig {
 			workers: Vec::new(),
 			gatekeepers: Vec::new(),
 			benchmark_duration: 50,
+			attestation_providers: vec![AttestationProvider::Ias],
 		},
 	};
 
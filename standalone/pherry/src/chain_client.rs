@@ -91,6 +91,17 @@ pub async fn mq_next_sequence(
     Ok(seq)
 }
 
+/// Fetch the max age of the worker attestations in seconds, if they ever expire
+pub async fn max_attestation_age(client: &XtClient) -> Result<Option<u64>> {
+    let key = StorageKey(storage_value_key_vec("PhalaRegistry", "MaxAttestationAge"));
+    match get_storage(client, None, key).await? {
+        Some(data) => Decode::decode(&mut &data[..])
+            .map(Some)
+            .map_err(|_| anyhow!(Error::FailedToDecode)),
+        None => Ok(None),
+    }
+}
+
 pub fn paras_heads_key(para_id: &ParaId) -> StorageKey {
     StorageKey(storage_map_key_vec("Paras", "Heads", &para_id.encode()))
 }
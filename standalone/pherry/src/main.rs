@@ -67,6 +67,13 @@ struct Args {
     #[structopt(long, help = "Skip registering the worker.")]
     no_register: bool,
 
+    #[structopt(
+        default_value = "3600",
+        long,
+        help = "Register the worker again this many seconds before its attestation expires on chain"
+    )]
+    attestation_refresh_margin: u64,
+
     #[structopt(
         long,
         help = "Inject dev key (0x1) to pRuntime. Cannot be used with remote attestation enabled."
@@ -178,6 +185,13 @@ struct Args {
     )]
     max_sync_msgs_per_round: u64,
 
+    #[structopt(
+        default_value = "0",
+        long,
+        help = "Max size in bytes of each page of egress messages fetched from pRuntime. 0 to let pRuntime decide"
+    )]
+    max_egress_page_bytes: u32,
+
     #[structopt(long, help = "Enable geolocaltion report")]
     enable_geolocation: bool,
 
@@ -808,18 +822,27 @@ async fn register_worker(
     attestation: prpc::Attestation,
     signer: &mut SrSigner,
 ) -> Result<()> {
-    let payload = attestation
-        .payload
-        .ok_or(anyhow!("Missing attestation payload"))?;
+    let attestation = match attestation.provider.as_str() {
+        prpc::ATTESTATION_PROVIDER_IAS => {
+            let payload = attestation
+                .payload
+                .ok_or(anyhow!("Missing attestation payload"))?;
+            Attestation::SgxIas {
+                ra_report: payload.report.as_bytes().to_vec(),
+                signature: payload.signature,
+                raw_signing_cert: payload.signing_cert,
+            }
+        }
+        prpc::ATTESTATION_PROVIDER_DCAP => Attestation::SgxDcap {
+            quote: attestation.dcap_quote,
+        },
+        provider => return Err(anyhow!("Unknown attestation provider: {}", provider)),
+    };
     let call = runtimes::phala_registry::RegisterWorkerCall {
         _runtime: PhantomData,
         pruntime_info: Decode::decode(&mut &encoded_runtime_info[..])
             .map_err(|_| anyhow!("Decode pruntime info failed"))?,
-        attestation: Attestation::SgxIas {
-            ra_report: payload.report.as_bytes().to_vec(),
-            signature: payload.signature,
-            raw_signing_cert: payload.signing_cert,
-        },
+        attestation,
     };
     chain_client::update_signer_nonce(paraclient, signer).await?;
     let ret = paraclient.watch(call, signer).await;
@@ -831,17 +854,39 @@ async fn register_worker(
     Ok(())
 }
 
+/// Registers the worker if there's an attestation. Returns whether it's registered.
 async fn try_register_worker(
     pr: &PrClient,
     paraclient: &XtClient,
     signer: &mut SrSigner,
-) -> Result<()> {
+) -> Result<bool> {
     let info = pr.get_runtime_info(()).await?;
     if let Some(attestation) = info.attestation {
         info!("Registering worker...");
         register_worker(&paraclient, info.encoded_runtime_info, attestation, signer).await?;
+        return Ok(true);
     }
-    Ok(())
+    Ok(false)
+}
+
+/// Checks if the attestation registered at `registered_at` is about to expire on chain
+async fn attestation_expiring(
+    paraclient: &XtClient,
+    registered_at: SystemTime,
+    margin: u64,
+) -> Result<bool> {
+    let max_age = match chain_client::max_attestation_age(paraclient).await? {
+        Some(max_age) => max_age,
+        None => return Ok(false),
+    };
+    // Refresh halfway if the margin doesn't fit in the max age
+    let refresh_after = if margin < max_age {
+        max_age - margin
+    } else {
+        max_age / 2
+    };
+    let age = registered_at.elapsed().unwrap_or_default().as_secs();
+    Ok(age >= refresh_after)
 }
 
 const DEV_KEY: &str = "0000000000000000000000000000000000000000000000000000000000000001";
@@ -909,6 +954,8 @@ async fn bridge(args: Args) -> Result<()> {
     let mut pruntime_new_init = false;
     let mut initial_sync_finished = false;
     let mut geolocation_report_ttl = SystemTime::now();
+    let mut registered_at: Option<SystemTime> = None;
+    let mut egress_cursor = prpc::EgressCursor::new();
 
     // Try to initialize pRuntime and register on-chain
     let info = pr.get_info(()).await?;
@@ -1091,8 +1138,22 @@ async fn bridge(args: Args) -> Result<()> {
 
         // check if pRuntime has already reached the chain tip.
         if synced_blocks == 0 && !more_blocks {
-            if !initial_sync_finished && !args.no_register {
-                try_register_worker(&pr, &paraclient, &mut signer).await?;
+            if !args.no_register {
+                let expiring = match registered_at {
+                    Some(at) => {
+                        attestation_expiring(&paraclient, at, args.attestation_refresh_margin)
+                            .await?
+                    }
+                    None => false,
+                };
+                if expiring {
+                    info!("The attestation is about to expire, registering again");
+                }
+                if (!initial_sync_finished || expiring)
+                    && try_register_worker(&pr, &paraclient, &mut signer).await?
+                {
+                    registered_at = Some(SystemTime::now());
+                }
             }
             if args.enable_geolocation {
                 geolocation_report_ttl =
@@ -1120,9 +1181,11 @@ async fn bridge(args: Args) -> Result<()> {
                     &paraclient,
                     &pr,
                     &mut signer,
+                    &mut egress_cursor,
                     args.tip,
                     args.longevity,
                     args.max_sync_msgs_per_round,
+                    args.max_egress_page_bytes,
                 );
                 msg_sync.maybe_sync_mq_egress().await?;
             }
@@ -1,6 +1,8 @@
 use anyhow::{anyhow, Result};
 use core::marker::PhantomData;
 use log::{error, info};
+use phactory_api::prpc::{self as pb, EgressCursor};
+use phala_types::messaging::{MessageOrigin, SignedMessage};
 use sp_core::H256;
 use std::time::Duration;
 
@@ -22,6 +24,8 @@ pub struct MsgSync<'a> {
     pr: &'a PrClient,
     /// SR25519 signer with a nonce
     signer: &'a mut SrSigner,
+    /// The senders seen in the previous rounds, with their next sequences on chain
+    known_senders: &'a mut EgressCursor,
     /// True if the nonce is ever updated from the blockchain during the lifetiem of MsgSync
     nonce_updated: bool,
     /// Extra transcation fee
@@ -30,6 +34,8 @@ pub struct MsgSync<'a> {
     longevity: u64,
     /// Max number of messages to sync at a time.
     max_sync_msgs_per_round: u64,
+    /// Max size in bytes of each page of egress messages. 0 to let pRuntime decide.
+    max_egress_page_bytes: u32,
 }
 
 impl<'a> MsgSync<'a> {
@@ -38,32 +44,87 @@ impl<'a> MsgSync<'a> {
         client: &'a XtClient,
         pr: &'a PrClient,
         signer: &'a mut SrSigner,
+        known_senders: &'a mut EgressCursor,
         tip: u64,
         longevity: u64,
         max_sync_msgs_per_round: u64,
+        max_egress_page_bytes: u32,
     ) -> Self {
         Self {
             client,
             pr,
             signer,
+            known_senders,
             nonce_updated: false,
             tip,
             longevity,
             max_sync_msgs_per_round,
+            max_egress_page_bytes,
         }
     }
 
     pub async fn maybe_sync_mq_egress(&mut self) -> Result<()> {
-        // Send the query
-        let messages = self.pr.get_egress_messages(()).await?.decode_messages()?;
-
-        // No pending message. We are done.
-        if messages.is_empty() {
-            return Ok(());
+        // Don't fetch the messages of the known senders already accepted on chain
+        for (sender, next) in self.known_senders.iter_mut() {
+            *next = mq_next_sequence(self.client, sender).await?;
         }
+        let mut cursor = self.known_senders.clone();
+        let mut era = None;
+        let mut sync_msgs_count = 0;
+        loop {
+            // Send the query
+            let page = self
+                .pr
+                .get_egress_messages_page(pb::GetEgressMessagesPageRequest::new(
+                    cursor.clone(),
+                    self.max_egress_page_bytes,
+                ))
+                .await?;
+            let messages = page.decode_messages()?;
+
+            // No pending message. We are done.
+            if messages.is_empty() {
+                return Ok(());
+            }
+
+            if era.is_none() {
+                self.maybe_update_signer_nonce().await?;
+                era = Some(self.era().await?);
+            }
+            let era = era.clone().expect("Era is set above; qed.");
+
+            cursor = page.decode_next_cursor()?;
+            for (sender, messages) in messages {
+                let min_seq = mq_next_sequence(self.client, &sender).await?;
+
+                info!("Next seq for {} is {}", sender, min_seq);
+
+                // Skip the messages already accepted on chain in the later pages
+                let next = cursor.entry(sender.clone()).or_default();
+                *next = (*next).max(min_seq);
+                self.known_senders.insert(sender.clone(), min_seq);
+
+                for message in messages {
+                    if message.sequence < min_seq {
+                        info!("{} has been submitted. Skipping...", message.sequence);
+                        continue;
+                    }
+                    self.submit_message(&sender, message, era.clone()).await?;
+                    sync_msgs_count += 1;
+                    if sync_msgs_count >= self.max_sync_msgs_per_round {
+                        info!("Synced {} messages, take a break", sync_msgs_count);
+                        return Ok(());
+                    }
+                }
+            }
 
-        self.maybe_update_signer_nonce().await?;
+            if !page.has_more {
+                return Ok(());
+            }
+        }
+    }
 
+    async fn era(&self) -> Result<Option<EraInfo>> {
         let era = if self.longevity > 0 {
             let header = self
                 .client
@@ -90,75 +151,60 @@ impl<'a> MsgSync<'a> {
         } else {
             None
         };
+        Ok(era)
+    }
 
-        let mut sync_msgs_count = 0;
-
-        'sync_outer: for (sender, messages) in messages {
-            if messages.is_empty() {
-                continue;
-            }
-            let min_seq = mq_next_sequence(self.client, &sender).await?;
-
-            info!("Next seq for {} is {}", sender, min_seq);
-
-            for message in messages {
-                if message.sequence < min_seq {
-                    info!("{} has been submitted. Skipping...", message.sequence);
-                    continue;
-                }
-                let msg_info = format!(
-                    "sender={} seq={} dest={} nonce={:?}",
-                    sender,
-                    message.sequence,
-                    String::from_utf8_lossy(&message.message.destination.path()[..]),
-                    self.signer.nonce()
-                );
-                info!("Submitting message: {}", msg_info);
-                let extrinsic = self
-                    .client
-                    .create_signed(
-                        runtimes::phala_mq::SyncOffchainMessageCall {
-                            _runtime: PhantomData,
-                            message,
-                        },
-                        self.signer,
-                        ExtraConfig {
-                            tip: self.tip,
-                            era: era.clone(),
-                        },
-                    )
-                    .await;
-                self.signer.increment_nonce();
-                match extrinsic {
-                    Ok(extrinsic) => {
-                        let client = self.client.clone();
-                        tokio::spawn(async move {
-                            const TIMEOUT: u64 = 120;
-                            let fut = client.submit_extrinsic(extrinsic);
-                            let result =
-                                tokio::time::timeout(Duration::from_secs(TIMEOUT), fut).await;
-                            match result {
-                                Err(_) => {
-                                    error!("Submit message timed out: {}", msg_info);
-                                }
-                                Ok(Err(err)) => {
-                                    error!("Error submitting message {}: {:?}", msg_info, err);
-                                }
-                                Ok(Ok(hash)) => {
-                                    info!("Message submited: {} xt-hash={:?}", msg_info, hash);
-                                }
-                            }
-                        });
-                    }
-                    Err(err) => {
-                        panic!("Failed to sign the call: {:?}", err);
+    async fn submit_message(
+        &mut self,
+        sender: &MessageOrigin,
+        message: SignedMessage,
+        era: Option<EraInfo>,
+    ) -> Result<()> {
+        let msg_info = format!(
+            "sender={} seq={} dest={} nonce={:?}",
+            sender,
+            message.sequence,
+            String::from_utf8_lossy(&message.message.destination.path()[..]),
+            self.signer.nonce()
+        );
+        info!("Submitting message: {}", msg_info);
+        let extrinsic = self
+            .client
+            .create_signed(
+                runtimes::phala_mq::SyncOffchainMessageCall {
+                    _runtime: PhantomData,
+                    message,
+                },
+                self.signer,
+                ExtraConfig {
+                    tip: self.tip,
+                    era,
+                },
+            )
+            .await;
+        self.signer.increment_nonce();
+        match extrinsic {
+            Ok(extrinsic) => {
+                let client = self.client.clone();
+                tokio::spawn(async move {
+                    const TIMEOUT: u64 = 120;
+                    let fut = client.submit_extrinsic(extrinsic);
+                    let result = tokio::time::timeout(Duration::from_secs(TIMEOUT), fut).await;
+                    match result {
+                        Err(_) => {
+                            error!("Submit message timed out: {}", msg_info);
+                        }
+                        Ok(Err(err)) => {
+                            error!("Error submitting message {}: {:?}", msg_info, err);
+                        }
+                        Ok(Ok(hash)) => {
+                            info!("Message submited: {} xt-hash={:?}", msg_info, hash);
+                        }
                     }
-                }
-                sync_msgs_count += 1;
-                if sync_msgs_count >= self.max_sync_msgs_per_round {
-                    info!("Synced {} messages, take a break", sync_msgs_count);
-                    break 'sync_outer;
-                }
+                });
+            }
+            Err(err) => {
+                panic!("Failed to sign the call: {:?}", err);
             }
         }
         Ok(())
@@ -0,0 +1,171 @@
+#![feature(decl_macro)]
+
+#[macro_use]
+extern crate rocket;
+#[macro_use]
+extern crate log;
+
+use std::fs;
+use std::io::Read as _;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+
+use colored::Colorize;
+use rocket::data::Data;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::State;
+use structopt::StructOpt;
+
+use phactory::{benchmark, Phactory};
+use phactory_api::{
+    ecall_args::{git_revision, EgressOverflowPolicy, InitArgs},
+    prpc,
+};
+use phactory_pal_native::{NativePlatform, SEAL_KEY_FILE};
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "pruntime-native",
+    about = "The Phala worker running without SGX. NOT SECURE, for testing only."
+)]
+struct Args {
+    /// Number of CPU cores to be used for mining.
+    #[structopt(short, long)]
+    cores: Option<u32>,
+
+    /// Run benchmark at startup.
+    #[structopt(long)]
+    init_bench: bool,
+
+    /// Checkpoint the runtime state every N blocks and restore from it at startup. 0 to disable.
+    #[structopt(long, default_value = "0")]
+    checkpoint_interval: u32,
+
+    /// The directory to store the sealed data and the sealing key.
+    #[structopt(long, default_value = "./data")]
+    sealing_path: PathBuf,
+
+    /// Produce mock DCAP quotes instead of mock IAS reports.
+    #[structopt(long)]
+    dcap: bool,
+
+    /// The egress queue capacity of each contract. 0 for unlimited. Must be the same on all the
+    /// workers, since it affects the message sequences of the contracts.
+    #[structopt(long, default_value = "1024")]
+    contract_egress_capacity: u32,
+
+    /// What to do when the egress queue of a contract is full: `reject` the new messages, or
+    /// `block` the contract from processing its commands until the queue gets purged. Must be the
+    /// same on all the workers.
+    #[structopt(long, default_value = "reject")]
+    contract_egress_policy: EgressOverflowPolicy,
+}
+
+const OUTPUT_BUF_MAX_LEN: usize = 10 * 2048 * 1024;
+
+type Application = Mutex<Phactory<NativePlatform>>;
+
+#[post("/<method>", data = "<data>")]
+fn prpc_proxy(method: String, data: Data, app: State<Application>) -> Custom<Vec<u8>> {
+    let mut input = Vec::new();
+    if data.open().read_to_end(&mut input).is_err() {
+        return Custom(Status::BadRequest, b"Read body failed".to_vec());
+    }
+
+    let mut factory = app.lock().unwrap();
+    let path = method.as_bytes();
+    let (code, output) = unsafe {
+        factory.dispatch_prpc_request(
+            path.as_ptr(),
+            path.len(),
+            input.as_ptr(),
+            input.len(),
+            OUTPUT_BUF_MAX_LEN,
+        )
+    };
+    info!("pRPC status code: {}, data len: {}", code, output.len());
+    match Status::from_code(code) {
+        Some(status) => Custom(status, output),
+        None => {
+            error!("[-] prpc: Invalid status code: {}!", code);
+            Custom(Status::ServiceUnavailable, vec![])
+        }
+    }
+}
+
+fn print_rpc_methods(prefix: &str, methods: &[&str]) {
+    info!("Methods under {}:", prefix);
+    for method in methods {
+        info!("    {}", format!("{}/{}", prefix, method).blue());
+    }
+}
+
+fn rocket(app: Application) -> rocket::Rocket {
+    print_rpc_methods("/prpc", prpc::phactory_api_server::supported_methods());
+    rocket::ignite()
+        .manage(app)
+        .mount("/prpc", routes![prpc_proxy])
+}
+
+fn set_thread_idle_policy() {
+    let param = libc::sched_param { sched_priority: 0 };
+    unsafe {
+        let rv = libc::sched_setscheduler(0, libc::SCHED_IDLE, &param);
+        if rv != 0 {
+            error!("Failed to set thread schedule prolicy to IDLE");
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::from_args();
+
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    warn!("Running pRuntime natively. The sealed data and the attestation are NOT protected.");
+
+    fs::create_dir_all(&args.sealing_path)?;
+    let platform = NativePlatform::load_or_generate(args.sealing_path.join(SEAL_KEY_FILE))?;
+
+    let log_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into());
+    let init_args = InitArgs {
+        sealing_path: args.sealing_path.to_string_lossy().into(),
+        log_filter,
+        init_bench: args.init_bench,
+        checkpoint_interval: args.checkpoint_interval,
+        dcap_attestation: args.dcap,
+        contract_egress_capacity: args.contract_egress_capacity,
+        contract_egress_policy: args.contract_egress_policy,
+        version: env!("CARGO_PKG_VERSION").into(),
+        git_revision: git_revision(),
+    };
+    info!("init_args: {:#?}", init_args);
+
+    let mut factory = Phactory::new(platform);
+    factory.init(init_args);
+
+    let bench_cores: u32 = args.cores.unwrap_or_else(|| num_cpus::get() as _);
+    info!("Bench cores: {}", bench_cores);
+    for i in 0..bench_cores {
+        thread::Builder::new()
+            .name(format!("bench-{}", i))
+            .spawn(move || {
+                set_thread_idle_policy();
+                loop {
+                    if !benchmark::puasing() {
+                        info!("[{}] Benchmark thread started", i);
+                        benchmark::run();
+                    }
+                    thread::sleep(std::time::Duration::from_millis(200));
+                }
+            })?;
+    }
+
+    let err = rocket(Mutex::new(factory)).launch();
+    Err(anyhow::anyhow!("Launch failed: {}", err))
+}
@@ -31,11 +31,14 @@ use std::env;
 fn main() {
     let sdk_dir = env::var("SGX_SDK").unwrap_or_else(|_| "/opt/intel/sgxsdk".to_string());
     let is_sim = env::var("SGX_MODE").unwrap_or_else(|_| "HW".to_string());
+    // The DCAP Quote Library isn't installed on the IAS only hosts
+    let dcap = env::var("SGX_DCAP") == Ok("1".to_string());
 
     println!("cargo:rustc-link-search=native=../lib");
     println!("cargo:rustc-link-lib=static=Enclave_u");
     println!("cargo:rerun-if-env-changed=SGX_SDK");
     println!("cargo:rerun-if-env-changed=SGX_MODE");
+    println!("cargo:rerun-if-env-changed=SGX_DCAP");
 
     println!("cargo:rustc-link-search=native={}/lib64", sdk_dir);
     println!("cargo:rustc-link-lib=static=sgx_uprotected_fs");
@@ -48,8 +51,14 @@ fn main() {
             // HW by default
             println!("cargo:rustc-link-lib=dylib=sgx_urts");
             println!("cargo:rustc-link-lib=dylib=sgx_uae_service");
+            if dcap {
+                println!("cargo:rustc-link-lib=dylib=sgx_dcap_ql");
+            }
         }
     };
+    if dcap && is_sim != "SW" {
+        println!("cargo:rustc-cfg=dcap");
+    }
 
     if env::var("SKIP_IAS").is_ok() || env::var("SGX_MODE") == Ok("SW".to_string()) {
         println!("cargo:rustc-env=IAS_SPID=''");
@@ -50,6 +50,7 @@ use std::sync::RwLock;
 use rocket::data::Data;
 use rocket::http::Method;
 use rocket::http::Status;
+use rocket::response::content::Plain;
 use rocket::response::status::Custom;
 use rocket_contrib::json::{Json, JsonValue};
 use rocket_cors::{AllowedHeaders, AllowedMethods, AllowedOrigins, CorsOptions};
@@ -59,7 +60,7 @@ use contract_input::ContractInput;
 use parity_scale_codec::Encode;
 use phactory_api::{
     actions,
-    ecall_args::{git_revision, InitArgs},
+    ecall_args::{git_revision, EgressOverflowPolicy, InitArgs},
     prpc,
 };
 
@@ -73,6 +74,26 @@ struct Args {
     /// Run benchmark at startup.
     #[structopt(long)]
     init_bench: bool,
+
+    /// Checkpoint the runtime state every N blocks and restore from it at startup. 0 to disable.
+    #[structopt(long, default_value = "0")]
+    checkpoint_interval: u32,
+
+    /// Attest with a DCAP quote instead of an IAS report. Requires the DCAP Quote Provider
+    /// Library on the host, and pRuntime built with `SGX_DCAP=1`.
+    #[structopt(long)]
+    dcap: bool,
+
+    /// The egress queue capacity of each contract. 0 for unlimited. Must be the same on all the
+    /// workers, since it affects the message sequences of the contracts.
+    #[structopt(long, default_value = "1024")]
+    contract_egress_capacity: u32,
+
+    /// What to do when the egress queue of a contract is full: `reject` the new messages, or
+    /// `block` the contract from processing its commands until the queue gets purged. Must be the
+    /// same on all the workers.
+    #[structopt(long, default_value = "reject")]
+    contract_egress_policy: EgressOverflowPolicy,
 }
 
 static ENCLAVE_FILE: &'static str = "enclave.signed.so";
@@ -258,6 +279,69 @@ pub extern "C" fn ocall_get_quote(
     ret
 }
 
+#[cfg(dcap)]
+#[no_mangle]
+pub extern "C" fn ocall_qe_get_target_info(ret_ti: *mut sgx_target_info_t) -> sgx_status_t {
+    info!("Entering ocall_qe_get_target_info");
+    let ret = unsafe { sgx_qe_get_target_info(ret_ti) };
+    if ret != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        warn!("sgx_qe_get_target_info returned {:?}", ret);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+    sgx_status_t::SGX_SUCCESS
+}
+
+#[cfg(dcap)]
+#[no_mangle]
+pub extern "C" fn ocall_qe_get_quote(
+    p_report: *const sgx_report_t,
+    p_quote: *mut u8,
+    maxlen: u32,
+    p_quote_len: *mut u32,
+) -> sgx_status_t {
+    info!("Entering ocall_qe_get_quote");
+
+    let mut quote_len: u32 = 0;
+    let ret = unsafe { sgx_qe_get_quote_size(&mut quote_len as *mut u32) };
+    if ret != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        warn!("sgx_qe_get_quote_size returned {:?}", ret);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+    info!("quote size = {}", quote_len);
+    unsafe {
+        *p_quote_len = quote_len;
+    }
+    if quote_len > maxlen {
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    let ret = unsafe { sgx_qe_get_quote(p_report, quote_len, p_quote) };
+    if ret != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        warn!("sgx_qe_get_quote returned {:?}", ret);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+    sgx_status_t::SGX_SUCCESS
+}
+
+#[cfg(not(dcap))]
+#[no_mangle]
+pub extern "C" fn ocall_qe_get_target_info(_ret_ti: *mut sgx_target_info_t) -> sgx_status_t {
+    warn!("DCAP is not supported by this build");
+    sgx_status_t::SGX_ERROR_FEATURE_NOT_SUPPORTED
+}
+
+#[cfg(not(dcap))]
+#[no_mangle]
+pub extern "C" fn ocall_qe_get_quote(
+    _p_report: *const sgx_report_t,
+    _p_quote: *mut u8,
+    _maxlen: u32,
+    _p_quote_len: *mut u32,
+) -> sgx_status_t {
+    warn!("DCAP is not supported by this build");
+    sgx_status_t::SGX_ERROR_FEATURE_NOT_SUPPORTED
+}
+
 #[no_mangle]
 pub extern "C" fn ocall_get_update_info(
     platform_blob: *const sgx_platform_info_t,
@@ -461,6 +545,28 @@ fn kick() {
     std::process::exit(0);
 }
 
+#[get("/metrics")]
+fn metrics() -> Result<Plain<String>, Custom<String>> {
+    let input_string = r#"{ "input": {} }"#.to_string();
+    let output = do_ecall_handle!(actions::ACTION_GET_METRICS, input_string);
+    // The payload is the json encoded exposition text
+    let payload = match output["payload"].as_str() {
+        Some(payload) => payload,
+        None => {
+            return Err(Custom(
+                Status::ServiceUnavailable,
+                "Bad enclave output".into(),
+            ))
+        }
+    };
+    if output["status"] != "ok" {
+        return Err(Custom(Status::ServiceUnavailable, payload.into()));
+    }
+    serde_json::from_str(payload)
+        .map(Plain)
+        .map_err(|_| Custom(Status::ServiceUnavailable, "Bad metrics payload".into()))
+}
+
 #[post("/<method>", data = "<data>")]
 fn prpc_proxy(method: String, data: Data) -> Custom<Vec<u8>> {
     let eid = crate::get_eid();
@@ -582,6 +688,7 @@ fn rocket() -> rocket::Rocket {
         server = server.mount("/", routes![kick]);
     }
 
+    server = server.mount("/", routes![metrics]);
     server = server.mount("/prpc", routes![prpc_proxy]);
     print_rpc_methods("/prpc", prpc::phactory_api_server::supported_methods());
 
@@ -608,6 +715,10 @@ fn main() {
         .parse_default_env()
         .init();
 
+    if args.dcap && !cfg!(dcap) {
+        panic!("[-] DCAP is not supported by this build, please rebuild with SGX_DCAP=1");
+    }
+
     let enclave = match init_enclave() {
         Ok(r) => {
             info!("[+] Init Enclave Successful, pid={}!", r.geteid());
@@ -631,6 +742,10 @@ fn main() {
         sealing_path,
         log_filter,
         init_bench: args.init_bench,
+        checkpoint_interval: args.checkpoint_interval,
+        dcap_attestation: args.dcap,
+        contract_egress_capacity: args.contract_egress_capacity,
+        contract_egress_policy: args.contract_egress_policy,
         version: env!("CARGO_PKG_VERSION").into(),
         git_revision: git_revision(),
     };
@@ -84,6 +84,10 @@ impl RA for SgxPlatform {
     ) -> Result<(String, String, String), Self::Error> {
         create_attestation_report(data, sgx_quote_sign_type_t::SGX_LINKABLE_SIGNATURE)
     }
+
+    fn create_dcap_quote(&self, data: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        create_dcap_quote(data)
+    }
 }
 
 impl Machine for SgxPlatform {
@@ -133,6 +137,19 @@ extern "C" {
         ret_gid: *mut sgx_epid_group_id_t,
     ) -> sgx_status_t;
 
+    pub fn ocall_qe_get_target_info(
+        ret_val: *mut sgx_status_t,
+        ret_ti: *mut sgx_target_info_t,
+    ) -> sgx_status_t;
+
+    pub fn ocall_qe_get_quote(
+        ret_val: *mut sgx_status_t,
+        p_report: *const sgx_report_t,
+        p_quote: *mut u8,
+        maxlen: u32,
+        p_quote_len: *mut u32,
+    ) -> sgx_status_t;
+
     pub fn ocall_get_quote(
         ret_val: *mut sgx_status_t,
         p_sigrl: *const u8,
@@ -584,3 +601,52 @@ fn generate_seal_key() -> [u8; 16] {
     let seal_key = rsgx_get_align_key(&key_request).unwrap();
     seal_key.key
 }
+
+/// Creates an ECDSA quote by the DCAP Quoting Enclave
+pub fn create_dcap_quote(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() > SGX_REPORT_DATA_SIZE {
+        return Err(anyhow!("data length over {} bytes", SGX_REPORT_DATA_SIZE));
+    }
+
+    // The report must target the QE
+    let mut ti = sgx_target_info_t::default();
+    let mut rt = sgx_status_t::SGX_ERROR_UNEXPECTED;
+    let res = unsafe { ocall_qe_get_target_info(&mut rt as *mut _, &mut ti as *mut _) };
+    if res != sgx_status_t::SGX_SUCCESS {
+        return Err(anyhow::Error::msg(res).context("qe get target info"));
+    }
+    if rt != sgx_status_t::SGX_SUCCESS {
+        return Err(anyhow::Error::msg(rt).context("qe get target info"));
+    }
+
+    let mut report_data = sgx_report_data_t::default();
+    report_data.d[..data.len()].clone_from_slice(data);
+    let report = rsgx_create_report(&ti, &report_data)
+        .map_err(|err| anyhow::Error::msg(err).context("create report"))?;
+
+    // Large enough for the quote with the PCK certificate chain
+    const RET_QUOTE_BUF_LEN: u32 = 8192;
+    let mut quote = vec![0u8; RET_QUOTE_BUF_LEN as usize];
+    let mut quote_len: u32 = 0;
+    let res = unsafe {
+        ocall_qe_get_quote(
+            &mut rt as *mut _,
+            &report as *const _,
+            quote.as_mut_ptr(),
+            RET_QUOTE_BUF_LEN,
+            &mut quote_len as *mut _,
+        )
+    };
+    if res != sgx_status_t::SGX_SUCCESS {
+        return Err(anyhow::Error::msg(res).context("qe get quote"));
+    }
+    if rt != sgx_status_t::SGX_SUCCESS {
+        return Err(anyhow::Error::msg(rt).context("qe get quote"));
+    }
+    if quote_len > RET_QUOTE_BUF_LEN {
+        return Err(anyhow!("quote too long: {}", quote_len));
+    }
+    quote.truncate(quote_len as usize);
+    info!("DCAP quote created, len={}", quote_len);
+    Ok(quote)
+}
@@ -104,6 +104,7 @@ pub use phala_pallets::{
 	pallet_registry,
 	pallet_mining,
 	pallet_stakepool,
+	pallet_random_beacon,
 };
 pub use pallet_bridge;
 pub use pallet_bridge_transfer;
@@ -1094,6 +1095,7 @@ impl pallet_registry::Config for Runtime {
 	type VerifyPRuntime = VerifyPRuntime;
 	type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
 	type GovernanceOrigin = EnsureRootOrHalfCouncil;
+	type MiningWorkers = PhalaMining;
 }
 impl pallet_mq::Config for Runtime {
 	type QueueNotifyConfig = msg_routing::MessageRouteConfig;
@@ -1127,6 +1129,10 @@ impl pallet_ott::Config for Runtime {
 	type Currency = Balances;
 }
 
+impl pallet_random_beacon::Config for Runtime {
+	type Event = Event;
+}
+
 construct_runtime!(
 	pub enum Runtime where
 		Block = Block,
@@ -1176,6 +1182,7 @@ construct_runtime!(
 		PhalaMining: pallet_mining::{Pallet, Call, Event<T>, Storage, Config},
 		PhalaStakePool: pallet_stakepool::{Pallet, Call, Event<T>, Storage},
 		PhalaOneshotTransfer: pallet_ott::{Pallet, Call, Event<T>, Storage},
+		PhalaRandomBeacon: pallet_random_beacon::{Pallet, Call, Event<T>, Storage},
 	}
 );
 
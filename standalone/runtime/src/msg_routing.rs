@@ -33,6 +33,7 @@ impl pallet_mq::QueueNotifyConfig for MessageRouteConfig {
             PhalaRegistry::on_message_received,
             PhalaMining::on_gk_message_received,
             PhalaMining::on_mining_message_received,
+            PhalaRandomBeacon::on_gk_message_received,
             BridgeTransfer::on_message_received,
             // KittyStorage::on_message_received,
         };
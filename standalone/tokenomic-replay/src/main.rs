@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use log::info;
+use parity_scale_codec::Decode;
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use phactory::{GatekeeperReplay, WorkerSample};
+use phala_mq::Message;
+use phala_types::messaging::TokenomicParameters;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "tokenomic-replay",
+    about = "Replay recorded mining messages through the gatekeeper and output the V, payout and slash of each worker."
+)]
+struct Args {
+    /// The recorded messages, one JSON object per line:
+    /// {"block_number": 1, "now_ms": 1630000000000, "messages": ["0x<SCALE encoded Message>", ...]}
+    ///
+    /// The messages of a block are the ones in `PhalaMq.OutboundMessages` at that block. Blocks
+    /// missing from the recording are replayed without messages. "-" for stdin.
+    #[structopt(default_value = "-")]
+    input: String,
+
+    /// The initial tokenomic parameters as a JSON file. The default ones of the gatekeeper are used
+    /// if omitted. They are updated by the `TokenomicParametersChanged` and
+    /// `TokenomicParametersScheduled` events in the recording.
+    #[structopt(long)]
+    params: Option<PathBuf>,
+
+    /// The output format, `csv` or `json`.
+    #[structopt(long, default_value = "csv")]
+    format: OutputFormat,
+
+    /// The output file. Writes to stdout if omitted.
+    #[structopt(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+enum OutputFormat {
+    Csv,
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(anyhow!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RecordedBlock {
+    block_number: u32,
+    now_ms: u64,
+    messages: Vec<String>,
+}
+
+fn decode_message(hex_message: &str) -> Result<Message> {
+    let data = hex::decode(hex_message.strip_prefix("0x").unwrap_or(hex_message))?;
+    Ok(Message::decode(&mut &data[..])?)
+}
+
+fn replay(input: impl BufRead, replay: &mut GatekeeperReplay) -> Result<Vec<WorkerSample>> {
+    let mut samples = vec![];
+    let mut last_block: Option<(u32, u64)> = None;
+    for (line_no, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let block: RecordedBlock = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid record at line {}", line_no + 1))?;
+
+        if let Some((last_number, last_ms)) = last_block {
+            if block.block_number <= last_number {
+                bail!(
+                    "Block {} is not after block {} at line {}",
+                    block.block_number,
+                    last_number,
+                    line_no + 1
+                );
+            }
+            // Fill the gap with empty blocks, interpolating their timestamps
+            let gap = (block.block_number - last_number) as u64;
+            let interval = block.now_ms.saturating_sub(last_ms) / gap;
+            for i in 1..gap {
+                let block_number = last_number + i as u32;
+                replay.process_block(block_number, last_ms + interval * i);
+                samples.extend(replay.samples(block_number));
+            }
+        }
+
+        let mut ignored = 0;
+        for hex_message in &block.messages {
+            let message = decode_message(hex_message)
+                .with_context(|| format!("Invalid message at line {}", line_no + 1))?;
+            if !replay.dispatch(message) {
+                ignored += 1;
+            }
+        }
+        let reports = replay.process_block(block.block_number, block.now_ms);
+        info!(
+            "Block {}: {} messages ({} ignored), {} reports",
+            block.block_number,
+            block.messages.len(),
+            ignored,
+            reports.len()
+        );
+        samples.extend(replay.samples(block.block_number));
+        last_block = Some((block.block_number, block.now_ms));
+    }
+    Ok(samples)
+}
+
+fn write_csv(mut output: impl Write, samples: &[WorkerSample]) -> Result<()> {
+    writeln!(
+        output,
+        "block_number,pubkey,paused,unresponsive,v,p_instant,payout,slash,total_payout,total_slash"
+    )?;
+    for s in samples {
+        writeln!(
+            output,
+            "{},{},{},{},{},{},{},{},{},{}",
+            s.block_number,
+            s.pubkey,
+            s.paused,
+            s.unresponsive,
+            s.v,
+            s.p_instant,
+            s.payout,
+            s.slash,
+            s.total_payout,
+            s.total_slash
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the time series grouped by worker
+fn write_json(output: impl Write, samples: Vec<WorkerSample>) -> Result<()> {
+    let mut series: BTreeMap<String, Vec<WorkerSample>> = BTreeMap::new();
+    for sample in samples {
+        series
+            .entry(sample.pubkey.clone())
+            .or_default()
+            .push(sample);
+    }
+    serde_json::to_writer_pretty(output, &series)?;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::from_args();
+
+    let params: Option<TokenomicParameters> = match &args.params {
+        Some(path) => {
+            let file = File::open(path).context("Failed to open the parameters")?;
+            Some(serde_json::from_reader(file).context("Invalid tokenomic parameters")?)
+        }
+        None => None,
+    };
+    let mut gk = GatekeeperReplay::new(params);
+
+    let samples = if args.input == "-" {
+        replay(io::stdin().lock(), &mut gk)?
+    } else {
+        let file = File::open(&args.input).context("Failed to open the input")?;
+        replay(BufReader::new(file), &mut gk)?
+    };
+
+    let output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path).context("Failed to create the output")?),
+        None => Box::new(io::stdout()),
+    };
+    match args.format {
+        OutputFormat::Csv => write_csv(output, &samples),
+        OutputFormat::Json => write_json(output, samples),
+    }
+}